@@ -0,0 +1,23 @@
+/* 処理系が用意する <stdarg.h>。va_list の実体は組み込みの型と関数で扱う。 */
+
+#ifndef __GNUC_VA_LIST
+#define __GNUC_VA_LIST
+typedef __builtin_va_list __gnuc_va_list;
+#endif
+
+/* glibc のヘッダは __gnuc_va_list だけを求めて取り込むことがある。 */
+#ifdef __need___va_list
+#undef __need___va_list
+#else
+#ifndef _STDARG_H
+#define _STDARG_H
+
+typedef __gnuc_va_list va_list;
+
+#define va_start(ap, last) __builtin_va_start(ap, last)
+#define va_arg(ap, type) __builtin_va_arg(ap, type)
+#define va_copy(dest, src) __builtin_va_copy(dest, src)
+#define va_end(ap) __builtin_va_end(ap)
+
+#endif
+#endif
@@ -0,0 +1,52 @@
+/* 処理系が用意する <stddef.h>。 */
+
+/* glibc のヘッダは __need_size_t などで一部の型だけを求めて取り込むことがある。 */
+#if !defined(__need_size_t) && !defined(__need_ptrdiff_t) && !defined(__need_wchar_t) \
+    && !defined(__need_wint_t) && !defined(__need_NULL)
+#define __need_size_t
+#define __need_ptrdiff_t
+#define __need_wchar_t
+#define __need_NULL
+#ifndef _STDDEF_H
+#define _STDDEF_H
+#define offsetof(type, member) ((size_t)&((type *)0)->member)
+#endif
+#endif
+
+#ifdef __need_size_t
+#undef __need_size_t
+#ifndef __size_t_defined
+#define __size_t_defined
+typedef unsigned long size_t;
+#endif
+#endif
+
+#ifdef __need_ptrdiff_t
+#undef __need_ptrdiff_t
+#ifndef __ptrdiff_t_defined
+#define __ptrdiff_t_defined
+typedef long ptrdiff_t;
+#endif
+#endif
+
+#ifdef __need_wchar_t
+#undef __need_wchar_t
+#ifndef __wchar_t_defined
+#define __wchar_t_defined
+typedef int wchar_t;
+#endif
+#endif
+
+#ifdef __need_wint_t
+#undef __need_wint_t
+#ifndef _WINT_T
+#define _WINT_T
+typedef unsigned int wint_t;
+#endif
+#endif
+
+#ifdef __need_NULL
+#undef __need_NULL
+#undef NULL
+#define NULL ((void *)0)
+#endif
@@ -0,0 +1,468 @@
+use crate::{
+    error::{CompileError, CompileErrorKind, CompileResult},
+    expr::{Expr, ExprKind},
+    lex::Span,
+    token::StringLiteral,
+    types::Type,
+};
+
+/// コンパイル時に値の決まる定数。
+#[derive(Debug, PartialEq, Clone)]
+pub enum Constant {
+    /// 値と型。値は型の大きさに切り詰め、符号付きの型なら符号拡張、符号なしの型ならゼロ拡張して持つ。
+    /// `unsigned long` はビット列をそのまま持つ。
+    Integer(i64, Type),
+    Float(f64),
+    /// 静的な変数のラベルとそこからのバイト数で表したアドレスと、そのポインタ型。
+    Address(String, i64, Type),
+    /// 文字列リテラルと、その先頭からのバイト数。
+    StringLiteral(StringLiteral, i64),
+}
+
+/// 定数式を評価する。整数は型を持ち、実行時と同じく整数拡張と通常の算術型変換をしてから計算する。
+/// 静的な記憶域を持つ変数と関数の名前から、そのラベルと型を引く。static 局所変数のラベルは名前と異なる。
+pub type StaticSymbols<'a> = &'a dyn Fn(&str) -> Option<(String, Type)>;
+
+pub struct ConstantEvaluator<'a> {
+    /// 構文解析中は `None` で、変数のアドレスを定数として扱わない。
+    symbols: Option<StaticSymbols<'a>>,
+    error_kind: CompileErrorKind,
+    not_constant_message: &'static str,
+}
+
+impl<'a> ConstantEvaluator<'a> {
+    /// 配列の大きさや列挙定数の値のような整数定数式を評価する。
+    pub const fn integer(error_kind: CompileErrorKind) -> Self {
+        Self {
+            symbols: None,
+            error_kind,
+            not_constant_message: "expression is not an integer constant",
+        }
+    }
+
+    /// 静的な変数の初期化子を評価する。
+    pub const fn initializer(symbols: StaticSymbols<'a>) -> Self {
+        Self {
+            symbols: Some(symbols),
+            error_kind: CompileErrorKind::Type,
+            not_constant_message: "initializer element is not a compile-time constant",
+        }
+    }
+
+    /// 値とその型を返す。
+    pub fn evaluate_integer(&self, expr: &Expr) -> CompileResult<(i64, Type)> {
+        match self.evaluate(expr)? {
+            Constant::Integer(value, ty) => Ok((value, ty)),
+            _ => Err(self.error(self.not_constant_message, expr.span)),
+        }
+    }
+
+    pub fn evaluate(&self, expr: &Expr) -> CompileResult<Constant> {
+        let not_constant = || self.error(self.not_constant_message, expr.span);
+        match &expr.kind {
+            ExprKind::Num(n) => Ok(Constant::Integer(i64::from(*n), Type::IntTyp)),
+            #[allow(clippy::cast_possible_wrap)]
+            ExprKind::IntNum(literal) => Ok(Constant::Integer(
+                convert_integer(literal.value as i64, &literal.ty()),
+                literal.ty(),
+            )),
+            ExprKind::FloatNum(literal) => Ok(Constant::Float(literal.value())),
+            ExprKind::StringLiteral(literal) => Ok(Constant::StringLiteral(literal.clone(), 0)),
+            ExprKind::Add(lhs, rhs)
+            | ExprKind::Sub(lhs, rhs)
+            | ExprKind::Mul(lhs, rhs)
+            | ExprKind::Div(lhs, rhs)
+            | ExprKind::Mod(lhs, rhs)
+            | ExprKind::BitAnd(lhs, rhs)
+            | ExprKind::BitOr(lhs, rhs)
+            | ExprKind::BitXor(lhs, rhs)
+            | ExprKind::LessThan(lhs, rhs)
+            | ExprKind::LessEqual(lhs, rhs)
+            | ExprKind::Equal(lhs, rhs)
+            | ExprKind::NotEqual(lhs, rhs)
+            | ExprKind::GreaterThan(lhs, rhs)
+            | ExprKind::GreaterEqual(lhs, rhs) => self.evaluate_binary(expr, lhs, rhs),
+            ExprKind::LeftShift(lhs, rhs) | ExprKind::RightShift(lhs, rhs) => {
+                match (self.evaluate(lhs)?, self.evaluate(rhs)?) {
+                    (Constant::Integer(lhs, lhs_type), Constant::Integer(rhs, _)) => {
+                        Ok(fold_shift(&expr.kind, (lhs, &lhs_type), rhs))
+                    }
+                    _ => Err(not_constant()),
+                }
+            }
+            ExprKind::LogicalAnd(lhs, rhs) | ExprKind::LogicalOr(lhs, rhs) => {
+                // 右辺は左辺で結果が決まらないときだけ評価する。
+                let is_and = matches!(expr.kind, ExprKind::LogicalAnd(_, _));
+                let lhs = self.truth(lhs)?;
+                let value = if lhs == is_and { self.truth(rhs)? } else { lhs };
+                Ok(Constant::Integer(i64::from(value), Type::IntTyp))
+            }
+            ExprKind::LogicalNot(operand) => Ok(Constant::Integer(
+                i64::from(!self.truth(operand)?),
+                Type::IntTyp,
+            )),
+            ExprKind::BitNot(operand) => match self.evaluate(operand)? {
+                Constant::Integer(value, ty) => {
+                    let ty = ty.promote();
+                    Ok(Constant::Integer(convert_integer(!value, &ty), ty))
+                }
+                _ => Err(not_constant()),
+            },
+            ExprKind::Conditional(cond, then, els) => {
+                let (chosen, other) = if self.truth(cond)? {
+                    (then, els)
+                } else {
+                    (els, then)
+                };
+                let value = self.evaluate(chosen)?;
+                // 選ばれなかった側は評価しないが、結果の型には影響する。
+                match (value, self.evaluate(other)) {
+                    (Constant::Integer(value, ty), Ok(Constant::Integer(_, other_type))) => {
+                        let ty = Type::common_integer_type(&ty, &other_type);
+                        Ok(Constant::Integer(convert_integer(value, &ty), ty))
+                    }
+                    (value, _) => Ok(value),
+                }
+            }
+            ExprKind::SizeofType(ty) if ty.is_complete() => Ok(Constant::Integer(
+                i64::try_from(ty.get_size()).map_err(|_| not_constant())?,
+                Type::ULongTyp,
+            )),
+            ExprKind::Alignof(ty) if ty.is_complete() => Ok(Constant::Integer(
+                i64::try_from(ty.get_align()).map_err(|_| not_constant())?,
+                Type::ULongTyp,
+            )),
+            ExprKind::Cast(ty, operand) => {
+                convert(self.evaluate(operand)?, ty.unqualified()).ok_or_else(not_constant)
+            }
+            ExprKind::Address(_)
+            | ExprKind::Variable(_)
+            | ExprKind::Dereference(_)
+            | ExprKind::Member(_, _) => self.evaluate_address(expr),
+            _ => Err(not_constant()),
+        }
+    }
+
+    /// 算術演算と比較。アドレスに整数を足し引きしたものもアドレス定数になる。
+    fn evaluate_binary(&self, expr: &Expr, lhs: &Expr, rhs: &Expr) -> CompileResult<Constant> {
+        let not_constant = || self.error(self.not_constant_message, expr.span);
+        match (self.evaluate(lhs)?, self.evaluate(rhs)?) {
+            (Constant::Integer(lhs, lhs_type), Constant::Integer(rhs, rhs_type)) => {
+                self.fold_integer(&expr.kind, (lhs, &lhs_type), (rhs, &rhs_type), expr.span)
+            }
+            (
+                address @ (Constant::Address(_, _, _) | Constant::StringLiteral(_, _)),
+                Constant::Integer(count, _),
+            ) => match expr.kind {
+                ExprKind::Add(_, _) => offset_address(address, count),
+                ExprKind::Sub(_, _) => offset_address(address, count.wrapping_neg()),
+                _ => None,
+            }
+            .ok_or_else(not_constant),
+            (
+                Constant::Integer(count, _),
+                address @ (Constant::Address(_, _, _) | Constant::StringLiteral(_, _)),
+            ) if matches!(expr.kind, ExprKind::Add(_, _)) => {
+                offset_address(address, count).ok_or_else(not_constant)
+            }
+            (lhs, rhs) => match (to_float(&lhs), to_float(&rhs)) {
+                (Some(lhs), Some(rhs)) => fold_float(&expr.kind, lhs, rhs).ok_or_else(not_constant),
+                _ => Err(not_constant()),
+            },
+        }
+    }
+
+    /// 静的な変数 (とその要素やメンバ) や関数のアドレスと、ポインタに変換される配列と関数。
+    fn evaluate_address(&self, expr: &Expr) -> CompileResult<Constant> {
+        if let ExprKind::Address(operand) = &expr.kind {
+            return self.evaluate_lvalue(operand);
+        }
+        match self.evaluate_lvalue(expr)? {
+            Constant::Address(label, offset, Type::Pointer(object)) => match object.unqualified() {
+                Type::Array(element_type, _) => Ok(Constant::Address(
+                    label,
+                    offset,
+                    Type::Pointer(element_type.clone()),
+                )),
+                Type::Function(_, _, _) => {
+                    Ok(Constant::Address(label, offset, Type::Pointer(object)))
+                }
+                _ => Err(self.error(self.not_constant_message, expr.span)),
+            },
+            _ => Err(self.error(self.not_constant_message, expr.span)),
+        }
+    }
+
+    /// 左辺値が指す静的な領域のアドレス。
+    fn evaluate_lvalue(&self, expr: &Expr) -> CompileResult<Constant> {
+        let not_constant = || self.error(self.not_constant_message, expr.span);
+        match &expr.kind {
+            ExprKind::Variable(name) => {
+                let (label, ty) = self.lookup(name).ok_or_else(not_constant)?;
+                Ok(Constant::Address(label, 0, Type::Pointer(Box::new(ty))))
+            }
+            ExprKind::Dereference(operand) => match self.evaluate(operand)? {
+                address @ (Constant::Address(_, _, _) | Constant::StringLiteral(_, _)) => {
+                    Ok(address)
+                }
+                _ => Err(not_constant()),
+            },
+            ExprKind::Member(operand, name) => match self.evaluate_lvalue(operand)? {
+                Constant::Address(label, offset, Type::Pointer(object)) => {
+                    let Type::Struct(struct_type) = object.unqualified() else {
+                        return Err(not_constant());
+                    };
+                    let member = struct_type.member(name).ok_or_else(not_constant)?;
+                    let member_offset = i64::try_from(member.offset).map_err(|_| not_constant())?;
+                    Ok(Constant::Address(
+                        label,
+                        offset + member_offset,
+                        Type::Pointer(Box::new(member.ty.qualified(object.qualifiers()))),
+                    ))
+                }
+                _ => Err(not_constant()),
+            },
+            _ => Err(not_constant()),
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<(String, Type)> {
+        self.symbols.and_then(|symbols| symbols(name))
+    }
+
+    /// 通常の算術型変換をしてから計算する。比較の結果は `int` になる。
+    fn fold_integer(
+        &self,
+        kind: &ExprKind,
+        (lhs, lhs_type): (i64, &Type),
+        (rhs, rhs_type): (i64, &Type),
+        span: Span,
+    ) -> CompileResult<Constant> {
+        let ty = Type::common_integer_type(lhs_type, rhs_type);
+        let (lhs, rhs) = (convert_integer(lhs, &ty), convert_integer(rhs, &ty));
+        let unsigned = ty.is_unsigned();
+        #[allow(clippy::cast_sign_loss)]
+        let (unsigned_lhs, unsigned_rhs) = (lhs as u64, rhs as u64);
+        let comparison = |ordering: fn(std::cmp::Ordering) -> bool| {
+            let order = if unsigned {
+                unsigned_lhs.cmp(&unsigned_rhs)
+            } else {
+                lhs.cmp(&rhs)
+            };
+            Ok(Constant::Integer(i64::from(ordering(order)), Type::IntTyp))
+        };
+        #[allow(clippy::cast_possible_wrap)]
+        let value = match kind {
+            ExprKind::Add(_, _) => lhs.wrapping_add(rhs),
+            ExprKind::Sub(_, _) => lhs.wrapping_sub(rhs),
+            ExprKind::Mul(_, _) => lhs.wrapping_mul(rhs),
+            ExprKind::Div(_, _) | ExprKind::Mod(_, _) if rhs == 0 => {
+                return Err(self.error("division by zero", span));
+            }
+            ExprKind::Div(_, _) if unsigned => (unsigned_lhs / unsigned_rhs) as i64,
+            ExprKind::Div(_, _) => lhs.wrapping_div(rhs),
+            ExprKind::Mod(_, _) if unsigned => (unsigned_lhs % unsigned_rhs) as i64,
+            ExprKind::Mod(_, _) => lhs.wrapping_rem(rhs),
+            ExprKind::BitAnd(_, _) => lhs & rhs,
+            ExprKind::BitOr(_, _) => lhs | rhs,
+            ExprKind::BitXor(_, _) => lhs ^ rhs,
+            ExprKind::LessThan(_, _) => return comparison(std::cmp::Ordering::is_lt),
+            ExprKind::LessEqual(_, _) => return comparison(std::cmp::Ordering::is_le),
+            ExprKind::Equal(_, _) => return comparison(std::cmp::Ordering::is_eq),
+            ExprKind::NotEqual(_, _) => return comparison(std::cmp::Ordering::is_ne),
+            ExprKind::GreaterThan(_, _) => return comparison(std::cmp::Ordering::is_gt),
+            _ => return comparison(std::cmp::Ordering::is_ge),
+        };
+        Ok(Constant::Integer(convert_integer(value, &ty), ty))
+    }
+
+    /// 条件としての真偽。
+    fn truth(&self, expr: &Expr) -> CompileResult<bool> {
+        match self.evaluate(expr)? {
+            Constant::Integer(value, _) => Ok(value != 0),
+            Constant::Float(value) => Ok(value != 0.0),
+            _ => Err(self.error(self.not_constant_message, expr.span)),
+        }
+    }
+
+    fn error(&self, message: &str, span: Span) -> CompileError {
+        CompileError::new(self.error_kind, message, Some(span))
+    }
+}
+
+/// アドレス定数を要素 `count` 個分ずらす。要素の大きさが分からなければ `None`。
+fn offset_address(address: Constant, count: i64) -> Option<Constant> {
+    let element_size = |ty: &Type| {
+        ty.is_complete()
+            .then(|| i64::try_from(ty.get_size()).ok())
+            .flatten()
+    };
+    match address {
+        Constant::Address(label, offset, Type::Pointer(pointee)) => {
+            let delta = count.checked_mul(element_size(&pointee)?)?;
+            Some(Constant::Address(
+                label,
+                offset.checked_add(delta)?,
+                Type::Pointer(pointee),
+            ))
+        }
+        Constant::StringLiteral(literal, offset) => {
+            let delta = count.checked_mul(element_size(&literal.encoding.element_type())?)?;
+            Some(Constant::StringLiteral(literal, offset.checked_add(delta)?))
+        }
+        _ => None,
+    }
+}
+
+/// 結果は左辺を整数拡張した型になる。符号なしの右シフトは論理シフトになる。
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn fold_shift(kind: &ExprKind, (lhs, lhs_type): (i64, &Type), rhs: i64) -> Constant {
+    let ty = lhs_type.promote();
+    let lhs = convert_integer(lhs, &ty);
+    #[allow(clippy::cast_possible_wrap)]
+    let value = match (kind, ty.is_unsigned()) {
+        (ExprKind::LeftShift(_, _), _) => lhs.wrapping_shl(rhs as u32),
+        (_, true) => (lhs as u64).wrapping_shr(rhs as u32) as i64,
+        (_, false) => lhs.wrapping_shr(rhs as u32),
+    };
+    Constant::Integer(convert_integer(value, &ty), ty)
+}
+
+/// 整数の値を `ty` に変換する。値は `ty` の大きさに切り詰め、符号の有無に応じて拡張する。
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn convert_integer(value: i64, ty: &Type) -> i64 {
+    match ty.unqualified() {
+        Type::BoolTyp => i64::from(value != 0),
+        Type::CharTyp => i64::from(value as i8),
+        Type::UCharTyp => i64::from(value as u8),
+        Type::ShortTyp => i64::from(value as i16),
+        Type::UShortTyp => i64::from(value as u16),
+        Type::IntTyp => i64::from(value as i32),
+        Type::UIntTyp => i64::from(value as u32),
+        _ => value,
+    }
+}
+
+/// 定数を `ty` の値に変換する。定数に定まらない変換なら `None` を返す。
+#[allow(clippy::cast_possible_truncation)]
+pub fn convert(constant: Constant, ty: &Type) -> Option<Constant> {
+    match constant {
+        Constant::Integer(value, _) if *ty == Type::BoolTyp => {
+            Some(Constant::Integer(i64::from(value != 0), Type::BoolTyp))
+        }
+        Constant::Float(value) if *ty == Type::BoolTyp => {
+            Some(Constant::Integer(i64::from(value != 0.0), Type::BoolTyp))
+        }
+        Constant::Integer(value, _) if ty.is_integer() => {
+            Some(Constant::Integer(convert_integer(value, ty), ty.clone()))
+        }
+        // 範囲外の値は実行時の cvttsd2si と違って飽和するが、どちらも未定義の動作になる。
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
+        Constant::Float(value) if ty.is_integer() => {
+            let value = if ty.is_unsigned() {
+                value as u64 as i64
+            } else {
+                value as i64
+            };
+            Some(Constant::Integer(convert_integer(value, ty), ty.clone()))
+        }
+        constant @ (Constant::Integer(_, _) | Constant::Float(_)) if ty.is_floating() => {
+            let value = to_float(&constant)?;
+            Some(Constant::Float(if *ty == Type::FloatTyp {
+                f64::from(value as f32)
+            } else {
+                value
+            }))
+        }
+        Constant::Integer(0, _) if matches!(ty, Type::Pointer(_)) => {
+            Some(Constant::Integer(0, Type::ULongTyp))
+        }
+        Constant::Address(label, offset, _) if matches!(ty, Type::Pointer(_)) => {
+            Some(Constant::Address(label, offset, ty.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// 算術型の定数を `f64` にする。符号なしの整数は符号なしとして変換する。
+#[allow(clippy::cast_precision_loss, clippy::cast_sign_loss)]
+pub fn to_float(constant: &Constant) -> Option<f64> {
+    match constant {
+        Constant::Integer(value, ty) if ty.is_unsigned() => Some(*value as u64 as f64),
+        Constant::Integer(value, _) => Some(*value as f64),
+        Constant::Float(value) => Some(*value),
+        _ => None,
+    }
+}
+
+fn fold_float(kind: &ExprKind, lhs: f64, rhs: f64) -> Option<Constant> {
+    let comparison = |value: bool| Some(Constant::Integer(i64::from(value), Type::IntTyp));
+    match kind {
+        ExprKind::Add(_, _) => Some(Constant::Float(lhs + rhs)),
+        ExprKind::Sub(_, _) => Some(Constant::Float(lhs - rhs)),
+        ExprKind::Mul(_, _) => Some(Constant::Float(lhs * rhs)),
+        ExprKind::Div(_, _) => Some(Constant::Float(lhs / rhs)),
+        ExprKind::LessThan(_, _) => comparison(lhs < rhs),
+        ExprKind::LessEqual(_, _) => comparison(lhs <= rhs),
+        #[allow(clippy::float_cmp)]
+        ExprKind::Equal(_, _) => comparison(lhs == rhs),
+        #[allow(clippy::float_cmp)]
+        ExprKind::NotEqual(_, _) => comparison(lhs != rhs),
+        ExprKind::GreaterThan(_, _) => comparison(lhs > rhs),
+        ExprKind::GreaterEqual(_, _) => comparison(lhs >= rhs),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lex::tokenize, parser::Parser};
+
+    use super::*;
+
+    fn evaluate(input: &str) -> CompileResult<(i64, Type)> {
+        let tokens = tokenize(&input.chars().collect::<Vec<char>>()).unwrap();
+        let expr = Parser::new(&tokens, input).munch_expr().unwrap();
+        ConstantEvaluator::integer(CompileErrorKind::Parse).evaluate_integer(&expr)
+    }
+
+    #[test]
+    fn test_evaluate_with_usual_arithmetic_conversions() {
+        assert_eq!(evaluate("-1 / 2u"), Ok((2_147_483_647, Type::UIntTyp)));
+        assert_eq!(evaluate("-1 / 2u > 0"), Ok((1, Type::IntTyp)));
+        assert_eq!(evaluate("-1 < 0u"), Ok((0, Type::IntTyp)));
+        assert_eq!(evaluate("-1 < 0l"), Ok((1, Type::IntTyp)));
+        assert_eq!(
+            evaluate("(unsigned)-1 / 1000000000"),
+            Ok((4, Type::UIntTyp))
+        );
+        assert_eq!(evaluate("-1ul >> 63"), Ok((1, Type::ULongTyp)));
+        assert_eq!(evaluate("(char)200 + 1"), Ok((-55, Type::IntTyp)));
+        assert_eq!(evaluate("sizeof(int) - 5"), Ok((-1, Type::ULongTyp)));
+        assert_eq!(evaluate("1 ? 1 : 2u"), Ok((1, Type::UIntTyp)));
+        assert_eq!(evaluate("0 && 1 / 0"), Ok((0, Type::IntTyp)));
+        assert!(evaluate("1 / 0").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_address_with_offset() {
+        let symbols = |name: &str| {
+            (name == "a").then(|| ("a".to_string(), Type::Array(Box::new(Type::IntTyp), 4)))
+        };
+        let evaluate = |input: &str| {
+            let tokens = tokenize(&input.chars().collect::<Vec<char>>()).unwrap();
+            let expr = Parser::new(&tokens, input).munch_expr().unwrap();
+            ConstantEvaluator::initializer(&symbols).evaluate(&expr)
+        };
+        let address = |offset| {
+            Constant::Address(
+                "a".to_string(),
+                offset,
+                Type::Pointer(Box::new(Type::IntTyp)),
+            )
+        };
+        assert_eq!(evaluate("&a[1] + 2"), Ok(address(12)));
+        assert_eq!(evaluate("a - 1"), Ok(address(-4)));
+        assert!(evaluate("b + 1").is_err());
+    }
+}
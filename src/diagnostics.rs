@@ -0,0 +1,445 @@
+use std::fmt::Write;
+
+use crate::{
+    error::{CompileError, NoteKind, Severity},
+    lex::{SourcePosition, Span},
+    preprocess::{ExpandedColumn, LineOrigin, Preprocessed},
+};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const CYAN: &str = "\x1b[1;36m";
+const GREEN: &str = "\x1b[1;32m";
+const MAGENTA: &str = "\x1b[1;35m";
+
+/// エラー表示のためのソースファイル。`SourcePosition` を行・列に変換する。
+pub struct SourceFile {
+    name: String,
+    text: Vec<char>,
+    line_starts: Vec<usize>,
+    /// 前処理の出力なら、各行の元のファイルと行番号。
+    origins: Vec<LineOrigin>,
+}
+
+impl SourceFile {
+    pub fn new(name: impl Into<String>, text: &str) -> Self {
+        let text = text.chars().collect::<Vec<_>>();
+        let line_starts = std::iter::once(0)
+            .chain(
+                text.iter()
+                    .enumerate()
+                    .filter(|(_, c)| **c == '\n')
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+        Self {
+            name: name.into(),
+            text,
+            line_starts,
+            origins: vec![],
+        }
+    }
+
+    /// 前処理の出力を表示する。位置は元のファイル名と行番号で示す。
+    pub fn preprocessed(name: impl Into<String>, preprocessed: &Preprocessed) -> Self {
+        Self {
+            origins: preprocessed.lines.clone(),
+            ..Self::new(name, &preprocessed.text)
+        }
+    }
+
+    /// `line` 行目の元のファイル名と行番号。
+    fn origin(&self, line: usize) -> (&str, usize) {
+        self.origins
+            .get(line - 1)
+            .map_or((&self.name, line), |origin| (&origin.file, origin.line))
+    }
+
+    /// 1 始まりの (行, 列) を返す。
+    /// 末尾の改行の直後 (入力の終端) は最終行の行末として扱う。
+    #[must_use]
+    pub fn line_column(&self, position: SourcePosition) -> (usize, usize) {
+        let mut offset = position.0.min(self.text.len());
+        if offset > 0 && offset == self.text.len() && self.text[offset - 1] == '\n' {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        (line + 1, offset - self.line_starts[line] + 1)
+    }
+
+    /// `line` 行目の表示する行と、1 始まりの列 `column` と下線の終わり `end_column` を
+    /// その行での列に直したもの。マクロを展開した行は展開前の行を表示する。
+    /// `(a)` の `a` のように、置換リストの字句に囲まれた実引数だけの範囲は、その実引数を指す。
+    fn display_line(
+        &self,
+        line: usize,
+        column: usize,
+        end_column: Option<usize>,
+    ) -> (String, usize, Option<usize>) {
+        let Some(expansion) = self
+            .origins
+            .get(line - 1)
+            .and_then(|origin| origin.expansion.as_ref())
+        else {
+            return (self.line_text(line), column, end_column);
+        };
+        let length = expansion.text.chars().count();
+        let (column, end_column) = end_column
+            .and_then(|end_column| {
+                let columns = expansion.columns.get(column - 1..end_column - 1)?;
+                if !columns.first()?.is_from_macro || !columns.last()?.is_from_macro {
+                    return None;
+                }
+                // 字句の間の空白は幅を持たない。
+                let is_original = |c: &ExpandedColumn| !c.is_from_macro && c.start < c.end;
+                let first = columns.iter().position(is_original)?;
+                let last = columns.iter().rposition(is_original)?;
+                columns[first..=last]
+                    .iter()
+                    .all(|c| !c.is_from_macro)
+                    .then_some((column + first, Some(column + last + 1)))
+            })
+            .unwrap_or((column, end_column));
+        let start = expansion
+            .columns
+            .get(column - 1)
+            .map_or(length, |range| range.start);
+        let end = end_column.map(|end_column| {
+            if end_column > column {
+                expansion
+                    .columns
+                    .get(end_column - 2)
+                    .map_or(length, |range| range.end)
+            } else {
+                start
+            }
+        });
+        (expansion.text.clone(), start + 1, end.map(|end| end + 1))
+    }
+
+    fn line_text(&self, line: usize) -> String {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.text.len(), |next| next - 1);
+        self.text[start..end].iter().collect()
+    }
+}
+
+/// `errors` を `file:line:column: error: message` の形式で、該当行と下線付きで描画する。
+/// 警告も同じ形式で `warning:` として描画する。
+#[must_use]
+pub fn render(errors: &[CompileError], source: &SourceFile, color: bool) -> String {
+    let painter = Painter { color };
+    let mut out = String::new();
+    for error in errors {
+        let label = match error.severity {
+            Severity::Error => (RED, "error"),
+            Severity::Warning => (MAGENTA, "warning"),
+        };
+        render_message(
+            &mut out,
+            source,
+            &painter,
+            label,
+            &error.message,
+            error.span,
+        );
+        for note in &error.notes {
+            let label = match note.kind {
+                NoteKind::Note => (CYAN, "note"),
+                NoteKind::Help => (GREEN, "help"),
+            };
+            render_message(&mut out, source, &painter, label, &note.message, note.span);
+        }
+    }
+
+    let warnings = errors
+        .iter()
+        .filter(|error| error.severity == Severity::Warning)
+        .count();
+    let counts = [(warnings, "warning"), (errors.len() - warnings, "error")]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| match count {
+            1 => format!("1 {label}"),
+            n => format!("{n} {label}s"),
+        })
+        .collect::<Vec<_>>();
+    if !counts.is_empty() {
+        writeln!(out, "{} generated.", counts.join(" and ")).unwrap();
+    }
+    out
+}
+
+fn render_message(
+    out: &mut String,
+    source: &SourceFile,
+    painter: &Painter,
+    (label_color, label): (&str, &str),
+    message: &str,
+    span: Option<Span>,
+) {
+    let Some(span) = span else {
+        writeln!(
+            out,
+            "{}: {} {}",
+            painter.paint(BOLD, &source.name),
+            painter.paint(label_color, &format!("{label}:")),
+            painter.paint(BOLD, message)
+        )
+        .unwrap();
+        return;
+    };
+
+    let (line, column) = source.line_column(span.start);
+    let (end_line, end_column) = source.line_column(span.end);
+    let (line_text, column, end_column) =
+        source.display_line(line, column, (end_line == line).then_some(end_column));
+    let (file, line_number) = source.origin(line);
+    writeln!(
+        out,
+        "{} {} {}",
+        painter.paint(BOLD, &format!("{file}:{line_number}:{column}:")),
+        painter.paint(label_color, &format!("{label}:")),
+        painter.paint(BOLD, message)
+    )
+    .unwrap();
+
+    let gutter = line_number.to_string().len();
+    writeln!(out, " {line_number} | {line_text}").unwrap();
+
+    // 複数行にまたがる範囲は開始行の行末まで下線を引く。
+    let line_length = line_text.chars().count();
+    let underline_end = end_column.unwrap_or(line_length + 1).max(column + 1);
+
+    let padding = line_text
+        .chars()
+        .take(column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    let underline = format!("^{}", "~".repeat(underline_end - column - 1));
+    writeln!(
+        out,
+        " {:gutter$} | {padding}{}",
+        "",
+        painter.paint(label_color, &underline)
+    )
+    .unwrap();
+}
+
+struct Painter {
+    color: bool,
+}
+
+impl Painter {
+    fn paint(&self, color: &str, text: &str) -> String {
+        if self.color {
+            format!("{color}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::CompileErrorKind,
+        preprocess::{preprocess, PreprocessOptions},
+    };
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(SourcePosition(start), SourcePosition(end))
+    }
+
+    #[test]
+    fn test_line_column() {
+        let source = SourceFile::new("a.c", "ab\ncd\n\nef\n");
+        assert_eq!(source.line_column(SourcePosition(0)), (1, 1));
+        assert_eq!(source.line_column(SourcePosition(2)), (1, 3));
+        assert_eq!(source.line_column(SourcePosition(3)), (2, 1));
+        assert_eq!(source.line_column(SourcePosition(6)), (3, 1));
+        assert_eq!(source.line_column(SourcePosition(8)), (4, 2));
+        assert_eq!(source.line_column(SourcePosition(10)), (4, 3));
+    }
+
+    #[test]
+    fn test_render_underlines_whole_span() {
+        let source = SourceFile::new("a.c", "int main() {\n  return foo + 1;\n}\n");
+        let errors = vec![CompileError::new(
+            CompileErrorKind::Type,
+            "undefined variable: foo",
+            Some(span(22, 25)),
+        )];
+        assert_eq!(
+            render(&errors, &source, false),
+            "a.c:2:10: error: undefined variable: foo\n 2 |   return foo + 1;\n   |          ^~~\n1 error generated.\n"
+        );
+    }
+
+    #[test]
+    fn test_render_multiple_errors_with_notes() {
+        let source = SourceFile::new("a.c", "int a;\nint a;");
+        let errors = vec![
+            CompileError::new(
+                CompileErrorKind::Type,
+                "variable a is already defined",
+                Some(span(7, 13)),
+            )
+            .with_note("previous definition is here", span(0, 6))
+            .with_help("rename one of the variables"),
+            CompileError::new(CompileErrorKind::Io, "broken pipe", None),
+        ];
+        assert_eq!(
+            render(&errors, &source, false),
+            "a.c:2:1: error: variable a is already defined\n 2 | int a;\n   | ^~~~~~\na.c:1:1: note: previous definition is here\n 1 | int a;\n   | ^~~~~~\na.c: help: rename one of the variables\na.c: error: broken pipe\n2 errors generated.\n"
+        );
+    }
+
+    #[test]
+    fn test_render_multiline_span_and_end_of_input() {
+        let source = SourceFile::new("a.c", "f(1,\n  2)");
+        let errors = vec![
+            CompileError::new(CompileErrorKind::Type, "bad call", Some(span(0, 9))),
+            CompileError::new(CompileErrorKind::Parse, "expected ';'", Some(span(9, 9))),
+        ];
+        assert_eq!(
+            render(&errors, &source, false),
+            "a.c:1:1: error: bad call\n 1 | f(1,\n   | ^~~~\na.c:2:5: error: expected ';'\n 2 |   2)\n   |     ^\n2 errors generated.\n"
+        );
+    }
+
+    #[test]
+    fn test_render_warnings() {
+        let source = SourceFile::new("a.c", "int f() {}");
+        let warning = CompileError::warning(
+            CompileErrorKind::Type,
+            "control reaches end of non-void function f",
+            Some(span(9, 10)),
+        );
+        assert_eq!(
+            render(std::slice::from_ref(&warning), &source, false),
+            "a.c:1:10: warning: control reaches end of non-void function f\n 1 | int f() {}\n   |          ^\n1 warning generated.\n"
+        );
+
+        let errors = vec![
+            warning.clone(),
+            warning,
+            CompileError::new(CompileErrorKind::Type, "bad", None),
+        ];
+        assert!(render(&errors, &source, false).ends_with("2 warnings and 1 error generated.\n"));
+    }
+
+    #[test]
+    fn test_render_with_line_origins() {
+        let preprocessed = Preprocessed {
+            text: "#include \"b.h\"\nint x = y;\nint z;\n".to_string(),
+            lines: vec![
+                LineOrigin {
+                    file: "a.c".to_string(),
+                    line: 1,
+                    expansion: None,
+                },
+                LineOrigin {
+                    file: "b.h".to_string(),
+                    line: 12,
+                    expansion: None,
+                },
+                LineOrigin {
+                    file: "a.c".to_string(),
+                    line: 2,
+                    expansion: None,
+                },
+            ],
+        };
+        let source = SourceFile::preprocessed("a.c", &preprocessed);
+        let errors = vec![CompileError::new(
+            CompileErrorKind::Type,
+            "undefined variable: y",
+            Some(span(23, 24)),
+        )];
+        assert_eq!(
+            render(&errors, &source, false),
+            "b.h:12:9: error: undefined variable: y\n 12 | int x = y;\n    |         ^\n1 error generated.\n"
+        );
+    }
+
+    #[test]
+    fn test_render_expanded_line_shows_original() {
+        let (preprocessed, _) = preprocess(
+            "#define N undefined_name\nint x = N + y;\n",
+            "a.c",
+            &PreprocessOptions::default(),
+        );
+        assert_eq!(
+            preprocessed.text,
+            "#define N undefined_name\nint x = undefined_name + y;\n"
+        );
+        let source = SourceFile::preprocessed("a.c", &preprocessed);
+        let errors = vec![
+            CompileError::new(
+                CompileErrorKind::Type,
+                "undefined variable: undefined_name",
+                Some(span(33, 47)),
+            ),
+            CompileError::new(
+                CompileErrorKind::Type,
+                "undefined variable: y",
+                Some(span(50, 51)),
+            ),
+        ];
+        assert_eq!(
+            render(&errors, &source, false),
+            "a.c:2:9: error: undefined variable: undefined_name\n 2 | int x = N + y;\n   |         ^\na.c:2:13: error: undefined variable: y\n 2 | int x = N + y;\n   |             ^\n2 errors generated.\n"
+        );
+    }
+
+    #[test]
+    fn test_render_macro_argument_points_to_argument() {
+        let (preprocessed, _) = preprocess(
+            "#define ADD(a, b) ((a) + (b))\nint x = ADD(1, y) + 1;\n",
+            "a.c",
+            &PreprocessOptions::default(),
+        );
+        let source = SourceFile::preprocessed("a.c", &preprocessed);
+        let output = preprocessed.text.chars().collect::<Vec<_>>();
+        let start = output
+            .windows(3)
+            .position(|w| w == ['(', 'y', ')'])
+            .unwrap();
+        let plus = output.iter().rposition(|c| *c == '+').unwrap();
+        let errors = vec![
+            CompileError::new(
+                CompileErrorKind::Type,
+                "undefined variable: y",
+                Some(span(start, start + 3)),
+            ),
+            CompileError::new(
+                CompileErrorKind::Type,
+                "invalid operands",
+                Some(span(start - 7, plus + 3)),
+            ),
+        ];
+        assert_eq!(
+            render(&errors, &source, false),
+            "a.c:2:16: error: undefined variable: y\n 2 | int x = ADD(1, y) + 1;\n   |                ^\na.c:2:9: error: invalid operands\n 2 | int x = ADD(1, y) + 1;\n   |         ^~~~~~~~~~~~~\n2 errors generated.\n"
+        );
+    }
+
+    #[test]
+    fn test_render_with_color() {
+        let source = SourceFile::new("a.c", "x");
+        let errors = vec![CompileError::new(
+            CompileErrorKind::Type,
+            "undefined variable: x",
+            Some(span(0, 1)),
+        )];
+        let rendered = render(&errors, &source, true);
+        assert!(rendered.contains(&format!("{RED}error:{RESET}")));
+        assert!(rendered.contains(&format!("{RED}^{RESET}")));
+    }
+}
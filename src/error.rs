@@ -0,0 +1,125 @@
+use std::fmt;
+
+use crate::lex::Span;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompileErrorKind {
+    Preprocess,
+    Lex,
+    Parse,
+    Type,
+    Codegen,
+    Io,
+}
+
+impl fmt::Display for CompileErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Preprocess => "preprocess",
+            Self::Lex => "lex",
+            Self::Parse => "parse",
+            Self::Type => "type",
+            Self::Codegen => "codegen",
+            Self::Io => "io",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// 警告はコンパイルを止めない。
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NoteKind {
+    Note,
+    Help,
+}
+
+/// エラーに添える補足情報 ("variable declared here" など)。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Note {
+    pub kind: NoteKind,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+/// コンパイルの各段階で発生したエラーか警告。
+/// 位置情報を持たないエラー (出力先への書き込み失敗など) もあるので `span` は `Option`。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub kind: CompileErrorKind,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<Note>,
+}
+
+impl CompileError {
+    pub fn new(kind: CompileErrorKind, message: impl Into<String>, span: Option<Span>) -> Self {
+        Self {
+            kind,
+            severity: Severity::Error,
+            message: message.into(),
+            span,
+            notes: vec![],
+        }
+    }
+
+    pub fn warning(kind: CompileErrorKind, message: impl Into<String>, span: Option<Span>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::new(kind, message, span)
+        }
+    }
+
+    #[must_use]
+    pub fn with_note(mut self, message: impl Into<String>, span: Span) -> Self {
+        self.notes.push(Note {
+            kind: NoteKind::Note,
+            message: message.into(),
+            span: Some(span),
+        });
+        self
+    }
+
+    #[must_use]
+    pub fn with_help(mut self, message: impl Into<String>) -> Self {
+        self.notes.push(Note {
+            kind: NoteKind::Help,
+            message: message.into(),
+            span: None,
+        });
+        self
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.span {
+            Some(span) => write!(
+                f,
+                "{} {severity} at {}: {}",
+                self.kind, span.start.0, self.message
+            ),
+            None => write!(f, "{} {severity}: {}", self.kind, self.message),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl From<CompileError> for Vec<CompileError> {
+    fn from(error: CompileError) -> Self {
+        vec![error]
+    }
+}
+
+pub type CompileResult<T> = Result<T, CompileError>;
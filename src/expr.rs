@@ -1,10 +1,42 @@
+use crate::{
+    lex::Span,
+    token::{FloatLiteral, IntegerLiteral, StringLiteral},
+    types::Type,
+};
+
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub const fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ExprKind {
     Add(Box<Expr>, Box<Expr>),
     Sub(Box<Expr>, Box<Expr>),
     Mul(Box<Expr>, Box<Expr>),
     Div(Box<Expr>, Box<Expr>),
+    Mod(Box<Expr>, Box<Expr>),
+    BitAnd(Box<Expr>, Box<Expr>),
+    BitOr(Box<Expr>, Box<Expr>),
+    BitXor(Box<Expr>, Box<Expr>),
+    LeftShift(Box<Expr>, Box<Expr>),
+    RightShift(Box<Expr>, Box<Expr>),
+    LogicalAnd(Box<Expr>, Box<Expr>),
+    LogicalOr(Box<Expr>, Box<Expr>),
+    LogicalNot(Box<Expr>),
+    BitNot(Box<Expr>),
     Num(i32),
+    /// 接尾辞か値の大きさで `int` 以外の型になる整数定数。`int` の定数は `Num` にする。
+    IntNum(IntegerLiteral),
+    FloatNum(FloatLiteral),
+    StringLiteral(StringLiteral),
     LessThan(Box<Expr>, Box<Expr>),
     LessEqual(Box<Expr>, Box<Expr>),
     Equal(Box<Expr>, Box<Expr>),
@@ -12,85 +44,171 @@ pub enum Expr {
     GreaterThan(Box<Expr>, Box<Expr>),
     GreaterEqual(Box<Expr>, Box<Expr>),
     Assign(Box<Expr>, Box<Expr>),
+    /// `a += b` などは演算 `a + b` を持ち、左辺 `a` は一度だけ評価する。
+    CompoundAssign(Box<Expr>),
+    /// `a++` と `a--`。`CompoundAssign` と同じく演算を持ち、値は更新前の左辺になる。
+    PostfixAssign(Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
     Variable(String),
-    FunctionCall(String, Vec<Expr>),
+    /// 呼び出す式と引数。
+    FunctionCall(Box<Expr>, Vec<Expr>),
     Address(Box<Expr>),
     Dereference(Box<Expr>),
+    Member(Box<Expr>, String),
+    /// オペランドは評価せず、型の大きさだけを使う。
     Sizeof(Box<Expr>),
+    SizeofType(Type),
+    Alignof(Type),
+    /// `(型名) 式` のキャスト。
+    Cast(Type, Box<Expr>),
+    /// `va_start` で初期化する `va_list`。最後の名前付き仮引数は使わない。
+    VaStart(Box<Expr>),
+    /// `va_arg` の `va_list` と、取り出す引数の型。
+    VaArg(Box<Expr>, Type),
 }
 
-use crate::types::Type;
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TypedExpr {
+    pub kind: TypedExprKind,
+    pub ty: Type,
+    pub span: Span,
+}
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum TypedExpr {
-    Add(Type, Box<TypedExpr>, Box<TypedExpr>),
-    Sub(Type, Box<TypedExpr>, Box<TypedExpr>),
-    Mul(Type, Box<TypedExpr>, Box<TypedExpr>),
-    Div(Type, Box<TypedExpr>, Box<TypedExpr>),
-    IntNum(i32),
+pub enum TypedExprKind {
+    Add(Box<TypedExpr>, Box<TypedExpr>),
+    Sub(Box<TypedExpr>, Box<TypedExpr>),
+    Mul(Box<TypedExpr>, Box<TypedExpr>),
+    Div(Box<TypedExpr>, Box<TypedExpr>),
+    Mod(Box<TypedExpr>, Box<TypedExpr>),
+    BitAnd(Box<TypedExpr>, Box<TypedExpr>),
+    BitOr(Box<TypedExpr>, Box<TypedExpr>),
+    BitXor(Box<TypedExpr>, Box<TypedExpr>),
+    LeftShift(Box<TypedExpr>, Box<TypedExpr>),
+    /// 左辺が符号付きなら算術シフト、符号なしなら論理シフトになる。
+    RightShift(Box<TypedExpr>, Box<TypedExpr>),
+    /// 算術型のオペランドは `_Bool` に変換しておく。
+    LogicalAnd(Box<TypedExpr>, Box<TypedExpr>),
+    LogicalOr(Box<TypedExpr>, Box<TypedExpr>),
+    LogicalNot(Box<TypedExpr>),
+    BitNot(Box<TypedExpr>),
+    /// 値のビット列を式の型の大きさに切り詰めて扱う。
+    IntNum(i64),
+    /// 値は式の型 (`float` か `double`) に丸めて扱う。
+    FloatNum(FloatLiteral),
+    StringLiteral(StringLiteral),
     LessThan(Box<TypedExpr>, Box<TypedExpr>),
     LessEqual(Box<TypedExpr>, Box<TypedExpr>),
     Equal(Box<TypedExpr>, Box<TypedExpr>),
     NotEqual(Box<TypedExpr>, Box<TypedExpr>),
     GreaterThan(Box<TypedExpr>, Box<TypedExpr>),
     GreaterEqual(Box<TypedExpr>, Box<TypedExpr>),
-    Assign(Type, Box<TypedExpr>, Box<TypedExpr>),
-    Variable(Type, String),
-    FunctionCall(Type, String, Vec<TypedExpr>),
-    Address(Type, Box<TypedExpr>),
-    Dereference(Type, Box<TypedExpr>),
-    Sizeof(Box<TypedExpr>),
+    Assign(Box<TypedExpr>, Box<TypedExpr>),
+    /// 左辺値と、`CompoundAssignLhs` で左辺の値を参照する代入値。
+    CompoundAssign(Box<TypedExpr>, Box<TypedExpr>),
+    PostfixAssign(Box<TypedExpr>, Box<TypedExpr>),
+    /// 評価中の複合代入の左辺の値。
+    CompoundAssignLhs,
+    Conditional(Box<TypedExpr>, Box<TypedExpr>, Box<TypedExpr>),
+    Comma(Box<TypedExpr>, Box<TypedExpr>),
+    Variable(String),
+    GlobalVariable(String),
+    /// 関数の名前。値としては関数のアドレスになる。
+    Function(String),
+    /// 呼び出す関数か関数へのポインタと、引数。
+    FunctionCall(Box<TypedExpr>, Vec<TypedExpr>),
+    Address(Box<TypedExpr>),
+    Dereference(Box<TypedExpr>),
+    /// 構造体や配列と、その中の部分オブジェクトのオフセット。
+    Member(Box<TypedExpr>, usize),
+    /// 値を式の型 `ty` に変換する。
+    Cast(Box<TypedExpr>),
+    /// `va_list` を指すポインタと、それを可変長引数の先頭を指すよう初期化する `va_start`。
+    VaStart(Box<TypedExpr>),
+    /// `va_list` を指すポインタから、式の型の次の可変長引数を取り出す。
+    VaArg(Box<TypedExpr>),
 }
 
 impl TypedExpr {
+    pub const fn new(kind: TypedExprKind, ty: Type, span: Span) -> Self {
+        Self { kind, ty, span }
+    }
+
+    /// 値としての型。修飾子は `ty` にだけ残す。
     pub fn get_type(&self) -> Type {
-        match self {
-            Self::Add(t, _, _)
-            | Self::Sub(t, _, _)
-            | Self::Mul(t, _, _)
-            | Self::Div(t, _, _)
-            | Self::Assign(t, _, _)
-            | Self::Variable(t, _)
-            | Self::FunctionCall(t, _, _)
-            | Self::Address(t, _)
-            | Self::Dereference(t, _) => t.clone(),
-            Self::IntNum(_)
-            | Self::LessThan(_, _)
-            | Self::LessEqual(_, _)
-            | Self::Equal(_, _)
-            | Self::NotEqual(_, _)
-            | Self::GreaterThan(_, _)
-            | Self::GreaterEqual(_, _)
-            | Self::Sizeof(_) => Type::IntTyp,
-        }
+        self.ty.unqualified().clone()
     }
 
+    /// 配列は先頭要素のアドレスとして、関数はそのアドレスとして扱う。
     pub fn decay_if_array(&self) -> Self {
-        if let Type::Array(ty, _) = self.get_type() {
-            let ty_pointer = Type::Pointer(ty);
-            match self {
-                Self::Add(_, lhs, rhs) => Self::Add(ty_pointer, lhs.clone(), rhs.clone()),
-                Self::Sub(_, lhs, rhs) => Self::Sub(ty_pointer, lhs.clone(), rhs.clone()),
-                Self::Mul(_, lhs, rhs) => Self::Mul(ty_pointer, lhs.clone(), rhs.clone()),
-                Self::Div(_, lhs, rhs) => Self::Div(ty_pointer, lhs.clone(), rhs.clone()),
-                Self::Assign(_, lhs, rhs) => Self::Assign(ty_pointer, lhs.clone(), rhs.clone()),
-                Self::Variable(_, name) => Self::Variable(ty_pointer, name.clone()),
-                Self::FunctionCall(_, name, args) => {
-                    Self::FunctionCall(ty_pointer, name.clone(), args.clone())
-                }
-                Self::Address(_, expr) => Self::Address(ty_pointer, expr.clone()),
-                Self::Dereference(_, expr) => Self::Dereference(ty_pointer, expr.clone()),
-                Self::IntNum(_)
-                | Self::LessThan(_, _)
-                | Self::LessEqual(_, _)
-                | Self::Equal(_, _)
-                | Self::NotEqual(_, _)
-                | Self::GreaterThan(_, _)
-                | Self::GreaterEqual(_, _)
-                | Self::Sizeof(_) => self.clone(),
+        let pointer_type = match self.ty.unqualified() {
+            Type::Array(ty, _) | Type::IncompleteArray(ty) => Type::Pointer(ty.clone()),
+            ty @ Type::Function(_, _, _) => Type::Pointer(Box::new(ty.clone())),
+            _ => return self.clone(),
+        };
+        Self::new(
+            TypedExprKind::Address(Box::new(self.clone())),
+            pointer_type,
+            self.span,
+        )
+    }
+
+    /// 算術型同士で型が異なれば `ty` への変換を挟む。
+    #[must_use]
+    pub fn convert_to(self, ty: &Type) -> Self {
+        let ty = ty.unqualified();
+        if self.get_type() == *ty || !self.ty.is_arithmetic() || !ty.is_arithmetic() {
+            return self;
+        }
+        let span = self.span;
+        Self::new(TypedExprKind::Cast(Box::new(self)), ty.clone(), span)
+    }
+
+    /// 直下の部分式。関数呼び出しでは呼び出し先、引数の順に並べる。
+    pub fn children(&self) -> Vec<&Self> {
+        match &self.kind {
+            TypedExprKind::Add(lhs, rhs)
+            | TypedExprKind::Sub(lhs, rhs)
+            | TypedExprKind::Mul(lhs, rhs)
+            | TypedExprKind::Div(lhs, rhs)
+            | TypedExprKind::Mod(lhs, rhs)
+            | TypedExprKind::BitAnd(lhs, rhs)
+            | TypedExprKind::BitOr(lhs, rhs)
+            | TypedExprKind::BitXor(lhs, rhs)
+            | TypedExprKind::LeftShift(lhs, rhs)
+            | TypedExprKind::RightShift(lhs, rhs)
+            | TypedExprKind::LogicalAnd(lhs, rhs)
+            | TypedExprKind::LogicalOr(lhs, rhs)
+            | TypedExprKind::LessThan(lhs, rhs)
+            | TypedExprKind::LessEqual(lhs, rhs)
+            | TypedExprKind::Equal(lhs, rhs)
+            | TypedExprKind::NotEqual(lhs, rhs)
+            | TypedExprKind::GreaterThan(lhs, rhs)
+            | TypedExprKind::GreaterEqual(lhs, rhs)
+            | TypedExprKind::Assign(lhs, rhs)
+            | TypedExprKind::CompoundAssign(lhs, rhs)
+            | TypedExprKind::PostfixAssign(lhs, rhs)
+            | TypedExprKind::Comma(lhs, rhs) => vec![lhs, rhs],
+            TypedExprKind::Conditional(cond, then, els) => vec![cond, then, els],
+            TypedExprKind::FunctionCall(callee, args) => {
+                std::iter::once(callee.as_ref()).chain(args).collect()
             }
-        } else {
-            self.clone()
+            TypedExprKind::LogicalNot(operand)
+            | TypedExprKind::BitNot(operand)
+            | TypedExprKind::Address(operand)
+            | TypedExprKind::Dereference(operand)
+            | TypedExprKind::Member(operand, _)
+            | TypedExprKind::Cast(operand)
+            | TypedExprKind::VaStart(operand)
+            | TypedExprKind::VaArg(operand) => vec![operand],
+            TypedExprKind::IntNum(_)
+            | TypedExprKind::FloatNum(_)
+            | TypedExprKind::StringLiteral(_)
+            | TypedExprKind::CompoundAssignLhs
+            | TypedExprKind::Variable(_)
+            | TypedExprKind::GlobalVariable(_)
+            | TypedExprKind::Function(_) => vec![],
         }
     }
 }
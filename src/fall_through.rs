@@ -0,0 +1,160 @@
+use crate::{
+    expr::{Expr, ExprKind},
+    statement::{Statement, StatementKind},
+};
+
+/// 文の並びの末尾まで実行が到達しうるなら真。
+/// `goto` の飛び先は追わず、ラベルの付いた文には常に到達しうるとみなす。
+/// 条件が 0 でない定数のループは、`break` がなければ抜けないものとして扱う。
+pub fn can_fall_through(statements: &[Statement]) -> bool {
+    let mut reachable = true;
+    for statement in statements {
+        if matches!(
+            statement.kind,
+            StatementKind::Case(_, _) | StatementKind::Default(_) | StatementKind::Labeled(_, _)
+        ) {
+            reachable = true;
+        }
+        if reachable {
+            reachable = can_complete(statement);
+        }
+    }
+    reachable
+}
+
+/// 文を実行し始めたとき、その次の文に進みうるなら真。
+fn can_complete(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Return(_)
+        | StatementKind::Goto(_)
+        | StatementKind::Break
+        | StatementKind::Continue => false,
+        StatementKind::Expr(_)
+        | StatementKind::VariableDeclaration(_)
+        | StatementKind::Empty
+        | StatementKind::If(_, _) => true,
+        StatementKind::IfElse(_, then, els) => can_complete(then) || can_complete(els),
+        StatementKind::While(cond, body) | StatementKind::For(_, Some(cond), _, body) => {
+            !is_always_true(cond) || contains_break(body)
+        }
+        StatementKind::For(_, None, _, body) => contains_break(body),
+        StatementKind::DoWhile(body, cond) => {
+            contains_break(body)
+                || (!is_always_true(cond) && (can_complete(body) || contains_continue(body)))
+        }
+        StatementKind::Switch(_, body) => {
+            !contains_default(body) || can_complete(body) || contains_break(body)
+        }
+        StatementKind::Case(_, body)
+        | StatementKind::Default(body)
+        | StatementKind::Labeled(_, body) => can_complete(body),
+        StatementKind::Block(statements) => can_fall_through(statements),
+    }
+}
+
+const fn is_always_true(cond: &Expr) -> bool {
+    match cond.kind {
+        ExprKind::Num(value) => value != 0,
+        ExprKind::IntNum(literal) => literal.value != 0,
+        _ => false,
+    }
+}
+
+/// `statement` 自身から抜ける `break` があれば真。内側のループや switch の `break` は数えない。
+fn contains_break(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Break => true,
+        StatementKind::While(_, _)
+        | StatementKind::For(_, _, _, _)
+        | StatementKind::DoWhile(_, _)
+        | StatementKind::Switch(_, _) => false,
+        _ => children(statement).any(contains_break),
+    }
+}
+
+/// 内側の switch の中の `continue` も外側のループに作用する。
+fn contains_continue(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Continue => true,
+        StatementKind::While(_, _)
+        | StatementKind::For(_, _, _, _)
+        | StatementKind::DoWhile(_, _) => false,
+        _ => children(statement).any(contains_continue),
+    }
+}
+
+fn contains_default(statement: &Statement) -> bool {
+    match &statement.kind {
+        StatementKind::Default(_) => true,
+        StatementKind::Switch(_, _) => false,
+        _ => children(statement).any(contains_default),
+    }
+}
+
+fn children(statement: &Statement) -> Box<dyn Iterator<Item = &Statement> + '_> {
+    match &statement.kind {
+        StatementKind::If(_, body)
+        | StatementKind::While(_, body)
+        | StatementKind::For(_, _, _, body)
+        | StatementKind::DoWhile(body, _)
+        | StatementKind::Switch(_, body)
+        | StatementKind::Case(_, body)
+        | StatementKind::Default(body)
+        | StatementKind::Labeled(_, body) => Box::new(std::iter::once(body.as_ref())),
+        StatementKind::IfElse(_, then, els) => Box::new([then.as_ref(), els.as_ref()].into_iter()),
+        StatementKind::Block(statements) => Box::new(statements.iter()),
+        StatementKind::Expr(_)
+        | StatementKind::Return(_)
+        | StatementKind::Break
+        | StatementKind::Continue
+        | StatementKind::Goto(_)
+        | StatementKind::VariableDeclaration(_)
+        | StatementKind::Empty => Box::new(std::iter::empty()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lex::tokenize, parser::Parser};
+
+    fn falls_through(input: &str) -> bool {
+        let input = format!("{{ {input} }}");
+        let tokens = tokenize(&input.chars().collect::<Vec<char>>()).unwrap();
+        let mut parser = Parser::new(&tokens, &input);
+        let StatementKind::Block(statements) = parser.munch_statement().unwrap().kind else {
+            panic!("expected a block");
+        };
+        can_fall_through(&statements)
+    }
+
+    #[test]
+    fn test_can_fall_through_returns() {
+        assert!(falls_through(""));
+        assert!(falls_through("1;"));
+        assert!(!falls_through("return 1;"));
+        assert!(!falls_through("if (1) return 1; else { return 2; }"));
+        assert!(falls_through("if (1) return 1;"));
+        assert!(falls_through("return 1; l: 2;"));
+        assert!(!falls_through("goto l; l: return 0;"));
+    }
+
+    #[test]
+    fn test_can_fall_through_loops_and_switches() {
+        assert!(!falls_through("while (1) {}"));
+        assert!(falls_through("while (1) { break; }"));
+        assert!(!falls_through("while (1) { while (1) break; }"));
+        assert!(falls_through("while (a) return 1;"));
+        assert!(!falls_through("do { return 1; } while (a);"));
+        assert!(falls_through(
+            "do { if (a) continue; return 1; } while (a);"
+        ));
+        assert!(!falls_through(
+            "switch (a) { case 1: return 1; default: return 2; }"
+        ));
+        assert!(falls_through("switch (a) { case 1: return 1; }"));
+        assert!(falls_through(
+            "switch (a) { case 1: break; default: return 2; }"
+        ));
+    }
+}
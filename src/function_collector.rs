@@ -1,20 +1,159 @@
-use std::collections::HashMap;
+use std::collections::{hash_map, HashMap};
 
-use crate::{top_level::TopLevel, types::FunctionType};
+use crate::{
+    error::{CompileError, CompileErrorKind},
+    top_level::{TopLevel, TopLevelKind},
+    types::FunctionType,
+};
 
-pub fn collect_functions(program: &Vec<TopLevel>) -> HashMap<String, FunctionType> {
+/// 関数の型を集める。同じ関数を 2 度定義したり、宣言と型が食い違ったりすればエラーにする。
+/// 宣言は、同じ型の定義や宣言と何度でも並べられる。
+pub fn collect_functions(
+    program: &[TopLevel],
+) -> Result<HashMap<String, FunctionType>, Vec<CompileError>> {
     let mut functions = HashMap::new();
+    // 最初の宣言 (または定義) と定義の位置。
+    let mut declaration_spans = HashMap::new();
+    let mut definition_spans = HashMap::new();
+    let mut errors = vec![];
     for top_level in program {
-        match top_level {
-            TopLevel::FunctionDefinition(name, args, return_type, _)
-            | TopLevel::ExternalFunctionDeclaration(name, args, return_type) => {
-                let mut arg_types = Vec::new();
-                for (_, arg_type) in args {
-                    arg_types.push(arg_type.clone());
+        let (TopLevelKind::FunctionDefinition(name, args, is_variadic, return_type, _, _)
+        | TopLevelKind::ExternalFunctionDeclaration(name, args, is_variadic, return_type)) =
+            &top_level.kind
+        else {
+            continue;
+        };
+        if let TopLevelKind::FunctionDefinition(_, _, _, _, _, _) = top_level.kind {
+            match definition_spans.entry(name.clone()) {
+                hash_map::Entry::Vacant(e) => {
+                    e.insert(top_level.span);
+                }
+                hash_map::Entry::Occupied(e) => {
+                    errors.push(
+                        CompileError::new(
+                            CompileErrorKind::Type,
+                            format!("{name} is already defined"),
+                            Some(top_level.span),
+                        )
+                        .with_note("previous definition is here", *e.get()),
+                    );
+                    continue;
                 }
-                functions.insert(name.clone(), (arg_types, Box::new(return_type.clone())));
             }
         }
+        // 仮引数と戻り値の型の修飾子は関数の型には影響しない。
+        let function_type: FunctionType = (
+            args.iter()
+                .map(|(_, arg_type)| arg_type.unqualified().clone())
+                .collect(),
+            Box::new(return_type.unqualified().clone()),
+            *is_variadic,
+        );
+        match functions.entry(name.clone()) {
+            hash_map::Entry::Vacant(e) => {
+                e.insert(function_type);
+                declaration_spans.insert(name.clone(), top_level.span);
+            }
+            hash_map::Entry::Occupied(e) if *e.get() != function_type => {
+                errors.push(
+                    CompileError::new(
+                        CompileErrorKind::Type,
+                        format!("conflicting types for {name}"),
+                        Some(top_level.span),
+                    )
+                    .with_note("previous declaration is here", declaration_spans[name]),
+                );
+            }
+            hash_map::Entry::Occupied(_) => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(functions)
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        lex::{SourcePosition, Span},
+        top_level::Linkage,
+        types::Type,
+    };
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(SourcePosition(start), SourcePosition(end))
+    }
+
+    fn declaration(return_type: Type, start: usize, end: usize) -> TopLevel {
+        TopLevel::new(
+            TopLevelKind::ExternalFunctionDeclaration(
+                "f".to_string(),
+                vec![(String::new(), Type::IntTyp)],
+                false,
+                return_type,
+            ),
+            span(start, end),
+        )
+    }
+
+    fn definition(start: usize, end: usize) -> TopLevel {
+        TopLevel::new(
+            TopLevelKind::FunctionDefinition(
+                "f".to_string(),
+                vec![("x".to_string(), Type::IntTyp)],
+                false,
+                Type::IntTyp,
+                vec![],
+                Linkage::External,
+            ),
+            span(start, end),
+        )
+    }
+
+    #[test]
+    fn test_collect_functions_merges_declarations() {
+        let program = vec![
+            declaration(Type::IntTyp, 0, 11),
+            definition(12, 36),
+            declaration(Type::IntTyp, 37, 48),
+        ];
+        let functions = collect_functions(&program).unwrap();
+        assert_eq!(
+            functions["f"],
+            (vec![Type::IntTyp], Box::new(Type::IntTyp), false)
+        );
+    }
+
+    #[test]
+    fn test_collect_functions_rejects_conflicts_and_redefinition() {
+        let program = vec![
+            declaration(Type::IntTyp, 0, 11),
+            definition(12, 36),
+            declaration(Type::CharTyp, 37, 49),
+            definition(50, 74),
+        ];
+        let errors = collect_functions(&program).unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.message.as_str(), error.span, error.notes[0].span))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "conflicting types for f",
+                    Some(span(37, 49)),
+                    Some(span(0, 11))
+                ),
+                (
+                    "f is already defined",
+                    Some(span(50, 74)),
+                    Some(span(12, 36))
+                ),
+            ]
+        );
     }
-    functions
 }
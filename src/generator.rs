@@ -1,105 +1,252 @@
 use std::{
-    collections::{hash_map, HashMap},
+    collections::{HashMap, VecDeque},
     io::Write,
 };
 
-use crate::{expr::TypedExpr, statement::TypedStatement, top_level::TypedTopLevel, types::Type};
+use crate::{
+    error::{CompileError, CompileErrorKind, CompileResult},
+    expr::{TypedExpr, TypedExprKind},
+    lex::Span,
+    statement::{TypedDeclaration, TypedStatement, TypedStatementKind},
+    token::{FloatLiteral, StringLiteral},
+    top_level::{Linkage, StaticValue, TypedTopLevel, TypedTopLevelKind},
+    types::Type,
+};
 
 const SYSTEM_V_CALLER_SAVE_REGISTERS: [&str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+const SYSTEM_V_CALLER_SAVE_REGISTERS_32: [&str; 6] = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
+const SYSTEM_V_CALLER_SAVE_REGISTERS_16: [&str; 6] = ["di", "si", "dx", "cx", "r8w", "r9w"];
+const SYSTEM_V_CALLER_SAVE_REGISTERS_8: [&str; 6] = ["dil", "sil", "dl", "cl", "r8b", "r9b"];
+const SYSTEM_V_FLOAT_ARGUMENT_REGISTER_COUNT: usize = 8;
+/// レジスタ保存領域で整数レジスタの後ろに xmm レジスタを 16 バイトずつ置く。
+const REGISTER_SAVE_AREA_FLOAT_START: usize = 8 * SYSTEM_V_CALLER_SAVE_REGISTERS.len();
+const REGISTER_SAVE_AREA_SIZE: usize =
+    REGISTER_SAVE_AREA_FLOAT_START + 16 * SYSTEM_V_FLOAT_ARGUMENT_REGISTER_COUNT;
+/// これ以上 `case` があり、値が密に並んでいればジャンプテーブルを使う。
+const JUMP_TABLE_MIN_CASES: usize = 4;
 
 pub struct Program<'a, W: Write> {
     fresh_counter: usize,
-    program: Vec<TypedTopLevel>,
+    top_levels: Vec<TypedTopLevel>,
+    /// .rodata に置く (ラベル, 文字列リテラル)。
+    string_literals: Vec<(String, StringLiteral)>,
     write: &'a mut W,
 }
 
 impl<'a, W: Write> Program<'a, W> {
-    pub fn new(program: Vec<TypedTopLevel>, write: &'a mut W) -> Self {
+    pub const fn new(top_levels: Vec<TypedTopLevel>, write: &'a mut W) -> Self {
         Self {
             fresh_counter: 0,
-            program,
+            top_levels,
+            string_literals: vec![],
             write,
         }
     }
-    pub fn gen(&mut self) {
-        writeln!(self.write, ".intel_syntax noprefix").unwrap();
-        writeln!(self.write, "  push rbp").unwrap();
-        writeln!(self.write, "  mov rbp, rsp").unwrap();
-
-        writeln!(self.write, "  sub rsp, 8").unwrap();
 
-        writeln!(self.write, "  call main").unwrap();
+    pub fn gen(&mut self) -> CompileResult<()> {
+        writeln!(self.write, ".intel_syntax noprefix").unwrap();
 
-        writeln!(self.write, "  add rsp, 8").unwrap();
+        let top_levels = self.top_levels.clone();
 
-        writeln!(self.write, "  mov rsp, rbp").unwrap();
-        writeln!(self.write, "  pop rbp").unwrap();
-        writeln!(self.write, "  ret").unwrap();
+        for top_level in top_levels {
+            self.gen_top_level(&top_level)?;
+        }
 
-        let program = self.program.clone();
+        self.gen_string_literals();
 
-        for top_level in program {
-            self.gen_top_level(&top_level);
-        }
+        // 実行可能スタックを要求しないことをリンカに伝える。
+        writeln!(self.write, ".section .note.GNU-stack,\"\",@progbits").unwrap();
+        Ok(())
     }
 
-    fn gen_top_level(&mut self, top_level: &TypedTopLevel) {
-        match top_level {
-            TypedTopLevel::FunctionDefinition(
+    fn gen_top_level(&mut self, top_level: &TypedTopLevel) -> CompileResult<()> {
+        match &top_level.kind {
+            TypedTopLevelKind::FunctionDefinition(
                 name,
                 params,
-                _,
+                return_type,
                 statements,
-                variable_type_environment,
+                linkage,
             ) => {
                 let mut function_generator = Function::new(
                     name.clone(),
-                    variable_type_environment,
                     params.clone(),
+                    return_type,
                     statements.clone(),
+                    *linkage,
                     self.fresh_counter,
                     self.write,
                 );
-                self.fresh_counter = function_generator.gen();
+                self.fresh_counter = function_generator.gen()?;
+                self.string_literals
+                    .append(&mut function_generator.string_literals);
+            }
+            TypedTopLevelKind::GlobalVariable(name, ty, values, linkage) => {
+                self.gen_global_variable(name, ty, values.as_deref(), *linkage);
+            }
+        }
+        Ok(())
+    }
+
+    /// 初期値のあるものは .data に、ないものは .bss に置く。
+    fn gen_global_variable(
+        &mut self,
+        name: &str,
+        ty: &Type,
+        values: Option<&[StaticValue]>,
+        linkage: Linkage,
+    ) {
+        let section = if values.is_some() { ".data" } else { ".bss" };
+        writeln!(self.write, "{section}").unwrap();
+        if linkage == Linkage::External {
+            writeln!(self.write, ".globl {name}").unwrap();
+        }
+        writeln!(self.write, ".align {}", ty.get_align()).unwrap();
+        writeln!(self.write, "{name}:").unwrap();
+
+        let Some(values) = values else {
+            writeln!(self.write, "  .zero {}", ty.get_size()).unwrap();
+            return;
+        };
+        for value in values {
+            match value {
+                #[allow(clippy::cast_possible_truncation)]
+                StaticValue::Integer(value, 4) => {
+                    writeln!(self.write, "  .long {}", *value as i32).unwrap();
+                }
+                #[allow(clippy::cast_possible_truncation)]
+                StaticValue::Integer(value, 2) => {
+                    writeln!(self.write, "  .short {}", *value as i16).unwrap();
+                }
+                #[allow(clippy::cast_possible_truncation)]
+                StaticValue::Integer(value, 1) => {
+                    writeln!(self.write, "  .byte {}", *value as i8).unwrap();
+                }
+                StaticValue::Integer(value, _) => {
+                    writeln!(self.write, "  .quad {value}").unwrap();
+                }
+                StaticValue::Address(label, offset) => {
+                    writeln!(self.write, "  .quad {}", address_operand(label, *offset)).unwrap();
+                }
+                StaticValue::StringLiteral(literal, offset) => {
+                    self.fresh_counter += 1;
+                    let label = format!(".LC{}", self.fresh_counter);
+                    writeln!(self.write, "  .quad {}", address_operand(&label, *offset)).unwrap();
+                    self.string_literals.push((label, literal.clone()));
+                }
+                StaticValue::Zero(size) => {
+                    writeln!(self.write, "  .zero {size}").unwrap();
+                }
+            }
+        }
+    }
+
+    fn gen_string_literals(&mut self) {
+        if self.string_literals.is_empty() {
+            return;
+        }
+
+        writeln!(self.write, ".section .rodata").unwrap();
+        for (label, literal) in &self.string_literals {
+            if literal.terminator_size() > 1 {
+                writeln!(self.write, "  .balign {}", literal.terminator_size()).unwrap();
+            }
+            writeln!(self.write, "{label}:").unwrap();
+            writeln!(
+                self.write,
+                "  .string \"{}\"",
+                escape_string_literal(&literal.bytes)
+            )
+            .unwrap();
+            // `.string` が置く NUL は 1 バイトなので、`L"..."` などは残りの終端を足す。
+            if literal.terminator_size() > 1 {
+                writeln!(self.write, "  .zero {}", literal.terminator_size() - 1).unwrap();
             }
         }
     }
 }
 
+/// `label+8` や `label-4` のような、ラベルからずらしたアドレスの式。
+fn address_operand(label: &str, offset: i64) -> String {
+    if offset == 0 {
+        label.to_string()
+    } else {
+        format!("{label}{offset:+}")
+    }
+}
+
+/// アセンブラの `.string` に渡せるようにエスケープする。
+/// `\x` は後続の 16 進数字をすべて読んでしまうので 3 桁の 8 進数を使う。
+fn escape_string_literal(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| match byte {
+            b'\\' | b'"' => format!("\\{}", char::from(*byte)),
+            b' '..=b'~' => char::from(*byte).to_string(),
+            _ => format!("\\{byte:03o}"),
+        })
+        .collect()
+}
+
 pub struct Function<'a, W: Write> {
     variable_offsets: HashMap<String, usize>,
     variables_offset: usize,
+    /// 構造体をメモリで返すとき、呼び出し元から受け取った書き込み先のアドレスを置く位置。
+    return_buffer_offset: Option<usize>,
+    /// 最後に割り当てた一時領域の位置。構造体を返す呼び出しの結果を置く。
+    temporaries_offset: usize,
+    /// `va_start` を使う関数で、引数レジスタをすべて書き出しておく位置。
+    register_save_area_offset: Option<usize>,
     name: String,
     params: Vec<(String, Type)>,
     body: Vec<TypedStatement>,
+    linkage: Linkage,
 
     // TODO: うまくmutable な composition　が作れなかったのでとりあえずfresh_counterを持たせている
     // base_generator: &'a mut ProgramGenerator,
     fresh_counter: usize,
     write: &'a mut W,
     rsp_offset: usize,
+    /// 評価中の複合代入の左辺のアドレスを積んだときの `rsp_offset`。内側のものほど後ろにある。
+    compound_assign_addresses: Vec<usize>,
+    /// 囲んでいるループと switch の `break` の飛び先。内側のものほど後ろにある。
+    break_labels: Vec<String>,
+    continue_labels: Vec<String>,
+    switches: Vec<Switch>,
+    /// .rodata に置く (ラベル, 文字列リテラル)。生成後に `Program` が引き取る。
+    string_literals: Vec<(String, StringLiteral)>,
 }
 
 impl<'a, W: Write> Function<'a, W> {
     pub fn new(
         name: String,
-        variable_type_environment: &HashMap<String, Type>,
         params: Vec<(String, Type)>,
+        return_type: &Type,
         body: Vec<TypedStatement>,
+        linkage: Linkage,
         fresh_counter: usize,
         write: &'a mut W,
     ) -> Self {
-        let (variable_offsets, variables_offset) =
-            Self::calc_variable_offset(variable_type_environment);
+        let frame = Self::calc_variable_offset(&params, return_type, &body);
         Self {
-            variable_offsets,
-            variables_offset,
+            variable_offsets: frame.variable_offsets,
+            variables_offset: frame.size,
+            return_buffer_offset: frame.return_buffer_offset,
+            temporaries_offset: frame.temporaries_offset,
+            register_save_area_offset: frame.register_save_area_offset,
             name,
             params,
             body,
+            linkage,
             fresh_counter,
             write,
-            rsp_offset: 8 + variables_offset,
+            // リターンアドレスと退避した rbp の分
+            rsp_offset: 16 + frame.size,
+            compound_assign_addresses: vec![],
+            break_labels: vec![],
+            continue_labels: vec![],
+            switches: vec![],
+            string_literals: vec![],
         }
     }
 
@@ -109,287 +256,1360 @@ impl<'a, W: Write> Function<'a, W> {
         format!("{}", self.fresh_counter)
     }
 
+    /// 変数は `rbp - offset` から `size` バイトを占める。退避した rbp を壊さないよう、
+    /// 先に大きさの分だけずらしてから位置を決める。
+    /// 兄弟のブロックの変数は同時に生きることがないので、ブロックを抜けたら同じ位置を使い回す。
+    /// 構造体を返す呼び出しの結果は、変数の後ろに呼び出しごとの一時領域を取って置く。
+    /// `va_start` を使う関数では、可変長引数を読むためのレジスタ保存領域も取る。
     fn calc_variable_offset(
-        local_variable_type_environment: &HashMap<String, Type>,
-    ) -> (HashMap<String, usize>, usize) {
-        let mut offset_map = HashMap::new();
-        let mut offset = 8;
-        for (variable, ty) in local_variable_type_environment {
-            if let hash_map::Entry::Vacant(e) = offset_map.entry(variable.clone()) {
-                e.insert(offset);
-                offset += round_up_as_multiple_of_8(ty.get_size());
-            }
+        params: &[(String, Type)],
+        return_type: &Type,
+        body: &[TypedStatement],
+    ) -> Frame {
+        let mut allocator = VariableAllocator::default();
+        let return_buffer_offset =
+            is_returned_in_memory(return_type).then(|| allocator.allocate_anonymous(8));
+        for (param, ty) in params {
+            allocator.allocate(param, ty);
+        }
+        for statement in body {
+            allocator.allocate_in_statement(statement);
+        }
+        let register_save_area_offset = allocator
+            .uses_va_start
+            .then(|| allocator.allocate_anonymous(REGISTER_SAVE_AREA_SIZE));
+        let temporaries_offset = round_up_as_multiple_of_8(allocator.max_offset);
+        Frame {
+            variable_offsets: allocator.offset_map,
+            return_buffer_offset,
+            temporaries_offset,
+            register_save_area_offset,
+            size: temporaries_offset + allocator.temporaries_size,
         }
-        (offset_map, offset)
     }
-    fn gen(&mut self) -> usize {
-        writeln!(&mut self.write, ".globl {}", self.name).unwrap();
+    fn gen(&mut self) -> CompileResult<usize> {
+        writeln!(self.write, ".text").unwrap();
+        if self.linkage == Linkage::External {
+            writeln!(self.write, ".globl {}", self.name).unwrap();
+        }
         writeln!(self.write, "{}:", self.name).unwrap();
 
         writeln!(self.write, "  push rbp").unwrap();
         writeln!(self.write, "  mov rbp, rsp").unwrap();
         writeln!(self.write, "  sub rsp, {}", self.variables_offset).unwrap();
 
-        for (i, param) in self.params.iter().enumerate() {
-            writeln!(
-                self.write,
-                "  mov [rbp-{}], {}",
-                self.variable_offsets[&param.0], SYSTEM_V_CALLER_SAVE_REGISTERS[i]
-            )
-            .unwrap();
-        }
+        self.gen_parameters();
 
         let body = &self.body.clone(); // TODO: borrow checker　が通してくれない...
 
-        self.gen_statements(body);
+        self.gen_statements(body)?;
 
+        // C99 以降、main の末尾に達したときは 0 を返す。
+        if self.name == "main" {
+            writeln!(self.write, "  mov rax, 0").unwrap();
+        }
         writeln!(self.write, "  mov rsp, rbp").unwrap();
         writeln!(self.write, "  pop rbp").unwrap();
         writeln!(self.write, "  ret").unwrap();
 
-        self.fresh_counter
+        Ok(self.fresh_counter)
+    }
+
+    /// レジスタとスタックで渡された引数を仮引数の位置に書き込む。
+    fn gen_parameters(&mut self) {
+        if let Some(offset) = self.return_buffer_offset {
+            writeln!(self.write, "  mov [rbp-{offset}], rdi").unwrap();
+        }
+        if let Some(offset) = self.register_save_area_offset {
+            for (i, register) in SYSTEM_V_CALLER_SAVE_REGISTERS.iter().enumerate() {
+                writeln!(self.write, "  mov [rbp-{}], {register}", offset - i * 8).unwrap();
+            }
+            for i in 0..SYSTEM_V_FLOAT_ARGUMENT_REGISTER_COUNT {
+                let address = offset - REGISTER_SAVE_AREA_FLOAT_START - i * 16;
+                writeln!(self.write, "  movsd [rbp-{address}], xmm{i}").unwrap();
+            }
+        }
+        let (locations, _) = self.parameter_locations();
+        for ((param, ty), location) in self.params.iter().zip(locations) {
+            let offset = self.variable_offsets[param];
+            match location {
+                ArgumentLocation::Float(i) => {
+                    let mov = if *ty == Type::FloatTyp {
+                        "movss"
+                    } else {
+                        "movsd"
+                    };
+                    writeln!(self.write, "  {mov} [rbp-{offset}], xmm{i}").unwrap();
+                }
+                ArgumentLocation::Integer(i) => {
+                    let register = match ty.get_size() {
+                        1 => SYSTEM_V_CALLER_SAVE_REGISTERS_8[i],
+                        2 => SYSTEM_V_CALLER_SAVE_REGISTERS_16[i],
+                        4 => SYSTEM_V_CALLER_SAVE_REGISTERS_32[i],
+                        _ => SYSTEM_V_CALLER_SAVE_REGISTERS[i],
+                    };
+                    writeln!(self.write, "  mov [rbp-{offset}], {register}").unwrap();
+                }
+                // 構造体の各 8 バイトは仮引数の領域にそのまま書き込む。領域は 8 の倍数の大きさがある。
+                ArgumentLocation::Registers(registers) => {
+                    for (i, register) in registers.iter().enumerate() {
+                        let address = offset - i * 8;
+                        match register {
+                            ArgumentLocation::Integer(j) => writeln!(
+                                self.write,
+                                "  mov [rbp-{address}], {}",
+                                SYSTEM_V_CALLER_SAVE_REGISTERS[*j]
+                            )
+                            .unwrap(),
+                            ArgumentLocation::Float(j) => {
+                                writeln!(self.write, "  movsd [rbp-{address}], xmm{j}").unwrap();
+                            }
+                            _ => unreachable!(),
+                        }
+                    }
+                }
+                // 戻りアドレスと退避した rbp の上に、スタックで渡された引数が並んでいる。
+                // 8 バイトの枠ごとに写す。
+                ArgumentLocation::Stack(slot) => {
+                    for i in 0..ty.get_size().div_ceil(8) {
+                        writeln!(self.write, "  mov rax, [rbp+{}]", 16 + (slot + i) * 8).unwrap();
+                        writeln!(self.write, "  mov [rbp-{}], rax", offset - i * 8).unwrap();
+                    }
+                }
+            }
+        }
+    }
+
+    fn parameter_locations(&self) -> (Vec<ArgumentLocation>, usize) {
+        let param_types = self
+            .params
+            .iter()
+            .map(|(_, ty)| ty.clone())
+            .collect::<Vec<_>>();
+        Self::assign_argument_locations(&param_types, self.return_buffer_offset.is_some())
     }
 
-    fn gen_statements(&mut self, statements: &Vec<TypedStatement>) {
+    fn gen_return_value(&mut self, expr: &TypedExpr) -> CompileResult<()> {
+        self.gen_expr(expr)?;
+        if let Type::Struct(_) = expr.get_type() {
+            // 構造体の値はアドレスとして積まれている。
+            writeln!(self.write, "  pop rsi").unwrap();
+            self.gen_return_struct(&expr.get_type());
+            return Ok(());
+        }
+        writeln!(self.write, "  pop rax").unwrap();
+        if expr.get_type().is_floating() {
+            writeln!(self.write, "  movq xmm0, rax").unwrap();
+        }
+        Ok(())
+    }
+
+    /// rsi が指す構造体を返す。メモリで返すときは呼び出し元の領域に写し、そのアドレスを rax に入れる。
+    fn gen_return_struct(&mut self, ty: &Type) {
+        if let Some(offset) = self.return_buffer_offset {
+            writeln!(self.write, "  mov rdi, [rbp-{offset}]").unwrap();
+            writeln!(self.write, "  mov rcx, {}", ty.get_size()).unwrap();
+            writeln!(self.write, "  rep movsb").unwrap();
+            writeln!(self.write, "  mov rax, [rbp-{offset}]").unwrap();
+            return;
+        }
+        let size = ty.get_size();
+        let mut integer_registers = ["rax", "rdx"].into_iter();
+        let mut float_registers = ["xmm0", "xmm1"].into_iter();
+        for (i, class) in classify_struct(ty).unwrap_or_default().iter().enumerate() {
+            self.gen_load_eightbyte("rsi", i * 8, (size - i * 8).min(8));
+            match class {
+                EightbyteClass::Integer => {
+                    let register = integer_registers.next().unwrap();
+                    writeln!(self.write, "  mov {register}, r11").unwrap();
+                }
+                EightbyteClass::Sse => {
+                    let register = float_registers.next().unwrap();
+                    writeln!(self.write, "  movq {register}, r11").unwrap();
+                }
+            }
+        }
+    }
+
+    fn gen_statements(&mut self, statements: &Vec<TypedStatement>) -> CompileResult<()> {
         for statement in statements {
-            self.gen_statement(statement);
+            self.gen_statement(statement)?;
         }
+        Ok(())
     }
 
-    fn gen_statement(&mut self, statement: &TypedStatement) {
-        match statement {
-            TypedStatement::VariableDeclaration(_, _) => {}
-            TypedStatement::Expr(expr) => {
-                self.gen_expr(expr);
-                writeln!(self.write, "  pop rax").unwrap();
+    fn gen_statement(&mut self, statement: &TypedStatement) -> CompileResult<()> {
+        match &statement.kind {
+            TypedStatementKind::Empty => {}
+            TypedStatementKind::VariableDeclaration(declarations) => {
+                for declaration in declarations {
+                    self.gen_variable_declaration(declaration)?;
+                }
             }
-            TypedStatement::Return(expr) => {
-                self.gen_expr(expr);
+            TypedStatementKind::Expr(expr) => {
+                self.gen_expr(expr)?;
                 writeln!(self.write, "  pop rax").unwrap();
+            }
+            TypedStatementKind::Return(expr) => {
+                if let Some(expr) = expr {
+                    self.gen_return_value(expr)?;
+                }
                 writeln!(self.write, "  mov rsp, rbp").unwrap();
                 writeln!(self.write, "  pop rbp").unwrap();
                 writeln!(self.write, "  ret").unwrap();
             }
-            TypedStatement::If(expr, then_statement) => {
+            TypedStatementKind::If(expr, then_statement) => {
                 let suffix = self.get_fresh_suffix();
 
-                self.gen_expr(expr);
+                self.gen_expr(expr)?;
                 writeln!(self.write, "  pop rax").unwrap();
                 writeln!(self.write, "  cmp rax, 0").unwrap();
                 writeln!(self.write, "  je .Lend{suffix}").unwrap();
 
-                self.gen_statement(then_statement);
+                self.gen_statement(then_statement)?;
 
                 writeln!(self.write, ".Lend{suffix}:").unwrap();
             }
-            TypedStatement::IfElse(expr, then_statement, else_statement) => {
+            TypedStatementKind::IfElse(expr, then_statement, else_statement) => {
                 let suffix = self.get_fresh_suffix();
 
-                self.gen_expr(expr);
+                self.gen_expr(expr)?;
                 writeln!(self.write, "  pop rax").unwrap();
                 writeln!(self.write, "  cmp rax, 0").unwrap();
                 writeln!(self.write, "  je .Lelse{suffix}").unwrap();
 
-                self.gen_statement(then_statement);
+                self.gen_statement(then_statement)?;
 
                 writeln!(self.write, "  jmp .Lend{suffix}").unwrap();
                 writeln!(self.write, ".Lelse{suffix}:").unwrap();
 
-                self.gen_statement(else_statement);
+                self.gen_statement(else_statement)?;
 
                 writeln!(self.write, ".Lend{suffix}:").unwrap();
             }
-            TypedStatement::While(expr, statement) => {
-                let suffix = self.get_fresh_suffix();
+            TypedStatementKind::While(expr, statement) => {
+                self.gen_while(expr, statement)?;
+            }
+            TypedStatementKind::For(init, cond, update, body) => {
+                self.gen_for(init, cond.as_deref(), update.as_deref(), body)?;
+            }
+            TypedStatementKind::DoWhile(body, expr) => {
+                self.gen_do_while(body, expr)?;
+            }
+            TypedStatementKind::Switch(expr, body) => {
+                self.gen_switch(expr, body)?;
+            }
+            TypedStatementKind::Case(_, body) => {
+                let switch = self.switches.last_mut().unwrap();
+                let label = switch.case_labels.pop_front().unwrap();
+                writeln!(self.write, "{label}:").unwrap();
+                self.gen_statement(body)?;
+            }
+            TypedStatementKind::Default(body) => {
+                let label = self.switches.last().unwrap().default_label.clone();
+                writeln!(self.write, "{label}:").unwrap();
+                self.gen_statement(body)?;
+            }
+            TypedStatementKind::Break => {
+                let label = self.break_labels.last().unwrap();
+                writeln!(self.write, "  jmp {label}").unwrap();
+            }
+            TypedStatementKind::Continue => {
+                let label = self.continue_labels.last().unwrap();
+                writeln!(self.write, "  jmp {label}").unwrap();
+            }
+            TypedStatementKind::Goto(label) => {
+                writeln!(self.write, "  jmp {}", self.goto_label(label)).unwrap();
+            }
+            TypedStatementKind::Labeled(label, body) => {
+                writeln!(self.write, "{}:", self.goto_label(label)).unwrap();
+                self.gen_statement(body)?;
+            }
+            TypedStatementKind::Block(statements) => {
+                self.gen_statements(statements)?;
+            }
+        }
+        Ok(())
+    }
 
-                writeln!(self.write, ".Lbegin{suffix}:").unwrap();
+    /// 配列と構造体は、初期化子で値を与えない部分のために先に全体を 0 で埋める。
+    fn gen_variable_declaration(&mut self, declaration: &TypedDeclaration) -> CompileResult<()> {
+        let Some(assignments) = &declaration.initializer else {
+            return Ok(());
+        };
+        if let Type::Array(_, _) | Type::Struct(_) = declaration.ty {
+            let offset = self.variable_offsets[&declaration.name];
+            writeln!(self.write, "  lea rdi, [rbp-{offset}]").unwrap();
+            writeln!(self.write, "  mov rcx, {}", declaration.ty.get_size()).unwrap();
+            writeln!(self.write, "  xor eax, eax").unwrap();
+            writeln!(self.write, "  rep stosb").unwrap();
+        }
+        for assignment in assignments {
+            self.gen_expr(assignment)?;
+            writeln!(self.write, "  pop rax").unwrap();
+        }
+        Ok(())
+    }
 
-                self.gen_expr(expr);
-                writeln!(self.write, "  pop rax").unwrap();
-                writeln!(self.write, "  cmp rax, 0").unwrap();
-                writeln!(self.write, "  je .Lend{suffix}").unwrap();
+    fn gen_while(&mut self, cond: &TypedExpr, body: &TypedStatement) -> CompileResult<()> {
+        let suffix = self.get_fresh_suffix();
 
-                self.gen_statement(statement);
+        writeln!(self.write, ".Lbegin{suffix}:").unwrap();
 
-                writeln!(self.write, "  jmp .Lbegin{suffix}").unwrap();
-                writeln!(self.write, ".Lend{suffix}:").unwrap();
-            }
-            TypedStatement::For(init, cond, update, body) => {
-                let suffix = self.get_fresh_suffix();
+        self.gen_expr(cond)?;
+        writeln!(self.write, "  pop rax").unwrap();
+        writeln!(self.write, "  cmp rax, 0").unwrap();
+        writeln!(self.write, "  je .Lend{suffix}").unwrap();
 
-                self.gen_expr(init);
-                writeln!(self.write, "  pop rax").unwrap();
+        self.gen_loop_body(body, &suffix, &format!(".Lbegin{suffix}"))?;
 
-                writeln!(self.write, ".Lbegin{suffix}:").unwrap();
+        writeln!(self.write, "  jmp .Lbegin{suffix}").unwrap();
+        writeln!(self.write, ".Lend{suffix}:").unwrap();
+        Ok(())
+    }
 
-                self.gen_expr(cond);
-                writeln!(self.write, "  pop rax").unwrap();
-                writeln!(self.write, "  cmp rax, 0").unwrap();
-                writeln!(self.write, "  je .Lend{suffix}").unwrap();
+    fn gen_for(
+        &mut self,
+        init: &TypedStatement,
+        cond: Option<&TypedExpr>,
+        update: Option<&TypedExpr>,
+        body: &TypedStatement,
+    ) -> CompileResult<()> {
+        let suffix = self.get_fresh_suffix();
 
-                self.gen_statement(body);
+        self.gen_statement(init)?;
 
-                self.gen_expr(update);
-                writeln!(self.write, "  pop rax").unwrap();
+        writeln!(self.write, ".Lbegin{suffix}:").unwrap();
 
-                writeln!(self.write, "  jmp .Lbegin{suffix}").unwrap();
-                writeln!(self.write, ".Lend{suffix}:").unwrap();
+        if let Some(cond) = cond {
+            self.gen_expr(cond)?;
+            writeln!(self.write, "  pop rax").unwrap();
+            writeln!(self.write, "  cmp rax, 0").unwrap();
+            writeln!(self.write, "  je .Lend{suffix}").unwrap();
+        }
+
+        self.gen_loop_body(body, &suffix, &format!(".Lcontinue{suffix}"))?;
+
+        writeln!(self.write, ".Lcontinue{suffix}:").unwrap();
+        if let Some(update) = update {
+            self.gen_expr(update)?;
+            writeln!(self.write, "  pop rax").unwrap();
+        }
+
+        writeln!(self.write, "  jmp .Lbegin{suffix}").unwrap();
+        writeln!(self.write, ".Lend{suffix}:").unwrap();
+        Ok(())
+    }
+
+    fn gen_do_while(&mut self, body: &TypedStatement, cond: &TypedExpr) -> CompileResult<()> {
+        let suffix = self.get_fresh_suffix();
+
+        writeln!(self.write, ".Lbegin{suffix}:").unwrap();
+
+        self.gen_loop_body(body, &suffix, &format!(".Lcontinue{suffix}"))?;
+
+        writeln!(self.write, ".Lcontinue{suffix}:").unwrap();
+        self.gen_expr(cond)?;
+        writeln!(self.write, "  pop rax").unwrap();
+        writeln!(self.write, "  cmp rax, 0").unwrap();
+        writeln!(self.write, "  jne .Lbegin{suffix}").unwrap();
+        writeln!(self.write, ".Lend{suffix}:").unwrap();
+        Ok(())
+    }
+
+    /// `break` は `.Lend{suffix}` に、`continue` は `continue_label` に飛ぶ。
+    fn gen_loop_body(
+        &mut self,
+        body: &TypedStatement,
+        suffix: &str,
+        continue_label: &str,
+    ) -> CompileResult<()> {
+        self.break_labels.push(format!(".Lend{suffix}"));
+        self.continue_labels.push(continue_label.to_string());
+        let result = self.gen_statement(body);
+        self.break_labels.pop();
+        self.continue_labels.pop();
+        result
+    }
+
+    /// 関数ごとにラベルの名前空間を分ける。
+    fn goto_label(&self, label: &str) -> String {
+        format!(".Llabel_{}_{label}", self.name)
+    }
+
+    /// `case` の値が密に並んでいればジャンプテーブルで、そうでなければ比較の連鎖で分岐する。
+    fn gen_switch(&mut self, expr: &TypedExpr, body: &TypedStatement) -> CompileResult<()> {
+        let suffix = self.get_fresh_suffix();
+        let mut values = vec![];
+        let mut has_default = false;
+        collect_switch_cases(body, &mut values, &mut has_default);
+        let case_labels = (0..values.len())
+            .map(|i| format!(".Lcase{suffix}_{i}"))
+            .collect::<VecDeque<_>>();
+        let default_label = if has_default {
+            format!(".Ldefault{suffix}")
+        } else {
+            format!(".Lend{suffix}")
+        };
+
+        self.gen_expr(expr)?;
+        writeln!(self.write, "  pop rax").unwrap();
+        let min = values.iter().copied().min().unwrap_or(0);
+        let max = values.iter().copied().max().unwrap_or(0);
+        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+        let range = (i128::from(max) - i128::from(min) + 1) as usize;
+        if values.len() >= JUMP_TABLE_MIN_CASES && range <= values.len() * 3 {
+            writeln!(self.write, "  mov rdi, {min}").unwrap();
+            writeln!(self.write, "  sub rax, rdi").unwrap();
+            writeln!(self.write, "  cmp rax, {}", range - 1).unwrap();
+            writeln!(self.write, "  ja {default_label}").unwrap();
+            writeln!(self.write, "  lea rdi, [rip+.Ljump_table{suffix}]").unwrap();
+            writeln!(self.write, "  movsxd rax, dword ptr [rdi+rax*4]").unwrap();
+            writeln!(self.write, "  add rax, rdi").unwrap();
+            writeln!(self.write, "  jmp rax").unwrap();
+            // テーブルにはテーブル自身からの相対位置を置く。
+            writeln!(self.write, ".Ljump_table{suffix}:").unwrap();
+            for value in min..=max {
+                let label = values
+                    .iter()
+                    .position(|v| *v == value)
+                    .map_or(&default_label, |i| &case_labels[i]);
+                writeln!(self.write, "  .long {label}-.Ljump_table{suffix}").unwrap();
             }
-            TypedStatement::Block(statements) => {
-                self.gen_statements(statements);
+        } else {
+            for (value, label) in values.iter().zip(&case_labels) {
+                writeln!(self.write, "  mov rdi, {value}").unwrap();
+                writeln!(self.write, "  cmp rax, rdi").unwrap();
+                writeln!(self.write, "  je {label}").unwrap();
             }
+            writeln!(self.write, "  jmp {default_label}").unwrap();
         }
+
+        self.switches.push(Switch {
+            case_labels,
+            default_label: format!(".Ldefault{suffix}"),
+        });
+        self.break_labels.push(format!(".Lend{suffix}"));
+        let result = self.gen_statement(body);
+        self.break_labels.pop();
+        self.switches.pop();
+        result?;
+        writeln!(self.write, ".Lend{suffix}:").unwrap();
+        Ok(())
     }
 
-    fn gen_expr(&mut self, expr: &TypedExpr) {
-        if let Type::Array(_, _) = expr.get_type() {
-            self.gen_address_of_lvalue(expr);
-            return;
+    /// 配列・構造体・関数の値はアドレスとして扱う。
+    fn gen_expr(&mut self, expr: &TypedExpr) -> CompileResult<()> {
+        match (&expr.kind, expr.get_type()) {
+            (
+                TypedExprKind::Assign(_, _)
+                | TypedExprKind::FunctionCall(_, _)
+                | TypedExprKind::Conditional(_, _, _)
+                | TypedExprKind::Comma(_, _)
+                | TypedExprKind::VaArg(_),
+                _,
+            ) => {}
+            (_, Type::Array(_, _) | Type::Struct(_) | Type::Function(_, _, _)) => {
+                return self.gen_address_of_lvalue(expr);
+            }
+            _ => {}
         }
 
-        match expr {
-            TypedExpr::IntNum(n) => {
-                writeln!(self.write, "  push {n}").unwrap();
+        match &expr.kind {
+            TypedExprKind::IntNum(n) => {
+                // push の即値は 32 ビットを符号拡張したものしか書けない。
+                if i32::try_from(*n).is_ok() {
+                    writeln!(self.write, "  push {n}").unwrap();
+                } else {
+                    writeln!(self.write, "  mov rax, {n}").unwrap();
+                    writeln!(self.write, "  push rax").unwrap();
+                }
             }
-            TypedExpr::Add(_, lhs, rhs) => {
-                self.gen_add_sub_operation(lhs, rhs, "add");
+            TypedExprKind::FloatNum(literal) => {
+                self.gen_float_literal(*literal, &expr.get_type());
+            }
+            TypedExprKind::Add(lhs, rhs) => {
+                self.gen_add_sub_operation(lhs, rhs, "add", expr)?;
             }
 
-            TypedExpr::Sub(_, lhs, rhs) => {
-                self.gen_add_sub_operation(lhs, rhs, "sub");
+            TypedExprKind::Sub(lhs, rhs) => {
+                self.gen_add_sub_operation(lhs, rhs, "sub", expr)?;
             }
-            TypedExpr::Mul(_, lhs, rhs) => {
-                self.gen_binary_operation(lhs, rhs, &["  imul rax, rdi"]);
+            TypedExprKind::Mul(lhs, rhs) => {
+                self.gen_multiplication(lhs, rhs, expr)?;
             }
 
-            TypedExpr::Div(_, lhs, rhs) => {
-                self.gen_binary_operation(lhs, rhs, &["  cqo", "idiv rdi"]);
+            TypedExprKind::Div(lhs, rhs) | TypedExprKind::Mod(lhs, rhs) => {
+                self.gen_division(lhs, rhs, expr)?;
+            }
+            TypedExprKind::BitAnd(lhs, rhs)
+            | TypedExprKind::BitOr(lhs, rhs)
+            | TypedExprKind::BitXor(lhs, rhs)
+            | TypedExprKind::LeftShift(lhs, rhs)
+            | TypedExprKind::RightShift(lhs, rhs) => {
+                self.gen_bitwise_operation(lhs, rhs, expr)?;
+            }
+            TypedExprKind::LogicalAnd(lhs, rhs) | TypedExprKind::LogicalOr(lhs, rhs) => {
+                self.gen_logical_operation(lhs, rhs, expr)?;
+            }
+            TypedExprKind::LogicalNot(operand) | TypedExprKind::BitNot(operand) => {
+                self.gen_unary_operation(operand, expr)?;
+            }
+            TypedExprKind::LessThan(lhs, rhs)
+            | TypedExprKind::LessEqual(lhs, rhs)
+            | TypedExprKind::Equal(lhs, rhs)
+            | TypedExprKind::NotEqual(lhs, rhs)
+            | TypedExprKind::GreaterThan(lhs, rhs)
+            | TypedExprKind::GreaterEqual(lhs, rhs) => {
+                self.gen_comparator(lhs, rhs, expr)?;
+            }
+            TypedExprKind::Assign(lhs, rhs) => {
+                self.gen_assign(lhs, rhs)?;
             }
-            TypedExpr::LessThan(lhs, rhs) => {
-                self.gen_comparator(lhs, rhs, "setl");
+            TypedExprKind::CompoundAssign(lhs, value)
+            | TypedExprKind::PostfixAssign(lhs, value) => {
+                self.gen_compound_assign(lhs, value, expr)?;
             }
-            TypedExpr::LessEqual(lhs, rhs) => {
-                self.gen_comparator(lhs, rhs, "setle");
+            TypedExprKind::CompoundAssignLhs => {
+                self.gen_compound_assign_lhs(expr)?;
             }
-            TypedExpr::Equal(lhs, rhs) => {
-                self.gen_comparator(lhs, rhs, "sete");
+            TypedExprKind::Conditional(cond, then, els) => {
+                self.gen_conditional(cond, then, els)?;
             }
-            TypedExpr::NotEqual(lhs, rhs) => {
-                self.gen_comparator(lhs, rhs, "setne");
+            TypedExprKind::Comma(lhs, rhs) => {
+                self.gen_expr(lhs)?;
+                writeln!(self.write, "  add rsp, 8").unwrap();
+                self.gen_expr(rhs)?;
             }
-            TypedExpr::GreaterThan(lhs, rhs) => {
-                self.gen_comparator(lhs, rhs, "setg");
+            TypedExprKind::StringLiteral(_) | TypedExprKind::Function(_) => {
+                self.gen_address_of_lvalue(expr)?;
             }
-            TypedExpr::GreaterEqual(lhs, rhs) => {
-                self.gen_comparator(lhs, rhs, "setge");
+            TypedExprKind::FunctionCall(callee, args) => {
+                self.gen_function_call(callee, args, expr)?;
             }
-            TypedExpr::Assign(_, lhs, rhs) => {
-                self.gen_address_of_lvalue(lhs);
-                self.rsp_offset += 8;
-                self.gen_expr(rhs);
-                self.rsp_offset -= 8;
+            TypedExprKind::Address(expr) => {
+                self.gen_address_of_lvalue(expr)?;
+            }
+            TypedExprKind::Variable(_)
+            | TypedExprKind::GlobalVariable(_)
+            | TypedExprKind::Dereference(_)
+            | TypedExprKind::Member(_, _) => {
+                self.gen_address_of_lvalue(expr)?;
+                writeln!(self.write, "  pop rax").unwrap();
+                self.gen_load(expr)?;
+                writeln!(self.write, "  push rax").unwrap();
+            }
+            TypedExprKind::Cast(operand) => self.gen_cast(operand, &expr.get_type())?,
+            TypedExprKind::VaStart(ap) => self.gen_va_start(ap)?,
+            TypedExprKind::VaArg(ap) => self.gen_va_arg(ap, expr)?,
+        }
+        Ok(())
+    }
 
-                let di_register = match rhs.get_type().get_size() {
-                    4 => "edi",
-                    8 => "rdi",
-                    _ => panic!("unexpected size"),
-                };
+    /// 名前付きの仮引数が使ったレジスタとスタックの枠の次から読むよう `va_list` を初期化する。
+    /// 値は持たないが、式として 8 バイトを積む。
+    fn gen_va_start(&mut self, ap: &TypedExpr) -> CompileResult<()> {
+        let (locations, stack_count) = self.parameter_locations();
+        let registers = locations
+            .iter()
+            .flat_map(|location| match location {
+                ArgumentLocation::Registers(registers) => registers.iter().collect(),
+                ArgumentLocation::Stack(_) => vec![],
+                location => vec![location],
+            })
+            .collect::<Vec<_>>();
+        let float_count = registers
+            .iter()
+            .filter(|location| matches!(location, ArgumentLocation::Float(_)))
+            .count();
+        let integer_count =
+            registers.len() - float_count + usize::from(self.return_buffer_offset.is_some());
+        let save_area_offset = self.register_save_area_offset.unwrap();
 
-                writeln!(self.write, "  pop rdi").unwrap();
-                writeln!(self.write, "  pop rax").unwrap();
-                writeln!(self.write, "  mov [rax], {di_register}").unwrap();
-                writeln!(self.write, "  push rdi").unwrap();
-            }
-            TypedExpr::Variable(ty, _) => {
-                let ax_register = match ty.get_size() {
-                    4 => "eax",
-                    8 => "rax",
-                    _ => panic!("unexpected size"),
-                };
+        self.gen_expr(ap)?;
+        writeln!(self.write, "  pop rax").unwrap();
+        writeln!(self.write, "  mov dword ptr [rax], {}", integer_count * 8).unwrap();
+        writeln!(
+            self.write,
+            "  mov dword ptr [rax+4], {}",
+            REGISTER_SAVE_AREA_FLOAT_START + float_count * 16
+        )
+        .unwrap();
+        writeln!(self.write, "  lea rdi, [rbp+{}]", 16 + stack_count * 8).unwrap();
+        writeln!(self.write, "  mov [rax+8], rdi").unwrap();
+        writeln!(self.write, "  lea rdi, [rbp-{save_area_offset}]").unwrap();
+        writeln!(self.write, "  mov [rax+16], rdi").unwrap();
+        writeln!(self.write, "  push rax").unwrap();
+        Ok(())
+    }
 
-                self.gen_address_of_lvalue(expr);
-                writeln!(self.write, "  pop rax").unwrap();
-                writeln!(self.write, "  mov {ax_register}, [rax]").unwrap();
-                writeln!(self.write, "  push rax").unwrap();
+    /// 次の可変長引数を取り出す。引数の 8 バイトがすべて残りのレジスタに収まっていれば
+    /// レジスタ保存領域から、そうでなければスタックで渡された領域から読む。
+    /// レジスタから読む構造体は、一時領域に集めてからそのアドレスを積む。
+    fn gen_va_arg(&mut self, ap: &TypedExpr, expr: &TypedExpr) -> CompileResult<()> {
+        let ty = expr.get_type();
+        let is_struct = matches!(ty, Type::Struct(_));
+        let classes = if is_struct {
+            classify_struct(&ty)
+        } else if ty.is_floating() {
+            Some(vec![EightbyteClass::Sse])
+        } else {
+            Some(vec![EightbyteClass::Integer])
+        };
+        let temporary = is_struct.then(|| self.allocate_temporary(ty.get_size()));
+        let suffix = self.get_fresh_suffix();
+
+        self.gen_expr(ap)?;
+        writeln!(self.write, "  pop rdi").unwrap();
+        if let Some(classes) = classes {
+            let sse_count = classes
+                .iter()
+                .filter(|class| **class == EightbyteClass::Sse)
+                .count();
+            let integer_count = classes.len() - sse_count;
+            if integer_count > 0 {
+                writeln!(self.write, "  mov eax, dword ptr [rdi]").unwrap();
+                writeln!(
+                    self.write,
+                    "  cmp eax, {}",
+                    REGISTER_SAVE_AREA_FLOAT_START - integer_count * 8
+                )
+                .unwrap();
+                writeln!(self.write, "  ja .Lva_arg_stack{suffix}").unwrap();
             }
-            TypedExpr::FunctionCall(_, name, args) => {
-                for arg in args {
-                    self.gen_expr(arg);
-                    self.rsp_offset += 8;
+            if sse_count > 0 {
+                writeln!(self.write, "  mov eax, dword ptr [rdi+4]").unwrap();
+                writeln!(
+                    self.write,
+                    "  cmp eax, {}",
+                    REGISTER_SAVE_AREA_SIZE - sse_count * 16
+                )
+                .unwrap();
+                writeln!(self.write, "  ja .Lva_arg_stack{suffix}").unwrap();
+            }
+            for (i, class) in classes.iter().enumerate() {
+                let (field, step) = match class {
+                    EightbyteClass::Integer => ("rdi", 8),
+                    EightbyteClass::Sse => ("rdi+4", 16),
+                };
+                writeln!(self.write, "  mov eax, dword ptr [{field}]").unwrap();
+                writeln!(self.write, "  mov rsi, [rdi+16]").unwrap();
+                writeln!(self.write, "  add rsi, rax").unwrap();
+                writeln!(self.write, "  add dword ptr [{field}], {step}").unwrap();
+                if let Some(offset) = temporary {
+                    writeln!(self.write, "  mov rax, [rsi]").unwrap();
+                    writeln!(self.write, "  mov [rbp-{}], rax", offset - i * 8).unwrap();
                 }
+            }
+            if let Some(offset) = temporary {
+                writeln!(self.write, "  lea rsi, [rbp-{offset}]").unwrap();
+            }
+            writeln!(self.write, "  jmp .Lva_arg_end{suffix}").unwrap();
+        }
+        writeln!(self.write, ".Lva_arg_stack{suffix}:").unwrap();
+        writeln!(self.write, "  mov rsi, [rdi+8]").unwrap();
+        writeln!(
+            self.write,
+            "  lea rax, [rsi+{}]",
+            round_up_as_multiple_of_8(ty.get_size())
+        )
+        .unwrap();
+        writeln!(self.write, "  mov [rdi+8], rax").unwrap();
+        writeln!(self.write, ".Lva_arg_end{suffix}:").unwrap();
+        if is_struct {
+            writeln!(self.write, "  push rsi").unwrap();
+        } else {
+            writeln!(self.write, "  mov rax, rsi").unwrap();
+            self.gen_load(expr)?;
+            writeln!(self.write, "  push rax").unwrap();
+        }
+        Ok(())
+    }
+
+    /// 浮動小数点数はビット列を即値として積む。
+    fn gen_float_literal(&mut self, literal: FloatLiteral, ty: &Type) {
+        let bits = if *ty == Type::FloatTyp {
+            #[allow(clippy::cast_possible_truncation)]
+            u64::from((literal.value() as f32).to_bits())
+        } else {
+            literal.value().to_bits()
+        };
+        writeln!(self.write, "  mov rax, {bits}").unwrap();
+        writeln!(self.write, "  push rax").unwrap();
+    }
 
-                self.rsp_offset -= args.len() * 8;
+    fn gen_assign(&mut self, lhs: &TypedExpr, rhs: &TypedExpr) -> CompileResult<()> {
+        self.gen_address_of_lvalue(lhs)?;
+        self.rsp_offset += 8;
+        self.gen_expr(rhs)?;
+        self.rsp_offset -= 8;
+
+        if let Type::Struct(_) = lhs.get_type() {
+            writeln!(self.write, "  pop rsi").unwrap();
+            writeln!(self.write, "  pop rdi").unwrap();
+            writeln!(self.write, "  mov rax, rdi").unwrap();
+            writeln!(self.write, "  mov rcx, {}", lhs.get_type().get_size()).unwrap();
+            writeln!(self.write, "  rep movsb").unwrap();
+            writeln!(self.write, "  push rax").unwrap();
+            return Ok(());
+        }
+
+        let di_register = Self::di_register(lhs)?;
+
+        writeln!(self.write, "  pop rdi").unwrap();
+        writeln!(self.write, "  pop rax").unwrap();
+        writeln!(self.write, "  mov [rax], {di_register}").unwrap();
+        writeln!(self.write, "  push rdi").unwrap();
+        Ok(())
+    }
+
+    /// 左辺のアドレスは一度だけ計算してスタックに置き、代入値の中の `CompoundAssignLhs` から参照する。
+    fn gen_compound_assign(
+        &mut self,
+        lhs: &TypedExpr,
+        value: &TypedExpr,
+        expr: &TypedExpr,
+    ) -> CompileResult<()> {
+        self.gen_address_of_lvalue(lhs)?;
+        self.rsp_offset += 8;
+        self.compound_assign_addresses.push(self.rsp_offset);
+        self.gen_expr(value)?;
+        self.compound_assign_addresses.pop();
+        self.rsp_offset -= 8;
 
-                for i in (0..args.len()).rev() {
-                    writeln!(self.write, "  pop {}", SYSTEM_V_CALLER_SAVE_REGISTERS[i]).unwrap();
+        let di_register = Self::di_register(lhs)?;
+        writeln!(self.write, "  pop rdi").unwrap();
+        writeln!(self.write, "  pop rax").unwrap();
+        if let TypedExprKind::PostfixAssign(_, _) = expr.kind {
+            // 後置の場合は更新前の値を結果にする。
+            writeln!(self.write, "  mov rsi, rax").unwrap();
+            self.gen_load(lhs)?;
+            writeln!(self.write, "  mov [rsi], {di_register}").unwrap();
+            writeln!(self.write, "  push rax").unwrap();
+        } else {
+            writeln!(self.write, "  mov [rax], {di_register}").unwrap();
+            writeln!(self.write, "  push rdi").unwrap();
+        }
+        Ok(())
+    }
+
+    fn gen_compound_assign_lhs(&mut self, expr: &TypedExpr) -> CompileResult<()> {
+        let address_offset = self.compound_assign_addresses.last().unwrap();
+        let distance = self.rsp_offset - address_offset;
+        writeln!(self.write, "  mov rax, [rsp+{distance}]").unwrap();
+        self.gen_load(expr)?;
+        writeln!(self.write, "  push rax").unwrap();
+        Ok(())
+    }
+
+    fn gen_conditional(
+        &mut self,
+        cond: &TypedExpr,
+        then: &TypedExpr,
+        els: &TypedExpr,
+    ) -> CompileResult<()> {
+        let suffix = self.get_fresh_suffix();
+        self.gen_expr(cond)?;
+        writeln!(self.write, "  pop rax").unwrap();
+        writeln!(self.write, "  cmp rax, 0").unwrap();
+        writeln!(self.write, "  je .Lelse{suffix}").unwrap();
+        self.gen_expr(then)?;
+        writeln!(self.write, "  jmp .Lend{suffix}").unwrap();
+        writeln!(self.write, ".Lelse{suffix}:").unwrap();
+        self.gen_expr(els)?;
+        writeln!(self.write, ".Lend{suffix}:").unwrap();
+        Ok(())
+    }
+
+    fn gen_multiplication(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        expr: &TypedExpr,
+    ) -> CompileResult<()> {
+        if expr.get_type().is_floating() {
+            return self.gen_float_operation(lhs, rhs, "mul", &expr.get_type());
+        }
+        let mut ops = vec!["  imul rax, rdi"];
+        ops.extend(extend_instruction(&expr.get_type()));
+        self.gen_binary_operation(lhs, rhs, &ops)
+    }
+
+    /// `/` と `%` を生成する。剰余は `rdx` に残る。
+    fn gen_division(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        expr: &TypedExpr,
+    ) -> CompileResult<()> {
+        if expr.get_type().is_floating() {
+            return self.gen_float_operation(lhs, rhs, "div", &expr.get_type());
+        }
+        let mut ops = if expr.get_type().is_unsigned() {
+            vec!["  xor edx, edx", "  div rdi"]
+        } else {
+            vec!["  cqo", "  idiv rdi"]
+        };
+        if let TypedExprKind::Mod(_, _) = expr.kind {
+            ops.push("  mov rax, rdx");
+        }
+        ops.extend(extend_instruction(&expr.get_type()));
+        self.gen_binary_operation(lhs, rhs, &ops)
+    }
+
+    /// 右シフトは符号付きなら `sar`、符号なしなら `shr` を使う。
+    fn gen_bitwise_operation(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        expr: &TypedExpr,
+    ) -> CompileResult<()> {
+        let mut ops = match expr.kind {
+            TypedExprKind::BitAnd(_, _) => vec!["  and rax, rdi"],
+            TypedExprKind::BitOr(_, _) => vec!["  or rax, rdi"],
+            TypedExprKind::BitXor(_, _) => vec!["  xor rax, rdi"],
+            TypedExprKind::LeftShift(_, _) => vec!["  mov rcx, rdi", "  shl rax, cl"],
+            _ if expr.get_type().is_unsigned() => vec!["  mov rcx, rdi", "  shr rax, cl"],
+            _ => vec!["  mov rcx, rdi", "  sar rax, cl"],
+        };
+        ops.extend(extend_instruction(&expr.get_type()));
+        self.gen_binary_operation(lhs, rhs, &ops)
+    }
+
+    /// `&&` と `||` は左辺で結果が決まれば右辺を評価しない。
+    fn gen_logical_operation(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        expr: &TypedExpr,
+    ) -> CompileResult<()> {
+        let suffix = self.get_fresh_suffix();
+        // `&&` は偽で、`||` は真で評価を打ち切る。
+        let (jump, short_circuit_value) = if let TypedExprKind::LogicalAnd(_, _) = expr.kind {
+            ("je", 0)
+        } else {
+            ("jne", 1)
+        };
+        for operand in [lhs, rhs] {
+            self.gen_expr(operand)?;
+            writeln!(self.write, "  pop rax").unwrap();
+            writeln!(self.write, "  cmp rax, 0").unwrap();
+            writeln!(self.write, "  {jump} .Lshort_circuit{suffix}").unwrap();
+        }
+        writeln!(self.write, "  push {}", 1 - short_circuit_value).unwrap();
+        writeln!(self.write, "  jmp .Lend{suffix}").unwrap();
+        writeln!(self.write, ".Lshort_circuit{suffix}:").unwrap();
+        writeln!(self.write, "  push {short_circuit_value}").unwrap();
+        writeln!(self.write, ".Lend{suffix}:").unwrap();
+        Ok(())
+    }
+
+    fn gen_unary_operation(&mut self, operand: &TypedExpr, expr: &TypedExpr) -> CompileResult<()> {
+        self.gen_expr(operand)?;
+        writeln!(self.write, "  pop rax").unwrap();
+        if let TypedExprKind::LogicalNot(_) = expr.kind {
+            writeln!(self.write, "  cmp rax, 0").unwrap();
+            writeln!(self.write, "  sete al").unwrap();
+            writeln!(self.write, "  movzx eax, al").unwrap();
+        } else {
+            writeln!(self.write, "  not rax").unwrap();
+            self.gen_extend(&expr.get_type());
+        }
+        writeln!(self.write, "  push rax").unwrap();
+        Ok(())
+    }
+
+    /// `_Bool` への変換は 0 か 1 にし、整数型へは `ty` の大きさに切り詰めて拡張する。
+    fn gen_cast(&mut self, operand: &TypedExpr, ty: &Type) -> CompileResult<()> {
+        self.gen_expr(operand)?;
+        writeln!(self.write, "  pop rax").unwrap();
+        let from = operand.get_type();
+        match (from.is_floating(), ty.is_floating()) {
+            (false, false) => {
+                if *ty == Type::BoolTyp {
+                    writeln!(self.write, "  cmp rax, 0").unwrap();
+                    writeln!(self.write, "  setne al").unwrap();
                 }
+                self.gen_extend(ty);
+            }
+            (false, true) => self.gen_integer_to_float(&from, ty),
+            (true, false) => self.gen_float_to_integer(&from, ty),
+            (true, true) => {
+                writeln!(self.write, "  movq xmm0, rax").unwrap();
+                writeln!(
+                    self.write,
+                    "  cvt{}2{} xmm0, xmm0",
+                    float_suffix(&from),
+                    float_suffix(ty)
+                )
+                .unwrap();
+                writeln!(self.write, "{}", float_result_instruction(ty)).unwrap();
+            }
+        }
+        writeln!(self.write, "  push rax").unwrap();
+        Ok(())
+    }
 
-                let misalignment = self.rsp_offset % 16;
-                writeln!(self.write, "  sub rsp, {misalignment}").unwrap();
-                writeln!(self.write, "  call {name}").unwrap();
-                writeln!(self.write, "  add rsp, {misalignment}").unwrap();
-                writeln!(self.write, "  push rax").unwrap();
+    /// `rax` の浮動小数点数を整数に変換する。`cvttsd2si` は符号付きとして扱うので、
+    /// 2^63 以上の値を 64 ビット符号なし整数にするときは 2^63 を引いてから変換し、最上位ビットを立てる。
+    fn gen_float_to_integer(&mut self, from: &Type, ty: &Type) {
+        let suffix = float_suffix(from);
+        writeln!(self.write, "  movq xmm0, rax").unwrap();
+        if *ty == Type::BoolTyp {
+            // NaN も真になるよう、比較不能 (PF) の場合も 1 にする。
+            writeln!(self.write, "  xorps xmm1, xmm1").unwrap();
+            writeln!(self.write, "  ucomi{suffix} xmm0, xmm1").unwrap();
+            writeln!(self.write, "  setne al").unwrap();
+            writeln!(self.write, "  setp dl").unwrap();
+            writeln!(self.write, "  or al, dl").unwrap();
+        } else if ty.is_unsigned() && ty.get_size() == 8 {
+            let label_suffix = self.get_fresh_suffix();
+            let two_to_the_63 = if *from == Type::FloatTyp {
+                u64::from(9_223_372_036_854_775_808_f32.to_bits())
+            } else {
+                9_223_372_036_854_775_808_f64.to_bits()
+            };
+            writeln!(self.write, "  mov rdi, {two_to_the_63}").unwrap();
+            writeln!(self.write, "  movq xmm1, rdi").unwrap();
+            writeln!(self.write, "  ucomi{suffix} xmm0, xmm1").unwrap();
+            writeln!(self.write, "  jae .Lfloat_large{label_suffix}").unwrap();
+            writeln!(self.write, "  cvtt{suffix}2si rax, xmm0").unwrap();
+            writeln!(self.write, "  jmp .Lconverted{label_suffix}").unwrap();
+            writeln!(self.write, ".Lfloat_large{label_suffix}:").unwrap();
+            writeln!(self.write, "  sub{suffix} xmm0, xmm1").unwrap();
+            writeln!(self.write, "  cvtt{suffix}2si rax, xmm0").unwrap();
+            writeln!(self.write, "  btc rax, 63").unwrap();
+            writeln!(self.write, ".Lconverted{label_suffix}:").unwrap();
+        } else {
+            writeln!(self.write, "  cvtt{suffix}2si rax, xmm0").unwrap();
+        }
+        self.gen_extend(ty);
+    }
+
+    /// `rax` の整数を浮動小数点数に変換する。`cvtsi2sd` は符号付きとして扱うので、
+    /// 最上位ビットの立った 64 ビット符号なし整数は半分にしてから変換して 2 倍する。
+    fn gen_integer_to_float(&mut self, from: &Type, ty: &Type) {
+        let suffix = float_suffix(ty);
+        if from.is_unsigned() && from.get_size() == 8 {
+            let label_suffix = self.get_fresh_suffix();
+            writeln!(self.write, "  test rax, rax").unwrap();
+            writeln!(self.write, "  js .Lunsigned_large{label_suffix}").unwrap();
+            writeln!(self.write, "  cvtsi2{suffix} xmm0, rax").unwrap();
+            writeln!(self.write, "  jmp .Lconverted{label_suffix}").unwrap();
+            writeln!(self.write, ".Lunsigned_large{label_suffix}:").unwrap();
+            writeln!(self.write, "  mov rdi, rax").unwrap();
+            writeln!(self.write, "  shr rdi, 1").unwrap();
+            writeln!(self.write, "  and eax, 1").unwrap();
+            writeln!(self.write, "  or rdi, rax").unwrap();
+            writeln!(self.write, "  cvtsi2{suffix} xmm0, rdi").unwrap();
+            writeln!(self.write, "  add{suffix} xmm0, xmm0").unwrap();
+            writeln!(self.write, ".Lconverted{label_suffix}:").unwrap();
+        } else {
+            writeln!(self.write, "  cvtsi2{suffix} xmm0, rax").unwrap();
+        }
+        writeln!(self.write, "{}", float_result_instruction(ty)).unwrap();
+    }
+
+    /// 浮動小数点数の値はビット列のまま汎用レジスタとスタックに置き、演算のときだけ xmm に移す。
+    fn gen_float_operation(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        op: &str,
+        ty: &Type,
+    ) -> CompileResult<()> {
+        self.gen_binary_operation(
+            lhs,
+            rhs,
+            &[
+                "  movq xmm0, rax",
+                "  movq xmm1, rdi",
+                &format!("  {op}{} xmm0, xmm1", float_suffix(ty)),
+                float_result_instruction(ty),
+            ],
+        )
+    }
+
+    /// NaN との比較は `!=` 以外すべて偽になるよう、`<` と `<=` は左右を入れ替えて
+    /// `seta`/`setae` で判定する。
+    fn gen_float_comparator(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        signed_op: &str,
+    ) -> CompileResult<()> {
+        let ucomi = format!("  ucomi{}", float_suffix(&lhs.get_type()));
+        let ops = match signed_op {
+            "setl" => vec![format!("{ucomi} xmm1, xmm0"), "  seta al".to_string()],
+            "setle" => vec![format!("{ucomi} xmm1, xmm0"), "  setae al".to_string()],
+            "setg" => vec![format!("{ucomi} xmm0, xmm1"), "  seta al".to_string()],
+            "setge" => vec![format!("{ucomi} xmm0, xmm1"), "  setae al".to_string()],
+            "sete" => vec![
+                format!("{ucomi} xmm0, xmm1"),
+                "  sete al".to_string(),
+                "  setnp dl".to_string(),
+                "  and al, dl".to_string(),
+            ],
+            _ => vec![
+                format!("{ucomi} xmm0, xmm1"),
+                "  setne al".to_string(),
+                "  setp dl".to_string(),
+                "  or al, dl".to_string(),
+            ],
+        };
+        let ops = [
+            "  movq xmm0, rax".to_string(),
+            "  movq xmm1, rdi".to_string(),
+        ]
+        .into_iter()
+        .chain(ops)
+        .chain(["  movzx eax, al".to_string()])
+        .collect::<Vec<_>>();
+        self.gen_binary_operation(
+            lhs,
+            rhs,
+            &ops.iter().map(String::as_str).collect::<Vec<_>>(),
+        )
+    }
+
+    /// 引数を System V の規約に従って整数レジスタと xmm レジスタに割り当てる。
+    /// 構造体は 8 バイトごとの分類に従って複数のレジスタで渡す。
+    /// レジスタが足りなくなった引数とメモリで渡す構造体は、左から順にスタックの 8 バイトの枠に置く。
+    /// 構造体をメモリで返す関数では、書き込み先のアドレスが最初の整数レジスタを使う。
+    /// スタックの枠の数も返す。
+    fn assign_argument_locations(
+        types: &[Type],
+        uses_return_buffer: bool,
+    ) -> (Vec<ArgumentLocation>, usize) {
+        let mut integer_count = usize::from(uses_return_buffer);
+        let mut float_count = 0;
+        let mut stack_count = 0;
+        let locations = types
+            .iter()
+            .map(|ty| {
+                let is_struct = matches!(ty.unqualified(), Type::Struct(_));
+                let classes = if is_struct {
+                    classify_struct(ty)
+                } else if ty.is_floating() {
+                    Some(vec![EightbyteClass::Sse])
+                } else {
+                    Some(vec![EightbyteClass::Integer])
+                };
+                // 構造体は全体がレジスタに収まらなければ、まるごとスタックで渡す。
+                let Some(classes) = classes.filter(|classes| {
+                    let sse_count = classes
+                        .iter()
+                        .filter(|class| **class == EightbyteClass::Sse)
+                        .count();
+                    integer_count + classes.len() - sse_count
+                        <= SYSTEM_V_CALLER_SAVE_REGISTERS.len()
+                        && float_count + sse_count <= SYSTEM_V_FLOAT_ARGUMENT_REGISTER_COUNT
+                }) else {
+                    let slot = stack_count;
+                    stack_count += ty.get_size().div_ceil(8);
+                    return ArgumentLocation::Stack(slot);
+                };
+                let mut registers = classes
+                    .into_iter()
+                    .map(|class| match class {
+                        EightbyteClass::Integer => {
+                            integer_count += 1;
+                            ArgumentLocation::Integer(integer_count - 1)
+                        }
+                        EightbyteClass::Sse => {
+                            float_count += 1;
+                            ArgumentLocation::Float(float_count - 1)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if is_struct {
+                    ArgumentLocation::Registers(registers)
+                } else {
+                    registers.pop().unwrap()
+                }
+            })
+            .collect();
+        (locations, stack_count)
+    }
+
+    fn gen_function_call(
+        &mut self,
+        callee: &TypedExpr,
+        args: &[TypedExpr],
+        expr: &TypedExpr,
+    ) -> CompileResult<()> {
+        let return_type = expr.get_type();
+        // 構造体の戻り値は一時領域に置き、そのアドレスを式の値とする。
+        let temporary = matches!(return_type, Type::Struct(_))
+            .then(|| self.allocate_temporary(return_type.get_size()));
+        let uses_return_buffer = is_returned_in_memory(&return_type);
+        // 関数名なら直接呼び出し、それ以外は呼び出し先のアドレスを r10 に置いて呼び出す。
+        let direct_name = match &callee.kind {
+            TypedExprKind::Function(name) => Some(name.as_str()),
+            _ => None,
+        };
+        let (locations, stack_count) = Self::assign_argument_locations(
+            &args.iter().map(TypedExpr::get_type).collect::<Vec<_>>(),
+            uses_return_buffer,
+        );
+
+        // スタックで渡す引数を積み終えたときに rsp が 16 の倍数になるよう、先に詰め物を入れる。
+        let padding = (self.rsp_offset + stack_count * 8) % 16;
+        writeln!(self.write, "  sub rsp, {padding}").unwrap();
+        self.rsp_offset += padding;
+
+        // スタックで渡す引数を右から積み、その上にレジスタで渡す引数を右から積む。
+        // レジスタの引数を左から取り出すと、最初のスタック引数が rsp の位置に残る。
+        let (stack_args, register_args): (Vec<_>, Vec<_>) = args
+            .iter()
+            .zip(&locations)
+            .partition(|(_, location)| matches!(location, ArgumentLocation::Stack(_)));
+        for (arg, _) in stack_args.iter().rev() {
+            self.gen_stack_argument(arg)?;
+        }
+        for (arg, _) in register_args.iter().rev() {
+            self.gen_expr(arg)?;
+            self.rsp_offset += 8;
+        }
+
+        // 呼び出し先のアドレスは引数の後で評価する。
+        if direct_name.is_none() {
+            self.gen_expr(callee)?;
+            // rax は引数の数の受け渡しに使うので、呼び出し先は r10 に置く。
+            writeln!(self.write, "  pop r10").unwrap();
+        }
+
+        let mut float_count = 0;
+        for (arg, location) in &register_args {
+            float_count += self.gen_pop_argument(arg, location);
+        }
+        self.rsp_offset -= register_args.len() * 8;
+        if let Some(offset) = temporary.filter(|_| uses_return_buffer) {
+            writeln!(self.write, "  lea rdi, [rbp-{offset}]").unwrap();
+        }
+
+        // 可変長引数の関数のために、ベクタレジスタで渡す引数の数を al に入れる。
+        writeln!(self.write, "  mov eax, {float_count}").unwrap();
+        match direct_name {
+            Some(name) => writeln!(self.write, "  call {name}").unwrap(),
+            None => writeln!(self.write, "  call r10").unwrap(),
+        }
+        let stack_size = stack_count * 8 + padding;
+        writeln!(self.write, "  add rsp, {stack_size}").unwrap();
+        self.rsp_offset -= stack_size;
+        match temporary {
+            // メモリで返す構造体は、呼び出し先が書き込み先のアドレスを rax に入れて返す。
+            Some(_) if uses_return_buffer => {}
+            Some(offset) => self.gen_store_struct_result(&return_type, offset),
+            None if return_type.is_floating() => {
+                writeln!(self.write, "{}", float_result_instruction(&return_type)).unwrap();
             }
-            TypedExpr::Address(_, expr) => {
-                self.gen_address_of_lvalue(expr);
+            // 戻り値の上位ビットは不定なので拡張する。
+            None => self.gen_extend(&return_type),
+        }
+        writeln!(self.write, "  push rax").unwrap();
+        Ok(())
+    }
+
+    /// スタックで渡す引数を積む。構造体はアドレスの代わりに中身を積む。
+    fn gen_stack_argument(&mut self, arg: &TypedExpr) -> CompileResult<()> {
+        self.gen_expr(arg)?;
+        if let Type::Struct(_) = arg.get_type() {
+            let size = arg.get_type().get_size();
+            let slots_size = round_up_as_multiple_of_8(size);
+            writeln!(self.write, "  pop rsi").unwrap();
+            writeln!(self.write, "  sub rsp, {slots_size}").unwrap();
+            writeln!(self.write, "  mov rdi, rsp").unwrap();
+            writeln!(self.write, "  mov rcx, {size}").unwrap();
+            writeln!(self.write, "  rep movsb").unwrap();
+            self.rsp_offset += slots_size;
+        } else {
+            self.rsp_offset += 8;
+        }
+        Ok(())
+    }
+
+    /// 積んであるレジスタ引数を取り出してレジスタに入れ、使った xmm レジスタの数を返す。
+    fn gen_pop_argument(&mut self, arg: &TypedExpr, location: &ArgumentLocation) -> usize {
+        match location {
+            ArgumentLocation::Integer(i) => {
+                writeln!(self.write, "  pop {}", SYSTEM_V_CALLER_SAVE_REGISTERS[*i]).unwrap();
+                0
             }
-            TypedExpr::Dereference(_, expr) => {
-                self.gen_expr(expr);
+            ArgumentLocation::Float(i) => {
                 writeln!(self.write, "  pop rax").unwrap();
-                writeln!(self.write, "  mov rax, [rax]").unwrap();
-                writeln!(self.write, "  push rax").unwrap();
+                writeln!(self.write, "  movq xmm{i}, rax").unwrap();
+                1
             }
-            TypedExpr::Sizeof(expr) => {
-                self.gen_expr(expr);
+            ArgumentLocation::Registers(registers) => {
                 writeln!(self.write, "  pop rax").unwrap();
-                writeln!(self.write, "  mov rax, {}", expr.get_type().get_size()).unwrap();
-                writeln!(self.write, "  push rax").unwrap();
+                let size = arg.get_type().get_size();
+                for (i, register) in registers.iter().enumerate() {
+                    self.gen_load_eightbyte("rax", i * 8, (size - i * 8).min(8));
+                    match register {
+                        ArgumentLocation::Integer(j) => {
+                            writeln!(
+                                self.write,
+                                "  mov {}, r11",
+                                SYSTEM_V_CALLER_SAVE_REGISTERS[*j]
+                            )
+                            .unwrap();
+                        }
+                        ArgumentLocation::Float(j) => {
+                            writeln!(self.write, "  movq xmm{j}, r11").unwrap();
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                registers
+                    .iter()
+                    .filter(|register| matches!(register, ArgumentLocation::Float(_)))
+                    .count()
             }
+            ArgumentLocation::Stack(_) => unreachable!(),
         }
     }
 
-    fn gen_add_sub_operation(&mut self, lhs: &TypedExpr, rhs: &TypedExpr, op: &str) {
-        match (lhs.get_type(), rhs.get_type()) {
-            (Type::Pointer(_), Type::Pointer(_)) => {
-                panic!("pointer + pointer is not supported")
-            }
-            (Type::Pointer(_), _) => {
-                self.gen_binary_operation(
-                    lhs,
-                    rhs,
-                    &["  imul rdi, 8", &format!("  {op} rax, rdi")],
-                );
+    /// レジスタで返された構造体を `rbp - offset` の一時領域に書き込み、そのアドレスを rax に入れる。
+    fn gen_store_struct_result(&mut self, ty: &Type, offset: usize) {
+        let mut integer_registers = ["rax", "rdx"].into_iter();
+        let mut float_registers = ["xmm0", "xmm1"].into_iter();
+        for (i, class) in classify_struct(ty).unwrap_or_default().iter().enumerate() {
+            let address = offset - i * 8;
+            match class {
+                EightbyteClass::Integer => {
+                    let register = integer_registers.next().unwrap();
+                    writeln!(self.write, "  mov [rbp-{address}], {register}").unwrap();
+                }
+                EightbyteClass::Sse => {
+                    let register = float_registers.next().unwrap();
+                    writeln!(self.write, "  movsd [rbp-{address}], {register}").unwrap();
+                }
             }
-            (_, Type::Pointer(_)) => {
-                self.gen_binary_operation(
-                    lhs,
-                    rhs,
-                    &["  imul rax, 8", &format!("  {op} rax, rdi")],
-                );
+        }
+        writeln!(self.write, "  lea rax, [rbp-{offset}]").unwrap();
+    }
+
+    /// `address` から `offset` バイト先の `size` (8 以下) バイトを r11 にゼロ拡張して読み込む。
+    /// 構造体の末尾を越えて読まないよう、大きさちょうどだけ読む。
+    fn gen_load_eightbyte(&mut self, address: &str, offset: usize, size: usize) {
+        match size {
+            8 => writeln!(self.write, "  mov r11, [{address}+{offset}]").unwrap(),
+            4 => writeln!(self.write, "  mov r11d, dword ptr [{address}+{offset}]").unwrap(),
+            2 => writeln!(self.write, "  movzx r11d, word ptr [{address}+{offset}]").unwrap(),
+            1 => writeln!(self.write, "  movzx r11d, byte ptr [{address}+{offset}]").unwrap(),
+            _ => {
+                writeln!(self.write, "  xor r11d, r11d").unwrap();
+                for i in (0..size).rev() {
+                    writeln!(self.write, "  shl r11, 8").unwrap();
+                    writeln!(
+                        self.write,
+                        "  mov r11b, byte ptr [{address}+{}]",
+                        offset + i
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+
+    /// 一時領域を割り当て、その位置を返す。
+    const fn allocate_temporary(&mut self, size: usize) -> usize {
+        self.temporaries_offset += round_up_as_multiple_of_8(size);
+        self.temporaries_offset
+    }
+
+    fn gen_add_sub_operation(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        op: &str,
+        expr: &TypedExpr,
+    ) -> CompileResult<()> {
+        match (lhs.get_type(), rhs.get_type()) {
+            (Type::Pointer(pointee), Type::Pointer(_)) => self.gen_binary_operation(
+                lhs,
+                rhs,
+                &[
+                    "  sub rax, rdi",
+                    "  cqo",
+                    &format!("  mov rdi, {}", pointee.get_size()),
+                    "  idiv rdi",
+                ],
+            ),
+            (Type::Pointer(pointee), _) => self.gen_binary_operation(
+                lhs,
+                rhs,
+                &[
+                    &format!("  imul rdi, {}", pointee.get_size()),
+                    &format!("  {op} rax, rdi"),
+                ],
+            ),
+            (_, Type::Pointer(pointee)) => self.gen_binary_operation(
+                lhs,
+                rhs,
+                &[
+                    &format!("  imul rax, {}", pointee.get_size()),
+                    &format!("  {op} rax, rdi"),
+                ],
+            ),
+            _ if expr.get_type().is_floating() => {
+                self.gen_float_operation(lhs, rhs, op, &expr.get_type())
             }
             _ => {
-                self.gen_binary_operation(lhs, rhs, &[&format!("  {op} rax, rdi")]);
+                let op = format!("  {op} rax, rdi");
+                let mut ops = vec![op.as_str()];
+                ops.extend(extend_instruction(&expr.get_type()));
+                self.gen_binary_operation(lhs, rhs, &ops)
             }
         }
     }
 
-    fn gen_binary_operation(&mut self, lhs: &TypedExpr, rhs: &TypedExpr, ops: &[&str]) {
-        self.gen_expr(lhs);
+    fn gen_binary_operation(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        ops: &[&str],
+    ) -> CompileResult<()> {
+        self.gen_expr(lhs)?;
         self.rsp_offset += 8;
-        self.gen_expr(rhs);
+        self.gen_expr(rhs)?;
         self.rsp_offset -= 8;
 
         writeln!(self.write, "  pop rdi").unwrap();
         writeln!(self.write, "  pop rax").unwrap();
 
-        for op in ops.iter() {
+        for op in ops {
             writeln!(self.write, "{op}").unwrap();
         }
 
         writeln!(self.write, "  push rax").unwrap();
+        Ok(())
     }
 
-    fn gen_comparator(&mut self, lhs: &TypedExpr, rhs: &TypedExpr, op: &str) {
+    /// 符号付きと符号なしで set 命令を使い分ける。ポインタは符号なしで比較する。
+    fn gen_comparator(
+        &mut self,
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        expr: &TypedExpr,
+    ) -> CompileResult<()> {
+        let [signed_op, unsigned_op] = match expr.kind {
+            TypedExprKind::LessThan(_, _) => ["setl", "setb"],
+            TypedExprKind::LessEqual(_, _) => ["setle", "setbe"],
+            TypedExprKind::Equal(_, _) => ["sete", "sete"],
+            TypedExprKind::NotEqual(_, _) => ["setne", "setne"],
+            TypedExprKind::GreaterThan(_, _) => ["setg", "seta"],
+            _ => ["setge", "setae"],
+        };
+        if lhs.get_type().is_floating() {
+            return self.gen_float_comparator(lhs, rhs, signed_op);
+        }
+        let op = match lhs.get_type() {
+            ty if ty.is_unsigned() => unsigned_op,
+            Type::Pointer(_) => unsigned_op,
+            _ => signed_op,
+        };
         self.gen_binary_operation(
             lhs,
             rhs,
@@ -398,30 +1618,329 @@ impl<'a, W: Write> Function<'a, W> {
                 format!("  {op} al").as_str(),
                 "  movzb rax, al",
             ],
-        );
+        )
     }
 
-    fn gen_address_of_lvalue(&mut self, expr: &TypedExpr) {
-        match expr {
-            TypedExpr::Variable(_, name) => {
-                let error_message = format!("variable {name} not found");
-
-                let offset = self.variable_offsets.get(name).expect(&error_message);
+    fn gen_address_of_lvalue(&mut self, expr: &TypedExpr) -> CompileResult<()> {
+        match &expr.kind {
+            TypedExprKind::Variable(name) => {
+                let offset = self
+                    .variable_offsets
+                    .get(name)
+                    .ok_or_else(|| Self::error(format!("variable {name} not found"), expr.span))?;
 
                 writeln!(self.write, "  mov rax, rbp").unwrap();
                 writeln!(self.write, "  sub rax, {offset}").unwrap();
                 writeln!(self.write, "  push rax").unwrap();
+                Ok(())
+            }
+            TypedExprKind::GlobalVariable(name) => {
+                writeln!(self.write, "  lea rax, [rip+{name}]").unwrap();
+                writeln!(self.write, "  push rax").unwrap();
+                Ok(())
             }
-            TypedExpr::Dereference(_, expr) => {
-                self.gen_expr(expr);
+            // 共有ライブラリの関数も指せるよう、GOT を経由してアドレスを得る。
+            TypedExprKind::Function(name) => {
+                writeln!(self.write, "  mov rax, [rip+{name}@GOTPCREL]").unwrap();
+                writeln!(self.write, "  push rax").unwrap();
+                Ok(())
+            }
+            TypedExprKind::StringLiteral(literal) => {
+                let label = format!(".LC{}", self.get_fresh_suffix());
+                writeln!(self.write, "  lea rax, [rip+{label}]").unwrap();
+                writeln!(self.write, "  push rax").unwrap();
+                self.string_literals.push((label, literal.clone()));
+                Ok(())
+            }
+            TypedExprKind::Dereference(pointer) => self.gen_expr(pointer),
+            // 構造体の値になる式は、値の置かれた場所のアドレスを積む。
+            TypedExprKind::FunctionCall(_, _)
+            | TypedExprKind::Assign(_, _)
+            | TypedExprKind::Conditional(_, _, _)
+            | TypedExprKind::Comma(_, _)
+            | TypedExprKind::VaArg(_)
+                if matches!(expr.get_type(), Type::Struct(_)) =>
+            {
+                self.gen_expr(expr)
+            }
+            TypedExprKind::Member(operand, offset) => {
+                self.gen_address_of_lvalue(operand)?;
+                writeln!(self.write, "  pop rax").unwrap();
+                writeln!(self.write, "  add rax, {offset}").unwrap();
+                writeln!(self.write, "  push rax").unwrap();
+                Ok(())
             }
-            _ => todo!(),
+            _ => Err(Self::error("expression is not an lvalue", expr.span)),
+        }
+    }
+
+    fn gen_extend(&mut self, ty: &Type) {
+        if let Some(instruction) = extend_instruction(ty) {
+            writeln!(self.write, "{instruction}").unwrap();
         }
     }
+
+    /// `rax` が指す `expr` の値を `rax` に読み込む。
+    /// 8 バイト未満の値は型に応じて符号拡張かゼロ拡張する。
+    fn gen_load(&mut self, expr: &TypedExpr) -> CompileResult<()> {
+        let unsigned = expr.get_type().is_unsigned() || expr.get_type().is_floating();
+        let instruction = match expr.get_type().get_size() {
+            1 if unsigned => "movzx eax, byte ptr [rax]",
+            1 => "movsx rax, byte ptr [rax]",
+            2 if unsigned => "movzx eax, word ptr [rax]",
+            2 => "movsx rax, word ptr [rax]",
+            4 if unsigned => "mov eax, dword ptr [rax]",
+            4 => "movsxd rax, dword ptr [rax]",
+            8 => "mov rax, [rax]",
+            size => return Err(Self::error(format!("unexpected size: {size}"), expr.span)),
+        };
+        writeln!(self.write, "  {instruction}").unwrap();
+        Ok(())
+    }
+
+    fn di_register(expr: &TypedExpr) -> CompileResult<&'static str> {
+        match expr.get_type().get_size() {
+            1 => Ok("dil"),
+            2 => Ok("di"),
+            4 => Ok("edi"),
+            8 => Ok("rdi"),
+            size => Err(Self::error(format!("unexpected size: {size}"), expr.span)),
+        }
+    }
+
+    fn error(message: impl Into<String>, span: Span) -> CompileError {
+        CompileError::new(CompileErrorKind::Codegen, message, Some(span))
+    }
+}
+
+/// 生成中の switch で、まだ出力していない `case` のラベル (出現順) と `default` のラベル。
+struct Switch {
+    case_labels: VecDeque<String>,
+    default_label: String,
+}
+
+/// 入れ子の switch の中は除いて、`case` の値を出現順に集める。
+fn collect_switch_cases(statement: &TypedStatement, values: &mut Vec<i64>, has_default: &mut bool) {
+    match &statement.kind {
+        TypedStatementKind::Case(value, body) => {
+            values.push(*value);
+            collect_switch_cases(body, values, has_default);
+        }
+        TypedStatementKind::Default(body) => {
+            *has_default = true;
+            collect_switch_cases(body, values, has_default);
+        }
+        TypedStatementKind::If(_, body)
+        | TypedStatementKind::While(_, body)
+        | TypedStatementKind::For(_, _, _, body)
+        | TypedStatementKind::DoWhile(body, _)
+        | TypedStatementKind::Labeled(_, body) => collect_switch_cases(body, values, has_default),
+        TypedStatementKind::IfElse(_, then, els) => {
+            collect_switch_cases(then, values, has_default);
+            collect_switch_cases(els, values, has_default);
+        }
+        TypedStatementKind::Block(statements) => {
+            for statement in statements {
+                collect_switch_cases(statement, values, has_default);
+            }
+        }
+        TypedStatementKind::Expr(_)
+        | TypedStatementKind::Return(_)
+        | TypedStatementKind::Switch(_, _)
+        | TypedStatementKind::Break
+        | TypedStatementKind::Continue
+        | TypedStatementKind::Goto(_)
+        | TypedStatementKind::VariableDeclaration(_)
+        | TypedStatementKind::Empty => {}
+    }
+}
+
+/// 関数のスタックフレームの配置。位置はどれも `rbp` から下に数えたもの。
+struct Frame {
+    variable_offsets: HashMap<String, usize>,
+    return_buffer_offset: Option<usize>,
+    /// 一時領域の始まり。一時領域はここから下に順に取る。
+    temporaries_offset: usize,
+    register_save_area_offset: Option<usize>,
+    size: usize,
+}
+
+/// ローカル変数のスタック上の位置を決める。
+#[derive(Default)]
+struct VariableAllocator {
+    offset_map: HashMap<String, usize>,
+    /// 現在のブロックまでで使っている大きさ。
+    offset: usize,
+    /// 関数全体で必要な大きさ。
+    max_offset: usize,
+    /// 構造体を返す呼び出しや `va_arg` の結果を置く一時領域の大きさの合計。
+    temporaries_size: usize,
+    uses_va_start: bool,
+}
+
+impl VariableAllocator {
+    fn allocate(&mut self, variable: &str, ty: &Type) {
+        let offset = self.allocate_anonymous(ty.get_size());
+        self.offset_map.insert(variable.to_string(), offset);
+    }
+
+    fn allocate_anonymous(&mut self, size: usize) -> usize {
+        self.offset += round_up_as_multiple_of_8(size);
+        self.max_offset = self.max_offset.max(self.offset);
+        self.offset
+    }
+
+    fn allocate_in_expr(&mut self, expr: &TypedExpr) {
+        match (&expr.kind, expr.get_type()) {
+            (TypedExprKind::FunctionCall(_, _) | TypedExprKind::VaArg(_), Type::Struct(_)) => {
+                self.temporaries_size += round_up_as_multiple_of_8(expr.get_type().get_size());
+            }
+            (TypedExprKind::VaStart(_), _) => self.uses_va_start = true,
+            _ => {}
+        }
+        for child in expr.children() {
+            self.allocate_in_expr(child);
+        }
+    }
+
+    fn allocate_in_statement(&mut self, statement: &TypedStatement) {
+        match &statement.kind {
+            TypedStatementKind::VariableDeclaration(declarations) => {
+                for declaration in declarations {
+                    self.allocate(&declaration.name, &declaration.ty);
+                    for expr in declaration.initializer.iter().flatten() {
+                        self.allocate_in_expr(expr);
+                    }
+                }
+            }
+            TypedStatementKind::Block(statements) => {
+                let offset = self.offset;
+                for statement in statements {
+                    self.allocate_in_statement(statement);
+                }
+                self.offset = offset;
+            }
+            TypedStatementKind::For(init, cond, step, body) => {
+                let offset = self.offset;
+                self.allocate_in_statement(init);
+                for expr in [cond, step].into_iter().flatten() {
+                    self.allocate_in_expr(expr);
+                }
+                self.allocate_in_statement(body);
+                self.offset = offset;
+            }
+            TypedStatementKind::IfElse(cond, then, els) => {
+                self.allocate_in_expr(cond);
+                self.allocate_in_statement(then);
+                self.allocate_in_statement(els);
+            }
+            TypedStatementKind::If(expr, body)
+            | TypedStatementKind::While(expr, body)
+            | TypedStatementKind::DoWhile(body, expr)
+            | TypedStatementKind::Switch(expr, body) => {
+                self.allocate_in_expr(expr);
+                self.allocate_in_statement(body);
+            }
+            TypedStatementKind::Case(_, body)
+            | TypedStatementKind::Default(body)
+            | TypedStatementKind::Labeled(_, body) => self.allocate_in_statement(body),
+            TypedStatementKind::Expr(expr) | TypedStatementKind::Return(Some(expr)) => {
+                self.allocate_in_expr(expr);
+            }
+            TypedStatementKind::Return(None)
+            | TypedStatementKind::Break
+            | TypedStatementKind::Continue
+            | TypedStatementKind::Goto(_)
+            | TypedStatementKind::Empty => {}
+        }
+    }
+}
+
+/// 引数を渡す場所。レジスタの番号か、呼び出し時の rsp から数えたスタックの枠の番号。
+enum ArgumentLocation {
+    Integer(usize),
+    Float(usize),
+    /// 構造体は大きさに応じて続く枠も占める。
+    Stack(usize),
+    /// 構造体を 8 バイトずつ渡すレジスタ。要素は `Integer` か `Float`。
+    Registers(Vec<Self>),
+}
+
+/// System V ABI での 8 バイトごとの分類。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EightbyteClass {
+    Integer,
+    Sse,
+}
+
+/// 構造体を 8 バイトごとに分類する。16 バイトを超えるものはメモリで渡すので `None` を返す。
+/// 浮動小数点数だけを含む 8 バイトは xmm レジスタ、それ以外は整数レジスタで渡す。
+fn classify_struct(ty: &Type) -> Option<Vec<EightbyteClass>> {
+    let size = ty.get_size();
+    if size > 16 {
+        return None;
+    }
+    let mut classes = vec![EightbyteClass::Sse; size.div_ceil(8)];
+    classify_scalars(ty, 0, &mut classes);
+    Some(classes)
+}
+
+fn classify_scalars(ty: &Type, offset: usize, classes: &mut [EightbyteClass]) {
+    match ty.unqualified() {
+        Type::Struct(struct_type) => {
+            for member in struct_type.members() {
+                classify_scalars(&member.ty, offset + member.offset, classes);
+            }
+        }
+        Type::Array(element, length) => {
+            for i in 0..*length {
+                classify_scalars(element, offset + i * element.get_size(), classes);
+            }
+        }
+        ty if ty.is_floating() => {}
+        _ => classes[offset / 8] = EightbyteClass::Integer,
+    }
+}
+
+/// 呼び出し元が用意した領域に書き込んで返す構造体か。
+fn is_returned_in_memory(ty: &Type) -> bool {
+    matches!(ty.unqualified(), Type::Struct(_)) && classify_struct(ty).is_none()
+}
+
+/// スカラー命令の接尾辞。
+fn float_suffix(ty: &Type) -> &'static str {
+    if *ty == Type::FloatTyp {
+        "ss"
+    } else {
+        "sd"
+    }
+}
+
+/// `xmm0` の結果を `rax` に移す命令。`float` は上位 32 ビットを 0 にする。
+fn float_result_instruction(ty: &Type) -> &'static str {
+    if *ty == Type::FloatTyp {
+        "  movd eax, xmm0"
+    } else {
+        "  movq rax, xmm0"
+    }
+}
+
+/// `rax` の下位にある `ty` の値を 64 ビットに符号拡張かゼロ拡張する命令。
+/// スタック上の整数はすべてこの形に揃えておく。
+const fn extend_instruction(ty: &Type) -> Option<&'static str> {
+    match ty {
+        Type::BoolTyp | Type::UCharTyp => Some("  movzx eax, al"),
+        Type::CharTyp => Some("  movsx rax, al"),
+        Type::UShortTyp => Some("  movzx eax, ax"),
+        Type::ShortTyp => Some("  movsx rax, ax"),
+        Type::UIntTyp => Some("  mov eax, eax"),
+        Type::IntTyp => Some("  movsxd rax, eax"),
+        _ => None,
+    }
 }
 
 pub const fn round_up_as_multiple_of_8(num: usize) -> usize {
-    if num % 8 == 0 {
+    if num.is_multiple_of(8) {
         num
     } else {
         num + 8 - (num % 8)
@@ -0,0 +1,230 @@
+use std::collections::{hash_map, HashMap};
+
+use crate::{
+    error::{CompileError, CompileErrorKind},
+    initializer::complete_array_type,
+    lex::Span,
+    top_level::{TopLevel, TopLevelKind},
+    types::Type,
+};
+
+/// グローバル変数の型を集める。同名のグローバル変数や関数があればエラーにする。
+/// `extern` 宣言と初期化子のない定義 (仮定義) は、同じ型の定義や宣言と何度でも並べられる。
+/// 初期化子のある定義は 1 つまで。
+pub fn collect_globals(program: &[TopLevel]) -> Result<HashMap<String, Type>, Vec<CompileError>> {
+    let mut globals = HashMap::new();
+    let mut definition_spans = HashMap::new();
+    let mut initialized_spans = HashMap::new();
+    let mut errors = vec![];
+    for top_level in program {
+        if let TopLevelKind::GlobalVariable(name, ty, initializer, _) = &top_level.kind {
+            let ty = match initializer {
+                Some(initializer) => match complete_array_type(ty, initializer, &|_| false) {
+                    Ok(ty) => ty,
+                    Err(error) => {
+                        errors.push(error);
+                        continue;
+                    }
+                },
+                None => ty.clone(),
+            };
+            if !ty.is_complete() {
+                errors.push(CompileError::new(
+                    CompileErrorKind::Type,
+                    format!("variable {name} has incomplete type {ty}"),
+                    Some(top_level.span),
+                ));
+            } else if let (Some(_), Some(previous_span)) =
+                (initializer, initialized_spans.get(name))
+            {
+                errors.push(redefinition_error(name, top_level.span, *previous_span));
+            } else {
+                match globals.entry(name.clone()) {
+                    hash_map::Entry::Vacant(e) => {
+                        e.insert(ty);
+                        definition_spans.insert(name.clone(), top_level.span);
+                    }
+                    hash_map::Entry::Occupied(e) if *e.get() != ty => {
+                        errors.push(conflicting_types_error(
+                            name,
+                            top_level.span,
+                            definition_spans[name],
+                        ));
+                    }
+                    hash_map::Entry::Occupied(_) => {}
+                }
+                if initializer.is_some() {
+                    initialized_spans.insert(name.clone(), top_level.span);
+                }
+            }
+        }
+    }
+
+    for top_level in program {
+        if let TopLevelKind::ExternalVariableDeclaration(name, ty) = &top_level.kind {
+            match globals.entry(name.clone()) {
+                hash_map::Entry::Vacant(e) => {
+                    e.insert(ty.clone());
+                    definition_spans.insert(name.clone(), top_level.span);
+                }
+                hash_map::Entry::Occupied(e) if e.get() != ty => {
+                    errors.push(conflicting_types_error(
+                        name,
+                        top_level.span,
+                        definition_spans[name],
+                    ));
+                }
+                hash_map::Entry::Occupied(_) => {}
+            }
+        }
+    }
+
+    for top_level in program {
+        if let TopLevelKind::FunctionDefinition(name, _, _, _, _, _)
+        | TopLevelKind::ExternalFunctionDeclaration(name, _, _, _) = &top_level.kind
+        {
+            if let Some(global_span) = definition_spans.get(name) {
+                errors.push(redefinition_error(name, top_level.span, *global_span));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(globals)
+    } else {
+        Err(errors)
+    }
+}
+
+fn redefinition_error(name: &str, span: Span, previous_span: Span) -> CompileError {
+    CompileError::new(
+        CompileErrorKind::Type,
+        format!("{name} is already defined"),
+        Some(span),
+    )
+    .with_note("previous definition is here", previous_span)
+}
+
+fn conflicting_types_error(name: &str, span: Span, previous_span: Span) -> CompileError {
+    CompileError::new(
+        CompileErrorKind::Type,
+        format!("conflicting types for {name}"),
+        Some(span),
+    )
+    .with_note("previous declaration is here", previous_span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        expr::{Expr, ExprKind},
+        lex::SourcePosition,
+        top_level::{Initializer, Linkage},
+    };
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(SourcePosition(start), SourcePosition(end))
+    }
+
+    fn global(name: &str, ty: Type, start: usize, end: usize) -> TopLevel {
+        TopLevel::new(
+            TopLevelKind::GlobalVariable(name.to_string(), ty, None, Linkage::External),
+            span(start, end),
+        )
+    }
+
+    fn initialized_global(name: &str, start: usize, end: usize) -> TopLevel {
+        let initializer = Initializer::Expr(Expr::new(ExprKind::Num(1), span(end - 2, end - 1)));
+        TopLevel::new(
+            TopLevelKind::GlobalVariable(
+                name.to_string(),
+                Type::IntTyp,
+                Some(initializer),
+                Linkage::External,
+            ),
+            span(start, end),
+        )
+    }
+
+    #[test]
+    fn test_collect_globals() {
+        let program = vec![
+            global("a", Type::IntTyp, 0, 6),
+            global("b", Type::Array(Box::new(Type::IntTyp), 3), 7, 16),
+        ];
+        let globals = collect_globals(&program).unwrap();
+        assert_eq!(globals["a"], Type::IntTyp);
+        assert_eq!(globals["b"], Type::Array(Box::new(Type::IntTyp), 3));
+    }
+
+    #[test]
+    fn test_collect_globals_merges_extern_declarations() {
+        let external = |ty, start, end| {
+            TopLevel::new(
+                TopLevelKind::ExternalVariableDeclaration("a".to_string(), ty),
+                span(start, end),
+            )
+        };
+        let program = vec![
+            external(Type::IntTyp, 0, 13),
+            global("a", Type::IntTyp, 14, 20),
+            external(Type::IntTyp, 21, 34),
+            external(Type::LongTyp, 35, 49),
+        ];
+        let errors = collect_globals(&program).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, Some(span(35, 49)));
+        assert_eq!(errors[0].notes[0].span, Some(span(14, 20)));
+
+        let globals = collect_globals(&program[..3]).unwrap();
+        assert_eq!(globals["a"], Type::IntTyp);
+    }
+
+    #[test]
+    fn test_collect_globals_merges_tentative_definitions() {
+        let program = vec![
+            global("a", Type::IntTyp, 0, 6),
+            initialized_global("a", 7, 17),
+            global("a", Type::IntTyp, 18, 24),
+            global("a", Type::LongTyp, 25, 32),
+        ];
+        let errors = collect_globals(&program).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "conflicting types for a");
+        assert_eq!(errors[0].span, Some(span(25, 32)));
+        assert_eq!(errors[0].notes[0].span, Some(span(0, 6)));
+
+        let globals = collect_globals(&program[..3]).unwrap();
+        assert_eq!(globals["a"], Type::IntTyp);
+    }
+
+    #[test]
+    fn test_collect_globals_rejects_redefinition() {
+        let program = vec![
+            initialized_global("a", 0, 10),
+            TopLevel::new(
+                TopLevelKind::ExternalFunctionDeclaration(
+                    "a".to_string(),
+                    vec![],
+                    false,
+                    Type::IntTyp,
+                ),
+                span(7, 22),
+            ),
+            global("a", Type::IntTyp, 23, 29),
+            initialized_global("a", 30, 40),
+        ];
+        let errors = collect_globals(&program).unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.span, error.notes[0].span))
+                .collect::<Vec<_>>(),
+            vec![
+                (Some(span(30, 40)), Some(span(0, 10))),
+                (Some(span(7, 22)), Some(span(0, 10))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,459 @@
+use crate::{
+    error::{CompileError, CompileErrorKind, CompileResult},
+    expr::{Expr, ExprKind},
+    lex::Span,
+    token::StringLiteral,
+    top_level::{Designator, Initializer},
+    types::{StructKind, StructType, Type},
+};
+
+/// 初期化子が与える値 1 つ分。`offset` は変数の先頭からのバイト数。
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct InitialValue {
+    pub offset: usize,
+    pub ty: Type,
+    pub expr: Expr,
+}
+
+/// 初期化子を、値を与える部分オブジェクトごとの列に平らにする。
+/// 同じ位置に複数の値があれば後のものが優先される。値を与えられない部分は 0 で埋める。
+/// 波括弧を省いた要素は、並びの続きの値で初期化する。構造体の要素を式で初期化するときは、
+/// `is_struct_value` が真ならその値のコピー、偽なら波括弧の省略とみなす。
+pub fn flatten_initializer(
+    ty: &Type,
+    initializer: &Initializer,
+    is_struct_value: &dyn Fn(&Expr) -> bool,
+) -> CompileResult<Vec<InitialValue>> {
+    let mut flattener = Flattener {
+        values: vec![],
+        is_struct_value,
+    };
+    flattener.flatten(ty, initializer, 0)?;
+    Ok(flattener.values)
+}
+
+/// 要素数を省略した配列の型を、初期化子の要素数から決める。ほかの型はそのまま返す。
+pub fn complete_array_type(
+    ty: &Type,
+    initializer: &Initializer,
+    is_struct_value: &dyn Fn(&Expr) -> bool,
+) -> CompileResult<Type> {
+    let Type::IncompleteArray(element_type) = ty else {
+        return Ok(ty.clone());
+    };
+    let length = match array_string(element_type, initializer) {
+        Some((literal, _)) => literal.element_count() + 1,
+        None => match initializer {
+            Initializer::List(initializers, _) => {
+                let mut flattener = Flattener {
+                    values: vec![],
+                    is_struct_value,
+                };
+                flattener.flatten_array_list(
+                    element_type,
+                    usize::MAX,
+                    &mut initializers.as_slice(),
+                    0,
+                    true,
+                )?
+            }
+            _ => {
+                return Err(error(
+                    "array must be initialized with a brace-enclosed list",
+                    initializer.span(),
+                ))
+            }
+        },
+    };
+    Ok(Type::Array(element_type.clone(), length))
+}
+
+struct Flattener<'a> {
+    values: Vec<InitialValue>,
+    is_struct_value: &'a dyn Fn(&Expr) -> bool,
+}
+
+impl Flattener<'_> {
+    fn flatten(
+        &mut self,
+        ty: &Type,
+        initializer: &Initializer,
+        offset: usize,
+    ) -> CompileResult<()> {
+        if let Some((element_type, length, (literal, span))) = match ty.unqualified() {
+            Type::Array(element_type, length) => {
+                array_string(element_type, initializer).map(|string| (element_type, length, string))
+            }
+            _ => None,
+        } {
+            // 配列の長さちょうどの文字列なら終端の NUL は省く。
+            if literal.element_count() > *length {
+                return Err(error(
+                    format!("string literal is too long for an array of length {length}"),
+                    span,
+                ));
+            }
+            let element_type = element_type.unqualified();
+            for (index, value) in literal.values().into_iter().enumerate() {
+                #[allow(clippy::cast_possible_wrap)]
+                self.values.push(InitialValue {
+                    offset: offset + index * element_type.get_size(),
+                    ty: element_type.clone(),
+                    expr: Expr::new(ExprKind::Num(value as i32), span),
+                });
+            }
+            return Ok(());
+        }
+        match (ty.unqualified(), initializer) {
+            (_, Initializer::Designated(_, _, span)) => {
+                Err(error(format!("designator cannot be used for {ty}"), *span))
+            }
+            (Type::Array(element_type, length), Initializer::List(initializers, _)) => self
+                .flatten_array_list(
+                    element_type,
+                    *length,
+                    &mut initializers.as_slice(),
+                    offset,
+                    true,
+                )
+                .map(|_| ()),
+            (Type::Array(_, _), Initializer::Expr(expr)) => Err(error(
+                "array must be initialized with a brace-enclosed list",
+                expr.span,
+            )),
+            (Type::Struct(struct_type), Initializer::List(initializers, _)) => self
+                .flatten_struct_list(ty, struct_type, &mut initializers.as_slice(), offset, true),
+            (_, Initializer::List(initializers, span)) => match &initializers[..] {
+                [initializer] => self.flatten(ty, initializer, offset),
+                _ => Err(error(
+                    format!("{ty} must be initialized with exactly one value"),
+                    *span,
+                )),
+            },
+            // 構造体を式で初期化するのは同じ型の値のコピーになる。
+            (_, Initializer::Expr(expr)) => {
+                self.values.push(InitialValue {
+                    offset,
+                    ty: ty.unqualified().clone(),
+                    expr: expr.clone(),
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// 並びの先頭から要素 1 つ分の初期化子を取り出して平らにする。
+    /// 波括弧を省いた配列や構造体の要素は、並びから必要なだけの値を取る。
+    fn flatten_element(
+        &mut self,
+        ty: &Type,
+        initializers: &mut &[Initializer],
+        offset: usize,
+    ) -> CompileResult<()> {
+        let Some((initializer, rest)) = initializers.split_first() else {
+            return Ok(());
+        };
+        let elided = match (ty.unqualified(), initializer) {
+            (Type::Array(element_type, _), Initializer::Expr(_)) => {
+                array_string(element_type, initializer).is_none()
+            }
+            (Type::Struct(_), Initializer::Expr(expr)) => !(self.is_struct_value)(expr),
+            _ => false,
+        };
+        match ty.unqualified() {
+            Type::Array(element_type, length) if elided => self
+                .flatten_array_list(element_type, *length, initializers, offset, false)
+                .map(|_| ()),
+            Type::Struct(struct_type) if elided => {
+                self.flatten_struct_list(ty, struct_type, initializers, offset, false)
+            }
+            _ => {
+                *initializers = rest;
+                self.flatten(ty, initializer, offset)
+            }
+        }
+    }
+
+    /// 初期化した要素の数 (最後に初期化した要素の次の添字) を返す。
+    /// 波括弧を省いた並びは、要素を埋め終えるか指示子が現れたところで止まる。
+    fn flatten_array_list(
+        &mut self,
+        element_type: &Type,
+        length: usize,
+        initializers: &mut &[Initializer],
+        offset: usize,
+        braced: bool,
+    ) -> CompileResult<usize> {
+        let mut index = 0;
+        let mut count = 0;
+        while let Some((initializer, rest)) = initializers.split_first() {
+            let (designators, designated_initializer) = split_designators(initializer);
+            match designators.split_first() {
+                Some(_) if !braced => break,
+                Some((Designator::Index(designated, span), designators)) => {
+                    if *designated >= length {
+                        return Err(error(
+                            format!(
+                                "array index {designated} is out of range for an array of length {length}"
+                            ),
+                            *span,
+                        ));
+                    }
+                    index = *designated;
+                    *initializers = rest;
+                    let element_offset = offset + index * element_type.get_size();
+                    self.flatten_designated(
+                        element_type,
+                        designators,
+                        designated_initializer,
+                        element_offset,
+                    )?;
+                }
+                Some((Designator::Member(name, span), _)) => {
+                    return Err(error(
+                        format!("member designator .{name} used for an array"),
+                        *span,
+                    ));
+                }
+                None if index >= length && !braced => break,
+                None if index >= length => {
+                    return Err(error(
+                        format!("too many initializers for an array of length {length}"),
+                        initializer.span(),
+                    ));
+                }
+                None => {
+                    let element_offset = offset + index * element_type.get_size();
+                    self.flatten_element(element_type, initializers, element_offset)?;
+                }
+            }
+            index += 1;
+            count = count.max(index);
+        }
+        Ok(count)
+    }
+
+    fn flatten_struct_list(
+        &mut self,
+        ty: &Type,
+        struct_type: &StructType,
+        initializers: &mut &[Initializer],
+        offset: usize,
+        braced: bool,
+    ) -> CompileResult<()> {
+        let members = struct_type.members();
+        // 共用体は指示子がなければ最初のメンバだけを初期化する。
+        let positional_count = if struct_type.kind == StructKind::Union {
+            members.len().min(1)
+        } else {
+            members.len()
+        };
+        let mut index = 0;
+        while let Some((initializer, rest)) = initializers.split_first() {
+            let (designators, designated_initializer) = split_designators(initializer);
+            match designators.split_first() {
+                Some(_) if !braced => break,
+                Some((Designator::Member(name, span), designators)) => {
+                    index = members
+                        .iter()
+                        .position(|member| member.name == *name)
+                        .ok_or_else(|| error(format!("{ty} has no member named {name}"), *span))?;
+                    let member = &members[index];
+                    *initializers = rest;
+                    self.flatten_designated(
+                        &member.ty,
+                        designators,
+                        designated_initializer,
+                        offset + member.offset,
+                    )?;
+                }
+                Some((Designator::Index(_, span), _)) => {
+                    return Err(error(
+                        format!("array index designator used for {ty}"),
+                        *span,
+                    ));
+                }
+                None if index >= positional_count && !braced => break,
+                None if index >= positional_count => {
+                    return Err(error(
+                        format!("too many initializers for {ty}"),
+                        initializer.span(),
+                    ));
+                }
+                None => {
+                    let member = &members[index];
+                    self.flatten_element(&member.ty, initializers, offset + member.offset)?;
+                }
+            }
+            index += 1;
+        }
+        Ok(())
+    }
+
+    /// 残りの指示子は、選んだ要素やメンバの中をさらに指す。
+    fn flatten_designated(
+        &mut self,
+        ty: &Type,
+        designators: &[Designator],
+        initializer: &Initializer,
+        offset: usize,
+    ) -> CompileResult<()> {
+        if designators.is_empty() {
+            let mut initializers = std::slice::from_ref(initializer);
+            return self.flatten_element(ty, &mut initializers, offset);
+        }
+        let span = initializer.span();
+        let nested = Initializer::List(
+            vec![Initializer::Designated(
+                designators.to_vec(),
+                Box::new(initializer.clone()),
+                span,
+            )],
+            span,
+        );
+        self.flatten(ty, &nested, offset)
+    }
+}
+
+/// 配列を初期化する、要素の型の合う文字列リテラル。波括弧で囲んだものも同じに扱う。
+fn array_string<'a>(
+    element_type: &Type,
+    initializer: &'a Initializer,
+) -> Option<(&'a StringLiteral, Span)> {
+    match initializer {
+        Initializer::Expr(Expr {
+            kind: ExprKind::StringLiteral(literal),
+            span,
+        }) if *element_type.unqualified() == literal.encoding.element_type() => {
+            Some((literal, *span))
+        }
+        Initializer::List(initializers, _) => match &initializers[..] {
+            [initializer @ Initializer::Expr(_)] => array_string(element_type, initializer),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn split_designators(initializer: &Initializer) -> (&[Designator], &Initializer) {
+    match initializer {
+        Initializer::Designated(designators, initializer, _) => (designators, initializer),
+        _ => (&[], initializer),
+    }
+}
+
+fn error(message: impl Into<String>, span: Span) -> CompileError {
+    CompileError::new(CompileErrorKind::Type, message, Some(span))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lex::SourcePosition;
+
+    use super::*;
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(SourcePosition(start), SourcePosition(end))
+    }
+
+    fn num(n: i32) -> Initializer {
+        Initializer::Expr(Expr::new(ExprKind::Num(n), span(0, 0)))
+    }
+
+    fn offsets(values: &[InitialValue]) -> Vec<(usize, i32)> {
+        values
+            .iter()
+            .map(|value| match value.expr.kind {
+                ExprKind::Num(n) => (value.offset, n),
+                _ => panic!("unexpected initial value: {value:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_flatten_designated_array_initializer() {
+        let ty = Type::Array(Box::new(Type::IntTyp), 5);
+        let initializer = Initializer::List(
+            vec![
+                num(1),
+                Initializer::Designated(
+                    vec![Designator::Index(3, span(0, 0))],
+                    Box::new(num(4)),
+                    span(0, 0),
+                ),
+                num(5),
+                Initializer::Designated(
+                    vec![Designator::Index(0, span(0, 0))],
+                    Box::new(num(7)),
+                    span(0, 0),
+                ),
+            ],
+            span(0, 0),
+        );
+        let values = flatten_initializer(&ty, &initializer, &|_| false).unwrap();
+        assert_eq!(offsets(&values), vec![(0, 1), (12, 4), (16, 5), (0, 7)]);
+    }
+
+    #[test]
+    fn test_flatten_string_into_char_array() {
+        let ty = Type::Array(Box::new(Type::CharTyp), 3);
+        let string = |bytes: &[u8]| {
+            Initializer::Expr(Expr::new(
+                ExprKind::StringLiteral(StringLiteral::narrow(bytes.to_vec())),
+                span(4, 9),
+            ))
+        };
+        let values = flatten_initializer(&ty, &string(b"ab"), &|_| false).unwrap();
+        assert_eq!(offsets(&values), vec![(0, 97), (1, 98)]);
+
+        let error = flatten_initializer(&ty, &string(b"abcd"), &|_| false).unwrap_err();
+        assert_eq!(error.span, Some(span(4, 9)));
+    }
+
+    #[test]
+    fn test_flatten_rejects_out_of_range_designator() {
+        let ty = Type::Array(Box::new(Type::IntTyp), 2);
+        let initializer = Initializer::List(
+            vec![Initializer::Designated(
+                vec![Designator::Index(2, span(1, 4))],
+                Box::new(num(1)),
+                span(1, 8),
+            )],
+            span(0, 9),
+        );
+        let error = flatten_initializer(&ty, &initializer, &|_| false).unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::Type);
+        assert_eq!(error.span, Some(span(1, 4)));
+    }
+
+    #[test]
+    fn test_flatten_with_brace_elision() {
+        let ty = Type::Array(Box::new(Type::Array(Box::new(Type::IntTyp), 2)), 2);
+        let initializer = Initializer::List(vec![num(1), num(2), num(3)], span(0, 0));
+        let values = flatten_initializer(&ty, &initializer, &|_| false).unwrap();
+        assert_eq!(offsets(&values), vec![(0, 1), (4, 2), (8, 3)]);
+    }
+
+    #[test]
+    fn test_complete_array_type_from_initializer() {
+        let ty = Type::IncompleteArray(Box::new(Type::Array(Box::new(Type::IntTyp), 2)));
+        let initializer = Initializer::List(vec![num(1), num(2), num(3)], span(0, 0));
+        assert_eq!(
+            complete_array_type(&ty, &initializer, &|_| false),
+            Ok(Type::Array(
+                Box::new(Type::Array(Box::new(Type::IntTyp), 2)),
+                2
+            ))
+        );
+
+        let ty = Type::IncompleteArray(Box::new(Type::CharTyp));
+        let string = Initializer::Expr(Expr::new(
+            ExprKind::StringLiteral(StringLiteral::narrow(b"abc".to_vec())),
+            span(0, 5),
+        ));
+        assert_eq!(
+            complete_array_type(&ty, &string, &|_| false),
+            Ok(Type::Array(Box::new(Type::CharTyp), 4))
+        );
+    }
+}
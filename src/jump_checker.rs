@@ -0,0 +1,202 @@
+use std::collections::{hash_map, HashMap};
+
+use crate::{
+    error::{CompileError, CompileErrorKind, CompileResult},
+    lex::Span,
+    statement::{TypedStatement, TypedStatementKind},
+};
+
+/// `break`・`continue`・`case`・`default` が使える位置にあるかと、
+/// `goto` の飛び先のラベルが関数内にちょうど 1 つあるかを検査する。
+pub fn check_jumps(statements: &[TypedStatement]) -> CompileResult<()> {
+    let mut checker = JumpChecker::default();
+    checker.check_statements(statements)?;
+    for (label, span) in &checker.gotos {
+        if !checker.labels.contains_key(label) {
+            return Err(error(format!("label {label} used but not defined"), *span));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct JumpChecker {
+    /// 囲んでいるループの数。
+    loop_depth: usize,
+    /// 囲んでいる switch ごとの、`case` の値とその位置、`default` の位置。
+    /// 値は条件式の型に変換したもので比べる。
+    switches: Vec<(HashMap<i64, Span>, Option<Span>)>,
+    /// 囲んでいるループと switch の数。`break` はどちらの中でも使える。
+    breakable_depth: usize,
+    labels: HashMap<String, Span>,
+    gotos: Vec<(String, Span)>,
+}
+
+impl JumpChecker {
+    fn check_statements(&mut self, statements: &[TypedStatement]) -> CompileResult<()> {
+        for statement in statements {
+            self.check_statement(statement)?;
+        }
+        Ok(())
+    }
+
+    fn check_statement(&mut self, statement: &TypedStatement) -> CompileResult<()> {
+        match &statement.kind {
+            TypedStatementKind::Break if self.breakable_depth == 0 => Err(error(
+                "break statement not within loop or switch",
+                statement.span,
+            )),
+            TypedStatementKind::Continue if self.loop_depth == 0 => Err(error(
+                "continue statement not within a loop",
+                statement.span,
+            )),
+            TypedStatementKind::Expr(_)
+            | TypedStatementKind::Return(_)
+            | TypedStatementKind::VariableDeclaration(_)
+            | TypedStatementKind::Break
+            | TypedStatementKind::Continue
+            | TypedStatementKind::Empty => Ok(()),
+            TypedStatementKind::If(_, then) => self.check_statement(then),
+            TypedStatementKind::IfElse(_, then, els) => {
+                self.check_statement(then)?;
+                self.check_statement(els)
+            }
+            TypedStatementKind::While(_, body)
+            | TypedStatementKind::For(_, _, _, body)
+            | TypedStatementKind::DoWhile(body, _) => {
+                self.loop_depth += 1;
+                self.breakable_depth += 1;
+                let result = self.check_statement(body);
+                self.loop_depth -= 1;
+                self.breakable_depth -= 1;
+                result
+            }
+            TypedStatementKind::Switch(_, body) => {
+                self.switches.push((HashMap::new(), None));
+                self.breakable_depth += 1;
+                let result = self.check_statement(body);
+                self.switches.pop();
+                self.breakable_depth -= 1;
+                result
+            }
+            TypedStatementKind::Block(statements) => self.check_statements(statements),
+            TypedStatementKind::Case(value, body) => {
+                let Some((cases, _)) = self.switches.last_mut() else {
+                    return Err(error(
+                        "case label not within a switch statement",
+                        statement.span,
+                    ));
+                };
+                if let hash_map::Entry::Vacant(e) = cases.entry(*value) {
+                    e.insert(statement.span);
+                } else {
+                    return Err(
+                        error(format!("duplicate case value {value}"), statement.span)
+                            .with_note("previously used here", cases[value]),
+                    );
+                }
+                self.check_statement(body)
+            }
+            TypedStatementKind::Default(body) => {
+                let Some((_, default)) = self.switches.last_mut() else {
+                    return Err(error(
+                        "default label not within a switch statement",
+                        statement.span,
+                    ));
+                };
+                if let Some(previous) = default {
+                    return Err(
+                        error("multiple default labels in one switch", statement.span)
+                            .with_note("previous default is here", *previous),
+                    );
+                }
+                *default = Some(statement.span);
+                self.check_statement(body)
+            }
+            TypedStatementKind::Goto(label) => {
+                self.gotos.push((label.clone(), statement.span));
+                Ok(())
+            }
+            TypedStatementKind::Labeled(label, body) => {
+                if let Some(previous) = self.labels.get(label) {
+                    return Err(error(format!("duplicate label {label}"), statement.span)
+                        .with_note("previous definition is here", *previous));
+                }
+                self.labels.insert(label.clone(), statement.span);
+                self.check_statement(body)
+            }
+        }
+    }
+}
+
+fn error(message: impl Into<String>, span: Span) -> CompileError {
+    CompileError::new(CompileErrorKind::Type, message, Some(span))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        expr::{TypedExpr, TypedExprKind},
+        lex::SourcePosition,
+        types::Type,
+    };
+
+    use super::*;
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(SourcePosition(start), SourcePosition(end))
+    }
+
+    fn statement(kind: TypedStatementKind, start: usize, end: usize) -> TypedStatement {
+        TypedStatement::new(kind, span(start, end))
+    }
+
+    #[test]
+    fn test_check_jumps_accepts_nested_jumps() {
+        let empty = || Box::new(statement(TypedStatementKind::Empty, 0, 0));
+        let body = statement(
+            TypedStatementKind::Block(vec![
+                statement(TypedStatementKind::Case(1, empty()), 0, 0),
+                statement(TypedStatementKind::Default(empty()), 0, 0),
+                statement(TypedStatementKind::Break, 0, 0),
+            ]),
+            0,
+            0,
+        );
+        let statements = vec![
+            statement(TypedStatementKind::Goto("end".to_string()), 0, 0),
+            statement(
+                TypedStatementKind::Switch(
+                    Box::new(TypedExpr {
+                        kind: TypedExprKind::IntNum(1),
+                        ty: Type::IntTyp,
+                        span: span(0, 0),
+                    }),
+                    Box::new(body),
+                ),
+                0,
+                0,
+            ),
+            statement(
+                TypedStatementKind::Labeled("end".to_string(), empty()),
+                0,
+                0,
+            ),
+        ];
+        assert_eq!(check_jumps(&statements), Ok(()));
+    }
+
+    #[test]
+    fn test_check_jumps_rejects_misplaced_jumps() {
+        let break_statement = vec![statement(TypedStatementKind::Break, 3, 9)];
+        assert_eq!(
+            check_jumps(&break_statement).unwrap_err().span,
+            Some(span(3, 9))
+        );
+
+        let goto_statement = vec![statement(TypedStatementKind::Goto("l".to_string()), 4, 11)];
+        let error = check_jumps(&goto_statement).unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::Type);
+        assert_eq!(error.span, Some(span(4, 11)));
+    }
+}
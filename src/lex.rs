@@ -1,15 +1,59 @@
-use crate::token::Token;
+use crate::{
+    error::{CompileError, CompileErrorKind},
+    pp_token::munch_pp_number,
+    token::{FloatLiteral, IntegerLiteral, StringLiteral, Token},
+};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct SourcePosition(pub usize);
 
-pub type PositionedToken = (Token, SourcePosition);
+/// `start` から `end` の直前までのソース上の範囲。
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub start: SourcePosition,
+    pub end: SourcePosition,
+}
+
+impl Span {
+    #[must_use]
+    pub const fn new(start: SourcePosition, end: SourcePosition) -> Self {
+        Self { start, end }
+    }
 
-static TOKEN_MAP: [(&str, Token); 20] = [
+    /// `self` の先頭から `other` の末尾までを覆う範囲を返す。
+    #[must_use]
+    pub const fn to(self, other: Self) -> Self {
+        Self::new(self.start, other.end)
+    }
+}
+
+pub type PositionedToken = (Token, Span);
+
+static TOKEN_MAP: [(&str, Token); 50] = [
+    ("->", Token::Arrow),
+    // ダイグラフ。`%:` は前処理指令にしか現れない。
+    ("<:", Token::LBracket),
+    (":>", Token::RBracket),
+    ("<%", Token::LBrace),
+    ("%>", Token::RBrace),
+    ("...", Token::Ellipsis),
+    ("<<=", Token::LeftShiftAssign),
+    (">>=", Token::RightShiftAssign),
+    ("++", Token::Increment),
+    ("--", Token::Decrement),
+    ("+=", Token::PlusAssign),
+    ("-=", Token::MinusAssign),
+    ("*=", Token::AsteriskAssign),
+    ("/=", Token::SlashAssign),
+    ("%=", Token::PercentAssign),
+    ("&=", Token::AmpersandAssign),
+    ("|=", Token::PipeAssign),
+    ("^=", Token::CaretAssign),
     ("+", Token::Plus),
     ("-", Token::Minus),
     ("*", Token::Asterisk),
     ("/", Token::Slash),
+    ("%", Token::Percent),
     ("(", Token::LParen),
     (")", Token::RParen),
     ("{", Token::LBrace),
@@ -19,55 +63,177 @@ static TOKEN_MAP: [(&str, Token); 20] = [
     (",", Token::Comma),
     ("==", Token::Equality),
     ("!=", Token::Inequality),
+    ("<<", Token::LeftShift),
+    (">>", Token::RightShift),
     ("<=", Token::LessThanOrEqual),
     ("<", Token::LessThan),
     (">=", Token::GreaterThanOrEqual),
     (">", Token::GreaterThan),
     (";", Token::Semicolon),
+    ("?", Token::Question),
+    (":", Token::Colon),
     ("=", Token::Assign),
+    ("&&", Token::LogicalAnd),
     ("&", Token::Ampersand),
+    ("||", Token::LogicalOr),
+    ("|", Token::Pipe),
+    ("^", Token::Caret),
+    ("!", Token::Exclamation),
+    ("~", Token::Tilde),
+    (".", Token::Dot),
 ];
 
-static KEYWORDS: [(&str, Token); 8] = [
+static KEYWORDS: [(&str, Token); 44] = [
     ("if", Token::If),
     ("else", Token::Else),
     ("while", Token::While),
     ("for", Token::For),
+    ("do", Token::Do),
+    ("break", Token::Break),
+    ("continue", Token::Continue),
+    ("switch", Token::Switch),
+    ("case", Token::Case),
+    ("default", Token::Default),
+    ("goto", Token::Goto),
     ("return", Token::Return),
     ("int", Token::Int),
+    ("char", Token::Char),
+    ("short", Token::Short),
+    ("long", Token::Long),
+    ("signed", Token::Signed),
+    ("unsigned", Token::Unsigned),
+    ("_Bool", Token::Bool),
+    ("float", Token::Float),
+    ("double", Token::Double),
+    ("void", Token::Void),
+    ("struct", Token::Struct),
+    ("union", Token::Union),
+    ("enum", Token::Enum),
     ("extern", Token::Extern),
+    ("static", Token::Static),
+    ("typedef", Token::Typedef),
+    ("const", Token::Const),
+    ("volatile", Token::Volatile),
     ("sizeof", Token::Sizeof),
+    ("_Alignof", Token::Alignof),
+    ("auto", Token::Auto),
+    ("register", Token::Register),
+    ("inline", Token::Inline),
+    ("restrict", Token::Restrict),
+    ("_Noreturn", Token::Noreturn),
+    ("_Alignas", Token::Alignas),
+    ("_Atomic", Token::Atomic),
+    ("_Generic", Token::Generic),
+    ("_Static_assert", Token::StaticAssert),
+    ("_Thread_local", Token::ThreadLocal),
+    ("_Complex", Token::Complex),
+    ("_Imaginary", Token::Imaginary),
 ];
 
-pub fn tokenize(input: &[char]) -> Vec<PositionedToken> {
+/// 位置は文字単位の `SourcePosition` で持ち、行と列はエラーの表示時に `SourceFile` で求める。
+/// 不正な字句があっても読み進め、見つけたエラーをすべて返す。
+/// コメントは前処理で空白に置き換えてあるものとして扱う。
+pub fn tokenize(input: &[char]) -> Result<Vec<PositionedToken>, Vec<CompileError>> {
     let mut ans: Vec<PositionedToken> = vec![];
+    let mut errors = vec![];
     let mut pos = SourcePosition(0);
 
     while !input[pos.0..].is_empty() {
-        if let Some((length, token)) = TOKEN_MAP
+        if matches!(&input[pos.0..], ['#', ..] | ['%', ':', ..]) && is_line_start(input, pos.0) {
+            pos.0 += directive_length(&input[pos.0..]);
+        } else if input[pos.0..].starts_with(&['\\', '\n']) {
+            pos.0 += 2;
+        } else if let ['0'..='9', ..] | ['.', '0'..='9', ..] = &input[pos.0..] {
+            let length = munch_pp_number(&input[pos.0..]);
+            match munch_number(&input[pos.0..pos.0 + length]) {
+                Ok(token) => ans.push((token, span_of(pos, length))),
+                Err(message) => errors.push(CompileError::new(
+                    CompileErrorKind::Lex,
+                    message,
+                    Some(span_of(pos, length)),
+                )),
+            }
+            pos.0 += length;
+        } else if let Some((length, token)) = TOKEN_MAP
             .iter()
             .find(|(key, _)| input[pos.0..].starts_with(&key.chars().collect::<Vec<char>>()))
             .map(|(key, token)| (key.len(), token))
         {
-            ans.push((token.clone(), pos));
-            pos.0 += length;
-        } else if input[pos.0].is_ascii_digit() {
-            let (Some(num), length) = munch_int(&input[pos.0..]) else {
-                panic!("invalid number: {}", input[0]);
-            };
-
-            ans.push((Token::Num(num), pos));
+            ans.push((token.clone(), span_of(pos, length)));
             pos.0 += length;
+        } else if let Some(prefix_length) = literal_prefix_length(&input[pos.0..]) {
+            let prefix = &input[pos.0..pos.0 + prefix_length];
+            let rest = &input[pos.0 + prefix_length..];
+            let literal = match (prefix, rest[0]) {
+                (_, '"') => munch_string_literal(prefix, rest)
+                    .map(|(literal, length)| (Token::StringLiteral(literal), length)),
+                ([], _) => munch_char_literal(rest)
+                    .map(|(num, length)| (Token::Num(IntegerLiteral::int(num)), length)),
+                _ => munch_wide_char_literal(prefix[0], rest)
+                    .map(|(literal, length)| (Token::Num(literal), length)),
+            }
+            .map(|(token, length)| (token, prefix_length + length))
+            .map_err(|(message, length)| (message, prefix_length + length));
+            match literal {
+                Ok((token, length)) => {
+                    ans.push((token, span_of(pos, length)));
+                    pos.0 += length;
+                }
+                Err((message, length)) => {
+                    errors.push(CompileError::new(
+                        CompileErrorKind::Lex,
+                        message,
+                        Some(span_of(pos, length)),
+                    ));
+                    pos.0 += length;
+                }
+            }
         } else if let Some((token, length)) = try_lex_keyword_or_identifier(&input[pos.0..]) {
-            ans.push((token, pos));
+            ans.push((token, span_of(pos, length)));
             pos.0 += length;
         } else if input[pos.0].is_ascii_whitespace() {
             pos.0 += 1;
         } else {
-            panic!("invalid character: at {}", input[pos.0]);
+            errors.push(CompileError::new(
+                CompileErrorKind::Lex,
+                format!("invalid character: {}", input[pos.0]),
+                Some(span_of(pos, 1)),
+            ));
+            pos.0 += 1;
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(ans)
+    } else {
+        Err(errors)
+    }
+}
+
+/// `position` より前に、同じ行に空白以外の文字がなければ真。
+fn is_line_start(input: &[char], position: usize) -> bool {
+    input[..position]
+        .iter()
+        .rev()
+        .take_while(|c| **c != '\n')
+        .all(char::is_ascii_whitespace)
+}
+
+/// 前処理指令の行は前処理で処理済みなので、`\` による行の継続も含めて読み飛ばす。
+fn directive_length(input: &[char]) -> usize {
+    let mut length = 0;
+    while let Some(c) = input.get(length) {
+        match (c, input.get(length + 1)) {
+            ('\\', Some('\n')) => length += 2,
+            ('\n', _) => break,
+            _ => length += 1,
         }
     }
-    ans
+    length
+}
+
+const fn span_of(start: SourcePosition, length: usize) -> Span {
+    Span::new(start, SourcePosition(start.0 + length))
 }
 
 fn try_lex_keyword_or_identifier(input: &[char]) -> Option<(Token, usize)> {
@@ -82,29 +248,375 @@ fn try_lex_keyword_or_identifier(input: &[char]) -> Option<(Token, usize)> {
     }
 }
 
-const fn munch_int(mut input: &[char]) -> (Option<i32>, usize) {
-    let mut char_count = 0;
+/// 前処理数 (`pp-number`) として切り出した `input` 全体を 1 つの数として読む。
+/// `1e` や `12abc` のように数として読み切れなければエラーにする。
+fn munch_number(input: &[char]) -> Result<Token, String> {
+    let is_hex = matches!(input, ['0', 'x' | 'X', ..]);
+    let text = input.iter().collect::<String>();
+    let integer_digits = input.iter().take_while(|c| c.is_ascii_digit()).count();
+    let is_float = if is_hex {
+        input.iter().any(|c| matches!(c, '.' | 'p' | 'P'))
+    } else {
+        matches!(input.get(integer_digits), Some('.' | 'e' | 'E'))
+    };
+    if is_float {
+        let float = if is_hex {
+            munch_hex_float(&input[2..]).map(|(literal, length)| (literal, length + 2))
+        } else {
+            munch_float(input)
+        };
+        return match float {
+            Some((literal, length)) if length == input.len() => Ok(Token::FloatNum(literal)),
+            Some((_, length)) => Err(format!(
+                "invalid suffix '{}' on floating constant",
+                input[length..].iter().collect::<String>()
+            )),
+            None => Err(format!("invalid floating constant {text}")),
+        };
+    }
+    munch_int(input).map(Token::Num)
+}
 
-    if let ['0'..='9', ..] = input {
-        let mut ans = 0;
-        while let [digit @ '0'..='9', rest @ ..] = input {
-            ans = ans * 10 + (*digit as i32) - ('0' as i32);
-            input = rest;
-            char_count += 1;
+/// `0x1F`、`0b101`、`017`、`10UL` のような整数定数を読む。
+/// 型は C11 と同じく、接尾辞の許す型のうち値が収まる最初のものにする。
+/// 10 進数の定数は接尾辞に `u` がなければ符号付きの型にしかならない。
+fn munch_int(input: &[char]) -> Result<IntegerLiteral, String> {
+    let (radix, prefix_length, base_name) = match input {
+        ['0', 'x' | 'X', ..] => (16, 2, "hexadecimal"),
+        ['0', 'b' | 'B', ..] => (2, 2, "binary"),
+        ['0', ..] => (8, 1, "octal"),
+        _ => (10, 0, "decimal"),
+    };
+    let digits = input[prefix_length..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit() || (radix == 16 && c.is_ascii_hexdigit()))
+        .collect::<Vec<_>>();
+    let suffix = input[prefix_length + digits.len()..]
+        .iter()
+        .collect::<String>();
+    if digits.is_empty() && radix != 8 {
+        return Err(format!(
+            "invalid suffix '{}' on integer constant",
+            input[1..].iter().collect::<String>()
+        ));
+    }
+
+    let (is_unsigned, long_suffix) = suffix
+        .strip_prefix(['u', 'U'])
+        .or_else(|| suffix.strip_suffix(['u', 'U']))
+        .map_or((false, suffix.as_str()), |rest| (true, rest));
+    let is_long = match long_suffix {
+        "" => false,
+        "l" | "L" | "ll" | "LL" => true,
+        _ => return Err(format!("invalid suffix '{suffix}' on integer constant")),
+    };
+
+    let mut value = 0_u64;
+    for digit in digits {
+        let digit = digit
+            .to_digit(radix)
+            .ok_or_else(|| format!("invalid digit '{digit}' in {base_name} constant"))?;
+        value = value
+            .checked_mul(u64::from(radix))
+            .and_then(|value| value.checked_add(u64::from(digit)))
+            .ok_or_else(|| "integer constant is too large".to_string())?;
+    }
+
+    let is_decimal = radix == 10;
+    let fits_int = i32::try_from(value).is_ok();
+    let fits_unsigned_int = u32::try_from(value).is_ok();
+    let fits_long = i64::try_from(value).is_ok();
+    let (is_unsigned, is_long) = match (is_unsigned, is_long) {
+        (false, false) if fits_int => (false, false),
+        (false, false) if !is_decimal && fits_unsigned_int => (true, false),
+        (true, false) if fits_unsigned_int => (true, false),
+        (false, _) if fits_long => (false, true),
+        (false, _) if is_decimal => {
+            return Err("integer constant is too large for its type".to_string());
         }
+        _ => (true, true),
+    };
+    Ok(IntegerLiteral {
+        value,
+        is_unsigned,
+        is_long,
+    })
+}
 
-        (Some(ans), char_count)
-    } else {
-        (None, 0)
+/// `1.5`、`.5`、`1e-3`、`2.0f` のような浮動小数点数リテラルを読み、値と読んだ文字数を返す。
+/// 小数点も指数部もなければ整数リテラルなので `None` を返す。
+fn munch_float(input: &[char]) -> Option<(FloatLiteral, usize)> {
+    let digits = |from: usize| {
+        input.get(from..).map_or(0, |rest| {
+            rest.iter().take_while(|c| c.is_ascii_digit()).count()
+        })
+    };
+
+    let integer_digits = digits(0);
+    let mut length = integer_digits;
+    let mut has_point = false;
+    if input.get(length) == Some(&'.') {
+        let fraction_digits = digits(length + 1);
+        if integer_digits == 0 && fraction_digits == 0 {
+            return None;
+        }
+        has_point = true;
+        length += 1 + fraction_digits;
+    }
+    if length == 0 {
+        return None;
+    }
+
+    let mut has_exponent = false;
+    if let Some('e' | 'E') = input.get(length) {
+        let mut exponent = length + 1;
+        if let Some('+' | '-') = input.get(exponent) {
+            exponent += 1;
+        }
+        let exponent_digits = digits(exponent);
+        if exponent_digits > 0 {
+            has_exponent = true;
+            length = exponent + exponent_digits;
+        }
+    }
+    if !has_point && !has_exponent {
+        return None;
+    }
+
+    let value = input[..length]
+        .iter()
+        .collect::<String>()
+        .parse::<f64>()
+        .ok()?;
+    match input.get(length) {
+        Some('f' | 'F') => Some((FloatLiteral::new(value, true), length + 1)),
+        Some('l' | 'L') => Some((FloatLiteral::new(value, false), length + 1)),
+        _ => Some((FloatLiteral::new(value, false), length)),
+    }
+}
+
+/// `0x` の後の `1.8p3` のような 16 進の浮動小数点数リテラルを読み、値と読んだ文字数を返す。
+/// 2 進の指数部は省けない。
+fn munch_hex_float(input: &[char]) -> Option<(FloatLiteral, usize)> {
+    let hex_digits = |from: usize| {
+        input.get(from..).map_or(0, |rest| {
+            rest.iter().take_while(|c| c.is_ascii_hexdigit()).count()
+        })
+    };
+
+    let integer_digits = hex_digits(0);
+    let mut length = integer_digits;
+    let mut fraction_digits = 0;
+    if input.get(length) == Some(&'.') {
+        fraction_digits = hex_digits(length + 1);
+        length += 1 + fraction_digits;
+    }
+    if integer_digits == 0 && fraction_digits == 0 {
+        return None;
+    }
+    if !matches!(input.get(length), Some('p' | 'P')) {
+        return None;
+    }
+    let mut exponent_start = length + 1;
+    let negative = match input.get(exponent_start) {
+        Some('-') => {
+            exponent_start += 1;
+            true
+        }
+        Some('+') => {
+            exponent_start += 1;
+            false
+        }
+        _ => false,
+    };
+    let exponent_digits = input
+        .get(exponent_start..)?
+        .iter()
+        .take_while(|c| c.is_ascii_digit())
+        .count();
+    if exponent_digits == 0 {
+        return None;
+    }
+    length = exponent_start + exponent_digits;
+
+    let mantissa = input[..integer_digits]
+        .iter()
+        .chain(&input[integer_digits + 1..integer_digits + 1 + fraction_digits])
+        .filter_map(|c| c.to_digit(16))
+        .fold(0.0, |mantissa: f64, digit| {
+            mantissa.mul_add(16.0, f64::from(digit))
+        });
+    let exponent = input[exponent_start..length]
+        .iter()
+        .collect::<String>()
+        .parse::<i32>()
+        .unwrap_or(i32::MAX);
+    let exponent = if negative { -exponent } else { exponent };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    let exponent = exponent.saturating_sub(4 * fraction_digits as i32);
+    // 2 の冪を 2 回に分けて掛け、途中でのオーバーフローとアンダーフローを避ける。
+    let value = mantissa * 2_f64.powi(exponent / 2) * 2_f64.powi(exponent - exponent / 2);
+    match input.get(length) {
+        Some('f' | 'F') => Some((FloatLiteral::new(value, true), length + 1)),
+        Some('l' | 'L') => Some((FloatLiteral::new(value, false), length + 1)),
+        _ => Some((FloatLiteral::new(value, false), length)),
+    }
+}
+
+/// 文字列リテラルか文字定数が始まっていれば、接頭辞 (`L`、`u`、`U`、`u8`) の文字数を返す。
+/// `u8` は文字列リテラルにだけ付けられる。
+fn literal_prefix_length(input: &[char]) -> Option<usize> {
+    match input {
+        ['"' | '\'', ..] => Some(0),
+        ['L' | 'u' | 'U', '"' | '\'', ..] => Some(1),
+        ['u', '8', '"', ..] => Some(2),
+        _ => None,
     }
 }
 
+/// `"` から始まる文字列リテラルを読み、接頭辞 `prefix` の型の値と読んだ文字数を返す。
+fn munch_string_literal(
+    prefix: &[char],
+    input: &[char],
+) -> Result<(StringLiteral, usize), (String, usize)> {
+    let (bytes, length) = munch_quoted(input, '"')?;
+    let literal = match prefix {
+        [prefix @ ('L' | 'u' | 'U')] => StringLiteral::wide(*prefix, &bytes),
+        _ => StringLiteral::narrow(bytes),
+    };
+    Ok((literal, length))
+}
+
+/// `'` から始まる文字定数を読み、(`char` を符号拡張した) 値と読んだ文字数を返す。
+fn munch_char_literal(input: &[char]) -> Result<(i32, usize), (String, usize)> {
+    let (bytes, length) = munch_quoted(input, '\'')?;
+    match bytes[..] {
+        #[allow(clippy::cast_possible_wrap)]
+        [byte] => Ok((i32::from(byte as i8), length)),
+        [] => Err(("empty character constant".to_string(), length)),
+        _ => Err((
+            "multi-character character constant is not supported".to_string(),
+            length,
+        )),
+    }
+}
+
+/// `L'a'` のような接頭辞つきの文字定数を読み、値と `'` からの文字数を返す。
+/// `wchar_t` と `char16_t` は `int` に、`char32_t` は `unsigned int` に収まる値として扱う。
+fn munch_wide_char_literal(
+    prefix: char,
+    input: &[char],
+) -> Result<(IntegerLiteral, usize), (String, usize)> {
+    let (bytes, length) = munch_quoted(input, '\'')?;
+    let value = match bytes[..] {
+        [byte] => u32::from(byte),
+        [] => return Err(("empty character constant".to_string(), length)),
+        _ => match std::str::from_utf8(&bytes).map(|text| text.chars().collect::<Vec<_>>()) {
+            Ok(chars) if chars.len() == 1 => u32::from(chars[0]),
+            _ => {
+                return Err((
+                    "multi-character character constant is not supported".to_string(),
+                    length,
+                ))
+            }
+        },
+    };
+    if prefix == 'u' && value > 0xffff {
+        return Err((
+            "character too large for enclosing character literal type".to_string(),
+            length,
+        ));
+    }
+    Ok((
+        IntegerLiteral {
+            value: u64::from(value),
+            is_unsigned: prefix == 'U',
+            is_long: false,
+        },
+        length,
+    ))
+}
+
+/// `quote` で囲まれたリテラルを読み、エスケープを解釈したバイト列と読んだ文字数を返す。
+/// 不正なエスケープがあっても閉じ引用符まで読み進め、エラーと読んだ文字数を返す。
+fn munch_quoted(input: &[char], quote: char) -> Result<(Vec<u8>, usize), (String, usize)> {
+    let mut bytes = vec![];
+    let mut error = None;
+    let mut length = 1;
+    loop {
+        match &input[length..] {
+            [c, ..] if *c == quote => {
+                length += 1;
+                break;
+            }
+            [] | ['\n', ..] => {
+                return Err((format!("missing terminating {quote} character"), length));
+            }
+            ['\\', rest @ ..] => match munch_escape(rest) {
+                Ok((byte, escape_length)) => {
+                    bytes.push(byte);
+                    length += 1 + escape_length;
+                }
+                Err(message) => {
+                    error.get_or_insert(message);
+                    length += 1 + rest.len().min(1);
+                }
+            },
+            [c, ..] => {
+                bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                length += 1;
+            }
+        }
+    }
+
+    error.map_or(Ok((bytes, length)), |message| Err((message, length)))
+}
+
+/// `\` の直後から始まるエスケープシーケンスを読み、値と読んだ文字数を返す。
+fn munch_escape(input: &[char]) -> Result<(u8, usize), String> {
+    let (radix, max_digits, prefix_length) = match input {
+        ['n', ..] => return Ok((b'\n', 1)),
+        ['t', ..] => return Ok((b'\t', 1)),
+        ['r', ..] => return Ok((b'\r', 1)),
+        ['a', ..] => return Ok((0x07, 1)),
+        ['b', ..] => return Ok((0x08, 1)),
+        ['f', ..] => return Ok((0x0c, 1)),
+        ['v', ..] => return Ok((0x0b, 1)),
+        ['\\', ..] => return Ok((b'\\', 1)),
+        ['\'', ..] => return Ok((b'\'', 1)),
+        ['"', ..] => return Ok((b'"', 1)),
+        ['?', ..] => return Ok((b'?', 1)),
+        ['x', ..] => (16, usize::MAX, 1),
+        ['0'..='7', ..] => (8, 3, 0),
+        [c, ..] => return Err(format!("unknown escape sequence: \\{c}")),
+        [] => return Err("missing escape sequence".to_string()),
+    };
+
+    let digits = input[prefix_length..]
+        .iter()
+        .take(max_digits)
+        .map_while(|c| c.to_digit(radix))
+        .collect::<Vec<_>>();
+    if digits.is_empty() {
+        return Err("\\x used with no following hex digits".to_string());
+    }
+    let value = digits
+        .iter()
+        .try_fold(0_u8, |value, digit| {
+            value
+                .checked_mul(u8::try_from(radix).ok()?)?
+                .checked_add(u8::try_from(*digit).ok()?)
+        })
+        .ok_or_else(|| "escape sequence out of range".to_string())?;
+    Ok((value, prefix_length + digits.len()))
+}
+
 fn munch_identifier(mut input: &[char]) -> (Option<String>, usize) {
     let mut char_count = 0;
 
-    if let ['a'..='z', ..] = input {
+    if let ['a'..='z' | 'A'..='Z' | '_', ..] = input {
         let mut ans = String::new();
-        while let [alpha @ ('a'..='z' | '0'..='9' | '_'), rest @ ..] = input {
+        while let [alpha @ ('a'..='z' | 'A'..='Z' | '0'..='9' | '_'), rest @ ..] = input {
             ans.push(*alpha);
             input = rest;
             char_count += 1;
@@ -119,54 +631,379 @@ fn munch_identifier(mut input: &[char]) -> (Option<String>, usize) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::Type;
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(SourcePosition(start), SourcePosition(end))
+    }
+
+    #[test]
+    fn test_tokenize_skips_directive_lines() {
+        let input = "#define A \\\n 1\n  # if 0\na # b";
+        let tokens = tokenize(&input.chars().collect::<Vec<char>>());
+        assert_eq!(
+            tokens.map_err(|errors| errors[0].message.clone()),
+            Err("invalid character: #".to_string())
+        );
+        let input = "#define A \\\n 1\n  # if 0\na";
+        assert_eq!(
+            tokenize(&input.chars().collect::<Vec<char>>()).unwrap(),
+            vec![(Token::Identifier("a".to_string()), span(24, 25))]
+        );
+    }
 
     #[test]
     fn test_tokenize() {
         let input =
-            "+ - * / ( ) { } , == != <= < >= > ; = & if else while for return 12345abcedef12345 int extern sizeof []";
+            "+ - * / ( ) { } , == != <= < >= > ; = & if else while for return 12345 abcedef12345 int extern sizeof [] . -> struct union enum short long signed unsigned _Bool Abc_1";
+        let expected = vec![
+            (Token::Plus, span(0, 1)),
+            (Token::Minus, span(2, 3)),
+            (Token::Asterisk, span(4, 5)),
+            (Token::Slash, span(6, 7)),
+            (Token::LParen, span(8, 9)),
+            (Token::RParen, span(10, 11)),
+            (Token::LBrace, span(12, 13)),
+            (Token::RBrace, span(14, 15)),
+            (Token::Comma, span(16, 17)),
+            (Token::Equality, span(18, 20)),
+            (Token::Inequality, span(21, 23)),
+            (Token::LessThanOrEqual, span(24, 26)),
+            (Token::LessThan, span(27, 28)),
+            (Token::GreaterThanOrEqual, span(29, 31)),
+            (Token::GreaterThan, span(32, 33)),
+            (Token::Semicolon, span(34, 35)),
+            (Token::Assign, span(36, 37)),
+            (Token::Ampersand, span(38, 39)),
+            (Token::If, span(40, 42)),
+            (Token::Else, span(43, 47)),
+            (Token::While, span(48, 53)),
+            (Token::For, span(54, 57)),
+            (Token::Return, span(58, 64)),
+            (Token::Num(IntegerLiteral::int(12345)), span(65, 70)),
+            (Token::Identifier("abcedef12345".to_string()), span(71, 83)),
+            (Token::Int, span(84, 87)),
+            (Token::Extern, span(88, 94)),
+            (Token::Sizeof, span(95, 101)),
+            (Token::LBracket, span(102, 103)),
+            (Token::RBracket, span(103, 104)),
+            (Token::Dot, span(105, 106)),
+            (Token::Arrow, span(107, 109)),
+            (Token::Struct, span(110, 116)),
+            (Token::Union, span(117, 122)),
+            (Token::Enum, span(123, 127)),
+            (Token::Short, span(128, 133)),
+            (Token::Long, span(134, 138)),
+            (Token::Signed, span(139, 145)),
+            (Token::Unsigned, span(146, 154)),
+            (Token::Bool, span(155, 160)),
+            (Token::Identifier("Abc_1".to_string()), span(161, 166)),
+        ];
+        assert_eq!(
+            tokenize(&input.chars().collect::<Vec<char>>()),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_tokenize_reports_every_invalid_character() {
+        let input = "1 @ 2 $";
+        let errors = tokenize(&input.chars().collect::<Vec<char>>()).unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.kind, error.span))
+                .collect::<Vec<_>>(),
+            vec![
+                (CompileErrorKind::Lex, Some(span(2, 3))),
+                (CompileErrorKind::Lex, Some(span(6, 7))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_string_and_char_literals() {
+        let input = r#"char "a\n\t\\\"\101\x42\0" 'c' '\n' '\377'"#;
+        let expected = vec![
+            (Token::Char, span(0, 4)),
+            (
+                Token::StringLiteral(StringLiteral::narrow(b"a\n\t\\\"AB\0".to_vec())),
+                span(5, 26),
+            ),
+            (Token::Num(IntegerLiteral::int(99)), span(27, 30)),
+            (Token::Num(IntegerLiteral::int(10)), span(31, 35)),
+            (Token::Num(IntegerLiteral::int(-1)), span(36, 42)),
+        ];
+        assert_eq!(
+            tokenize(&input.chars().collect::<Vec<char>>()),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_tokenize_wide_char_literals() {
+        let input = "L'\\0' u'\u{3042}' U'\\xff' L";
         let expected = vec![
-            (Token::Plus, SourcePosition(0)),
-            (Token::Minus, SourcePosition(2)),
-            (Token::Asterisk, SourcePosition(4)),
-            (Token::Slash, SourcePosition(6)),
-            (Token::LParen, SourcePosition(8)),
-            (Token::RParen, SourcePosition(10)),
-            (Token::LBrace, SourcePosition(12)),
-            (Token::RBrace, SourcePosition(14)),
-            (Token::Comma, SourcePosition(16)),
-            (Token::Equality, SourcePosition(18)),
-            (Token::Inequality, SourcePosition(21)),
-            (Token::LessThanOrEqual, SourcePosition(24)),
-            (Token::LessThan, SourcePosition(27)),
-            (Token::GreaterThanOrEqual, SourcePosition(29)),
-            (Token::GreaterThan, SourcePosition(32)),
-            (Token::Semicolon, SourcePosition(34)),
-            (Token::Assign, SourcePosition(36)),
-            (Token::Ampersand, SourcePosition(38)),
-            (Token::If, SourcePosition(40)),
-            (Token::Else, SourcePosition(43)),
-            (Token::While, SourcePosition(48)),
-            (Token::For, SourcePosition(54)),
-            (Token::Return, SourcePosition(58)),
-            (Token::Num(12345), SourcePosition(65)),
+            (Token::Num(IntegerLiteral::int(0)), span(0, 5)),
+            (Token::Num(IntegerLiteral::int(0x3042)), span(6, 10)),
             (
-                Token::Identifier("abcedef12345".to_string()),
-                SourcePosition(70),
+                Token::Num(IntegerLiteral {
+                    value: 255,
+                    is_unsigned: true,
+                    is_long: false,
+                }),
+                span(11, 18),
             ),
-            (Token::Int, SourcePosition(83)),
-            (Token::Extern, SourcePosition(87)),
-            (Token::Sizeof, SourcePosition(94)),
-            (Token::LBracket, SourcePosition(101)),
-            (Token::RBracket, SourcePosition(102)),
+            (Token::Identifier("L".to_string()), span(19, 20)),
         ];
-        assert_eq!(tokenize(&input.chars().collect::<Vec<char>>()), expected);
+        assert_eq!(
+            tokenize(&input.chars().collect::<Vec<char>>()),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_tokenize_reports_invalid_literals() {
+        let input = "\"\\q\" '' 'ab' \"abc";
+        let errors = tokenize(&input.chars().collect::<Vec<char>>()).unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.message.as_str(), error.span))
+                .collect::<Vec<_>>(),
+            vec![
+                ("unknown escape sequence: \\q", Some(span(0, 4))),
+                ("empty character constant", Some(span(5, 7))),
+                (
+                    "multi-character character constant is not supported",
+                    Some(span(8, 12))
+                ),
+                ("missing terminating \" character", Some(span(13, 17))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_reports_invalid_numbers() {
+        let input = "12abc 1e 0x 08 1.5q 0x1p 99999999999999999999";
+        let errors = tokenize(&input.chars().collect::<Vec<char>>()).unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.message.as_str(), error.span))
+                .collect::<Vec<_>>(),
+            vec![
+                ("invalid suffix 'abc' on integer constant", Some(span(0, 5))),
+                ("invalid floating constant 1e", Some(span(6, 8))),
+                ("invalid suffix 'x' on integer constant", Some(span(9, 11))),
+                ("invalid digit '8' in octal constant", Some(span(12, 14))),
+                (
+                    "invalid suffix 'q' on floating constant",
+                    Some(span(15, 19))
+                ),
+                ("invalid floating constant 0x1p", Some(span(20, 24))),
+                ("integer constant is too large", Some(span(25, 45))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_line_continuations() {
+        let input = "a \\\n b\\\nc";
+        assert_eq!(
+            tokenize(&input.chars().collect::<Vec<char>>()).unwrap(),
+            vec![
+                (Token::Identifier("a".to_string()), span(0, 1)),
+                (Token::Identifier("b".to_string()), span(5, 6)),
+                (Token::Identifier("c".to_string()), span(8, 9)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_digraphs_prefixed_strings_and_hex_floats() {
+        let tokens = tokenize(
+            &"<: :> <% %> L\"a\" u8\"b\" u\"\u{1f600}\" U\"c\" 0x1p4 0x.8p1f 0xAp-2"
+                .chars()
+                .collect::<Vec<char>>(),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LBracket,
+                Token::RBracket,
+                Token::LBrace,
+                Token::RBrace,
+                Token::StringLiteral(StringLiteral::wide('L', b"a")),
+                Token::StringLiteral(StringLiteral::narrow(b"b".to_vec())),
+                Token::StringLiteral(StringLiteral::wide('u', "\u{1f600}".as_bytes())),
+                Token::StringLiteral(StringLiteral::wide('U', b"c")),
+                Token::FloatNum(FloatLiteral::new(16.0, false)),
+                Token::FloatNum(FloatLiteral::new(1.0, true)),
+                Token::FloatNum(FloatLiteral::new(2.5, false)),
+            ]
+        );
+        let Token::StringLiteral(emoji) = &tokens[6] else {
+            unreachable!()
+        };
+        assert_eq!(emoji.values(), vec![0xd83d, 0xde00]);
+        assert_eq!(emoji.ty(), Type::Array(Box::new(Type::UShortTyp), 3));
+    }
+
+    #[test]
+    fn test_tokenize_integer_suffixes_and_c11_tokens() {
+        let tokens = tokenize(
+            &"0xffu 10L ... restrict _Static_assert"
+                .chars()
+                .collect::<Vec<char>>(),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Num(IntegerLiteral {
+                    value: 255,
+                    is_unsigned: true,
+                    is_long: false
+                }),
+                Token::Num(IntegerLiteral {
+                    value: 10,
+                    is_unsigned: false,
+                    is_long: true
+                }),
+                Token::Ellipsis,
+                Token::Restrict,
+                Token::StaticAssert,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_float_literals() {
+        let input = "1.5 .25 1e-3 2.0f 3. 7 a.b 1.5L";
+        let expected = vec![
+            (Token::FloatNum(FloatLiteral::new(1.5, false)), span(0, 3)),
+            (Token::FloatNum(FloatLiteral::new(0.25, false)), span(4, 7)),
+            (Token::FloatNum(FloatLiteral::new(1e-3, false)), span(8, 12)),
+            (Token::FloatNum(FloatLiteral::new(2.0, true)), span(13, 17)),
+            (Token::FloatNum(FloatLiteral::new(3.0, false)), span(18, 20)),
+            (Token::Num(IntegerLiteral::int(7)), span(21, 22)),
+            (Token::Identifier("a".to_string()), span(23, 24)),
+            (Token::Dot, span(24, 25)),
+            (Token::Identifier("b".to_string()), span(25, 26)),
+            (Token::FloatNum(FloatLiteral::new(1.5, false)), span(27, 31)),
+        ];
+        assert_eq!(
+            tokenize(&input.chars().collect::<Vec<char>>()),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn test_tokenize_logical_and_bitwise_operators() {
+        let input = "a&&b&c||d|e^f<<g>>h%i!=!j~k<=l";
+        let tokens = tokenize(&input.chars().collect::<Vec<char>>())
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .filter(|token| !matches!(token, Token::Identifier(_)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LogicalAnd,
+                Token::Ampersand,
+                Token::LogicalOr,
+                Token::Pipe,
+                Token::Caret,
+                Token::LeftShift,
+                Token::RightShift,
+                Token::Percent,
+                Token::Inequality,
+                Token::Exclamation,
+                Token::Tilde,
+                Token::LessThanOrEqual,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_assignment_operators() {
+        let input = "+= -= *= /= %= <<= >>= &= |= ^= ++ -- ? : <<";
+        let tokens = tokenize(&input.chars().collect::<Vec<char>>())
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::PlusAssign,
+                Token::MinusAssign,
+                Token::AsteriskAssign,
+                Token::SlashAssign,
+                Token::PercentAssign,
+                Token::LeftShiftAssign,
+                Token::RightShiftAssign,
+                Token::AmpersandAssign,
+                Token::PipeAssign,
+                Token::CaretAssign,
+                Token::Increment,
+                Token::Decrement,
+                Token::Question,
+                Token::Colon,
+                Token::LeftShift,
+            ]
+        );
     }
 
     #[test]
     fn test_munch_int() {
-        let input = "12345";
-        let expected = (Some(12345), 5);
-        assert_eq!(munch_int(&input.chars().collect::<Vec<char>>()), expected);
+        let literal = |value, is_unsigned, is_long| IntegerLiteral {
+            value,
+            is_unsigned,
+            is_long,
+        };
+        let munch = |input: &str| munch_int(&input.chars().collect::<Vec<char>>());
+        assert_eq!(munch("12345"), Ok(literal(12345, false, false)));
+        assert_eq!(munch("0x1fU"), Ok(literal(31, true, false)));
+        assert_eq!(munch("0b101l"), Ok(literal(5, false, true)));
+        assert_eq!(munch("017LLu"), Ok(literal(15, true, true)));
+        assert_eq!(munch("0"), Ok(literal(0, false, false)));
+        assert_eq!(munch("2147483648"), Ok(literal(2_147_483_648, false, true)));
+        assert_eq!(munch("0x80000000"), Ok(literal(0x8000_0000, true, false)));
+        assert_eq!(munch("4294967296u"), Ok(literal(4_294_967_296, true, true)));
+        assert_eq!(
+            munch("0xffffffffffffffff"),
+            Ok(literal(u64::MAX, true, true))
+        );
+        assert_eq!(
+            munch("9223372036854775808"),
+            Err("integer constant is too large for its type".to_string())
+        );
+        assert_eq!(
+            munch("18446744073709551616"),
+            Err("integer constant is too large".to_string())
+        );
+        assert_eq!(
+            munch("09"),
+            Err("invalid digit '9' in octal constant".to_string())
+        );
+        assert_eq!(
+            munch("1lul"),
+            Err("invalid suffix 'lul' on integer constant".to_string())
+        );
+        assert_eq!(
+            munch("0x"),
+            Err("invalid suffix 'x' on integer constant".to_string())
+        );
     }
 
     #[test]
@@ -1,31 +1,108 @@
 #![deny(clippy::all, clippy::pedantic, clippy::nursery, clippy::cargo)]
 #![allow(clippy::module_name_repetitions)]
+mod constant;
+mod diagnostics;
+mod error;
 mod expr;
+mod fall_through;
 mod function_collector;
 mod generator;
+mod global_collector;
+mod initializer;
+mod jump_checker;
 mod lex;
+mod local_scope;
+mod macros;
 mod parser;
+mod pp_token;
+mod preprocess;
 mod statement;
 mod token;
 mod top_level;
 mod types;
 mod typing;
-mod variable_collector;
 
 use std::io::Write;
 
-pub fn process<W: Write>(raw_input: &str, mut write: W) {
+pub use diagnostics::{render, SourceFile};
+pub use error::{CompileError, CompileErrorKind, Note, NoteKind, Severity};
+pub use lex::{SourcePosition, Span};
+pub use preprocess::{preprocess, LineOrigin, MacroOption, PreprocessOptions, Preprocessed};
+
+/// `raw_input` を前処理してコンパイルし、アセンブリを `write` に書き出す。成功したときは警告を返す。
+/// 失敗したときも、それまでに見つかった警告はエラーと一緒に返す。
+/// `#include "..."` はカレントディレクトリから探す。
+///
+/// # Errors
+///
+/// 前処理でエラーがあればそれを返し、なければ [`compile`] のエラーを返す。
+pub fn process<W: Write>(
+    raw_input: &str,
+    write: W,
+) -> Result<Vec<CompileError>, Vec<CompileError>> {
+    let (preprocessed, errors) = preprocess(raw_input, "<stdin>", &PreprocessOptions::default());
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    compile(&preprocessed.text, write)
+}
+
+/// 前処理済みの `raw_input` をコンパイルし、アセンブリを `write` に書き出す。成功したときは警告を返す。
+///
+/// # Errors
+///
+/// 字句解析・構文解析・型検査・コード生成で見つかったエラーをすべて返す。
+/// 型検査で見つかった警告も、ソース上の位置の順にエラーの間に並べて返す。
+/// エラーがあった場合 `write` には何も書き込まない。
+pub fn compile<W: Write>(
+    raw_input: &str,
+    mut write: W,
+) -> Result<Vec<CompileError>, Vec<CompileError>> {
     let input = raw_input.chars().collect::<Vec<_>>();
-    let tokens = &lex::tokenize(&input);
+    let tokens = &lex::tokenize(&input)?;
     let mut parser = parser::Parser::new(tokens, raw_input);
-    let program = parser.munch_program();
-    let function_type_environment = function_collector::collect_functions(&program);
-    let typist = typing::Typist::new(function_type_environment);
-    let typed_program = typist.type_program(&program);
+    let program = parser.munch_program()?;
+    let function_type_environment = function_collector::collect_functions(&program)?;
+    let global_type_environment = global_collector::collect_globals(&program)?;
+    let mut typist = typing::Typist::new(function_type_environment, global_type_environment);
+    let typed_program = typist
+        .type_program(&program)
+        .map_err(|errors| merge_diagnostics(std::mem::take(&mut typist.warnings), errors))?;
 
-    let mut generator = generator::Program::new(typed_program, &mut write);
+    let mut assembly = Vec::new();
+    let mut generator = generator::Program::new(typed_program, &mut assembly);
+
+    generator
+        .gen()
+        .map_err(|error| merge_diagnostics(std::mem::take(&mut typist.warnings), vec![error]))?;
+
+    write.write_all(&assembly).map_err(|error| {
+        merge_diagnostics(
+            std::mem::take(&mut typist.warnings),
+            vec![CompileError::new(
+                CompileErrorKind::Io,
+                error.to_string(),
+                None,
+            )],
+        )
+    })?;
+    Ok(typist.warnings)
+}
 
-    generator.gen();
+/// どちらもソース上の位置の順に並んでいるとして、順序を保ったまま 1 つにまとめる。
+/// 位置のない診断は最後に置く。
+fn merge_diagnostics(warnings: Vec<CompileError>, errors: Vec<CompileError>) -> Vec<CompileError> {
+    let start = |diagnostic: &CompileError| diagnostic.span.map_or(usize::MAX, |span| span.start.0);
+    let mut merged = Vec::with_capacity(warnings.len() + errors.len());
+    let mut warnings = warnings.into_iter().peekable();
+    for error in errors {
+        while let Some(warning) = warnings.next_if(|warning| start(warning) <= start(&error)) {
+            merged.push(warning);
+        }
+        merged.push(error);
+    }
+    merged.extend(warnings);
+    merged
 }
 
 // この関数は integration_test でテストされる。
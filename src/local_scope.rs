@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::{CompileError, CompileErrorKind, CompileResult},
+    lex::Span,
+    statement::Storage,
+    types::Type,
+};
+
+/// 関数内のローカル変数のスコープの入れ子。内側のスコープほど後ろにある。
+///
+/// 同名の変数を区別するため、2 つ目以降の宣言には `名前.番号` の一意な名前を付ける。
+/// `.` は識別子に使えないので、ほかの変数の名前とは衝突しない。
+pub struct LocalScopes {
+    scopes: Vec<HashMap<String, LocalVariable>>,
+    /// 名前ごとの、関数内でこれまでに宣言された数。
+    declaration_counts: HashMap<String, usize>,
+}
+
+struct LocalVariable {
+    unique_name: String,
+    ty: Type,
+    storage: Storage,
+    span: Span,
+}
+
+impl LocalScopes {
+    /// 仮引数と関数本体の最も外側の宣言が入るスコープだけを持つ。
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            declaration_counts: HashMap::new(),
+        }
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// 現在のスコープに変数を宣言し、一意な名前を返す。外側のスコープの同名の変数は隠す。
+    /// `extern` 宣言は定義ではないので、不完全型でもよい。
+    pub fn declare(
+        &mut self,
+        name: &str,
+        ty: &Type,
+        storage: Storage,
+        span: Span,
+    ) -> CompileResult<String> {
+        if storage != Storage::Extern && !ty.is_complete() {
+            return Err(error(
+                format!("variable {name} has incomplete type {ty}"),
+                span,
+            ));
+        }
+        let scope = self.scopes.last_mut().unwrap();
+        if let Some(previous) = scope.get(name) {
+            return Err(error(format!("variable {name} is already defined"), span)
+                .with_note("previous definition is here", previous.span));
+        }
+
+        let count = self.declaration_counts.entry(name.to_string()).or_insert(0);
+        let unique_name = if *count == 0 {
+            name.to_string()
+        } else {
+            format!("{name}.{count}")
+        };
+        *count += 1;
+        scope.insert(
+            name.to_string(),
+            LocalVariable {
+                unique_name: unique_name.clone(),
+                ty: ty.clone(),
+                storage,
+                span,
+            },
+        );
+        Ok(unique_name)
+    }
+
+    /// 内側のスコープから順に探し、一意な名前と型と記憶域を返す。
+    pub fn lookup(&self, name: &str) -> Option<(&str, &Type, Storage)> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .map(|variable| {
+                (
+                    variable.unique_name.as_str(),
+                    &variable.ty,
+                    variable.storage,
+                )
+            })
+    }
+}
+
+fn error(message: String, span: Span) -> CompileError {
+    CompileError::new(CompileErrorKind::Type, message, Some(span))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lex::SourcePosition;
+
+    use super::*;
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(SourcePosition(start), SourcePosition(end))
+    }
+
+    #[test]
+    fn test_lookup_finds_innermost_declaration() {
+        let mut scopes = LocalScopes::new();
+        assert_eq!(
+            scopes.declare("a", &Type::IntTyp, Storage::Automatic, span(0, 6)),
+            Ok("a".to_string())
+        );
+        scopes.push_scope();
+        assert_eq!(
+            scopes.declare("a", &Type::CharTyp, Storage::Automatic, span(10, 17)),
+            Ok("a.1".to_string())
+        );
+        assert_eq!(
+            scopes.lookup("a"),
+            Some(("a.1", &Type::CharTyp, Storage::Automatic))
+        );
+        scopes.pop_scope();
+        assert_eq!(
+            scopes.lookup("a"),
+            Some(("a", &Type::IntTyp, Storage::Automatic))
+        );
+        assert_eq!(scopes.lookup("b"), None);
+    }
+
+    #[test]
+    fn test_sibling_scopes_get_distinct_names() {
+        let mut scopes = LocalScopes::new();
+        scopes.push_scope();
+        assert_eq!(
+            scopes.declare("i", &Type::IntTyp, Storage::Automatic, span(0, 6)),
+            Ok("i".to_string())
+        );
+        scopes.pop_scope();
+        assert_eq!(scopes.lookup("i"), None);
+        scopes.push_scope();
+        assert_eq!(
+            scopes.declare("i", &Type::IntTyp, Storage::Automatic, span(10, 16)),
+            Ok("i.1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_declare_rejects_redefinition_in_same_scope() {
+        let mut scopes = LocalScopes::new();
+        scopes
+            .declare("a", &Type::IntTyp, Storage::Automatic, span(0, 20))
+            .unwrap();
+        let error = scopes
+            .declare("a", &Type::IntTyp, Storage::Automatic, span(10, 16))
+            .unwrap_err();
+        assert_eq!(error.kind, CompileErrorKind::Type);
+        assert_eq!(error.span, Some(span(10, 16)));
+        assert_eq!(error.notes[0].span, Some(span(0, 20)));
+    }
+}
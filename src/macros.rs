@@ -0,0 +1,517 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    error::{CompileError, CompileErrorKind, CompileResult},
+    lex::{SourcePosition, Span},
+    pp_token::{paste, stringify, PpToken, PpTokenKind},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Macro {
+    Object(Vec<PpToken>),
+    /// 可変長引数のマクロでは、最後の仮引数の後ろの引数が `__VA_ARGS__` になる。
+    Function {
+        parameters: Vec<String>,
+        is_variadic: bool,
+        body: Vec<PpToken>,
+    },
+    /// `__FILE__`。展開する位置のファイル名になる。
+    File,
+    /// `__LINE__`。展開する位置の行番号になる。
+    Line,
+}
+
+const VA_ARGS: &str = "__VA_ARGS__";
+
+/// `#define` の後ろの字句から、マクロの名前と定義を読む。
+/// 名前の直後に空白なしで `(` が続けば関数形式のマクロになる。
+pub fn parse_definition(tokens: &[PpToken], position: usize) -> CompileResult<(String, Macro)> {
+    let Some((name, rest)) = tokens.split_first() else {
+        return Err(error("macro name missing", position, 1));
+    };
+    if name.kind != PpTokenKind::Identifier {
+        return Err(error(
+            "macro name must be an identifier",
+            name.position,
+            name.text.len(),
+        ));
+    }
+    if name.text == "defined" {
+        return Err(error(
+            "\"defined\" cannot be used as a macro name",
+            name.position,
+            name.text.len(),
+        ));
+    }
+    let definition = match rest {
+        [lparen, rest @ ..] if lparen.is_punctuator("(") && !lparen.leading_space => {
+            let (parameters, is_variadic, body) = parse_parameters(rest, lparen)?;
+            check_operators(body, &parameters, is_variadic)?;
+            Macro::Function {
+                parameters,
+                is_variadic,
+                body: body_of(body),
+            }
+        }
+        _ => {
+            check_operators(rest, &[], false)?;
+            Macro::Object(body_of(rest))
+        }
+    };
+    Ok((name.text.clone(), definition))
+}
+
+type Parameters<'a> = (Vec<String>, bool, &'a [PpToken]);
+
+fn parse_parameters<'a>(tokens: &'a [PpToken], lparen: &PpToken) -> CompileResult<Parameters<'a>> {
+    let mut parameters: Vec<String> = vec![];
+    let mut rest = tokens;
+    if let [rparen, body @ ..] = rest {
+        if rparen.is_punctuator(")") {
+            return Ok((parameters, false, body));
+        }
+    }
+    loop {
+        match rest {
+            [ellipsis, rparen, body @ ..]
+                if ellipsis.is_punctuator("...") && rparen.is_punctuator(")") =>
+            {
+                return Ok((parameters, true, body));
+            }
+            [parameter, delimiter, body @ ..] if parameter.kind == PpTokenKind::Identifier => {
+                if parameters.contains(&parameter.text) || parameter.text == VA_ARGS {
+                    return Err(error(
+                        format!("duplicate macro parameter {}", parameter.text),
+                        parameter.position,
+                        parameter.text.len(),
+                    ));
+                }
+                parameters.push(parameter.text.clone());
+                if delimiter.is_punctuator(")") {
+                    return Ok((parameters, false, body));
+                }
+                if !delimiter.is_punctuator(",") {
+                    return Err(error(
+                        "expected ',' or ')' in macro parameter list",
+                        delimiter.position,
+                        delimiter.text.len(),
+                    ));
+                }
+                rest = body;
+            }
+            [token, ..] => {
+                return Err(error(
+                    format!("invalid macro parameter {}", token.text),
+                    token.position,
+                    token.text.len(),
+                ));
+            }
+            [] => {
+                return Err(error(
+                    "missing ')' in macro parameter list",
+                    lparen.position,
+                    1,
+                ));
+            }
+        }
+    }
+}
+
+/// `##` は置換リストの両端に置けず、関数形式のマクロの `#` の後ろは仮引数でなければならない。
+fn check_operators(
+    body: &[PpToken],
+    parameters: &[String],
+    is_variadic: bool,
+) -> CompileResult<()> {
+    for token in [body.first(), body.last()].into_iter().flatten() {
+        if token.is_punctuator("##") {
+            return Err(error(
+                "'##' cannot appear at either end of a macro expansion",
+                token.position,
+                2,
+            ));
+        }
+    }
+    if parameters.is_empty() && !is_variadic {
+        return Ok(());
+    }
+    for pair in body.windows(2) {
+        if pair[0].is_punctuator("#")
+            && parameter_index(&pair[1], parameters, is_variadic).is_none()
+        {
+            return Err(error(
+                "'#' is not followed by a macro parameter",
+                pair[0].position,
+                1,
+            ));
+        }
+    }
+    if body.last().is_some_and(|token| token.is_punctuator("#")) {
+        let token = body.last().unwrap();
+        return Err(error(
+            "'#' is not followed by a macro parameter",
+            token.position,
+            1,
+        ));
+    }
+    Ok(())
+}
+
+/// 置換リストの先頭の空白は意味を持たない。
+fn body_of(tokens: &[PpToken]) -> Vec<PpToken> {
+    let mut body = tokens.to_vec();
+    if let Some(first) = body.first_mut() {
+        first.leading_space = false;
+    }
+    body
+}
+
+fn parameter_index(token: &PpToken, parameters: &[String], is_variadic: bool) -> Option<usize> {
+    if token.kind != PpTokenKind::Identifier {
+        return None;
+    }
+    if is_variadic && token.text == VA_ARGS {
+        return Some(parameters.len());
+    }
+    parameters
+        .iter()
+        .position(|parameter| *parameter == token.text)
+}
+
+/// マクロを展開する。展開した字句の `hideset` に展開中のマクロを加えて、再帰的な展開を止める。
+pub struct Expander<'a> {
+    pub macros: &'a HashMap<String, Macro>,
+    pub file: &'a str,
+    pub line: usize,
+}
+
+impl Expander<'_> {
+    /// 展開した字句の列と、1 つでもマクロを展開したかを返す。
+    pub fn expand(&self, tokens: Vec<PpToken>) -> CompileResult<(Vec<PpToken>, bool)> {
+        let mut input = VecDeque::from(tokens);
+        let mut output = vec![];
+        let mut expanded = false;
+        while let Some(token) = input.pop_front() {
+            if token.kind != PpTokenKind::Identifier || token.hideset.contains(&token.text) {
+                output.push(token);
+                continue;
+            }
+            let Some(definition) = self.macros.get(&token.text) else {
+                output.push(token);
+                continue;
+            };
+            let replacement = match definition {
+                Macro::Object(body) => {
+                    let mut hideset = token.hideset.clone();
+                    hideset.insert(token.text.clone());
+                    self.substitute(body, &[], false, &[], &hideset, &token)?
+                }
+                Macro::Function {
+                    parameters,
+                    is_variadic,
+                    body,
+                } => {
+                    // 直後に `(` がなければ関数形式のマクロの名前はそのまま残る。
+                    if !input.front().is_some_and(|next| next.is_punctuator("(")) {
+                        output.push(token);
+                        continue;
+                    }
+                    let (arguments, rparen) =
+                        collect_arguments(&mut input, &token, parameters.len(), *is_variadic)?;
+                    let mut hideset = token
+                        .hideset
+                        .intersection(&rparen.hideset)
+                        .cloned()
+                        .collect::<HashSet<_>>();
+                    hideset.insert(token.text.clone());
+                    self.substitute(body, parameters, *is_variadic, &arguments, &hideset, &token)?
+                }
+                Macro::File => vec![stringify(
+                    &[PpToken::new(
+                        PpTokenKind::Literal,
+                        self.file,
+                        token.position,
+                    )],
+                    token.position,
+                )],
+                Macro::Line => vec![PpToken::new(
+                    PpTokenKind::Number,
+                    self.line.to_string(),
+                    token.position,
+                )],
+            };
+            expanded = true;
+            // 展開結果が前後の字句とつながって別の字句にならないよう、空白を挟む。
+            if let Some(next) = input.front_mut() {
+                next.leading_space = true;
+            }
+            for replaced in replacement.into_iter().rev() {
+                if replaced.kind == PpTokenKind::Placemarker {
+                    continue;
+                }
+                input.push_front(replaced);
+            }
+            if let Some(first) = input.front_mut() {
+                first.leading_space = true;
+            }
+        }
+        Ok((output, expanded))
+    }
+
+    /// 置換リストの仮引数を実引数で置き換え、`#` と `##` を適用する。
+    /// `#` と `##` の被演算子になる実引数は展開せずに使う。
+    fn substitute(
+        &self,
+        body: &[PpToken],
+        parameters: &[String],
+        is_variadic: bool,
+        arguments: &[Vec<PpToken>],
+        hideset: &HashSet<String>,
+        invocation: &PpToken,
+    ) -> CompileResult<Vec<PpToken>> {
+        let is_function = !parameters.is_empty() || is_variadic;
+        let index_of = |token: &PpToken| parameter_index(token, parameters, is_variadic);
+        let raw_argument = |index: usize| -> Vec<PpToken> {
+            if arguments[index].is_empty() {
+                vec![PpToken::new(
+                    PpTokenKind::Placemarker,
+                    "",
+                    invocation.position,
+                )]
+            } else {
+                arguments[index].clone()
+            }
+        };
+        let mut result: Vec<PpToken> = vec![];
+        let mut i = 0;
+        while i < body.len() {
+            let token = &body[i];
+            let next = body.get(i + 1);
+            if is_function && token.is_punctuator("#") {
+                if let Some(index) = next.and_then(index_of) {
+                    let mut string = stringify(&arguments[index], invocation.position);
+                    string.leading_space = token.leading_space;
+                    result.push(string);
+                    i += 2;
+                    continue;
+                }
+            }
+            if token.is_punctuator("##") {
+                let Some(next) = next else { break };
+                // GNU 拡張: `, ## __VA_ARGS__` はつながず、可変長引数が空ならコンマを消す。
+                if is_variadic
+                    && next.is_identifier(VA_ARGS)
+                    && result.last().is_some_and(|last| last.is_punctuator(","))
+                {
+                    let variadic = &arguments[parameters.len()];
+                    if variadic.is_empty() {
+                        result.pop();
+                    } else {
+                        let mut variadic = variadic.clone();
+                        variadic[0].leading_space = next.leading_space;
+                        result.extend(variadic);
+                    }
+                    i += 2;
+                    continue;
+                }
+                let mut rhs = index_of(next)
+                    .map_or_else(|| vec![at_invocation(next, invocation)], raw_argument);
+                let lhs = result.pop().unwrap_or_else(|| {
+                    PpToken::new(PpTokenKind::Placemarker, "", invocation.position)
+                });
+                let first = rhs.remove(0);
+                let pasted = paste(&lhs, &first).ok_or_else(|| {
+                    error(
+                        format!(
+                            "pasting \"{}\" and \"{}\" does not give a valid preprocessing token",
+                            lhs.text, first.text
+                        ),
+                        invocation.position,
+                        invocation.text.len(),
+                    )
+                })?;
+                result.push(pasted);
+                result.extend(rhs);
+                i += 2;
+                continue;
+            }
+            if let Some(index) = index_of(token) {
+                let is_pasted = next.is_some_and(|next| next.is_punctuator("##"));
+                let mut argument = if is_pasted {
+                    raw_argument(index)
+                } else {
+                    self.expand(arguments[index].clone())?.0
+                };
+                if let Some(first) = argument.first_mut() {
+                    first.leading_space = token.leading_space;
+                }
+                result.extend(argument);
+                i += 1;
+                continue;
+            }
+            result.push(at_invocation(token, invocation));
+            i += 1;
+        }
+        for token in &mut result {
+            token.hideset.extend(hideset.iter().cloned());
+        }
+        Ok(result)
+    }
+}
+
+/// 置換リストの字句は、展開したマクロの名前の位置に置く。
+fn at_invocation(token: &PpToken, invocation: &PpToken) -> PpToken {
+    let mut token = token.clone();
+    token.position = invocation.position;
+    token
+}
+
+/// `(` から対応する `)` までを読み、コンマで区切った実引数と `)` の字句を返す。
+/// 可変長引数のマクロでは、名前付きの仮引数より後ろのコンマでは区切らない。
+fn collect_arguments(
+    input: &mut VecDeque<PpToken>,
+    name: &PpToken,
+    parameter_count: usize,
+    is_variadic: bool,
+) -> CompileResult<(Vec<Vec<PpToken>>, PpToken)> {
+    input.pop_front();
+    let mut arguments = vec![vec![]];
+    let mut depth = 0;
+    let rparen = loop {
+        let Some(token) = input.pop_front() else {
+            return Err(error(
+                format!("unterminated argument list invoking macro {}", name.text),
+                name.position,
+                name.text.len(),
+            ));
+        };
+        if token.is_punctuator("(") {
+            depth += 1;
+        } else if token.is_punctuator(")") {
+            if depth == 0 {
+                break token;
+            }
+            depth -= 1;
+        } else if token.is_punctuator(",")
+            && depth == 0
+            && !(is_variadic && arguments.len() > parameter_count)
+        {
+            arguments.push(vec![]);
+            continue;
+        }
+        arguments.last_mut().unwrap().push(token);
+    };
+
+    // `f()` は仮引数が 0 個なら実引数なし、1 個なら空の実引数 1 個とみなす。
+    if parameter_count == 0 && arguments.len() == 1 && arguments[0].is_empty() && !is_variadic {
+        arguments.clear();
+    }
+    if is_variadic && arguments.len() == parameter_count {
+        arguments.push(vec![]);
+    }
+    let expected = parameter_count + usize::from(is_variadic);
+    if arguments.len() != expected {
+        return Err(error(
+            format!(
+                "macro {} takes {parameter_count} arguments, but {} were given",
+                name.text,
+                arguments.len()
+            ),
+            name.position,
+            name.text.len(),
+        ));
+    }
+    Ok((arguments, rparen))
+}
+
+fn error(message: impl Into<String>, position: usize, length: usize) -> CompileError {
+    CompileError::new(
+        CompileErrorKind::Preprocess,
+        message,
+        Some(Span::new(
+            SourcePosition(position),
+            SourcePosition(position + length),
+        )),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pp_token::{spell, tokenize_line};
+
+    fn tokens_of(line: &str) -> Vec<PpToken> {
+        let chars = line.chars().collect::<Vec<_>>();
+        tokenize_line(&chars, &(0..chars.len()).collect::<Vec<_>>())
+    }
+
+    fn expand(definitions: &[&str], input: &str) -> CompileResult<String> {
+        let mut macros = HashMap::new();
+        for definition in definitions {
+            let (name, definition) = parse_definition(&tokens_of(definition), 0)?;
+            macros.insert(name, definition);
+        }
+        let expander = Expander {
+            macros: &macros,
+            file: "a.c",
+            line: 3,
+        };
+        Ok(spell(&expander.expand(tokens_of(input))?.0))
+    }
+
+    #[test]
+    fn test_parse_definition() {
+        assert_eq!(
+            parse_definition(&tokens_of("F(a, ...) a __VA_ARGS__"), 0).map(|(name, definition)| (
+                name,
+                matches!(
+                    definition,
+                    Macro::Function {
+                        is_variadic: true,
+                        ..
+                    }
+                )
+            )),
+            Ok(("F".to_string(), true))
+        );
+        // 名前と `(` の間に空白があればオブジェクト形式のマクロになる。
+        assert!(matches!(
+            parse_definition(&tokens_of("F (a) a"), 0),
+            Ok((_, Macro::Object(body))) if body.len() == 4
+        ));
+        assert!(parse_definition(&tokens_of("1 2"), 0).is_err());
+        assert!(parse_definition(&tokens_of("F(a, a) a"), 0).is_err());
+        assert!(parse_definition(&tokens_of("F(a) #b"), 0).is_err());
+        assert!(parse_definition(&tokens_of("F(a) a ##"), 0).is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand(&["N 1 + N"], "N * 2").unwrap(), "1 + N * 2");
+        assert_eq!(
+            expand(&["f(x) x * g", "g f"], "f(2)(3)").unwrap(),
+            "2 * f (3)"
+        );
+        assert_eq!(
+            expand(&["F(a, b) a##b #a", "xy 1"], "F(x, y) F(, )").unwrap(),
+            "1 \"x\" \"\""
+        );
+        assert_eq!(
+            expand(&["E(...) g(0, ## __VA_ARGS__)"], "E() E(1, 2)").unwrap(),
+            "g(0) g(0, 1, 2)"
+        );
+        assert_eq!(expand(&["F(x) x"], "F + F((1, 2))").unwrap(), "F + (1, 2)");
+        assert_eq!(
+            expand(&[], "__LINE__ __FILE__").unwrap(),
+            "__LINE__ __FILE__"
+        );
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        assert!(expand(&["F(a, b) a"], "F(1)").is_err());
+        assert!(expand(&["F(a) a"], "F(1, 2)").is_err());
+        assert!(expand(&["F(a) a"], "F(1").is_err());
+        assert!(expand(&["F(a, b) a ## b"], "F(+, /)").is_err());
+    }
+}
@@ -1,6 +1,327 @@
-use yuchiki_c_compiler::process;
+use std::{
+    io::{IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode, Stdio},
+};
 
-fn main() {
-    let raw_input = std::env::args().nth(1).expect("no arguments");
-    process(&raw_input, std::io::stdout().lock());
+use yuchiki_c_compiler::{compile, preprocess, render, MacroOption, PreprocessOptions, SourceFile};
+
+const USAGE: &str = "\
+Usage: yuchiki-c-compiler [options] <file>...
+
+Compiles C source files. `-` reads the source from stdin.
+Files ending in .s, .o or .a are passed to the linker as they are.
+
+Options:
+  -o <path>    Write the output to <path> (`-` for stdout with -S)
+  -S           Only compile; write assembly (default: <file>.s)
+  -c           Compile and assemble; write an object file (default: <file>.o)
+  -I <dir>     Add <dir> to the #include search path
+  -D <name>[=<value>]
+               Define the macro <name> (default value: 1)
+  -U <name>    Undefine the macro <name>
+  -l <lib>     Link with the library <lib>
+  -L <dir>     Add <dir> to the library search path
+  -Wl,<args>   Pass the comma-separated <args> to the linker
+  -h, --help     Print this help
+  -V, --version  Print the version
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Assembly,
+    Object,
+    Executable,
+}
+
+struct Options {
+    inputs: Vec<String>,
+    output: Option<String>,
+    mode: Mode,
+    preprocess: PreprocessOptions,
+    /// `cc` に渡す `-l` や `-L`。入力ファイルの後に並べる。
+    linker_args: Vec<String>,
+}
+
+enum Action {
+    Compile(Options),
+    Help,
+    Version,
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match parse_args(&args) {
+        Ok(Action::Help) => {
+            print!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        Ok(Action::Version) => {
+            println!("yuchiki-c-compiler {}", env!("CARGO_PKG_VERSION"));
+            ExitCode::SUCCESS
+        }
+        Ok(Action::Compile(options)) => match run(&options) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(message) => {
+                if !message.is_empty() {
+                    eprintln!("yuchiki-c-compiler: error: {message}");
+                }
+                ExitCode::FAILURE
+            }
+        },
+        Err(message) => {
+            eprintln!("yuchiki-c-compiler: error: {message}");
+            eprintln!("Try `yuchiki-c-compiler --help` for more information.");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Action, String> {
+    let mut inputs = vec![];
+    let mut output = None;
+    let mut mode = Mode::Executable;
+    let mut preprocess = PreprocessOptions::default();
+    let mut linker_args = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "-V" | "--version" => return Ok(Action::Version),
+            "-S" => mode = Mode::Assembly,
+            "-c" => mode = Mode::Object,
+            "-o" => {
+                let path = args.next().ok_or("missing filename after `-o`")?;
+                output = Some(path.clone());
+            }
+            "-" => inputs.push(arg.clone()),
+            _ if arg.starts_with("-o") => output = Some(arg["-o".len()..].to_string()),
+            _ if arg.starts_with("-I") => {
+                let dir = option_value(arg, "-I", &mut args)?;
+                preprocess.include_paths.push(PathBuf::from(dir));
+            }
+            _ if arg.starts_with("-D") => {
+                let definition = option_value(arg, "-D", &mut args)?;
+                let (name, value) = definition.split_once('=').unwrap_or((&definition, "1"));
+                preprocess
+                    .macro_options
+                    .push(MacroOption::Define(name.to_string(), value.to_string()));
+            }
+            _ if arg.starts_with("-U") => {
+                let name = option_value(arg, "-U", &mut args)?;
+                preprocess.macro_options.push(MacroOption::Undefine(name));
+            }
+            _ if arg.starts_with("-l") || arg.starts_with("-L") => {
+                let option = &arg[..2];
+                let value = option_value(arg, option, &mut args)?;
+                linker_args.push(format!("{option}{value}"));
+            }
+            _ if arg.starts_with("-Wl,") => linker_args.push(arg.clone()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`")),
+            _ => inputs.push(arg.clone()),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err("no input files".to_string());
+    }
+    let sources = inputs.iter().filter(|input| is_c_source(input)).count();
+    if mode != Mode::Executable {
+        if sources != inputs.len() {
+            return Err("-S and -c only accept C source files".to_string());
+        }
+        if output.is_some() && inputs.len() > 1 {
+            return Err("cannot specify `-o` with -S or -c and multiple files".to_string());
+        }
+    }
+
+    Ok(Action::Compile(Options {
+        inputs,
+        output,
+        mode,
+        preprocess,
+        linker_args,
+    }))
+}
+
+/// `-Ifoo` のように続けて書いた値か、次の引数を値として返す。
+fn option_value<'a>(
+    arg: &str,
+    option: &str,
+    args: &mut impl Iterator<Item = &'a String>,
+) -> Result<String, String> {
+    if arg.len() > option.len() {
+        return Ok(arg[option.len()..].to_string());
+    }
+    args.next()
+        .cloned()
+        .ok_or_else(|| format!("missing argument after `{option}`"))
+}
+
+fn is_c_source(input: &str) -> bool {
+    !matches!(
+        Path::new(input).extension().and_then(|ext| ext.to_str()),
+        Some("s" | "o" | "a")
+    )
+}
+
+/// 全入力をコンパイルしてから出力する。1 つでもコンパイルエラーがあれば何も出力しない。
+fn run(options: &Options) -> Result<(), String> {
+    let mut assemblies = vec![];
+    let mut failed = false;
+    for input in options.inputs.iter().filter(|input| is_c_source(input)) {
+        let (name, source) = read_source(input)?;
+        let mut assembly = Vec::new();
+        let color = std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal();
+        let (preprocessed, errors) = preprocess(&source, &name, &options.preprocess);
+        let source_file = SourceFile::preprocessed(name, &preprocessed);
+        if !errors.is_empty() {
+            eprint!("{}", render(&errors, &source_file, color));
+            failed = true;
+            continue;
+        }
+        match compile(&preprocessed.text, &mut assembly) {
+            Ok(warnings) => {
+                if !warnings.is_empty() {
+                    eprint!("{}", render(&warnings, &source_file, color));
+                }
+                assemblies.push((input, assembly));
+            }
+            Err(errors) => {
+                eprint!("{}", render(&errors, &source_file, color));
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        return Err(String::new());
+    }
+
+    match options.mode {
+        Mode::Assembly => {
+            for (input, assembly) in &assemblies {
+                let output = output_path(options, input, "s");
+                if output == "-" {
+                    std::io::stdout()
+                        .write_all(assembly)
+                        .map_err(|error| format!("cannot write to stdout: {error}"))?;
+                } else {
+                    std::fs::write(&output, assembly)
+                        .map_err(|error| format!("cannot write {output}: {error}"))?;
+                }
+            }
+            Ok(())
+        }
+        Mode::Object => {
+            for (input, assembly) in &assemblies {
+                assemble(assembly, Path::new(&output_path(options, input, "o")))?;
+            }
+            Ok(())
+        }
+        Mode::Executable => link(options, &assemblies),
+    }
+}
+
+fn read_source(input: &str) -> Result<(String, String), String> {
+    if input == "-" {
+        let mut source = String::new();
+        std::io::stdin()
+            .read_to_string(&mut source)
+            .map_err(|error| format!("cannot read stdin: {error}"))?;
+        Ok(("<stdin>".to_string(), source))
+    } else {
+        let source = std::fs::read_to_string(input)
+            .map_err(|error| format!("cannot read {input}: {error}"))?;
+        Ok((input.to_string(), source))
+    }
+}
+
+/// `-o` がなければ gcc と同じく入力ファイル名の拡張子を差し替えた名前にする。
+/// 標準入力のアセンブリは標準出力に書き出す。
+fn output_path(options: &Options, input: &str, extension: &str) -> String {
+    if let Some(output) = &options.output {
+        return output.clone();
+    }
+    if input == "-" {
+        return if extension == "s" {
+            "-".to_string()
+        } else {
+            format!("stdin.{extension}")
+        };
+    }
+    Path::new(input)
+        .file_name()
+        .map_or_else(|| PathBuf::from(input), PathBuf::from)
+        .with_extension(extension)
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn assemble(assembly: &[u8], output: &Path) -> Result<(), String> {
+    let mut child = Command::new("as")
+        .arg("-o")
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("cannot run the assembler `as`: {error}"))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin of the assembler is piped")
+        .write_all(assembly)
+        .map_err(|error| format!("cannot pass the assembly to `as`: {error}"))?;
+    let status = child
+        .wait()
+        .map_err(|error| format!("cannot run the assembler `as`: {error}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("assembler failed with {status}"))
+    }
+}
+
+fn link(options: &Options, assemblies: &[(&String, Vec<u8>)]) -> Result<(), String> {
+    let objects = (0..assemblies.len())
+        .map(|i| {
+            std::env::temp_dir().join(format!("yuchiki-c-compiler-{}-{i}.o", std::process::id()))
+        })
+        .collect::<Vec<_>>();
+    let result = assemble_and_link(options, assemblies, &objects);
+    for object in &objects {
+        let _ = std::fs::remove_file(object);
+    }
+    result
+}
+
+fn assemble_and_link(
+    options: &Options,
+    assemblies: &[(&String, Vec<u8>)],
+    objects: &[PathBuf],
+) -> Result<(), String> {
+    for ((_, assembly), object) in assemblies.iter().zip(objects) {
+        assemble(assembly, object)?;
+    }
+
+    let mut objects = objects.iter();
+    let mut command = Command::new("cc");
+    command
+        .arg("-o")
+        .arg(options.output.as_deref().unwrap_or("a.out"));
+    for input in &options.inputs {
+        if is_c_source(input) {
+            command.arg(objects.next().expect("one object per C source"));
+        } else {
+            command.arg(input);
+        }
+    }
+    command.args(&options.linker_args);
+    let status = command
+        .status()
+        .map_err(|error| format!("cannot run the linker `cc`: {error}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("linker failed with {status}"))
+    }
 }
@@ -1,20 +1,100 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
 use crate::{
-    expr::Expr,
-    lex::{PositionedToken, SourcePosition},
-    statement::Statement,
-    token::Token,
-    top_level::TopLevel,
-    types::Type,
+    constant::ConstantEvaluator,
+    error::{CompileError, CompileErrorKind, CompileResult},
+    expr::{Expr, ExprKind},
+    lex::{PositionedToken, SourcePosition, Span},
+    statement::{Declaration, Statement, StatementKind, Storage},
+    token::{StringLiteral, Token},
+    top_level::{Designator, Initializer, Linkage, TopLevel, TopLevelKind},
+    types::{Qualifiers, StructKind, StructType, Type},
 };
 
 pub struct Parser<'a> {
     tokens: &'a [PositionedToken],
-    raw_input: &'a str,
+    previous_end: SourcePosition,
+    end_position: SourcePosition,
+    /// 先頭がファイルスコープで、ブロックに入るたびに積む。
+    scopes: Vec<Scope>,
+}
+
+/// 1 つのスコープで宣言された名前。
+#[derive(Default)]
+struct Scope {
+    /// struct・union・enum のタグは同じ名前空間を共有する。
+    tags: HashMap<String, Tag>,
+    /// 変数・列挙定数・typedef 名は同じ名前空間を共有する。
+    identifiers: HashMap<String, Identifier>,
+}
+
+#[derive(Clone)]
+enum Tag {
+    Struct(Rc<StructType>),
+    Enum,
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Struct(struct_type) => write!(f, "{struct_type}"),
+            Self::Enum => write!(f, "enum"),
+        }
+    }
+}
+
+enum Identifier {
+    Variable,
+    EnumConstant(i32),
+    Typedef(Type),
+}
+
+/// 宣言指定子の並びから読み取った型と記憶域クラス指定子。
+struct DeclarationSpecifiers {
+    ty: Type,
+    storage_class: Option<StorageClass>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum StorageClass {
+    Typedef,
+    Static,
+    Extern,
+}
+
+/// `va_start` などのマクロが展開される組み込み関数の名前。
+const VA_BUILTINS: [&str; 4] = [
+    "__builtin_va_start",
+    "__builtin_va_arg",
+    "__builtin_va_end",
+    "__builtin_va_copy",
+];
+
+/// 関数の仮引数の名前と型の並び。
+type Parameters = Vec<(String, Type)>;
+
+/// 宣言子で宣言される名前と型。
+struct Declarator {
+    name: Option<String>,
+    ty: Type,
+    /// 名前の直後が関数の接尾辞なら、その仮引数。関数定義で使う。
+    parameters: Option<Parameters>,
 }
 
 impl<'a> Parser<'a> {
-    pub const fn new(tokens: &'a [PositionedToken], raw_input: &'a str) -> Self {
-        Self { tokens, raw_input }
+    pub fn new(tokens: &'a [PositionedToken], raw_input: &str) -> Self {
+        Self {
+            tokens,
+            previous_end: SourcePosition(0),
+            end_position: SourcePosition(raw_input.chars().count()),
+            scopes: vec![Scope {
+                tags: HashMap::new(),
+                identifiers: HashMap::from([(
+                    "__builtin_va_list".to_string(),
+                    Identifier::Typedef(Type::va_list()),
+                )]),
+            }],
+        }
     }
 
     pub const fn fully_parsed(&self) -> bool {
@@ -22,171 +102,598 @@ impl<'a> Parser<'a> {
     }
 
     fn advance(&mut self, offset: usize) {
+        if offset > 0 {
+            self.previous_end = self.tokens[offset - 1].1.end;
+        }
         self.tokens = &self.tokens[offset..];
     }
 
-    pub fn munch_program(&mut self) -> Vec<TopLevel> {
+    /// 次に読むトークンの開始位置。
+    fn next_start(&self) -> SourcePosition {
+        match self.tokens {
+            [(_, span), ..] => span.start,
+            [] => self.end_position,
+        }
+    }
+
+    /// `start` から直前に読んだトークンの末尾までの範囲。
+    const fn span_from(&self, start: SourcePosition) -> Span {
+        Span::new(start, self.previous_end)
+    }
+
+    /// 構文エラーを記録したら、次のトップレベル定義の先頭まで読み飛ばして解析を続ける。
+    pub fn munch_program(&mut self) -> Result<Vec<TopLevel>, Vec<CompileError>> {
         let mut ans = vec![];
+        let mut errors = vec![];
         while !self.fully_parsed() {
-            ans.push(self.munch_top_level());
+            let top_level_start = self.tokens;
+            match self.munch_top_level() {
+                Ok(mut top_levels) => ans.append(&mut top_levels),
+                Err(error) => {
+                    errors.push(error);
+                    self.tokens = top_level_start;
+                    self.scopes.truncate(1);
+                    self.skip_top_level();
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(ans)
+        } else {
+            Err(errors)
         }
-        ans
     }
 
-    pub fn munch_top_level(&mut self) -> TopLevel {
-        match self.tokens {
-            [(Token::Extern, _), ..] => self.munch_external_function_declaration(),
-            _ => self.munch_function_definition(),
+    fn skip_top_level(&mut self) {
+        let mut depth = 0_usize;
+        while let [(token, _), ..] = self.tokens {
+            self.advance(1);
+            match token {
+                Token::LBrace => depth += 1,
+                // 関数本体はここで終わるが、`struct s { ... } x;` は `;` まで続く。
+                Token::RBrace => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0
+                        && !matches!(
+                            self.tokens,
+                            [
+                                (Token::Semicolon | Token::Identifier(_) | Token::Asterisk, _),
+                                ..
+                            ]
+                        )
+                    {
+                        return;
+                    }
+                }
+                Token::Semicolon if depth == 0 => return,
+                _ => {}
+            }
         }
     }
 
-    pub fn munch_external_function_declaration(&mut self) -> TopLevel {
-        assert_eq!(self.tokens[0].0, Token::Extern, "parse error");
-        self.advance(1);
-        let Some((name, return_ty)) = self.try_munch_variable_definition() else {
-                panic!("parse error at {:#?}, expected a function name", self.tokens)
+    /// `struct s { ... };` のように何も定義しないトップレベルの宣言なら空の列を返す。
+    /// `int a, b;` のようにまとめて宣言したグローバル変数は、それぞれを要素にする。
+    /// typedef もトップレベルには何も残さない。
+    pub fn munch_top_level(&mut self) -> CompileResult<Vec<TopLevel>> {
+        let start = self.next_start();
+        let Some(specifiers) = self.try_munch_declaration_specifiers()? else {
+            return Err(self.unexpected("a type"));
         };
+        if let [(Token::Semicolon, _), ..] = self.tokens {
+            self.advance(1);
+            return Ok(vec![]);
+        }
+        if specifiers.storage_class == Some(StorageClass::Typedef) {
+            self.munch_typedefs(&specifiers.ty)?;
+            return Ok(vec![]);
+        }
+        let declarator = self.munch_named_declarator(specifiers.ty.clone())?;
+        let name = declarator.name.unwrap_or_default();
+        self.declare_variable(&name);
+        match (declarator.ty, declarator.parameters) {
+            (Type::Function(_, return_ty, is_variadic), Some(parameters)) => {
+                if let [(Token::LBrace, _), ..] = self.tokens {
+                    let linkage = if specifiers.storage_class == Some(StorageClass::Static) {
+                        Linkage::Internal
+                    } else {
+                        Linkage::External
+                    };
+                    Ok(vec![self.munch_function_definition(
+                        start,
+                        name,
+                        parameters,
+                        is_variadic,
+                        *return_ty,
+                        linkage,
+                    )?])
+                } else {
+                    self.expect(&Token::Semicolon)?;
+                    Ok(vec![TopLevel::new(
+                        TopLevelKind::ExternalFunctionDeclaration(
+                            name,
+                            parameters,
+                            is_variadic,
+                            *return_ty,
+                        ),
+                        self.span_from(start),
+                    )])
+                }
+            }
+            (ty, _) => self.munch_global_variables(start, &specifiers, name, ty),
+        }
+    }
 
-        assert_eq!(self.tokens[0].0, Token::LParen, "parse error");
-        self.advance(1);
-
-        let mut args: Vec<(String, Type)> = vec![];
-        while self.tokens[0].0 != Token::RParen {
-            let Some((arg, arg_ty)) = self.try_munch_variable_definition() else {
-                    panic!("parse error at {:#?}", self.tokens)
-                };
+    /// `extern` を付けて初期化子のないものだけが宣言で、ほかは定義になる。
+    /// `F f;` のように関数型の typedef 名で宣言したものは関数の宣言になる。
+    fn munch_global_variables(
+        &mut self,
+        start: SourcePosition,
+        specifiers: &DeclarationSpecifiers,
+        mut name: String,
+        mut ty: Type,
+    ) -> CompileResult<Vec<TopLevel>> {
+        let mut variables = vec![];
+        loop {
+            let initializer = match self.tokens {
+                [(Token::Assign, _), ..] => {
+                    self.advance(1);
+                    Some(self.munch_initializer()?)
+                }
+                _ => None,
+            };
+            if let (Type::Function(_, _, _), Some(initializer)) = (&ty, &initializer) {
+                return Err(Self::error(
+                    &format!("function {name} cannot be initialized"),
+                    initializer.span(),
+                ));
+            }
+            variables.push((name, ty, initializer));
 
-            if self.tokens[0].0 == Token::Comma {
+            if let [(Token::Comma, _), ..] = self.tokens {
                 self.advance(1);
-            } else if self.tokens[0].0 == Token::RParen {
+                (name, ty) = self.munch_declarator(specifiers.ty.clone())?;
+                self.declare_variable(&name);
             } else {
-                panic!("parse error at {:#?}", self.tokens)
+                break;
             }
+        }
+        self.expect(&Token::Semicolon)?;
+        let span = self.span_from(start);
+        let linkage = if specifiers.storage_class == Some(StorageClass::Static) {
+            Linkage::Internal
+        } else {
+            Linkage::External
+        };
+        Ok(variables
+            .into_iter()
+            .map(|(name, ty, initializer)| {
+                let kind = match (specifiers.storage_class, initializer, ty) {
+                    (_, _, Type::Function(params, return_ty, is_variadic)) => {
+                        TopLevelKind::ExternalFunctionDeclaration(
+                            name,
+                            params.into_iter().map(|ty| (String::new(), ty)).collect(),
+                            is_variadic,
+                            *return_ty,
+                        )
+                    }
+                    (Some(StorageClass::Extern), None, ty) => {
+                        TopLevelKind::ExternalVariableDeclaration(name, ty)
+                    }
+                    (_, initializer, ty) => {
+                        TopLevelKind::GlobalVariable(name, ty, initializer, linkage)
+                    }
+                };
+                TopLevel::new(kind, span)
+            })
+            .collect())
+    }
 
-            args.push((arg, arg_ty));
+    /// `typedef int T, *P;` の宣言子ごとに型の別名を登録する。
+    fn munch_typedefs(&mut self, base_ty: &Type) -> CompileResult<()> {
+        loop {
+            let start = self.next_start();
+            let (name, ty) = self.munch_declarator(base_ty.clone())?;
+            self.declare_typedef(name, ty, self.span_from(start))?;
+            match self.tokens {
+                [(Token::Comma, _), ..] => self.advance(1),
+                _ => break,
+            }
         }
+        self.expect(&Token::Semicolon)
+    }
 
-        self.advance(1);
+    /// `{1, 2, 3}` のような波括弧の初期化子では末尾のカンマを許す。
+    fn munch_initializer(&mut self) -> CompileResult<Initializer> {
+        if !matches!(self.tokens, [(Token::LBrace, _), ..]) {
+            return Ok(Initializer::Expr(self.munch_assign()?));
+        }
 
-        assert_eq!(self.tokens[0].0, Token::Semicolon, "parse error");
+        let start = self.next_start();
         self.advance(1);
-        TopLevel::ExternalFunctionDeclaration(name, args, return_ty)
+        let mut initializers = vec![];
+        loop {
+            if let [(Token::RBrace, _), ..] = self.tokens {
+                self.advance(1);
+                break;
+            }
+
+            initializers.push(self.munch_designated_initializer()?);
+            match self.tokens {
+                [(Token::Comma, _), ..] => self.advance(1),
+                [(Token::RBrace, _), ..] => {}
+                _ => return Err(self.unexpected("',' or '}'")),
+            }
+        }
+        Ok(Initializer::List(initializers, self.span_from(start)))
     }
 
-    pub fn try_munch_variable_definition(&mut self) -> Option<(String, Type)> {
-        let ty = self.try_munch_type()?;
-        let name = if let Token::Identifier(name) = &self.tokens[0].0 {
-            name.clone()
-        } else {
-            panic!("parse error");
-        };
-        self.advance(1);
+    /// `[1].x = 3` のような指示子の並びがあれば `Designated` にする。
+    fn munch_designated_initializer(&mut self) -> CompileResult<Initializer> {
+        let start = self.next_start();
+        let mut designators = vec![];
+        loop {
+            let designator_start = self.next_start();
+            match self.tokens {
+                [(Token::LBracket, _), ..] => {
+                    self.advance(1);
+                    let index_expr = self.munch_conditional()?;
+                    let (index, _) = Self::evaluate_integer_constant(&index_expr)?;
+                    let index = usize::try_from(index)
+                        .map_err(|_| Self::error("array index is negative", index_expr.span))?;
+                    self.expect(&Token::RBracket)?;
+                    designators.push(Designator::Index(index, self.span_from(designator_start)));
+                }
+                [(Token::Dot, _), ..] => {
+                    self.advance(1);
+                    let name = self.munch_identifier()?;
+                    designators.push(Designator::Member(name, self.span_from(designator_start)));
+                }
+                _ => break,
+            }
+        }
+        if designators.is_empty() {
+            return self.munch_initializer();
+        }
 
-        if let [(Token::LBracket, _), (Token::Num(size), _), (Token::RBracket, _), ..] = self.tokens
-        {
-            self.advance(3);
-            #[allow(clippy::cast_sign_loss)]
-            Some((name, Type::Array(Box::new(ty), *size as usize)))
-        } else {
-            Some((name, ty))
+        self.expect(&Token::Assign)?;
+        let initializer = self.munch_initializer()?;
+        Ok(Initializer::Designated(
+            designators,
+            Box::new(initializer),
+            self.span_from(start),
+        ))
+    }
+
+    /// 名前を宣言する宣言子を読み、名前と型を返す。
+    fn munch_declarator(&mut self, base_ty: Type) -> CompileResult<(String, Type)> {
+        let declarator = self.munch_named_declarator(base_ty)?;
+        Ok((declarator.name.unwrap_or_default(), declarator.ty))
+    }
+
+    fn munch_named_declarator(&mut self, base_ty: Type) -> CompileResult<Declarator> {
+        let declarator = self.munch_any_declarator(base_ty)?;
+        if declarator.name.is_none() {
+            return Err(self.unexpected("an identifier"));
         }
+        Ok(declarator)
     }
 
-    pub fn munch_function_definition(&mut self) -> TopLevel {
-        let Some((name, return_ty)) = self.try_munch_variable_definition() else {
-                panic!("parse error at {:#?}, expected a function name", self.tokens)
-        } ;
+    /// `*` の並びに続く識別子か括弧で囲んだ宣言子と、配列や関数の接尾辞を読む。
+    /// 名前を省いた抽象宣言子なら名前は `None` になる。
+    fn munch_any_declarator(&mut self, base_ty: Type) -> CompileResult<Declarator> {
+        let ty = self.munch_pointers(base_ty);
+        if !self.is_nested_declarator() {
+            let name = match self.tokens {
+                [(Token::Identifier(name), _), ..] => {
+                    self.advance(1);
+                    Some(name.clone())
+                }
+                _ => None,
+            };
+            let (ty, parameters) = self.munch_type_suffix(ty)?;
+            return Ok(Declarator {
+                name,
+                ty,
+                parameters,
+            });
+        }
+
+        // `int (*p)[3]` の括弧の中は、外側の接尾辞まで付けた型に対する宣言子になる。
+        // いったん読み飛ばして接尾辞を読み、戻ってから読み直す。
+        let nested = self.tokens;
+        self.advance(1);
+        self.munch_any_declarator(Type::IntTyp)?;
+        self.expect(&Token::RParen)?;
+        let (ty, parameters) = self.munch_type_suffix(ty)?;
+        let (rest, end) = (self.tokens, self.previous_end);
 
-        assert_eq!(self.tokens[0].0, Token::LParen, "parse error");
+        self.tokens = nested;
         self.advance(1);
+        let mut declarator = self.munch_any_declarator(ty.clone())?;
+        self.expect(&Token::RParen)?;
+        (self.tokens, self.previous_end) = (rest, end);
 
-        let mut args: Vec<(String, Type)> = vec![];
-        while self.tokens[0].0 != Token::RParen {
-            let Some((arg, arg_ty)) = self.try_munch_variable_definition() else {
-                    panic!("parse error at {:#?}", self.tokens)
-                };
+        // `int (f)(int a)` のように名前を括弧で囲んだだけなら、外側の仮引数がその関数のもの。
+        if declarator.ty == ty && declarator.parameters.is_none() {
+            declarator.parameters = parameters;
+        }
+        Ok(declarator)
+    }
 
-            if self.tokens[0].0 == Token::Comma {
+    /// `(` の後が仮引数の並びでなく宣言子なら真。`(T)` の `T` が typedef 名なら仮引数の並び。
+    fn is_nested_declarator(&self) -> bool {
+        match self.tokens {
+            [(Token::LParen, _), (Token::Asterisk | Token::LParen, _), ..] => true,
+            [(Token::LParen, _), (Token::Identifier(name), _), ..] => {
+                self.find_typedef(name).is_none()
+            }
+            _ => false,
+        }
+    }
+
+    /// 配列の要素数と関数の仮引数の並びを読む。
+    /// 最初の接尾辞が関数のものなら、その仮引数も返す。
+    fn munch_type_suffix(&mut self, ty: Type) -> CompileResult<(Type, Option<Parameters>)> {
+        match self.tokens {
+            [(Token::LBracket, _), (Token::RBracket, _), ..] => {
+                self.advance(2);
+                let (element_type, _) = self.munch_type_suffix(ty)?;
+                Ok((Type::IncompleteArray(Box::new(element_type)), None))
+            }
+            [(Token::LBracket, _), ..] => {
                 self.advance(1);
-            } else if self.tokens[0].0 == Token::RParen {
-            } else {
-                panic!("parse error at {:#?}", self.tokens)
+                let size_expr = self.munch_conditional()?;
+                let (size, _) = Self::evaluate_integer_constant(&size_expr)?;
+                let size = usize::try_from(size)
+                    .map_err(|_| Self::error("array size is negative", size_expr.span))?;
+                self.expect(&Token::RBracket)?;
+                let (element_type, _) = self.munch_type_suffix(ty)?;
+                Ok((Type::Array(Box::new(element_type), size), None))
+            }
+            [(Token::LParen, _), ..] => {
+                self.scopes.push(Scope::default());
+                let parameters = self.munch_parameters();
+                self.scopes.pop();
+                let (parameters, is_variadic) = parameters?;
+                // 仮引数と戻り値の型の修飾子は関数の型には影響しない。
+                let parameter_types = parameters
+                    .iter()
+                    .map(|(_, ty)| ty.unqualified().clone())
+                    .collect();
+                Ok((
+                    Type::Function(
+                        parameter_types,
+                        Box::new(ty.unqualified().clone()),
+                        is_variadic,
+                    ),
+                    Some(parameters),
+                ))
             }
+            _ => Ok((ty, None)),
+        }
+    }
 
-            args.push((arg, arg_ty));
+    fn munch_function_definition(
+        &mut self,
+        start: SourcePosition,
+        name: String,
+        args: Vec<(String, Type)>,
+        is_variadic: bool,
+        return_ty: Type,
+        linkage: Linkage,
+    ) -> CompileResult<TopLevel> {
+        if args.iter().any(|(arg, _)| arg.is_empty()) {
+            return Err(Self::error(
+                "parameter name omitted in function definition",
+                self.span_from(start),
+            ));
+        }
+        self.scopes.push(Scope::default());
+        for (arg, _) in &args {
+            self.declare_variable(arg);
         }
 
+        self.expect(&Token::LBrace)?;
+        let mut statements = vec![];
+        while !matches!(self.tokens, [(Token::RBrace, _), ..]) {
+            statements.push(self.munch_statement()?);
+        }
         self.advance(1);
+        self.scopes.pop();
+        Ok(TopLevel::new(
+            TopLevelKind::FunctionDefinition(
+                name,
+                args,
+                is_variadic,
+                return_ty,
+                statements,
+                linkage,
+            ),
+            self.span_from(start),
+        ))
+    }
 
-        assert_eq!(self.tokens[0].0, Token::LBrace, "parse error");
+    /// 名前を省いた仮引数は空の名前にする。配列や関数の仮引数はポインタとして扱う。
+    /// `(void)` は仮引数がないことを表す。末尾の `...` があれば可変長引数を取る。
+    fn munch_parameters(&mut self) -> CompileResult<(Parameters, bool)> {
+        self.expect(&Token::LParen)?;
+        if let [(Token::Void, _), (Token::RParen, _), ..] = self.tokens {
+            self.advance(2);
+            return Ok((vec![], false));
+        }
 
-        self.advance(1);
-        let mut statements = vec![];
-        while self.tokens[0].0 != Token::RBrace {
-            statements.push(self.munch_statement());
+        let mut args: Vec<(String, Type)> = vec![];
+        let mut is_variadic = false;
+        while !matches!(self.tokens, [(Token::RParen, _), ..]) {
+            if let [(Token::Ellipsis, span), ..] = self.tokens {
+                if args.is_empty() {
+                    return Err(Self::error(
+                        "a named parameter is required before '...'",
+                        *span,
+                    ));
+                }
+                self.advance(1);
+                if !matches!(self.tokens, [(Token::RParen, _), ..]) {
+                    return Err(self.unexpected("')'"));
+                }
+                is_variadic = true;
+                break;
+            }
+            let Some(base_ty) = self.try_munch_base_type()? else {
+                return Err(self.unexpected("a parameter"));
+            };
+            let declarator = self.munch_any_declarator(base_ty)?;
+            let arg = declarator.name.unwrap_or_default();
+            if !arg.is_empty() {
+                self.declare_variable(&arg);
+            }
+            let arg_ty = match declarator.ty {
+                Type::Array(element_type, _) | Type::IncompleteArray(element_type) => {
+                    Type::Pointer(element_type)
+                }
+                ty @ Type::Function(_, _, _) => Type::Pointer(Box::new(ty)),
+                ty => ty,
+            };
+
+            match self.tokens {
+                [(Token::Comma, _), ..] => self.advance(1),
+                [(Token::RParen, _), ..] => {}
+                _ => return Err(self.unexpected("',' or ')'")),
+            }
+
+            args.push((arg, arg_ty));
         }
+
         self.advance(1);
-        TopLevel::FunctionDefinition(name, args, return_ty, statements)
+        Ok((args, is_variadic))
     }
 
-    pub fn munch_statement(&mut self) -> Statement {
-        match self.tokens {
+    pub fn munch_statement(&mut self) -> CompileResult<Statement> {
+        let start = self.next_start();
+        let kind = match self.tokens {
             [(Token::Return, _), ..] => self.munch_return(),
             [(Token::If, _), (Token::LParen, _), ..] => self.munch_if(),
             [(Token::While, _), (Token::LParen, _), ..] => self.munch_while(),
             [(Token::For, _), (Token::LParen, _), ..] => self.munch_for(),
+            [(Token::Do, _), ..] => self.munch_do_while(),
+            [(Token::Switch, _), (Token::LParen, _), ..] => self.munch_switch(),
+            [(Token::Break, _), ..] => {
+                self.advance(1);
+                self.expect(&Token::Semicolon)?;
+                Ok(StatementKind::Break)
+            }
+            [(Token::Continue, _), ..] => {
+                self.advance(1);
+                self.expect(&Token::Semicolon)?;
+                Ok(StatementKind::Continue)
+            }
+            [(Token::Goto, _), ..] => {
+                self.advance(1);
+                let label = self.munch_identifier()?;
+                self.expect(&Token::Semicolon)?;
+                Ok(StatementKind::Goto(label))
+            }
+            [(Token::Case, _), ..] => {
+                self.advance(1);
+                let expr = self.munch_conditional()?;
+                let (value, _) = Self::evaluate_integer_constant(&expr)?;
+                self.expect(&Token::Colon)?;
+                Ok(StatementKind::Case(
+                    value,
+                    Box::new(self.munch_statement()?),
+                ))
+            }
+            [(Token::Default, _), ..] => {
+                self.advance(1);
+                self.expect(&Token::Colon)?;
+                Ok(StatementKind::Default(Box::new(self.munch_statement()?)))
+            }
+            [(Token::Identifier(label), _), (Token::Colon, _), ..] => {
+                self.advance(2);
+                Ok(StatementKind::Labeled(
+                    label.clone(),
+                    Box::new(self.munch_statement()?),
+                ))
+            }
             [(Token::LBrace, _), ..] => self.munch_block(),
+            [(Token::Semicolon, _), ..] => {
+                self.advance(1);
+                Ok(StatementKind::Empty)
+            }
             _ => {
-                if let Some(ty) = self.try_munch_type() {
-                    self.munch_variable_declaration(ty)
+                if let Some(specifiers) = self.try_munch_declaration_specifiers()? {
+                    self.munch_variable_declaration(&specifiers)
                 } else {
                     self.munch_expr_statement()
                 }
             }
-        }
+        }?;
+        Ok(Statement::new(kind, self.span_from(start)))
     }
 
-    fn munch_expr_statement(&mut self) -> Statement {
-        let expr = self.munch_expr();
-        match self.tokens {
-            [(Token::Semicolon, _), ..] => {
-                self.advance(1);
-                Statement::Expr(expr)
-            }
-            _ => panic!("セミコロンがない"),
-        }
+    fn munch_expr_statement(&mut self) -> CompileResult<StatementKind> {
+        let expr = self.munch_expr()?;
+        self.expect(&Token::Semicolon)?;
+        Ok(StatementKind::Expr(expr))
     }
 
-    fn munch_variable_declaration(&mut self, ty: Type) -> Statement {
-        let name = if let Token::Identifier(name) = &self.tokens[0].0 {
-            name.clone()
-        } else {
-            panic!("parse error");
+    /// 初期化子の中では宣言したばかりの変数も参照できる。
+    fn munch_variable_declaration(
+        &mut self,
+        specifiers: &DeclarationSpecifiers,
+    ) -> CompileResult<StatementKind> {
+        if let [(Token::Semicolon, _), ..] = self.tokens {
+            self.advance(1);
+            return Ok(StatementKind::Empty);
+        }
+        let storage = match specifiers.storage_class {
+            Some(StorageClass::Typedef) => {
+                self.munch_typedefs(&specifiers.ty)?;
+                return Ok(StatementKind::Empty);
+            }
+            Some(StorageClass::Static) => Storage::Static,
+            Some(StorageClass::Extern) => Storage::Extern,
+            None => Storage::Automatic,
         };
-        self.advance(1);
 
-        match self.tokens {
-            [(Token::Semicolon, _), ..] => {
-                self.advance(1);
-                Statement::VariableDeclaration(name, ty)
-            }
-            [(Token::LBracket, _), (Token::Num(size), _), ..] => {
-                self.advance(2);
-                let size = *size;
-                assert!(self.tokens[0].0 == Token::RBracket);
-                self.advance(1);
-                assert!(self.tokens[0].0 == Token::Semicolon);
-                self.advance(1);
-                #[allow(clippy::cast_sign_loss)]
-                Statement::VariableDeclaration(name, Type::Array(Box::new(ty), size as usize))
+        let mut declarations = vec![];
+        loop {
+            let start = self.next_start();
+            let (name, ty) = self.munch_declarator(specifiers.ty.clone())?;
+            self.declare_variable(&name);
+            let initializer = match self.tokens {
+                [(Token::Assign, _), ..] if storage == Storage::Extern => {
+                    return Err(Self::error(
+                        &format!("{name} has both extern and an initializer"),
+                        self.span_from(start),
+                    ));
+                }
+                [(Token::Assign, _), ..] => {
+                    self.advance(1);
+                    Some(self.munch_initializer()?)
+                }
+                _ => None,
+            };
+            declarations.push(Declaration {
+                name,
+                ty,
+                initializer,
+                storage,
+            });
+
+            match self.tokens {
+                [(Token::Comma, _), ..] => self.advance(1),
+                _ => break,
             }
-            _ => panic!("セミコロンがない: {:?}", self.tokens[0].0),
         }
+        self.expect(&Token::Semicolon)?;
+        Ok(StatementKind::VariableDeclaration(declarations))
     }
 
-    fn munch_block(&mut self) -> Statement {
-        assert!(self.tokens[0].0 == Token::LBrace);
-        self.advance(1);
+    fn munch_block(&mut self) -> CompileResult<StatementKind> {
+        self.expect(&Token::LBrace)?;
+        self.scopes.push(Scope::default());
         let mut statements = Vec::new();
         loop {
             match self.tokens {
@@ -194,635 +701,1936 @@ impl<'a> Parser<'a> {
                     self.advance(1);
                     break;
                 }
-                _ => statements.push(self.munch_statement()),
+                _ => statements.push(self.munch_statement()?),
             }
         }
-        Statement::Block(statements)
+        self.scopes.pop();
+        Ok(StatementKind::Block(statements))
     }
 
-    fn munch_for(&mut self) -> Statement {
-        assert!(self.tokens[0].0 == Token::For);
-        assert!(self.tokens[1].0 == Token::LParen);
-        self.advance(2);
-        let init = self.munch_expr();
-        if let [(Token::Semicolon, _), ..] = self.tokens {
-            self.advance(1);
-        } else {
-            panic!("セミコロンがない");
-        }
-        let cond = self.munch_expr();
-        if let [(Token::Semicolon, _), ..] = self.tokens {
+    fn munch_for(&mut self) -> CompileResult<StatementKind> {
+        self.expect(&Token::For)?;
+        self.expect(&Token::LParen)?;
+        self.scopes.push(Scope::default());
+        let init_start = self.next_start();
+        let init_kind = if let [(Token::Semicolon, _), ..] = self.tokens {
             self.advance(1);
+            StatementKind::Empty
+        } else if let Some(ty) = self.try_munch_base_type()? {
+            let specifiers = DeclarationSpecifiers {
+                ty,
+                storage_class: None,
+            };
+            self.munch_variable_declaration(&specifiers)?
         } else {
-            panic!("セミコロンがない");
-        }
-        let update = self.munch_expr();
-        if let [(Token::RParen, _), ..] = self.tokens {
-            self.advance(1);
-        } else {
-            panic!("括弧が閉じられていない");
-        }
-        let body = self.munch_statement();
-        Statement::For(
+            self.munch_expr_statement()?
+        };
+        let init = Statement::new(init_kind, self.span_from(init_start));
+        let cond = match self.tokens {
+            [(Token::Semicolon, _), ..] => None,
+            _ => Some(Box::new(self.munch_expr()?)),
+        };
+        self.expect(&Token::Semicolon)?;
+        let update = match self.tokens {
+            [(Token::RParen, _), ..] => None,
+            _ => Some(Box::new(self.munch_expr()?)),
+        };
+        self.expect(&Token::RParen)?;
+        let body = self.munch_statement()?;
+        self.scopes.pop();
+        Ok(StatementKind::For(
             Box::new(init),
-            Box::new(cond),
-            Box::new(update),
+            cond,
+            update,
             Box::new(body),
-        )
+        ))
     }
 
-    fn munch_while(&mut self) -> Statement {
-        assert!(self.tokens[0].0 == Token::While, "parse error");
-        assert!(self.tokens[1].0 == Token::LParen, "parse error");
-        self.advance(2);
-        let cond = self.munch_expr();
+    fn munch_do_while(&mut self) -> CompileResult<StatementKind> {
+        self.expect(&Token::Do)?;
+        let body = self.munch_statement()?;
+        self.expect(&Token::While)?;
+        self.expect(&Token::LParen)?;
+        let cond = self.munch_expr()?;
+        self.expect(&Token::RParen)?;
+        self.expect(&Token::Semicolon)?;
+        Ok(StatementKind::DoWhile(Box::new(body), Box::new(cond)))
+    }
+
+    fn munch_switch(&mut self) -> CompileResult<StatementKind> {
+        self.expect(&Token::Switch)?;
+        self.expect(&Token::LParen)?;
+        let cond = self.munch_expr()?;
+        self.expect(&Token::RParen)?;
+        let body = self.munch_statement()?;
+        Ok(StatementKind::Switch(Box::new(cond), Box::new(body)))
+    }
+
+    fn munch_while(&mut self) -> CompileResult<StatementKind> {
+        self.expect(&Token::While)?;
+        self.expect(&Token::LParen)?;
+        let cond = self.munch_expr()?;
+        self.expect(&Token::RParen)?;
+        let body = self.munch_statement()?;
+        Ok(StatementKind::While(Box::new(cond), Box::new(body)))
+    }
+
+    fn munch_if(&mut self) -> CompileResult<StatementKind> {
+        self.expect(&Token::If)?;
+        self.expect(&Token::LParen)?;
+        let cond = self.munch_expr()?;
+        self.expect(&Token::RParen)?;
+        let then = self.munch_statement()?;
         match self.tokens {
-            [(Token::RParen, _), ..] => {
+            [(Token::Else, _), ..] => {
                 self.advance(1);
-                let body = self.munch_statement();
-                Statement::While(Box::new(cond), Box::new(body))
+                let els = self.munch_statement()?;
+                Ok(StatementKind::IfElse(
+                    Box::new(cond),
+                    Box::new(then),
+                    Box::new(els),
+                ))
             }
-            _ => panic!("括弧が閉じられていない"),
+            _ => Ok(StatementKind::If(Box::new(cond), Box::new(then))),
         }
     }
 
-    fn munch_if(&mut self) -> Statement {
-        assert!(self.tokens[0].0 == Token::If, "parse error");
-        assert!(self.tokens[1].0 == Token::LParen, "parse error");
-        self.advance(2);
-        let cond = self.munch_expr();
-        match self.tokens {
-            [(Token::RParen, _), ..] => {
-                self.advance(1);
-                let then = self.munch_statement();
-                match self.tokens {
-                    [(Token::Else, _), ..] => {
-                        self.advance(1);
-                        let els = self.munch_statement();
-                        Statement::IfElse(Box::new(cond), Box::new(then), Box::new(els))
-                    }
-                    _ => Statement::If(Box::new(cond), Box::new(then)),
+    fn munch_return(&mut self) -> CompileResult<StatementKind> {
+        self.expect(&Token::Return)?;
+        let expr = match self.tokens {
+            [(Token::Semicolon, _), ..] => None,
+            _ => Some(self.munch_expr()?),
+        };
+        let statment = StatementKind::Return(expr);
+        self.expect(&Token::Semicolon)?;
+        Ok(statment)
+    }
+
+    pub fn munch_expr(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_assign()?;
+
+        loop {
+            match self.tokens {
+                [(Token::Comma, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_assign()?;
+                    expr = Self::binary(ExprKind::Comma, expr, rhs);
                 }
+                _ => return Ok(expr),
             }
-            _ => panic!("括弧が閉じられていない"),
         }
     }
 
-    fn munch_return(&mut self) -> Statement {
-        assert!(self.tokens[0].0 == Token::Return, "parse error");
-        self.advance(1);
-        let statment = Statement::Return(self.munch_expr());
-        match self.tokens {
-            [(Token::Semicolon, _), ..] => {
+    pub fn munch_assign(&mut self) -> CompileResult<Expr> {
+        let expr = self.munch_conditional()?;
+
+        let operator: fn(_, _) -> _ = match self.tokens {
+            [(Token::Assign, _), ..] => {
                 self.advance(1);
-                statment
+                let rhs = self.munch_assign()?;
+                return Ok(Self::binary(ExprKind::Assign, expr, rhs));
             }
-            _ => panic!("セミコロンがない"),
-        }
+            [(Token::PlusAssign, _), ..] => ExprKind::Add,
+            [(Token::MinusAssign, _), ..] => ExprKind::Sub,
+            [(Token::AsteriskAssign, _), ..] => ExprKind::Mul,
+            [(Token::SlashAssign, _), ..] => ExprKind::Div,
+            [(Token::PercentAssign, _), ..] => ExprKind::Mod,
+            [(Token::LeftShiftAssign, _), ..] => ExprKind::LeftShift,
+            [(Token::RightShiftAssign, _), ..] => ExprKind::RightShift,
+            [(Token::AmpersandAssign, _), ..] => ExprKind::BitAnd,
+            [(Token::PipeAssign, _), ..] => ExprKind::BitOr,
+            [(Token::CaretAssign, _), ..] => ExprKind::BitXor,
+            _ => return Ok(expr),
+        };
+        self.advance(1);
+        let rhs = self.munch_assign()?;
+        let operation = Self::binary(operator, expr, rhs);
+        let span = operation.span;
+        Ok(Expr::new(
+            ExprKind::CompoundAssign(Box::new(operation)),
+            span,
+        ))
     }
 
-    pub fn munch_expr(&mut self) -> Expr {
-        self.munch_assign()
+    pub fn munch_conditional(&mut self) -> CompileResult<Expr> {
+        let cond = self.munch_logical_or()?;
+
+        let [(Token::Question, _), ..] = self.tokens else {
+            return Ok(cond);
+        };
+        self.advance(1);
+        let then = self.munch_expr()?;
+        self.expect(&Token::Colon)?;
+        let els = self.munch_conditional()?;
+        let span = cond.span.to(els.span);
+        Ok(Expr::new(
+            ExprKind::Conditional(Box::new(cond), Box::new(then), Box::new(els)),
+            span,
+        ))
     }
-    pub fn munch_assign(&mut self) -> Expr {
-        let mut expr = self.munch_equality();
+
+    pub fn munch_logical_or(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_logical_and()?;
 
         loop {
             match self.tokens {
-                [(Token::Assign, _), ..] => {
+                [(Token::LogicalOr, _), ..] => {
                     self.advance(1);
-                    let rhs = self.munch_assign();
-                    expr = Expr::Assign(Box::new(expr), Box::new(rhs));
+                    let rhs = self.munch_logical_and()?;
+                    expr = Self::binary(ExprKind::LogicalOr, expr, rhs);
                 }
-                _ => return expr,
+                _ => return Ok(expr),
             }
         }
     }
 
-    pub fn munch_equality(&mut self) -> Expr {
-        let mut expr = self.munch_relational();
+    fn munch_logical_and(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_bit_or()?;
 
         loop {
             match self.tokens {
-                [(Token::Equality, _), ..] => {
-                    self.advance(1);
-                    let rhs = self.munch_relational();
-                    expr = Expr::Equal(Box::new(expr), Box::new(rhs));
-                }
-                [(Token::Inequality, _), ..] => {
+                [(Token::LogicalAnd, _), ..] => {
                     self.advance(1);
-                    let rhs = self.munch_relational();
-                    expr = Expr::NotEqual(Box::new(expr), Box::new(rhs));
+                    let rhs = self.munch_bit_or()?;
+                    expr = Self::binary(ExprKind::LogicalAnd, expr, rhs);
                 }
-                _ => return expr,
+                _ => return Ok(expr),
             }
         }
     }
 
-    pub fn munch_relational(&mut self) -> Expr {
-        let mut expr = self.munch_add();
+    fn munch_bit_or(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_bit_xor()?;
 
         loop {
             match self.tokens {
-                [(Token::LessThan, _), ..] => {
-                    self.advance(1);
-                    let rhs = self.munch_add();
-                    expr = Expr::LessThan(Box::new(expr), Box::new(rhs));
-                }
-                [(Token::LessThanOrEqual, _), ..] => {
+                [(Token::Pipe, _), ..] => {
                     self.advance(1);
-                    let rhs = self.munch_add();
-                    expr = Expr::LessEqual(Box::new(expr), Box::new(rhs));
+                    let rhs = self.munch_bit_xor()?;
+                    expr = Self::binary(ExprKind::BitOr, expr, rhs);
                 }
-                [(Token::GreaterThan, _), ..] => {
-                    self.advance(1);
-                    let rhs = self.munch_add();
-                    expr = Expr::GreaterThan(Box::new(expr), Box::new(rhs));
-                }
-                [(Token::GreaterThanOrEqual, _), ..] => {
-                    self.advance(1);
-                    let rhs = self.munch_add();
-                    expr = Expr::GreaterEqual(Box::new(expr), Box::new(rhs));
-                }
-                _ => return expr,
+                _ => return Ok(expr),
             }
         }
     }
 
-    pub fn munch_add(&mut self) -> Expr {
-        let mut expr = self.munch_mul();
+    fn munch_bit_xor(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_bit_and()?;
 
         loop {
             match self.tokens {
-                [(Token::Plus, _), ..] => {
-                    self.advance(1);
-                    let rhs = self.munch_mul();
-                    expr = Expr::Add(Box::new(expr), Box::new(rhs));
-                }
-                [(Token::Minus, _), ..] => {
+                [(Token::Caret, _), ..] => {
                     self.advance(1);
-                    let rhs = self.munch_mul();
-                    expr = Expr::Sub(Box::new(expr), Box::new(rhs));
+                    let rhs = self.munch_bit_and()?;
+                    expr = Self::binary(ExprKind::BitXor, expr, rhs);
                 }
-                _ => return expr,
+                _ => return Ok(expr),
             }
         }
     }
 
-    fn munch_mul(&mut self) -> Expr {
-        let mut expr = self.munch_unary();
+    fn munch_bit_and(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_equality()?;
 
         loop {
             match self.tokens {
-                [(Token::Asterisk, _), ..] => {
-                    self.advance(1);
-                    let rhs = self.munch_unary();
-                    expr = Expr::Mul(Box::new(expr), Box::new(rhs));
-                }
-                [(Token::Slash, _), ..] => {
+                [(Token::Ampersand, _), ..] => {
                     self.advance(1);
-                    let rhs = self.munch_unary();
-                    expr = Expr::Div(Box::new(expr), Box::new(rhs));
+                    let rhs = self.munch_equality()?;
+                    expr = Self::binary(ExprKind::BitAnd, expr, rhs);
                 }
-                _ => return expr,
+                _ => return Ok(expr),
             }
         }
     }
 
-    fn munch_unary(&mut self) -> Expr {
-        match self.tokens {
-            [(Token::Plus, _), ..] => {
+    pub fn munch_equality(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_relational()?;
+
+        loop {
+            match self.tokens {
+                [(Token::Equality, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_relational()?;
+                    expr = Self::binary(ExprKind::Equal, expr, rhs);
+                }
+                [(Token::Inequality, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_relational()?;
+                    expr = Self::binary(ExprKind::NotEqual, expr, rhs);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    pub fn munch_relational(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_shift()?;
+
+        loop {
+            match self.tokens {
+                [(Token::LessThan, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_shift()?;
+                    expr = Self::binary(ExprKind::LessThan, expr, rhs);
+                }
+                [(Token::LessThanOrEqual, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_shift()?;
+                    expr = Self::binary(ExprKind::LessEqual, expr, rhs);
+                }
+                [(Token::GreaterThan, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_shift()?;
+                    expr = Self::binary(ExprKind::GreaterThan, expr, rhs);
+                }
+                [(Token::GreaterThanOrEqual, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_shift()?;
+                    expr = Self::binary(ExprKind::GreaterEqual, expr, rhs);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn munch_shift(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_add()?;
+
+        loop {
+            match self.tokens {
+                [(Token::LeftShift, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_add()?;
+                    expr = Self::binary(ExprKind::LeftShift, expr, rhs);
+                }
+                [(Token::RightShift, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_add()?;
+                    expr = Self::binary(ExprKind::RightShift, expr, rhs);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    pub fn munch_add(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_mul()?;
+
+        loop {
+            match self.tokens {
+                [(Token::Plus, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_mul()?;
+                    expr = Self::binary(ExprKind::Add, expr, rhs);
+                }
+                [(Token::Minus, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_mul()?;
+                    expr = Self::binary(ExprKind::Sub, expr, rhs);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn munch_mul(&mut self) -> CompileResult<Expr> {
+        let mut expr = self.munch_unary()?;
+
+        loop {
+            match self.tokens {
+                [(Token::Asterisk, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_unary()?;
+                    expr = Self::binary(ExprKind::Mul, expr, rhs);
+                }
+                [(Token::Slash, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_unary()?;
+                    expr = Self::binary(ExprKind::Div, expr, rhs);
+                }
+                [(Token::Percent, _), ..] => {
+                    self.advance(1);
+                    let rhs = self.munch_unary()?;
+                    expr = Self::binary(ExprKind::Mod, expr, rhs);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn munch_unary(&mut self) -> CompileResult<Expr> {
+        let start = self.next_start();
+        let kind = match self.tokens {
+            [(Token::Plus, _), ..] => {
                 self.advance(1);
-                self.munch_array_access()
+                self.munch_unary()?.kind
             }
             [(Token::Minus, _), ..] => {
                 self.advance(1);
-                Expr::Sub(Box::new(Expr::Num(0)), Box::new(self.munch_array_access()))
+                let zero = Expr::new(ExprKind::Num(0), self.span_from(start));
+                ExprKind::Sub(Box::new(zero), Box::new(self.munch_unary()?))
             }
             [(Token::Ampersand, _), ..] => {
                 self.advance(1);
-                Expr::Address(Box::new(self.munch_array_access()))
+                ExprKind::Address(Box::new(self.munch_unary()?))
+            }
+            [(Token::Exclamation, _), ..] => {
+                self.advance(1);
+                ExprKind::LogicalNot(Box::new(self.munch_unary()?))
+            }
+            [(token @ (Token::Increment | Token::Decrement), _), ..] => {
+                let operator: fn(_, _) -> _ = if *token == Token::Increment {
+                    ExprKind::Add
+                } else {
+                    ExprKind::Sub
+                };
+                self.advance(1);
+                let one = Expr::new(ExprKind::Num(1), self.span_from(start));
+                let operand = self.munch_unary()?;
+                let operation = Expr::new(
+                    operator(Box::new(operand), Box::new(one)),
+                    self.span_from(start),
+                );
+                ExprKind::CompoundAssign(Box::new(operation))
+            }
+            [(Token::Tilde, _), ..] => {
+                self.advance(1);
+                ExprKind::BitNot(Box::new(self.munch_unary()?))
             }
             [(Token::Asterisk, _), ..] => {
                 self.advance(1);
-                Expr::Dereference(Box::new(self.munch_array_access()))
+                ExprKind::Dereference(Box::new(self.munch_unary()?))
             }
-            _ => self.munch_array_access(),
-        }
+            [(Token::Sizeof, _), ..] => {
+                self.advance(1);
+                match self.try_munch_parenthesized_type()? {
+                    Some(ty) => ExprKind::SizeofType(ty),
+                    None => ExprKind::Sizeof(Box::new(self.munch_unary()?)),
+                }
+            }
+            [(Token::Alignof, _), ..] => {
+                self.advance(1);
+                let ty = self
+                    .try_munch_parenthesized_type()?
+                    .ok_or_else(|| self.unexpected("a parenthesized type name"))?;
+                ExprKind::Alignof(ty)
+            }
+            [(Token::LParen, _), ..] => match self.try_munch_parenthesized_type()? {
+                Some(ty) => ExprKind::Cast(ty, Box::new(self.munch_unary()?)),
+                None => return self.munch_postfix(),
+            },
+            _ => return self.munch_postfix(),
+        };
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    /// `(型名)` を読む。括弧の中が型名でなければ何も読まずに `None` を返す。
+    fn try_munch_parenthesized_type(&mut self) -> CompileResult<Option<Type>> {
+        let [(Token::LParen, _), ..] = self.tokens else {
+            return Ok(None);
+        };
+        let (rest, end) = (self.tokens, self.previous_end);
+        self.advance(1);
+        let Some(ty) = self.try_munch_type()? else {
+            (self.tokens, self.previous_end) = (rest, end);
+            return Ok(None);
+        };
+        self.expect(&Token::RParen)?;
+        Ok(Some(ty))
     }
 
-    pub fn munch_array_access(&mut self) -> Expr {
-        let mut expr = self.munch_primary();
+    /// `a[i]` は `*(a + i)` に、`p->m` は `(*p).m` に脱糖する。
+    pub fn munch_postfix(&mut self) -> CompileResult<Expr> {
+        let start = self.next_start();
+        let mut expr = self.munch_primary()?;
 
         loop {
             match self.tokens {
                 [(Token::LBracket, _), ..] => {
                     self.advance(1);
-                    let index = self.munch_expr();
-                    assert!(self.tokens[0].0 == Token::RBracket);
+                    let index = self.munch_expr()?;
+                    self.expect(&Token::RBracket)?;
+                    let span = self.span_from(start);
+                    let address = Expr::new(ExprKind::Add(Box::new(expr), Box::new(index)), span);
+                    expr = Expr::new(ExprKind::Dereference(Box::new(address)), span);
+                }
+                [(Token::LParen, _), ..] => {
+                    let args = self.munch_arguments()?;
+                    expr = Expr::new(
+                        ExprKind::FunctionCall(Box::new(expr), args),
+                        self.span_from(start),
+                    );
+                }
+                [(Token::Dot, _), ..] => {
                     self.advance(1);
-                    expr = Expr::Dereference(Box::new(Expr::Add(Box::new(expr), Box::new(index))));
+                    let member = self.munch_identifier()?;
+                    expr = Expr::new(
+                        ExprKind::Member(Box::new(expr), member),
+                        self.span_from(start),
+                    );
                 }
-                _ => return expr,
+                [(Token::Arrow, _), ..] => {
+                    self.advance(1);
+                    let member = self.munch_identifier()?;
+                    let span = self.span_from(start);
+                    let dereference = Expr::new(ExprKind::Dereference(Box::new(expr)), span);
+                    expr = Expr::new(ExprKind::Member(Box::new(dereference), member), span);
+                }
+                [(token @ (Token::Increment | Token::Decrement), token_span), ..] => {
+                    let operator: fn(_, _) -> _ = if *token == Token::Increment {
+                        ExprKind::Add
+                    } else {
+                        ExprKind::Sub
+                    };
+                    self.advance(1);
+                    let one = Expr::new(ExprKind::Num(1), *token_span);
+                    let span = self.span_from(start);
+                    let operation = Expr::new(operator(Box::new(expr), Box::new(one)), span);
+                    expr = Expr::new(ExprKind::PostfixAssign(Box::new(operation)), span);
+                }
+                _ => return Ok(expr),
             }
         }
     }
 
-    fn munch_primary(&mut self) -> Expr {
-        match self.tokens {
-            [(Token::Num(num), _), ..] => {
+    fn munch_arguments(&mut self) -> CompileResult<Vec<Expr>> {
+        self.expect(&Token::LParen)?;
+        let mut args = Vec::new();
+        loop {
+            if let [(Token::RParen, _), ..] = self.tokens {
                 self.advance(1);
-                Expr::Num(*num)
+                return Ok(args);
             }
-            [(Token::Identifier(name), _), (Token::LParen, _), ..] => {
-                self.advance(2);
-                let mut args = Vec::new();
-                loop {
-                    if let [(Token::RParen, _), ..] = self.tokens {
-                        self.advance(1);
-                        break;
-                    }
 
-                    args.push(self.munch_expr());
-                    match self.tokens {
-                        [(Token::Comma, _), ..] => {
-                            self.advance(1);
-                        }
-                        [(Token::RParen, _), ..] => {
-                            self.advance(1);
-                            break;
-                        }
-                        _ => panic!("引数の区切りが不正"),
-                    }
+            args.push(self.munch_assign()?);
+            match self.tokens {
+                [(Token::Comma, _), ..] => {
+                    self.advance(1);
                 }
-
-                Expr::FunctionCall(name.clone(), args)
+                [(Token::RParen, _), ..] => {
+                    self.advance(1);
+                    return Ok(args);
+                }
+                _ => return Err(self.unexpected("',' or ')'")),
             }
-            [(Token::Sizeof, _), (Token::LParen, _), ..] => {
-                self.advance(2);
-                let expr = self.munch_expr();
+        }
+    }
 
-                assert_eq!(self.tokens[0].0, Token::RParen,);
+    fn munch_primary(&mut self) -> CompileResult<Expr> {
+        let start = self.next_start();
+        let kind = match self.tokens {
+            [(Token::Num(literal), _), ..] => {
                 self.advance(1);
-
-                Expr::Sizeof(Box::new(expr))
+                #[allow(clippy::cast_possible_truncation)]
+                if literal.ty() == Type::IntTyp {
+                    ExprKind::Num(literal.value as i32)
+                } else {
+                    ExprKind::IntNum(*literal)
+                }
+            }
+            [(Token::StringLiteral(_), _), ..] => {
+                // 隣接する文字列リテラルは連結する。
+                let mut literals = vec![];
+                while let [(Token::StringLiteral(literal), _), ..] = self.tokens {
+                    literals.push(literal.clone());
+                    self.advance(1);
+                }
+                let literal = StringLiteral::concat(&literals).ok_or_else(|| {
+                    Self::error(
+                        "cannot concatenate string literals with different prefixes",
+                        self.span_from(start),
+                    )
+                })?;
+                ExprKind::StringLiteral(literal)
+            }
+            [(Token::FloatNum(literal), _), ..] => {
+                self.advance(1);
+                ExprKind::FloatNum(*literal)
+            }
+            [(Token::Identifier(name), _), (Token::LParen, _), ..]
+                if VA_BUILTINS.contains(&name.as_str()) =>
+            {
+                self.munch_va_builtin(name)?
             }
             [(Token::Identifier(name), _), ..] => {
                 self.advance(1);
-                Expr::Variable(name.clone())
+                self.find_enum_constant(name)
+                    .map_or_else(|| ExprKind::Variable(name.clone()), ExprKind::Num)
             }
             [(Token::LParen, _), ..] => {
                 self.advance(1);
-                let expr = self.munch_expr();
-                match self.tokens {
-                    [(Token::RParen, _), ..] => {
-                        self.advance(1);
-                        expr
+                let expr = self.munch_expr()?;
+                self.expect(&Token::RParen)?;
+                expr.kind
+            }
+            _ => return Err(self.unexpected("primary")),
+        };
+        Ok(Expr::new(kind, self.span_from(start)))
+    }
+
+    /// `<stdarg.h>` のマクロが展開される組み込み関数。
+    /// `va_end` は何もせず、`va_copy` は `va_list` の中身の代入にする。
+    fn munch_va_builtin(&mut self, name: &str) -> CompileResult<ExprKind> {
+        self.advance(2);
+        let ap = self.munch_assign()?;
+        let kind = match name {
+            "__builtin_va_start" => {
+                if let [(Token::Comma, _), ..] = self.tokens {
+                    self.advance(1);
+                    self.munch_assign()?;
+                }
+                ExprKind::VaStart(Box::new(ap))
+            }
+            "__builtin_va_arg" => {
+                self.expect(&Token::Comma)?;
+                let Some(ty) = self.try_munch_type()? else {
+                    return Err(self.unexpected("a type"));
+                };
+                ExprKind::VaArg(Box::new(ap), ty)
+            }
+            "__builtin_va_copy" => {
+                self.expect(&Token::Comma)?;
+                let source = self.munch_assign()?;
+                let dereference = |expr: Expr| {
+                    let span = expr.span;
+                    Expr::new(ExprKind::Dereference(Box::new(expr)), span)
+                };
+                let span = ap.span.to(source.span);
+                ExprKind::Cast(
+                    Type::Void,
+                    Box::new(Expr::new(
+                        ExprKind::Assign(Box::new(dereference(ap)), Box::new(dereference(source))),
+                        span,
+                    )),
+                )
+            }
+            _ => ExprKind::Cast(Type::Void, Box::new(ap)),
+        };
+        self.expect(&Token::RParen)?;
+        Ok(kind)
+    }
+
+    fn binary(constructor: fn(Box<Expr>, Box<Expr>) -> ExprKind, lhs: Expr, rhs: Expr) -> Expr {
+        let span = lhs.span.to(rhs.span);
+        Expr::new(constructor(Box::new(lhs), Box::new(rhs)), span)
+    }
+
+    fn munch_identifier(&mut self) -> CompileResult<String> {
+        if let [(Token::Identifier(name), _), ..] = self.tokens {
+            self.advance(1);
+            Ok(name.clone())
+        } else {
+            Err(self.unexpected("an identifier"))
+        }
+    }
+
+    /// 名前のない型 (`int *` や `int (*)[3]` など) を読む。
+    fn try_munch_type(&mut self) -> CompileResult<Option<Type>> {
+        let Some(base_ty) = self.try_munch_base_type()? else {
+            return Ok(None);
+        };
+        let start = self.next_start();
+        let declarator = self.munch_any_declarator(base_ty)?;
+        if declarator.name.is_some() {
+            return Err(Self::error(
+                "type name must not declare an identifier",
+                self.span_from(start),
+            ));
+        }
+        Ok(Some(declarator.ty))
+    }
+
+    /// 記憶域クラス指定子を書けない場所 (仮引数・メンバ・型名) の型を読む。
+    /// 型で始まらなければ `None` を返す。
+    fn try_munch_base_type(&mut self) -> CompileResult<Option<Type>> {
+        let start = self.next_start();
+        let Some(specifiers) = self.try_munch_declaration_specifiers()? else {
+            return Ok(None);
+        };
+        if specifiers.storage_class.is_some() {
+            return Err(Self::error(
+                "storage class specifier is not allowed here",
+                self.span_from(start),
+            ));
+        }
+        Ok(Some(specifiers.ty))
+    }
+
+    /// 型指定子・型修飾子・記憶域クラス指定子の並びを読む。順序は問わない。
+    /// typedef 名は、ほかの型指定子がまだなければ型指定子として読む。
+    fn try_munch_declaration_specifiers(&mut self) -> CompileResult<Option<DeclarationSpecifiers>> {
+        let start = self.next_start();
+        let mut storage_class = None;
+        let mut qualifiers = Qualifiers::default();
+        let mut arithmetic_specifiers = vec![];
+        let mut specified_type = None;
+        loop {
+            let has_type = specified_type.is_some() || !arithmetic_specifiers.is_empty();
+            match self.tokens {
+                [(token @ (Token::Typedef | Token::Static | Token::Extern), span), ..] => {
+                    if storage_class.is_some() {
+                        return Err(Self::error(
+                            "multiple storage classes in declaration specifiers",
+                            *span,
+                        ));
                     }
-                    [(token, pos), ..] => {
-                        self.error(&format!("expected ')', but got {token:?}"), *pos)
+                    storage_class = Some(match token {
+                        Token::Typedef => StorageClass::Typedef,
+                        Token::Static => StorageClass::Static,
+                        _ => StorageClass::Extern,
+                    });
+                    self.advance(1);
+                }
+                [(Token::Const, _), ..] => {
+                    qualifiers.is_const = true;
+                    self.advance(1);
+                }
+                [(Token::Volatile, _), ..] => {
+                    qualifiers.is_volatile = true;
+                    self.advance(1);
+                }
+                // このコンパイラでは生成するコードが変わらない指定子は読み飛ばす。
+                [(
+                    Token::Auto
+                    | Token::Register
+                    | Token::Inline
+                    | Token::Noreturn
+                    | Token::Restrict,
+                    _,
+                ), ..] => self.advance(1),
+                [(
+                    token @ (Token::Int
+                    | Token::Char
+                    | Token::Short
+                    | Token::Long
+                    | Token::Signed
+                    | Token::Unsigned
+                    | Token::Bool
+                    | Token::Float
+                    | Token::Double),
+                    span,
+                ), ..] => {
+                    if specified_type.is_some() {
+                        return Err(Self::error("invalid combination of type specifiers", *span));
                     }
-                    _ => panic!("かっこが閉じられていない"),
+                    arithmetic_specifiers.push(token.clone());
+                    self.advance(1);
+                }
+                [(Token::Void | Token::Struct | Token::Union | Token::Enum, span), ..] => {
+                    if has_type {
+                        return Err(Self::error("invalid combination of type specifiers", *span));
+                    }
+                    specified_type = Some(match self.tokens[0].0 {
+                        Token::Void => {
+                            self.advance(1);
+                            Type::Void
+                        }
+                        Token::Struct => self.munch_struct_specifier(StructKind::Struct)?,
+                        Token::Union => self.munch_struct_specifier(StructKind::Union)?,
+                        _ => self.munch_enum_specifier()?,
+                    });
+                }
+                [(Token::Identifier(name), _), ..] if !has_type => {
+                    let Some(ty) = self.find_typedef(name) else {
+                        break;
+                    };
+                    self.advance(1);
+                    specified_type = Some(ty);
                 }
+                _ => break,
             }
-            [(token, pos), ..] => {
-                self.error(&format!("expected primary but got {token:?}"), *pos);
+        }
+
+        let ty = match specified_type {
+            Some(ty) => ty,
+            None if !arithmetic_specifiers.is_empty() => {
+                Self::arithmetic_type(&arithmetic_specifiers, self.span_from(start))?
             }
-            [] => panic!("tokens are empty."),
+            None if storage_class.is_none() && qualifiers.is_empty() => return Ok(None),
+            None => return Err(self.unexpected("a type specifier")),
+        };
+        Ok(Some(DeclarationSpecifiers {
+            ty: ty.qualified(qualifiers),
+            storage_class,
+        }))
+    }
+
+    /// `unsigned long int` のような算術型の指定子の並びから型を決める。
+    fn arithmetic_type(specifiers: &[Token], span: Span) -> CompileResult<Type> {
+        let count = |token: Token| specifiers.iter().filter(|s| **s == token).count();
+        match (count(Token::Float), count(Token::Double), specifiers.len()) {
+            (1, 0, 1) => return Ok(Type::FloatTyp),
+            (0, 1, 1) => return Ok(Type::DoubleTyp),
+            (0, 1, 2) if count(Token::Long) == 1 => return Ok(Type::LongDoubleTyp),
+            (0, 0, _) => {}
+            _ => return Err(Self::error("invalid combination of type specifiers", span)),
         }
+        let ty = match (
+            count(Token::Bool),
+            count(Token::Char),
+            count(Token::Short),
+            count(Token::Long),
+            count(Token::Int),
+            count(Token::Signed),
+            count(Token::Unsigned),
+        ) {
+            (1, 0, 0, 0, 0, 0, 0) => Type::BoolTyp,
+            (0, 1, 0, 0, 0, 0 | 1, 0) => Type::CharTyp,
+            (0, 1, 0, 0, 0, 0, 1) => Type::UCharTyp,
+            (0, 0, 1, 0, 0 | 1, 0 | 1, 0) => Type::ShortTyp,
+            (0, 0, 1, 0, 0 | 1, 0, 1) => Type::UShortTyp,
+            (0, 0, 0, 0, 0 | 1, 0 | 1, 0) => Type::IntTyp,
+            (0, 0, 0, 0, 0 | 1, 0, 1) => Type::UIntTyp,
+            (0, 0, 0, 1, 0 | 1, 0 | 1, 0) => Type::LongTyp,
+            (0, 0, 0, 1, 0 | 1, 0, 1) => Type::ULongTyp,
+            (0, 0, 0, 2, 0 | 1, 0 | 1, 0) => Type::LongLongTyp,
+            (0, 0, 0, 2, 0 | 1, 0, 1) => Type::ULongLongTyp,
+            _ => return Err(Self::error("invalid combination of type specifiers", span)),
+        };
+        Ok(ty)
     }
 
-    fn try_munch_type(&mut self) -> Option<Type> {
-        let mut ty = match self.tokens {
-            [(Token::Int, _), ..] => {
+    /// `*` の後の修飾子はそのポインタ自身に付く。
+    fn munch_pointers(&mut self, mut ty: Type) -> Type {
+        while let [(Token::Asterisk, _), ..] = self.tokens {
+            self.advance(1);
+            let mut qualifiers = Qualifiers::default();
+            loop {
+                match self.tokens {
+                    [(Token::Const, _), ..] => qualifiers.is_const = true,
+                    [(Token::Volatile, _), ..] => qualifiers.is_volatile = true,
+                    [(Token::Restrict, _), ..] => {}
+                    _ => break,
+                }
                 self.advance(1);
-                Some(Type::IntTyp)
             }
-            _ => None,
-        }?;
+            ty = Type::Pointer(Box::new(ty)).qualified(qualifiers);
+        }
+        ty
+    }
+
+    /// `struct tag`、`struct tag { ... }`、`struct { ... }` を読む。union も同じ形をしている。
+    /// 本体を読む前にタグを登録するので、メンバから自分自身へのポインタを参照できる。
+    fn munch_struct_specifier(&mut self, kind: StructKind) -> CompileResult<Type> {
+        let start = self.next_start();
+        self.advance(1);
+        let tag = self.try_munch_tag();
+
+        if !matches!(self.tokens, [(Token::LBrace, _), ..]) {
+            let Some(tag) = tag else {
+                return Err(self.unexpected("a tag or '{'"));
+            };
+            return self
+                .find_or_declare_struct(kind, &tag, start)
+                .map(Type::Struct);
+        }
+
+        let struct_type = match &tag {
+            Some(tag) => self.declare_struct(kind, tag, start)?,
+            None => Rc::new(StructType::new(kind, None)),
+        };
+        self.advance(1);
+        let mut members: Vec<(String, Type)> = vec![];
+        while !matches!(self.tokens, [(Token::RBrace, _), ..]) {
+            let Some(base_ty) = self.try_munch_base_type()? else {
+                return Err(self.unexpected("a member declaration"));
+            };
+            loop {
+                let member_start = self.next_start();
+                let (name, ty) = self.munch_declarator(base_ty.clone())?;
+                if !ty.is_complete() {
+                    return Err(Self::error(
+                        &format!("member {name} has incomplete type {ty}"),
+                        self.span_from(member_start),
+                    ));
+                }
+                if members.iter().any(|(member, _)| *member == name) {
+                    return Err(Self::error(
+                        &format!("duplicate member {name}"),
+                        self.span_from(member_start),
+                    ));
+                }
+                members.push((name, ty));
+
+                if let [(Token::Comma, _), ..] = self.tokens {
+                    self.advance(1);
+                } else {
+                    break;
+                }
+            }
+            self.expect(&Token::Semicolon)?;
+        }
+        self.advance(1);
+
+        struct_type.define(members);
+        Ok(Type::Struct(struct_type))
+    }
+
+    /// `enum tag { A, B = 2 }` を読み、列挙定数を現在のスコープに登録する。
+    /// 列挙型は int として扱う。
+    fn munch_enum_specifier(&mut self) -> CompileResult<Type> {
+        let start = self.next_start();
+        self.expect(&Token::Enum)?;
+        let tag = self.try_munch_tag();
+
+        if !matches!(self.tokens, [(Token::LBrace, _), ..]) {
+            let Some(tag) = tag else {
+                return Err(self.unexpected("a tag or '{'"));
+            };
+            return match self.find_tag(&tag) {
+                Some(Tag::Enum) => Ok(Type::IntTyp),
+                Some(found) => Err(Self::tag_kind_mismatch(
+                    &tag,
+                    &found.to_string(),
+                    self.span_from(start),
+                )),
+                None => Err(Self::error(
+                    &format!("undefined enum {tag}"),
+                    self.span_from(start),
+                )),
+            };
+        }
+
+        if let Some(tag) = &tag {
+            if self.current_scope().tags.contains_key(tag) {
+                return Err(Self::error(
+                    &format!("redefinition of enum {tag}"),
+                    self.span_from(start),
+                ));
+            }
+            self.current_scope().tags.insert(tag.clone(), Tag::Enum);
+        }
+        self.advance(1);
+        let mut value = 0_i32;
+        loop {
+            let constant_start = self.next_start();
+            let name = self.munch_identifier()?;
+            if let [(Token::Assign, _), ..] = self.tokens {
+                self.advance(1);
+                let expr = self.munch_conditional()?;
+                let (constant, _) = Self::evaluate_integer_constant(&expr)?;
+                value = i32::try_from(constant).map_err(|_| {
+                    Self::error("enumerator value is out of range of int", expr.span)
+                })?;
+            }
+            if let Some(Identifier::EnumConstant(_)) = self.current_scope().identifiers.get(&name) {
+                return Err(Self::error(
+                    &format!("redefinition of enumerator {name}"),
+                    self.span_from(constant_start),
+                ));
+            }
+            self.current_scope()
+                .identifiers
+                .insert(name, Identifier::EnumConstant(value));
+
+            match self.tokens {
+                [(Token::Comma, _), (Token::RBrace, _), ..] => {
+                    self.advance(2);
+                    break;
+                }
+                [(Token::Comma, _), ..] => self.advance(1),
+                [(Token::RBrace, _), ..] => {
+                    self.advance(1);
+                    break;
+                }
+                _ => return Err(self.unexpected("',' or '}'")),
+            }
+            value = value.checked_add(1).ok_or_else(|| {
+                Self::error(
+                    "enumerator value overflows int",
+                    self.span_from(constant_start),
+                )
+            })?;
+        }
+        Ok(Type::IntTyp)
+    }
+
+    /// 列挙定数の値のように、構文解析の時点で値が決まる整数定数式を評価する。
+    fn evaluate_integer_constant(expr: &Expr) -> CompileResult<(i64, Type)> {
+        ConstantEvaluator::integer(CompileErrorKind::Parse).evaluate_integer(expr)
+    }
 
-        while self.tokens[0].0 == Token::Asterisk {
+    fn try_munch_tag(&mut self) -> Option<String> {
+        if let [(Token::Identifier(tag), _), ..] = self.tokens {
             self.advance(1);
-            ty = Type::Pointer(Box::new(ty));
+            Some(tag.clone())
+        } else {
+            None
         }
+    }
 
-        Some(ty)
+    /// 内側のスコープから順にタグを探す。
+    fn find_tag(&self, tag: &str) -> Option<Tag> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.tags.get(tag))
+            .cloned()
     }
 
-    fn error(&self, error_message: &str, pos: SourcePosition) -> ! {
-        eprint!(
-            "{input}\n{:width$}^{error_message}",
-            "",
-            width = pos.0,
-            input = self.raw_input
-        );
-        panic!("compile error @ {}", pos.0);
+    /// 見つからなければ不完全型として現在のスコープに宣言する。
+    fn find_or_declare_struct(
+        &mut self,
+        kind: StructKind,
+        tag: &str,
+        start: SourcePosition,
+    ) -> CompileResult<Rc<StructType>> {
+        match self.find_tag(tag) {
+            Some(Tag::Struct(struct_type)) if struct_type.kind == kind => Ok(struct_type),
+            Some(found) => Err(Self::tag_kind_mismatch(
+                tag,
+                &found.to_string(),
+                self.span_from(start),
+            )),
+            None => {
+                let struct_type = Rc::new(StructType::new(kind, Some(tag.to_string())));
+                self.current_scope()
+                    .tags
+                    .insert(tag.to_string(), Tag::Struct(struct_type.clone()));
+                Ok(struct_type)
+            }
+        }
+    }
+
+    /// 本体付きの宣言。同じスコープで前方宣言されていればそれを完成させる。
+    fn declare_struct(
+        &mut self,
+        kind: StructKind,
+        tag: &str,
+        start: SourcePosition,
+    ) -> CompileResult<Rc<StructType>> {
+        match self.current_scope().tags.get(tag).cloned() {
+            Some(Tag::Struct(struct_type)) if struct_type.kind == kind => {
+                if Type::Struct(struct_type.clone()).is_complete() {
+                    return Err(Self::error(
+                        &format!("redefinition of {struct_type}"),
+                        self.span_from(start),
+                    ));
+                }
+                Ok(struct_type)
+            }
+            Some(found) => Err(Self::tag_kind_mismatch(
+                tag,
+                &found.to_string(),
+                self.span_from(start),
+            )),
+            None => {
+                let struct_type = Rc::new(StructType::new(kind, Some(tag.to_string())));
+                self.current_scope()
+                    .tags
+                    .insert(tag.to_string(), Tag::Struct(struct_type.clone()));
+                Ok(struct_type)
+            }
+        }
+    }
+
+    fn tag_kind_mismatch(tag: &str, previous: &str, span: Span) -> CompileError {
+        Self::error(&format!("{tag} is already declared as {previous}"), span)
+    }
+
+    /// 変数は列挙定数を隠すので、宣言した名前も記録しておく。
+    fn declare_variable(&mut self, name: &str) {
+        self.current_scope()
+            .identifiers
+            .insert(name.to_string(), Identifier::Variable);
+    }
+
+    /// 同じスコープで同じ型に定義し直すことだけを許す。
+    fn declare_typedef(&mut self, name: String, ty: Type, span: Span) -> CompileResult<()> {
+        match self.current_scope().identifiers.get(&name) {
+            Some(Identifier::Typedef(previous)) if *previous != ty => {
+                return Err(Self::error(
+                    &format!("conflicting types for typedef {name}"),
+                    span,
+                ));
+            }
+            Some(Identifier::Variable | Identifier::EnumConstant(_)) => {
+                return Err(Self::error(
+                    &format!("{name} is redeclared as a different kind of symbol"),
+                    span,
+                ));
+            }
+            _ => {}
+        }
+        self.current_scope()
+            .identifiers
+            .insert(name, Identifier::Typedef(ty));
+        Ok(())
+    }
+
+    /// 内側のスコープで変数として宣言された名前は typedef 名を隠す。
+    fn find_typedef(&self, name: &str) -> Option<Type> {
+        match self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.identifiers.get(name))
+        {
+            Some(Identifier::Typedef(ty)) => Some(ty.clone()),
+            _ => None,
+        }
+    }
+
+    fn find_enum_constant(&self, name: &str) -> Option<i32> {
+        match self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.identifiers.get(name))
+        {
+            Some(Identifier::EnumConstant(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn current_scope(&mut self) -> &mut Scope {
+        self.scopes
+            .last_mut()
+            .expect("the file scope is never popped")
+    }
+
+    fn expect(&mut self, expected: &Token) -> CompileResult<()> {
+        match self.tokens {
+            [(token, _), ..] if token == expected => {
+                self.advance(1);
+                Ok(())
+            }
+            _ => Err(self.unexpected(&format!("'{expected}'"))),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> CompileError {
+        match self.tokens {
+            [(token, span), ..] => {
+                Self::error(&format!("expected {expected}, but got '{token}'"), *span)
+            }
+            [] => Self::error(
+                &format!("expected {expected}, but reached the end of input"),
+                Span::new(self.end_position, self.end_position),
+            ),
+        }
+    }
+
+    fn error(error_message: &str, span: Span) -> CompileError {
+        CompileError::new(CompileErrorKind::Parse, error_message, Some(span))
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_box_returns)]
 mod tests {
     use super::*;
+    use crate::lex::tokenize;
+
+    fn span(start: usize, end: usize) -> Span {
+        Span::new(SourcePosition(start), SourcePosition(end))
+    }
+
+    fn tokens_of(input: &str) -> Vec<PositionedToken> {
+        tokenize(&input.chars().collect::<Vec<char>>()).unwrap()
+    }
+
+    fn expr(kind: ExprKind, start: usize, end: usize) -> Box<Expr> {
+        Box::new(Expr::new(kind, span(start, end)))
+    }
+
+    fn num(n: i32, start: usize, end: usize) -> Box<Expr> {
+        expr(ExprKind::Num(n), start, end)
+    }
+
+    fn variable(name: &str, start: usize, end: usize) -> Box<Expr> {
+        expr(ExprKind::Variable(name.to_string()), start, end)
+    }
 
     #[test]
     fn test_munch_expr() {
-        let tokens = vec![
-            (Token::Identifier("b".to_string()), SourcePosition(45)),
-            (Token::LBracket, SourcePosition(0)),
-            (Token::Num(1), SourcePosition(0)),
-            (Token::RBracket, SourcePosition(2)),
-            (Token::Assign, SourcePosition(47)),
-            (Token::Plus, SourcePosition(0)),
-            (Token::Num(1), SourcePosition(0)),
-            (Token::Plus, SourcePosition(2)),
-            (Token::Num(2), SourcePosition(4)),
-            (Token::Asterisk, SourcePosition(6)),
-            (Token::Num(3), SourcePosition(8)),
-            (Token::Slash, SourcePosition(10)),
-            (Token::LParen, SourcePosition(12)),
-            (Token::Minus, SourcePosition(13)),
-            (Token::Num(4), SourcePosition(13)),
-            (Token::Minus, SourcePosition(15)),
-            (Token::Num(5), SourcePosition(16)),
-            (Token::RParen, SourcePosition(17)),
-            (Token::LessThan, SourcePosition(19)),
-            (Token::Identifier("a".to_string()), SourcePosition(21)),
-            (Token::GreaterThan, SourcePosition(23)),
-            (Token::Num(7), SourcePosition(25)),
-            (Token::GreaterThanOrEqual, SourcePosition(27)),
-            (Token::Ampersand, SourcePosition(0)),
-            (Token::Identifier("a".to_string()), SourcePosition(0)),
-            (Token::LessThanOrEqual, SourcePosition(32)),
-            (Token::Num(9), SourcePosition(35)),
-            (Token::Equality, SourcePosition(37)),
-            (Token::Num(10), SourcePosition(40)),
-            (Token::Inequality, SourcePosition(42)),
-            (Token::Sizeof, SourcePosition(45)),
-            (Token::LParen, SourcePosition(51)),
-            (Token::Asterisk, SourcePosition(46)),
-            (Token::Identifier("b".to_string()), SourcePosition(45)),
-            (Token::RParen, SourcePosition(47)),
-        ];
-
-        let mut parser = Parser::new(
-            &tokens,
-            "b[1] = +1 + 2 * 3 / (4-5) < a > 7 >= &a <= 9 == 10 != sizeof(*b);",
-        );
-        let expr = parser.munch_expr();
+        let input = "b[1] = +1 + 2 * 3 / (-4-5) < a > 7 >= &a <= 9 == 10 != sizeof(*b);";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let parsed = parser.munch_expr().unwrap();
         assert_eq!(
-            expr,
-            Expr::Assign(
-                Box::new(Expr::Dereference(Box::new(Expr::Add(
-                    Box::new(Expr::Variable("b".to_string())),
-                    Box::new(Expr::Num(1))
-                )))),
-                Box::new(Expr::NotEqual(
-                    Box::new(Expr::Equal(
-                        Box::new(Expr::LessEqual(
-                            Box::new(Expr::GreaterEqual(
-                                Box::new(Expr::GreaterThan(
-                                    Box::new(Expr::LessThan(
-                                        Box::new(Expr::Add(
-                                            Box::new(Expr::Num(1)),
-                                            Box::new(Expr::Div(
-                                                Box::new(Expr::Mul(
-                                                    Box::new(Expr::Num(2)),
-                                                    Box::new(Expr::Num(3))
-                                                )),
-                                                Box::new(Expr::Sub(
-                                                    Box::new(Expr::Sub(
-                                                        Box::new(Expr::Num(0)),
-                                                        Box::new(Expr::Num(4))
+            parsed,
+            Expr::new(
+                ExprKind::Assign(
+                    expr_dereference_add(),
+                    Box::new(Expr::new(
+                        ExprKind::NotEqual(
+                            Box::new(Expr::new(
+                                ExprKind::Equal(
+                                    Box::new(Expr::new(
+                                        ExprKind::LessEqual(
+                                            Box::new(Expr::new(
+                                                ExprKind::GreaterEqual(
+                                                    Box::new(Expr::new(
+                                                        ExprKind::GreaterThan(
+                                                            Box::new(Expr::new(
+                                                                ExprKind::LessThan(
+                                                                    expr_arithmetic(),
+                                                                    variable("a", 29, 30),
+                                                                ),
+                                                                span(7, 30),
+                                                            )),
+                                                            num(7, 33, 34),
+                                                        ),
+                                                        span(7, 34),
                                                     )),
-                                                    Box::new(Expr::Num(5))
-                                                ))
-                                            ))
-                                        )),
-                                        Box::new(Expr::Variable("a".to_string()))
+                                                    expr(
+                                                        ExprKind::Address(variable("a", 39, 40)),
+                                                        38,
+                                                        40,
+                                                    ),
+                                                ),
+                                                span(7, 40),
+                                            )),
+                                            num(9, 44, 45),
+                                        ),
+                                        span(7, 45),
                                     )),
-                                    Box::new(Expr::Num(7))
-                                )),
-                                Box::new(Expr::Address(Box::new(Expr::Variable("a".to_string()))))
+                                    num(10, 49, 51),
+                                ),
+                                span(7, 51),
                             )),
-                            Box::new(Expr::Num(9)),
-                        )),
-                        Box::new(Expr::Num(10)),
+                            expr(
+                                ExprKind::Sizeof(expr(
+                                    ExprKind::Dereference(variable("b", 63, 64)),
+                                    61,
+                                    65,
+                                )),
+                                55,
+                                65,
+                            ),
+                        ),
+                        span(7, 65),
                     )),
-                    Box::new(Expr::Sizeof(Box::new(Expr::Dereference(Box::new(
-                        Expr::Variable("b".to_string())
-                    )))))
-                ))
+                ),
+                span(0, 65),
             )
         );
     }
 
+    /// `b[1]`
+    fn expr_dereference_add() -> Box<Expr> {
+        expr(
+            ExprKind::Dereference(expr(ExprKind::Add(variable("b", 0, 1), num(1, 2, 3)), 0, 4)),
+            0,
+            4,
+        )
+    }
+
+    /// `+1 + 2 * 3 / (-4-5)`
+    fn expr_arithmetic() -> Box<Expr> {
+        expr(
+            ExprKind::Add(
+                num(1, 7, 9),
+                expr(
+                    ExprKind::Div(
+                        expr(ExprKind::Mul(num(2, 12, 13), num(3, 16, 17)), 12, 17),
+                        expr(
+                            ExprKind::Sub(
+                                expr(ExprKind::Sub(num(0, 21, 22), num(4, 22, 23)), 21, 23),
+                                num(5, 24, 25),
+                            ),
+                            20,
+                            26,
+                        ),
+                    ),
+                    12,
+                    26,
+                ),
+            ),
+            7,
+            26,
+        )
+    }
+
     #[test]
-    fn test_munch_statement_with_expr() {
-        let tokens = vec![
-            (Token::Identifier("a".to_string()), SourcePosition(0)),
-            (Token::Assign, SourcePosition(2)),
-            (Token::Num(1), SourcePosition(4)),
-            (Token::Semicolon, SourcePosition(5)),
-        ];
+    fn test_munch_adjacent_string_literals() {
+        let input = r#""ab" "c""#;
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        assert_eq!(
+            parser.munch_expr().unwrap(),
+            *expr(
+                ExprKind::StringLiteral(StringLiteral::narrow(b"abc".to_vec())),
+                0,
+                8
+            )
+        );
+    }
 
-        let mut parser = Parser::new(&tokens, "a = 1;");
-        let statement = parser.munch_statement();
+    #[test]
+    fn test_munch_statement_with_expr() {
+        let input = "a = 1;";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let statement = parser.munch_statement().unwrap();
 
         assert_eq!(
             statement,
-            Statement::Expr(Expr::Assign(
-                Box::new(Expr::Variable("a".to_string())),
-                Box::new(Expr::Num(1))
-            ))
+            Statement::new(
+                StatementKind::Expr(Expr::new(
+                    ExprKind::Assign(variable("a", 0, 1), num(1, 4, 5)),
+                    span(0, 5)
+                )),
+                span(0, 6)
+            )
         );
     }
 
     #[test]
     fn test_munch_statement_with_if() {
-        let tokens = vec![
-            (Token::If, SourcePosition(0)),
-            (Token::LParen, SourcePosition(2)),
-            (Token::Num(1), SourcePosition(3)),
-            (Token::RParen, SourcePosition(4)),
-            (Token::LBrace, SourcePosition(6)),
-            (Token::Num(1), SourcePosition(0)),
-            (Token::Semicolon, SourcePosition(1)),
-            (Token::Num(2), SourcePosition(2)),
-            (Token::Semicolon, SourcePosition(3)),
-            (Token::RBrace, SourcePosition(7)),
-        ];
-
-        let mut parser = Parser::new(&tokens, "if (1) {1; 2;}");
-        let statement = parser.munch_statement();
+        let input = "if (1) {1; 2;}";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let statement = parser.munch_statement().unwrap();
 
         assert_eq!(
             statement,
-            Statement::If(
-                Box::new(Expr::Num(1)),
-                Box::new(Statement::Block(vec![
-                    Statement::Expr(Expr::Num(1)),
-                    Statement::Expr(Expr::Num(2))
-                ]))
+            Statement::new(
+                StatementKind::If(
+                    num(1, 4, 5),
+                    Box::new(Statement::new(
+                        StatementKind::Block(vec![
+                            Statement::new(StatementKind::Expr(*num(1, 8, 9)), span(8, 10)),
+                            Statement::new(StatementKind::Expr(*num(2, 11, 12)), span(11, 13)),
+                        ]),
+                        span(7, 14)
+                    ))
+                ),
+                span(0, 14)
             )
         );
     }
 
     #[test]
     fn test_munch_statement_with_for() {
-        let tokens = vec![
-            (Token::For, SourcePosition(0)),
-            (Token::LParen, SourcePosition(3)),
-            (Token::Identifier("i".to_string()), SourcePosition(4)),
-            (Token::Assign, SourcePosition(6)),
-            (Token::Num(0), SourcePosition(8)),
-            (Token::Semicolon, SourcePosition(9)),
-            (Token::Identifier("i".to_string()), SourcePosition(11)),
-            (Token::LessThan, SourcePosition(13)),
-            (Token::Num(10), SourcePosition(15)),
-            (Token::Semicolon, SourcePosition(17)),
-            (Token::Identifier("i".to_string()), SourcePosition(19)),
-            (Token::Assign, SourcePosition(21)),
-            (Token::Identifier("i".to_string()), SourcePosition(23)),
-            (Token::Plus, SourcePosition(25)),
-            (Token::Num(1), SourcePosition(27)),
-            (Token::RParen, SourcePosition(28)),
-            (Token::LBrace, SourcePosition(30)),
-            (Token::Num(1), SourcePosition(0)),
-            (Token::Semicolon, SourcePosition(1)),
-            (Token::Num(2), SourcePosition(2)),
-            (Token::Semicolon, SourcePosition(3)),
-            (Token::RBrace, SourcePosition(31)),
-        ];
-
-        let mut parser = Parser::new(&tokens, "for (i = 0; i < 10; i = i + 1) {1; 2;}");
-        let statement = parser.munch_statement();
+        let input = "for (i = 0; i < 10; i = i + 1) {1; 2;}";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let statement = parser.munch_statement().unwrap();
 
         assert_eq!(
             statement,
-            Statement::For(
-                Box::new(Expr::Assign(
-                    Box::new(Expr::Variable("i".to_string())),
-                    Box::new(Expr::Num(0))
-                )),
-                Box::new(Expr::LessThan(
-                    Box::new(Expr::Variable("i".to_string())),
-                    Box::new(Expr::Num(10))
-                )),
-                Box::new(Expr::Assign(
-                    Box::new(Expr::Variable("i".to_string())),
-                    Box::new(Expr::Add(
-                        Box::new(Expr::Variable("i".to_string())),
-                        Box::new(Expr::Num(1))
+            Statement::new(
+                StatementKind::For(
+                    Box::new(Statement::new(
+                        StatementKind::Expr(*expr(
+                            ExprKind::Assign(variable("i", 5, 6), num(0, 9, 10)),
+                            5,
+                            10
+                        )),
+                        span(5, 11)
+                    )),
+                    Some(expr(
+                        ExprKind::LessThan(variable("i", 12, 13), num(10, 16, 18)),
+                        12,
+                        18
+                    )),
+                    Some(expr(
+                        ExprKind::Assign(
+                            variable("i", 20, 21),
+                            expr(ExprKind::Add(variable("i", 24, 25), num(1, 28, 29)), 24, 29)
+                        ),
+                        20,
+                        29
+                    )),
+                    Box::new(Statement::new(
+                        StatementKind::Block(vec![
+                            Statement::new(StatementKind::Expr(*num(1, 32, 33)), span(32, 34)),
+                            Statement::new(StatementKind::Expr(*num(2, 35, 36)), span(35, 37)),
+                        ]),
+                        span(31, 38)
                     ))
-                )),
-                Box::new(Statement::Block(vec![
-                    Statement::Expr(Expr::Num(1)),
-                    Statement::Expr(Expr::Num(2))
-                ]))
+                ),
+                span(0, 38)
+            )
+        );
+    }
+
+    #[test]
+    fn test_munch_for_without_clauses() {
+        let input = "for (;;) ;";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let statement = parser.munch_statement().unwrap();
+
+        assert_eq!(
+            statement,
+            Statement::new(
+                StatementKind::For(
+                    Box::new(Statement::new(StatementKind::Empty, span(5, 6))),
+                    None,
+                    None,
+                    Box::new(Statement::new(StatementKind::Empty, span(9, 10)))
+                ),
+                span(0, 10)
+            )
+        );
+    }
+
+    #[test]
+    fn test_munch_statement_with_initialized_declarations() {
+        let input = "int a = 1, b[2] = {[1] = a};";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let statement = parser.munch_statement().unwrap();
+
+        assert_eq!(
+            statement,
+            Statement::new(
+                StatementKind::VariableDeclaration(vec![
+                    Declaration {
+                        name: "a".to_string(),
+                        ty: Type::IntTyp,
+                        initializer: Some(Initializer::Expr(*num(1, 8, 9))),
+                        storage: Storage::Automatic,
+                    },
+                    Declaration {
+                        name: "b".to_string(),
+                        ty: Type::Array(Box::new(Type::IntTyp), 2),
+                        initializer: Some(Initializer::List(
+                            vec![Initializer::Designated(
+                                vec![Designator::Index(1, span(19, 22))],
+                                Box::new(Initializer::Expr(*variable("a", 25, 26))),
+                                span(19, 26)
+                            )],
+                            span(18, 27)
+                        )),
+                        storage: Storage::Automatic,
+                    },
+                ]),
+                span(0, 28)
             )
         );
     }
 
     #[test]
     fn test_munch_statement_with_while() {
-        let tokens = vec![
-            (Token::While, SourcePosition(0)),
-            (Token::LParen, SourcePosition(5)),
-            (Token::Num(1), SourcePosition(6)),
-            (Token::RParen, SourcePosition(7)),
-            (Token::LBrace, SourcePosition(9)),
-            (Token::Num(1), SourcePosition(0)),
-            (Token::Semicolon, SourcePosition(1)),
-            (Token::Num(2), SourcePosition(2)),
-            (Token::Semicolon, SourcePosition(3)),
-            (Token::RBrace, SourcePosition(10)),
-        ];
-
-        let mut parser = Parser::new(&tokens, "while (1){1;2;}");
-        let statement = parser.munch_statement();
+        let input = "while (1){1;2;}";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let statement = parser.munch_statement().unwrap();
 
         assert_eq!(
             statement,
-            Statement::While(
-                Box::new(Expr::Num(1)),
-                Box::new(Statement::Block(vec![
-                    Statement::Expr(Expr::Num(1)),
-                    Statement::Expr(Expr::Num(2))
-                ]))
+            Statement::new(
+                StatementKind::While(
+                    num(1, 7, 8),
+                    Box::new(Statement::new(
+                        StatementKind::Block(vec![
+                            Statement::new(StatementKind::Expr(*num(1, 10, 11)), span(10, 12)),
+                            Statement::new(StatementKind::Expr(*num(2, 12, 13)), span(12, 14)),
+                        ]),
+                        span(9, 15)
+                    ))
+                ),
+                span(0, 15)
+            )
+        );
+    }
+
+    #[test]
+    fn test_munch_statement_with_switch() {
+        let input = "switch (1) { case 2 + 1: break; default: l: continue; }";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let statement = parser.munch_statement().unwrap();
+
+        assert_eq!(
+            statement,
+            Statement::new(
+                StatementKind::Switch(
+                    num(1, 8, 9),
+                    Box::new(Statement::new(
+                        StatementKind::Block(vec![
+                            Statement::new(
+                                StatementKind::Case(
+                                    3,
+                                    Box::new(Statement::new(StatementKind::Break, span(25, 31)))
+                                ),
+                                span(13, 31)
+                            ),
+                            Statement::new(
+                                StatementKind::Default(Box::new(Statement::new(
+                                    StatementKind::Labeled(
+                                        "l".to_string(),
+                                        Box::new(Statement::new(
+                                            StatementKind::Continue,
+                                            span(44, 53)
+                                        ))
+                                    ),
+                                    span(41, 53)
+                                ))),
+                                span(32, 53)
+                            ),
+                        ]),
+                        span(11, 55)
+                    ))
+                ),
+                span(0, 55)
             )
         );
     }
 
     #[test]
     fn test_munch_statement_with_block() {
-        let tokens = vec![
-            (Token::LBrace, SourcePosition(0)),
-            (Token::Num(1), SourcePosition(0)),
-            (Token::Semicolon, SourcePosition(0)),
-            (Token::Num(2), SourcePosition(0)),
-            (Token::Semicolon, SourcePosition(0)),
-            (Token::RBrace, SourcePosition(0)),
-        ];
-
-        let mut parser = Parser::new(&tokens, "{}");
-        let statement = parser.munch_statement();
+        let input = "{1;2;}";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let statement = parser.munch_statement().unwrap();
 
         assert_eq!(
             statement,
-            Statement::Block(vec![
-                Statement::Expr(Expr::Num(1)),
-                Statement::Expr(Expr::Num(2))
-            ])
+            Statement::new(
+                StatementKind::Block(vec![
+                    Statement::new(StatementKind::Expr(*num(1, 1, 2)), span(1, 3)),
+                    Statement::new(StatementKind::Expr(*num(2, 3, 4)), span(3, 5)),
+                ]),
+                span(0, 6)
+            )
         );
     }
 
     #[test]
     fn test_munch_top_level() {
-        let tokens = vec![
-            (Token::Int, SourcePosition(0)),
-            (Token::Identifier("f".to_string()), SourcePosition(0)),
-            (Token::LParen, SourcePosition(0)),
-            (Token::Int, SourcePosition(0)),
-            (Token::Identifier("a".to_string()), SourcePosition(0)),
-            (Token::Comma, SourcePosition(0)),
-            (Token::Int, SourcePosition(0)),
-            (Token::Identifier("b".to_string()), SourcePosition(0)),
-            (Token::RParen, SourcePosition(0)),
-            (Token::LBrace, SourcePosition(0)),
-            (Token::Int, SourcePosition(0)),
-            (Token::Identifier("c".to_string()), SourcePosition(0)),
-            (Token::LBracket, SourcePosition(0)),
-            (Token::Num(5), SourcePosition(0)),
-            (Token::RBracket, SourcePosition(0)),
-            (Token::Semicolon, SourcePosition(0)),
-            (Token::Num(1), SourcePosition(0)),
-            (Token::Semicolon, SourcePosition(0)),
-            (Token::Num(2), SourcePosition(0)),
-            (Token::Semicolon, SourcePosition(0)),
-            (Token::RBrace, SourcePosition(0)),
-        ];
-
-        let mut parser = Parser::new(&tokens, "int f(int a, int b) {int c[5]; 1;2;}");
-        let top_level = parser.munch_top_level();
+        let input = "int f(int a, int b) {int c[5]; 1;2;}";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let top_levels = parser.munch_top_level().unwrap();
+
+        assert_eq!(
+            top_levels,
+            vec![TopLevel::new(
+                TopLevelKind::FunctionDefinition(
+                    "f".to_string(),
+                    vec![
+                        ("a".to_string(), Type::IntTyp),
+                        ("b".to_string(), Type::IntTyp)
+                    ],
+                    false,
+                    Type::IntTyp,
+                    vec![
+                        Statement::new(
+                            StatementKind::VariableDeclaration(vec![Declaration {
+                                name: "c".to_string(),
+                                ty: Type::Array(Box::new(Type::IntTyp), 5),
+                                initializer: None,
+                                storage: Storage::Automatic,
+                            }]),
+                            span(21, 30)
+                        ),
+                        Statement::new(StatementKind::Expr(*num(1, 31, 32)), span(31, 33)),
+                        Statement::new(StatementKind::Expr(*num(2, 33, 34)), span(33, 35)),
+                    ],
+                    Linkage::External
+                ),
+                span(0, 36)
+            )]
+        );
+    }
+
+    #[test]
+    fn test_munch_global_variable() {
+        let input = "int *p; int a[3] = {1, 2,};";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let program = parser.munch_program().unwrap();
 
         assert_eq!(
-            top_level,
-            TopLevel::FunctionDefinition(
-                "f".to_string(),
-                vec![
-                    ("a".to_string(), Type::IntTyp),
-                    ("b".to_string(), Type::IntTyp)
-                ],
-                Type::IntTyp,
-                vec![
-                    Statement::VariableDeclaration(
-                        "c".to_string(),
-                        Type::Array(Box::new(Type::IntTyp), 5)
+            program,
+            vec![
+                TopLevel::new(
+                    TopLevelKind::GlobalVariable(
+                        "p".to_string(),
+                        Type::Pointer(Box::new(Type::IntTyp)),
+                        None,
+                        Linkage::External
                     ),
-                    Statement::Expr(Expr::Num(1)),
-                    Statement::Expr(Expr::Num(2))
-                ]
+                    span(0, 7)
+                ),
+                TopLevel::new(
+                    TopLevelKind::GlobalVariable(
+                        "a".to_string(),
+                        Type::Array(Box::new(Type::IntTyp), 3),
+                        Some(Initializer::List(
+                            vec![
+                                Initializer::Expr(*num(1, 20, 21)),
+                                Initializer::Expr(*num(2, 23, 24)),
+                            ],
+                            span(19, 26)
+                        )),
+                        Linkage::External
+                    ),
+                    span(8, 27)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_munch_nested_declarators() {
+        let input = "int (*p)[3]; int *a[3]; int m[2][3]; int (*f)(int, char *); int (*g[2])(int);";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let types = parser
+            .munch_program()
+            .unwrap()
+            .into_iter()
+            .map(|top_level| match top_level.kind {
+                TopLevelKind::GlobalVariable(name, ty, _, _) => (name, ty),
+                kind => panic!("unexpected top level: {kind:?}"),
+            })
+            .collect::<Vec<_>>();
+
+        let int = || Box::new(Type::IntTyp);
+        let function = Type::Function(
+            vec![Type::IntTyp, Type::Pointer(Box::new(Type::CharTyp))],
+            int(),
+            false,
+        );
+        assert_eq!(
+            types,
+            vec![
+                (
+                    "p".to_string(),
+                    Type::Pointer(Box::new(Type::Array(int(), 3)))
+                ),
+                (
+                    "a".to_string(),
+                    Type::Array(Box::new(Type::Pointer(int())), 3)
+                ),
+                (
+                    "m".to_string(),
+                    Type::Array(Box::new(Type::Array(int(), 3)), 2)
+                ),
+                ("f".to_string(), Type::Pointer(Box::new(function))),
+                (
+                    "g".to_string(),
+                    Type::Array(
+                        Box::new(Type::Pointer(Box::new(Type::Function(
+                            vec![Type::IntTyp],
+                            int(),
+                            false
+                        )))),
+                        2
+                    )
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_munch_variadic_functions() {
+        let input =
+            "int printf(char *fmt, ...); int (*p)(int, ...); int f(int n, ...) { return n; }";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let program = parser.munch_program().unwrap();
+
+        let int = || Box::new(Type::IntTyp);
+        assert!(matches!(
+            &program[0].kind,
+            TopLevelKind::ExternalFunctionDeclaration(name, params, true, Type::IntTyp)
+                if name == "printf" && params.len() == 1
+        ));
+        let TopLevelKind::GlobalVariable(_, ty, _, _) = &program[1].kind else {
+            panic!("unexpected top level: {:?}", program[1].kind);
+        };
+        assert_eq!(
+            *ty,
+            Type::Pointer(Box::new(Type::Function(vec![Type::IntTyp], int(), true)))
+        );
+        assert!(matches!(
+            &program[2].kind,
+            TopLevelKind::FunctionDefinition(_, params, true, Type::IntTyp, _, _)
+                if params.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_munch_variadic_requires_named_parameter() {
+        let input = "int f(...);";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        assert!(parser.munch_program().is_err());
+    }
+
+    #[test]
+    fn test_munch_abstract_declarator_in_sizeof() {
+        let input = "sizeof(int (*)[3])";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let expr = parser.munch_expr().unwrap();
+
+        assert_eq!(
+            expr.kind,
+            ExprKind::SizeofType(Type::Pointer(Box::new(Type::Array(
+                Box::new(Type::IntTyp),
+                3
+            ))))
+        );
+    }
+
+    #[test]
+    fn test_munch_qualified_declarators() {
+        let input = "const int *volatile p; int const *const q;";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let program = parser.munch_program().unwrap();
+        let types = program
+            .iter()
+            .map(|top_level| match &top_level.kind {
+                TopLevelKind::GlobalVariable(_, ty, _, _) => ty.clone(),
+                kind => panic!("unexpected top level: {kind:?}"),
+            })
+            .collect::<Vec<_>>();
+        let const_only = Qualifiers {
+            is_const: true,
+            is_volatile: false,
+        };
+
+        assert_eq!(
+            types,
+            vec![
+                Type::Pointer(Box::new(Type::IntTyp.qualified(const_only))).qualified(Qualifiers {
+                    is_const: false,
+                    is_volatile: true,
+                }),
+                Type::Pointer(Box::new(Type::IntTyp.qualified(const_only))).qualified(const_only),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_munch_typedef() {
+        let input = "typedef int t, *tp; static tp p; int main() { int t = 1; return t; }";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let program = parser.munch_program().unwrap();
+
+        assert_eq!(program.len(), 2);
+        assert_eq!(
+            program[0].kind,
+            TopLevelKind::GlobalVariable(
+                "p".to_string(),
+                Type::Pointer(Box::new(Type::IntTyp)),
+                None,
+                Linkage::Internal
             )
         );
+        let TopLevelKind::FunctionDefinition(_, _, _, _, statements, _) = &program[1].kind else {
+            panic!("expected a function definition");
+        };
+        assert!(matches!(
+            &statements[0].kind,
+            StatementKind::VariableDeclaration(declarations) if declarations[0].name == "t"
+        ));
+    }
+
+    #[test]
+    fn test_munch_cast_and_sizeof_without_parentheses() {
+        let input = "(char *)p + sizeof x";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let parsed = parser.munch_expr().unwrap();
+
+        assert_eq!(
+            parsed,
+            *expr(
+                ExprKind::Add(
+                    expr(
+                        ExprKind::Cast(Type::Pointer(Box::new(Type::CharTyp)), variable("p", 8, 9)),
+                        0,
+                        9
+                    ),
+                    expr(ExprKind::Sizeof(variable("x", 19, 20)), 12, 20),
+                ),
+                0,
+                20
+            )
+        );
+    }
+
+    #[test]
+    fn test_munch_struct() {
+        let input =
+            "struct s { int a, *b; struct s *next; }; int f(struct s *p) { return p->next->a; }";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let program = parser.munch_program().unwrap();
+
+        let TopLevelKind::FunctionDefinition(_, args, _, _, statements, _) = &program[0].kind
+        else {
+            panic!("expected a function definition: {program:?}");
+        };
+        let Type::Pointer(struct_type) = &args[0].1 else {
+            panic!("expected a pointer parameter: {args:?}");
+        };
+        let Type::Struct(struct_type) = &**struct_type else {
+            panic!("expected a struct: {struct_type:?}");
+        };
+        assert_eq!(
+            struct_type
+                .members()
+                .iter()
+                .map(|member| (member.name.as_str(), member.offset))
+                .collect::<Vec<_>>(),
+            vec![("a", 0), ("b", 8), ("next", 16)]
+        );
+        assert_eq!(
+            struct_type.member("next").unwrap().ty,
+            Type::Pointer(Box::new(Type::Struct(struct_type.clone())))
+        );
+
+        let p_next = Expr::new(
+            ExprKind::Member(
+                Box::new(Expr::new(
+                    ExprKind::Dereference(variable("p", 69, 70)),
+                    span(69, 76),
+                )),
+                "next".to_string(),
+            ),
+            span(69, 76),
+        );
+        assert_eq!(
+            statements[0].kind,
+            StatementKind::Return(Some(Expr::new(
+                ExprKind::Member(
+                    Box::new(Expr::new(
+                        ExprKind::Dereference(Box::new(p_next)),
+                        span(69, 79)
+                    )),
+                    "a".to_string()
+                ),
+                span(69, 79)
+            )))
+        );
+    }
+
+    #[test]
+    fn test_munch_expr_precedence_of_logical_and_bitwise_operators() {
+        let input = "a || b && c | d ^ e & f == g << h % i";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let parsed = parser.munch_expr().unwrap();
+        let shift = expr(
+            ExprKind::LeftShift(
+                variable("g", 27, 28),
+                expr(
+                    ExprKind::Mod(variable("h", 32, 33), variable("i", 36, 37)),
+                    32,
+                    37,
+                ),
+            ),
+            27,
+            37,
+        );
+        let bit_and = expr(
+            ExprKind::BitAnd(
+                variable("e", 18, 19),
+                expr(ExprKind::Equal(variable("f", 22, 23), shift), 22, 37),
+            ),
+            18,
+            37,
+        );
+        let bit_or = expr(
+            ExprKind::BitOr(
+                variable("c", 10, 11),
+                expr(ExprKind::BitXor(variable("d", 14, 15), bit_and), 14, 37),
+            ),
+            10,
+            37,
+        );
+        assert_eq!(
+            parsed,
+            *expr(
+                ExprKind::LogicalOr(
+                    variable("a", 0, 1),
+                    expr(ExprKind::LogicalAnd(variable("b", 5, 6), bit_or), 5, 37),
+                ),
+                0,
+                37,
+            )
+        );
+    }
+
+    #[test]
+    fn test_munch_expr_with_compound_assignment_and_conditional() {
+        let input = "a += b ? c++ : --d, e";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let parsed = parser.munch_expr().unwrap();
+        let post_increment = expr(
+            ExprKind::PostfixAssign(expr(
+                ExprKind::Add(variable("c", 9, 10), num(1, 10, 12)),
+                9,
+                12,
+            )),
+            9,
+            12,
+        );
+        let pre_decrement = expr(
+            ExprKind::CompoundAssign(expr(
+                ExprKind::Sub(variable("d", 17, 18), num(1, 15, 17)),
+                15,
+                18,
+            )),
+            15,
+            18,
+        );
+        let conditional = expr(
+            ExprKind::Conditional(variable("b", 5, 6), post_increment, pre_decrement),
+            5,
+            18,
+        );
+        let compound_assign = expr(
+            ExprKind::CompoundAssign(expr(ExprKind::Add(variable("a", 0, 1), conditional), 0, 18)),
+            0,
+            18,
+        );
+        assert_eq!(
+            parsed,
+            *expr(
+                ExprKind::Comma(compound_assign, variable("e", 20, 21)),
+                0,
+                21
+            )
+        );
+    }
+
+    #[test]
+    fn test_munch_enum() {
+        let input = "enum e { a, b = 5, c }; int f() { return c; }";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let program = parser.munch_program().unwrap();
+
+        let TopLevelKind::FunctionDefinition(_, _, _, _, statements, _) = &program[0].kind else {
+            panic!("expected a function definition: {program:?}");
+        };
+        assert_eq!(
+            statements[0].kind,
+            StatementKind::Return(Some(*num(6, 41, 42)))
+        );
+    }
+
+    #[test]
+    fn test_munch_program_reports_error_of_each_top_level() {
+        let input = "int f() {return 1} int g() {return );}";
+        let tokens = tokens_of(input);
+        let mut parser = Parser::new(&tokens, input);
+        let errors = parser.munch_program().unwrap_err();
+
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.kind, error.span))
+                .collect::<Vec<_>>(),
+            vec![
+                (CompileErrorKind::Parse, Some(span(17, 18))),
+                (CompileErrorKind::Parse, Some(span(35, 36))),
+            ]
+        );
     }
 }
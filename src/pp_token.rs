@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+
+/// 前処理字句の種類。C の字句より粗く、数は `pp-number` としてまとめて読む。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpTokenKind {
+    Identifier,
+    Number,
+    /// 文字列リテラルと文字定数。
+    Literal,
+    Punctuator,
+    /// どの字句にも当てはまらない 1 文字。
+    Other,
+    /// `##` の被演算子が空のときに置く空の字句。出力には現れない。
+    Placemarker,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PpToken {
+    pub kind: PpTokenKind,
+    pub text: String,
+    /// 直前に空白があったか。出力と `#` による文字列化で使う。
+    pub leading_space: bool,
+    /// 前処理の出力での位置。マクロの置換リストから生まれた字句は展開したマクロ名の位置を、
+    /// 実引数から来た字句は実引数の中での位置を持つ。
+    pub position: usize,
+    /// この字句を生んだマクロの集合。ここにあるマクロはこの字句では再展開しない。
+    pub hideset: HashSet<String>,
+}
+
+impl PpToken {
+    pub fn new(kind: PpTokenKind, text: impl Into<String>, position: usize) -> Self {
+        Self {
+            kind,
+            text: text.into(),
+            leading_space: false,
+            position,
+            hideset: HashSet::new(),
+        }
+    }
+
+    pub fn is_identifier(&self, name: &str) -> bool {
+        self.kind == PpTokenKind::Identifier && self.text == name
+    }
+
+    /// ダイグラフは対応する記号として比べる。
+    pub fn is_punctuator(&self, punctuator: &str) -> bool {
+        self.kind == PpTokenKind::Punctuator
+            && match self.text.as_str() {
+                "<:" => "[",
+                ":>" => "]",
+                "<%" => "{",
+                "%>" => "}",
+                "%:" => "#",
+                "%:%:" => "##",
+                text => text,
+            } == punctuator
+    }
+}
+
+static PUNCTUATORS: [&str; 54] = [
+    "%:%:", "<:", ":>", "<%", "%>", "%:", "...", "<<=", ">>=", "->", "++", "--", "<<", ">>", "<=",
+    ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "##", "[", "]",
+    "(", ")", "{", "}", ".", "&", "*", "+", "-", "~", "!", "/", "%", "<", ">", "^", "|", "?", ":",
+    ";", "=", ",", "#",
+];
+
+/// 論理行 `line` を前処理字句に分ける。`positions[i]` は `line[i]` の出力での位置。
+/// コメントは呼び出し側で空白に置き換えておく。
+pub fn tokenize_line(line: &[char], positions: &[usize]) -> Vec<PpToken> {
+    let mut tokens = vec![];
+    let mut leading_space = false;
+    let mut i = 0;
+    while i < line.len() {
+        if line[i].is_whitespace() {
+            leading_space = true;
+            i += 1;
+            continue;
+        }
+        let (kind, length) = munch_pp_token(&line[i..]);
+        let mut token = PpToken::new(
+            kind,
+            line[i..i + length].iter().collect::<String>(),
+            positions[i],
+        );
+        token.leading_space = leading_space;
+        tokens.push(token);
+        leading_space = false;
+        i += length;
+    }
+    tokens
+}
+
+fn munch_pp_token(input: &[char]) -> (PpTokenKind, usize) {
+    let is_identifier_char = |c: &char| c.is_ascii_alphanumeric() || *c == '_';
+    match input {
+        // `L'a'` や `u8"a"` のような接頭辞つきのリテラルは 1 つの字句にする。
+        ['L' | 'u' | 'U', quote @ ('"' | '\''), ..] => {
+            (PpTokenKind::Literal, 1 + munch_quoted(&input[1..], *quote))
+        }
+        ['u', '8', '"', ..] => (PpTokenKind::Literal, 2 + munch_quoted(&input[2..], '"')),
+        [c, ..] if c.is_ascii_alphabetic() || *c == '_' => (
+            PpTokenKind::Identifier,
+            input.iter().take_while(|c| is_identifier_char(c)).count(),
+        ),
+        ['0'..='9', ..] | ['.', '0'..='9', ..] => (PpTokenKind::Number, munch_pp_number(input)),
+        [quote @ ('"' | '\''), ..] => (PpTokenKind::Literal, munch_quoted(input, *quote)),
+        _ => PUNCTUATORS
+            .iter()
+            .find(|punctuator| input.starts_with(&punctuator.chars().collect::<Vec<_>>()))
+            .map_or((PpTokenKind::Other, 1), |punctuator| {
+                (PpTokenKind::Punctuator, punctuator.len())
+            }),
+    }
+}
+
+/// `1e+5` や `0x1p-3` のように、指数の符号も数の一部として読む。
+pub fn munch_pp_number(input: &[char]) -> usize {
+    let mut length = 1;
+    while let Some(c) = input.get(length) {
+        match (input[length - 1], c) {
+            ('e' | 'E' | 'p' | 'P', '+' | '-') => length += 1,
+            (_, c) if c.is_ascii_alphanumeric() || *c == '_' || *c == '.' => length += 1,
+            _ => break,
+        }
+    }
+    length
+}
+
+/// 閉じ引用符がなければ行末までを 1 つの字句とする。エラーは字句解析で報告する。
+fn munch_quoted(input: &[char], quote: char) -> usize {
+    let mut length = 1;
+    while let Some(c) = input.get(length) {
+        length += 1;
+        match c {
+            '\\' => length = (length + 1).min(input.len()),
+            c if *c == quote => break,
+            _ => {}
+        }
+    }
+    length
+}
+
+/// 字句の並びを出力用の文字列にする。空白は 1 つにまとめる。
+pub fn spell(tokens: &[PpToken]) -> String {
+    spell_with_origins(tokens).0
+}
+
+/// `spell` と同じ文字列と、その各文字が何番目の字句の何文字目かを返す。字句の間の空白は `None`。
+pub fn spell_with_origins(tokens: &[PpToken]) -> (String, Vec<Option<(usize, usize)>>) {
+    let mut text = String::new();
+    let mut origins = vec![];
+    for (index, token) in tokens.iter().enumerate() {
+        if token.kind == PpTokenKind::Placemarker {
+            continue;
+        }
+        if token.leading_space && !text.is_empty() {
+            text.push(' ');
+            origins.push(None);
+        }
+        text.push_str(&token.text);
+        origins.extend((0..token.text.chars().count()).map(|offset| Some((index, offset))));
+    }
+    (text, origins)
+}
+
+/// `#` 演算子。字句の綴りを並べ、文字列と文字定数の中の `"` と `\` をエスケープする。
+pub fn stringify(tokens: &[PpToken], position: usize) -> PpToken {
+    let mut text = String::from("\"");
+    for token in tokens {
+        if token.kind == PpTokenKind::Placemarker {
+            continue;
+        }
+        if token.leading_space && text.len() > 1 {
+            text.push(' ');
+        }
+        if token.kind == PpTokenKind::Literal {
+            for c in token.text.chars() {
+                if c == '"' || c == '\\' {
+                    text.push('\\');
+                }
+                text.push(c);
+            }
+        } else {
+            text.push_str(&token.text);
+        }
+    }
+    text.push('"');
+    PpToken::new(PpTokenKind::Literal, text, position)
+}
+
+/// `##` 演算子。綴りをつないで読み直し、1 つの字句にならなければ `None` を返す。
+pub fn paste(lhs: &PpToken, rhs: &PpToken) -> Option<PpToken> {
+    match (lhs.kind, rhs.kind) {
+        (PpTokenKind::Placemarker, _) => return Some(rhs.clone()),
+        (_, PpTokenKind::Placemarker) => return Some(lhs.clone()),
+        _ => {}
+    }
+    let text = format!("{}{}", lhs.text, rhs.text)
+        .chars()
+        .collect::<Vec<_>>();
+    let (kind, length) = munch_pp_token(&text);
+    (length == text.len()).then(|| {
+        let mut token = PpToken::new(kind, text.iter().collect::<String>(), lhs.position);
+        token.leading_space = lhs.leading_space;
+        token
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens_of(line: &str) -> Vec<PpToken> {
+        let chars = line.chars().collect::<Vec<_>>();
+        tokenize_line(&chars, &(0..chars.len()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_tokenize_line() {
+        let tokens = tokens_of("#define f(a, ...) a##1.5e+3 \"x\\\"y\" 'c' ... @");
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| (token.kind, token.text.as_str(), token.leading_space))
+                .collect::<Vec<_>>(),
+            vec![
+                (PpTokenKind::Punctuator, "#", false),
+                (PpTokenKind::Identifier, "define", false),
+                (PpTokenKind::Identifier, "f", true),
+                (PpTokenKind::Punctuator, "(", false),
+                (PpTokenKind::Identifier, "a", false),
+                (PpTokenKind::Punctuator, ",", false),
+                (PpTokenKind::Punctuator, "...", true),
+                (PpTokenKind::Punctuator, ")", false),
+                (PpTokenKind::Identifier, "a", true),
+                (PpTokenKind::Punctuator, "##", false),
+                (PpTokenKind::Number, "1.5e+3", false),
+                (PpTokenKind::Literal, "\"x\\\"y\"", true),
+                (PpTokenKind::Literal, "'c'", true),
+                (PpTokenKind::Punctuator, "...", true),
+                (PpTokenKind::Other, "@", true),
+            ]
+        );
+        assert_eq!(tokens[2].position, 8);
+    }
+
+    #[test]
+    fn test_stringify_and_paste() {
+        let tokens = tokens_of("a  + \"b\\n\"");
+        assert_eq!(stringify(&tokens, 0).text, r#""a + \"b\\n\"""#);
+
+        let tokens = tokens_of("x 1 + =");
+        assert_eq!(paste(&tokens[0], &tokens[1]).unwrap().text, "x1");
+        assert_eq!(paste(&tokens[2], &tokens[3]).unwrap().text, "+=");
+        assert_eq!(paste(&tokens[3], &tokens[2]), None);
+    }
+}
@@ -0,0 +1,1182 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{CompileError, CompileErrorKind, CompileResult},
+    lex::{self, SourcePosition, Span},
+    macros::{parse_definition, Expander, Macro},
+    pp_token::{spell, spell_with_origins, tokenize_line, PpToken, PpTokenKind},
+    token::Token,
+};
+
+/// `<...>` の `#include` で、`-I` の後に探すディレクトリ。
+const SYSTEM_INCLUDE_PATHS: [&str; 3] = [
+    "/usr/local/include",
+    "/usr/include/x86_64-linux-gnu",
+    "/usr/include",
+];
+
+/// 処理系が用意するヘッダ。`-I` の後、システムのディレクトリより先に探す。
+const BUILTIN_HEADERS: [(&str, &str); 3] = [
+    ("stdarg.h", include_str!("../include/stdarg.h")),
+    ("stdbool.h", include_str!("../include/stdbool.h")),
+    ("stddef.h", include_str!("../include/stddef.h")),
+];
+
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+/// コマンドラインの `-D` と `-U`。指定した順に適用する。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MacroOption {
+    /// `-D NAME=VALUE`。`NAME` は `F(x)` のように仮引数を持ってもよい。
+    Define(String, String),
+    Undefine(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PreprocessOptions {
+    pub include_paths: Vec<PathBuf>,
+    pub macro_options: Vec<MacroOption>,
+}
+
+/// 出力の各行がどのファイルの何行目から来たか。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineOrigin {
+    pub file: String,
+    pub line: usize,
+    /// マクロを展開した行なら、展開前の行。
+    pub expansion: Option<Expansion>,
+}
+
+/// マクロを展開した行の、展開前の行 (`\` でつないだ行は最初の物理行) とそこへの対応。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub text: String,
+    /// 出力した行の各文字の由来。
+    pub columns: Vec<ExpandedColumn>,
+}
+
+/// 展開した行の 1 文字が由来する、展開前の行での 0 始まりの列の範囲。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpandedColumn {
+    pub start: usize,
+    pub end: usize,
+    /// マクロの置換リストから現れた字句の文字なら真。範囲は呼び出したマクロの名前になる。
+    pub is_from_macro: bool,
+}
+
+/// 前処理の結果。`text` の `i` 行目は `lines[i]` に由来する。
+/// 指令の行はそのまま残し、字句解析で読み飛ばす。コメントは同じ長さの空白になり、
+/// マクロを展開しない行は入力と同じ位置に同じ文字が並ぶ。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preprocessed {
+    pub text: String,
+    pub lines: Vec<LineOrigin>,
+}
+
+/// `source` を前処理する。エラーがあっても最後まで処理し、見つけたエラーをすべて返す。
+#[must_use]
+pub fn preprocess(
+    source: &str,
+    file_name: &str,
+    options: &PreprocessOptions,
+) -> (Preprocessed, Vec<CompileError>) {
+    let mut preprocessor = Preprocessor::new(options);
+    for option in &options.macro_options {
+        preprocessor.apply_option(option);
+    }
+    let file = File {
+        name: file_name.to_string(),
+        path: (file_name != "<stdin>").then(|| PathBuf::from(file_name)),
+        is_main: true,
+    };
+    preprocessor.process_file(source, &file);
+    (
+        Preprocessed {
+            text: preprocessor.output,
+            lines: preprocessor.lines,
+        },
+        preprocessor.errors,
+    )
+}
+
+struct File {
+    name: String,
+    /// 標準入力なら `None`。
+    path: Option<PathBuf>,
+    is_main: bool,
+}
+
+impl File {
+    /// `"..."` の `#include` で最初に探すディレクトリ。
+    fn directory(&self) -> PathBuf {
+        self.path
+            .as_ref()
+            .and_then(|path| path.parent())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+    }
+}
+
+/// `#if` から `#endif` までの状態。
+struct Conditional {
+    state: ConditionalState,
+    has_else: bool,
+    position: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConditionalState {
+    /// 今の枝が選ばれている。
+    Active,
+    /// まだどの枝も選ばれていない。
+    Waiting,
+    /// すでに枝が選ばれたか、外側が無効なので、残りの枝は選ばれない。
+    Done,
+}
+
+struct Preprocessor<'a> {
+    options: &'a PreprocessOptions,
+    macros: HashMap<String, Macro>,
+    output: String,
+    /// `output` の文字数。字句の位置は文字単位で数える。
+    output_length: usize,
+    lines: Vec<LineOrigin>,
+    errors: Vec<CompileError>,
+    pragma_once: HashSet<PathBuf>,
+    depth: usize,
+}
+
+impl<'a> Preprocessor<'a> {
+    fn new(options: &'a PreprocessOptions) -> Self {
+        let mut macros = HashMap::from([
+            ("__FILE__".to_string(), Macro::File),
+            ("__LINE__".to_string(), Macro::Line),
+        ]);
+        for (name, value) in [
+            ("__STDC__", "1"),
+            ("__STDC_VERSION__", "201112L"),
+            ("__STDC_HOSTED__", "1"),
+            ("__x86_64__", "1"),
+            ("__LP64__", "1"),
+            ("__linux__", "1"),
+        ] {
+            macros.insert(
+                name.to_string(),
+                Macro::Object(vec![PpToken::new(PpTokenKind::Number, value, 0)]),
+            );
+        }
+        Self {
+            options,
+            macros,
+            output: String::new(),
+            output_length: 0,
+            lines: vec![],
+            errors: vec![],
+            pragma_once: HashSet::new(),
+            depth: 0,
+        }
+    }
+
+    fn apply_option(&mut self, option: &MacroOption) {
+        match option {
+            MacroOption::Define(name, value) => {
+                let chars = format!("{name} {value}").chars().collect::<Vec<_>>();
+                let tokens = tokenize_line(&chars, &vec![0; chars.len()]);
+                match parse_definition(&tokens, 0) {
+                    Ok((name, definition)) => {
+                        self.macros.insert(name, definition);
+                    }
+                    Err(error) => self.errors.push(CompileError::new(
+                        error.kind,
+                        format!("invalid -D option {name}: {}", error.message),
+                        None,
+                    )),
+                }
+            }
+            MacroOption::Undefine(name) => {
+                self.macros.remove(name);
+            }
+        }
+    }
+
+    fn process_file(&mut self, text: &str, file: &File) {
+        let (text, unterminated_comment) = blank_comments(text);
+        let mut physical_lines = text
+            .split('\n')
+            .map(|line| line.chars().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let has_final_newline = physical_lines.last().is_some_and(Vec::is_empty);
+        if has_final_newline {
+            physical_lines.pop();
+        }
+        let mut file_lines = FileLines {
+            lines: physical_lines,
+            next: 0,
+            is_main: file.is_main,
+            has_final_newline,
+            position: 0,
+            output_starts: vec![],
+        };
+
+        let mut conditionals: Vec<Conditional> = vec![];
+        while let Some(start) = file_lines.peek_start() {
+            file_lines.position = self.output_length;
+            let line_start = file_lines.position;
+            let (mut chars, mut positions) = file_lines.join_logical_line();
+            let tokens = tokenize_line(&chars, &positions);
+            let active = conditionals
+                .last()
+                .is_none_or(|conditional| conditional.state == ConditionalState::Active);
+
+            if tokens.first().is_some_and(|token| token.is_punctuator("#")) {
+                self.emit_verbatim(&mut file_lines, start, file);
+                self.run_directive(&tokens, file, start + 1, &mut conditionals, active);
+                continue;
+            }
+            if !active {
+                self.emit_blank(&mut file_lines, start, file);
+                continue;
+            }
+
+            // 関数形式のマクロの実引数が閉じていなければ、次の行も続けて読む。
+            let mut tokens = tokens;
+            while needs_more_lines(&tokens, &self.macros) && file_lines.next_is_text() {
+                // 改行は空白として字句を区切る。
+                chars.push(' ');
+                positions.push(file_lines.position - 1);
+                let (more_chars, more_positions) = file_lines.join_logical_line();
+                chars.extend(more_chars);
+                positions.extend(more_positions);
+                tokens = tokenize_line(&chars, &positions);
+            }
+            let expander = Expander {
+                macros: &self.macros,
+                file: &file.name,
+                line: start + 1,
+            };
+            // `\` で行をつないだ行は、字句の途中でつないでいることがあるので論理行を出力する。
+            let has_splice = file_lines.lines[start..file_lines.next]
+                .iter()
+                .any(|line| line.last() == Some(&'\\'));
+            match expander.expand(tokens) {
+                Ok((replaced, is_replaced)) if is_replaced || has_splice => {
+                    self.emit_expanded(&mut file_lines, start, file, &replaced, line_start);
+                }
+                Ok(_) => self.emit_verbatim(&mut file_lines, start, file),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.emit_verbatim(&mut file_lines, start, file);
+                }
+            }
+        }
+
+        for conditional in conditionals {
+            self.errors.push(error(
+                "unterminated conditional directive",
+                conditional.position,
+                1,
+            ));
+        }
+        if let Some((line, column)) = unterminated_comment {
+            let position = file_lines
+                .output_starts
+                .get(line)
+                .map_or(self.output_length, |start| start + column);
+            self.errors.push(error("unterminated comment", position, 2));
+        }
+    }
+
+    /// `start` 行目から読んだ行を、入力のまま出力する。
+    fn emit_verbatim(&mut self, file_lines: &mut FileLines, start: usize, file: &File) {
+        for line in start..file_lines.next {
+            let text = file_lines.lines[line].iter().collect::<String>();
+            self.emit_line(file_lines, line, &text, file);
+        }
+    }
+
+    fn emit_blank(&mut self, file_lines: &mut FileLines, start: usize, file: &File) {
+        for line in start..file_lines.next {
+            self.emit_line(file_lines, line, "", file);
+        }
+    }
+
+    /// 展開結果を最初の行に出力し、残りの行は空行にして行の対応を保つ。
+    /// `line_start` は最初の行をそのまま出力したときの行頭の位置で、字句の位置を列に直すのに使う。
+    fn emit_expanded(
+        &mut self,
+        file_lines: &mut FileLines,
+        start: usize,
+        file: &File,
+        tokens: &[PpToken],
+        line_start: usize,
+    ) {
+        let (text, origins) = spell_with_origins(tokens);
+        let original = &file_lines.lines[start];
+        let expansion = Expansion {
+            text: original.iter().collect(),
+            columns: expansion_columns(tokens, &origins, original, line_start),
+        };
+        self.emit_line(file_lines, start, &text, file);
+        if let Some(origin) = self.lines.last_mut() {
+            origin.expansion = Some(expansion);
+        }
+        for line in start + 1..file_lines.next {
+            self.emit_line(file_lines, line, "", file);
+        }
+    }
+
+    fn emit_line(&mut self, file_lines: &mut FileLines, line: usize, text: &str, file: &File) {
+        file_lines.output_starts.push(self.output_length);
+        self.output.push_str(text);
+        self.output_length += text.chars().count();
+        if !file_lines.is_main || file_lines.has_final_newline || line + 1 < file_lines.lines.len()
+        {
+            self.output.push('\n');
+            self.output_length += 1;
+        }
+        self.lines.push(LineOrigin {
+            file: file.name.clone(),
+            line: line + 1,
+            expansion: None,
+        });
+    }
+
+    fn run_directive(
+        &mut self,
+        tokens: &[PpToken],
+        file: &File,
+        line: usize,
+        conditionals: &mut Vec<Conditional>,
+        active: bool,
+    ) {
+        // `#` だけの行は何もしない。
+        let Some(name) = tokens.get(1) else { return };
+        let arguments = &tokens[2..];
+        let result = match name.text.as_str() {
+            "if" | "ifdef" | "ifndef" => {
+                let state = if !active {
+                    ConditionalState::Done
+                } else if self.evaluate_condition(name, arguments, file, line) {
+                    ConditionalState::Active
+                } else {
+                    ConditionalState::Waiting
+                };
+                conditionals.push(Conditional {
+                    state,
+                    has_else: false,
+                    position: tokens[0].position,
+                });
+                Ok(())
+            }
+            "elif" => match conditionals.last_mut() {
+                None => Err(directive_error("#elif without #if", name)),
+                Some(conditional) if conditional.has_else => {
+                    Err(directive_error("#elif after #else", name))
+                }
+                Some(conditional) => {
+                    conditional.state = match conditional.state {
+                        ConditionalState::Waiting
+                            if self.evaluate_condition(name, arguments, file, line) =>
+                        {
+                            ConditionalState::Active
+                        }
+                        ConditionalState::Waiting => ConditionalState::Waiting,
+                        _ => ConditionalState::Done,
+                    };
+                    Ok(())
+                }
+            },
+            "else" => match conditionals.last_mut() {
+                None => Err(directive_error("#else without #if", name)),
+                Some(conditional) if conditional.has_else => {
+                    Err(directive_error("#else after #else", name))
+                }
+                Some(conditional) => {
+                    conditional.state = match conditional.state {
+                        ConditionalState::Waiting => ConditionalState::Active,
+                        _ => ConditionalState::Done,
+                    };
+                    conditional.has_else = true;
+                    Ok(())
+                }
+            },
+            "endif" => conditionals
+                .pop()
+                .map(|_| ())
+                .ok_or_else(|| directive_error("#endif without #if", name)),
+            _ if !active => Ok(()),
+            "define" => parse_definition(arguments, name.position + name.text.len()).map(
+                |(name, definition)| {
+                    self.macros.insert(name, definition);
+                },
+            ),
+            "undef" => match arguments.first() {
+                Some(macro_name) if macro_name.kind == PpTokenKind::Identifier => {
+                    self.macros.remove(&macro_name.text);
+                    Ok(())
+                }
+                _ => Err(directive_error("macro name missing", name)),
+            },
+            "include" => self.include(name, arguments, file, line),
+            "error" => Err(directive_error(
+                format!("#error {}", spell(arguments)),
+                name,
+            )),
+            "pragma" => {
+                if arguments
+                    .first()
+                    .is_some_and(|token| token.is_identifier("once"))
+                {
+                    if let Some(path) = file.path.as_ref().and_then(|path| path.canonicalize().ok())
+                    {
+                        self.pragma_once.insert(path);
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(directive_error(
+                format!("invalid preprocessing directive #{}", name.text),
+                name,
+            )),
+        };
+        if let Err(error) = result {
+            self.errors.push(error);
+        }
+    }
+
+    /// エラーは報告し、条件は偽として扱う。
+    fn evaluate_condition(
+        &mut self,
+        name: &PpToken,
+        arguments: &[PpToken],
+        file: &File,
+        line: usize,
+    ) -> bool {
+        let result = match name.text.as_str() {
+            "ifdef" | "ifndef" => match arguments.first() {
+                Some(macro_name) if macro_name.kind == PpTokenKind::Identifier => {
+                    Ok(self.macros.contains_key(&macro_name.text) == (name.text == "ifdef"))
+                }
+                _ => Err(directive_error("macro name missing", name)),
+            },
+            _ => self.evaluate_expression(name, arguments, file, line),
+        };
+        result.unwrap_or_else(|error| {
+            self.errors.push(error);
+            false
+        })
+    }
+
+    /// `defined` を 0 か 1 に置き換えてからマクロを展開し、残った識別子を 0 として評価する。
+    fn evaluate_expression(
+        &self,
+        name: &PpToken,
+        arguments: &[PpToken],
+        file: &File,
+        line: usize,
+    ) -> CompileResult<bool> {
+        if arguments.is_empty() {
+            return Err(directive_error(
+                format!("#{} with no expression", name.text),
+                name,
+            ));
+        }
+        let mut tokens = vec![];
+        let mut i = 0;
+        while i < arguments.len() {
+            let token = &arguments[i];
+            if !token.is_identifier("defined") {
+                tokens.push(token.clone());
+                i += 1;
+                continue;
+            }
+            let (macro_name, length) = match &arguments[i + 1..] {
+                [lparen, macro_name, rparen, ..]
+                    if lparen.is_punctuator("(") && rparen.is_punctuator(")") =>
+                {
+                    (macro_name, 4)
+                }
+                [macro_name, ..] => (macro_name, 2),
+                [] => return Err(directive_error("macro name missing after defined", token)),
+            };
+            if macro_name.kind != PpTokenKind::Identifier {
+                return Err(directive_error("macro name missing after defined", token));
+            }
+            let value = u8::from(self.macros.contains_key(&macro_name.text));
+            tokens.push(PpToken::new(
+                PpTokenKind::Number,
+                value.to_string(),
+                token.position,
+            ));
+            i += length;
+        }
+
+        let expander = Expander {
+            macros: &self.macros,
+            file: &file.name,
+            line,
+        };
+        let tokens = expander
+            .expand(tokens)?
+            .0
+            .into_iter()
+            .map(|token| {
+                if token.kind == PpTokenKind::Identifier {
+                    PpToken::new(PpTokenKind::Number, "0", token.position)
+                } else {
+                    token
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut evaluator = Evaluator {
+            tokens: &tokens,
+            next: 0,
+            directive: name,
+        };
+        let value = evaluator.conditional(true)?;
+        if let Some(token) = tokens.get(evaluator.next) {
+            return Err(error(
+                format!("unexpected token {} in #{}", token.text, name.text),
+                token.position,
+                token.text.len(),
+            ));
+        }
+        Ok(value.is_true())
+    }
+
+    fn include(
+        &mut self,
+        name: &PpToken,
+        arguments: &[PpToken],
+        file: &File,
+        line: usize,
+    ) -> CompileResult<()> {
+        // どちらの形でもなければ、マクロを展開してから読み直す。
+        let (header, is_quoted) = if let Some(header) = header_name(arguments) {
+            header
+        } else {
+            let expander = Expander {
+                macros: &self.macros,
+                file: &file.name,
+                line,
+            };
+            header_name(&expander.expand(arguments.to_vec())?.0).ok_or_else(|| {
+                directive_error("#include expects \"FILENAME\" or <FILENAME>", name)
+            })?
+        };
+
+        let quoted_directory = is_quoted.then(|| file.directory());
+        let find_in = |directories: &mut dyn Iterator<Item = PathBuf>| {
+            directories
+                .map(|directory| directory.join(&header))
+                .find(|path| path.is_file())
+        };
+        let path = find_in(
+            &mut quoted_directory
+                .iter()
+                .chain(&self.options.include_paths)
+                .cloned(),
+        );
+        if path.is_none() {
+            if let Some((_, text)) = BUILTIN_HEADERS
+                .iter()
+                .find(|(builtin, _)| *builtin == header)
+            {
+                let included = File {
+                    name: format!("<{header}>"),
+                    path: None,
+                    is_main: false,
+                };
+                return self.include_text(text, &included, name);
+            }
+        }
+        let path = path
+            .or_else(|| find_in(&mut SYSTEM_INCLUDE_PATHS.iter().map(PathBuf::from)))
+            .ok_or_else(|| directive_error(format!("{header}: file not found"), name))?;
+
+        if path
+            .canonicalize()
+            .is_ok_and(|path| self.pragma_once.contains(&path))
+        {
+            return Ok(());
+        }
+        let text = std::fs::read_to_string(&path).map_err(|io_error| {
+            directive_error(format!("cannot read {}: {io_error}", path.display()), name)
+        })?;
+        let included = File {
+            name: path.to_string_lossy().into_owned(),
+            path: Some(path),
+            is_main: false,
+        };
+        self.include_text(&text, &included, name)
+    }
+
+    fn include_text(&mut self, text: &str, included: &File, name: &PpToken) -> CompileResult<()> {
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(directive_error("#include nested too deeply", name));
+        }
+        self.depth += 1;
+        self.process_file(text, included);
+        self.depth -= 1;
+        Ok(())
+    }
+}
+
+/// 1 つのファイルの物理行と、どこまで読んだか。
+struct FileLines {
+    lines: Vec<Vec<char>>,
+    next: usize,
+    is_main: bool,
+    has_final_newline: bool,
+    /// 次に読む行をそのまま出力したときの、行頭の出力での位置。
+    position: usize,
+    /// 出力した各行の先頭の、出力での位置。
+    output_starts: Vec<usize>,
+}
+
+impl FileLines {
+    fn peek_start(&self) -> Option<usize> {
+        (self.next < self.lines.len()).then_some(self.next)
+    }
+
+    /// 次の行が指令でない行なら真。
+    fn next_is_text(&self) -> bool {
+        self.lines
+            .get(self.next)
+            .is_some_and(|line| line.iter().find(|c| !c.is_whitespace()) != Some(&'#'))
+    }
+
+    /// `\` で終わる物理行を次の行とつなげて論理行を読む。
+    /// 各文字の位置は、読んだ行をそのまま出力したときの出力での位置になる。
+    fn join_logical_line(&mut self) -> (Vec<char>, Vec<usize>) {
+        let mut chars = vec![];
+        let mut positions = vec![];
+        while let Some(line) = self.lines.get(self.next) {
+            self.next += 1;
+            let spliced = line.last() == Some(&'\\');
+            let content = if spliced {
+                &line[..line.len() - 1]
+            } else {
+                line
+            };
+            chars.extend(content);
+            positions.extend(self.position..self.position + content.len());
+            self.position += line.len() + 1;
+            if !spliced {
+                break;
+            }
+        }
+        (chars, positions)
+    }
+}
+
+/// 展開結果の各文字を展開前の行での列の範囲に対応させる。字句の間の空白は直前の文字の終わりに、
+/// 元の行にない字句はその位置にあるマクロの名前に、最初の行より後ろの字句は行末に対応させる。
+fn expansion_columns(
+    tokens: &[PpToken],
+    origins: &[Option<(usize, usize)>],
+    original: &[char],
+    line_start: usize,
+) -> Vec<ExpandedColumn> {
+    let mut previous_end = 0;
+    origins
+        .iter()
+        .map(|origin| {
+            let (start, end, is_from_macro) = match origin {
+                None => (previous_end, previous_end, false),
+                Some((index, offset)) => {
+                    let token = &tokens[*index];
+                    let column = token
+                        .position
+                        .saturating_sub(line_start)
+                        .min(original.len());
+                    let rest = &original[column..];
+                    if rest.starts_with(&token.text.chars().collect::<Vec<_>>()) {
+                        (column + offset, column + offset + 1, false)
+                    } else {
+                        let name_length = rest
+                            .iter()
+                            .take_while(|c| c.is_ascii_alphanumeric() || **c == '_')
+                            .count();
+                        (column, column + name_length, true)
+                    }
+                }
+            };
+            previous_end = end;
+            ExpandedColumn {
+                start,
+                end,
+                is_from_macro,
+            }
+        })
+        .collect()
+}
+
+/// コメントを同じ長さの空白に置き換える。改行は残すので行の対応は変わらない。
+/// 閉じていないブロックコメントがあれば、その開始位置 (行, 列) を返す。
+fn blank_comments(text: &str) -> (String, Option<(usize, usize)>) {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut result = String::with_capacity(text.len());
+    let mut unterminated = None;
+    let mut quote = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match (quote, c, chars.get(i + 1)) {
+            (Some(_), '\\', Some(next)) => {
+                result.push(c);
+                result.push(*next);
+                i += 2;
+                continue;
+            }
+            (Some(q), c, _) if c == q || c == '\n' => quote = None,
+            (None, '"' | '\'', _) => quote = Some(c),
+            // 行をつなぐ `\` はコメントより先に処理されるので、`//` のコメントは次の行に続く。
+            (None, '/', Some('/')) => {
+                while i < chars.len() && chars[i] != '\n' {
+                    if chars[i] == '\\' && chars.get(i + 1) == Some(&'\n') {
+                        result.push_str(" \n");
+                        i += 2;
+                        continue;
+                    }
+                    result.push(' ');
+                    i += 1;
+                }
+                continue;
+            }
+            (None, '/', Some('*')) => {
+                let end = chars[i + 2..]
+                    .windows(2)
+                    .position(|pair| pair == ['*', '/'])
+                    .map_or_else(
+                        || {
+                            let line_start = chars[..i]
+                                .iter()
+                                .rposition(|c| *c == '\n')
+                                .map_or(0, |newline| newline + 1);
+                            let line = chars[..i].iter().filter(|c| **c == '\n').count();
+                            unterminated = Some((line, i - line_start));
+                            chars.len()
+                        },
+                        |offset| i + 2 + offset + 2,
+                    );
+                result.extend(
+                    chars[i..end]
+                        .iter()
+                        .map(|c| if *c == '\n' { '\n' } else { ' ' }),
+                );
+                i = end;
+                continue;
+            }
+            _ => {}
+        }
+        result.push(c);
+        i += 1;
+    }
+    (result, unterminated)
+}
+
+/// 関数形式のマクロの呼び出しで、`(` が行末までに閉じていなければ真。
+fn needs_more_lines(tokens: &[PpToken], macros: &HashMap<String, Macro>) -> bool {
+    let mut i = 0;
+    while i + 1 < tokens.len() {
+        let is_invocation = matches!(macros.get(&tokens[i].text), Some(Macro::Function { .. }))
+            && tokens[i].kind == PpTokenKind::Identifier
+            && tokens[i + 1].is_punctuator("(");
+        i += 1;
+        if !is_invocation {
+            continue;
+        }
+        let mut depth = 0;
+        loop {
+            let Some(token) = tokens.get(i) else {
+                return true;
+            };
+            i += 1;
+            if token.is_punctuator("(") {
+                depth += 1;
+            } else if token.is_punctuator(")") {
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// `"name"` か `<name>` の形ならファイル名と `"..."` かどうかを返す。
+fn header_name(tokens: &[PpToken]) -> Option<(String, bool)> {
+    match tokens {
+        [literal] if literal.kind == PpTokenKind::Literal && literal.text.starts_with('"') => {
+            let name = literal.text.strip_prefix('"')?.strip_suffix('"')?;
+            Some((name.to_string(), true))
+        }
+        [lt, inner @ .., gt] if lt.is_punctuator("<") && gt.is_punctuator(">") => {
+            let mut inner = inner.to_vec();
+            if let Some(first) = inner.first_mut() {
+                first.leading_space = false;
+            }
+            Some((spell(&inner), false))
+        }
+        _ => None,
+    }
+}
+
+/// `#if` の値。整数はすべて `intmax_t` か `uintmax_t` として扱う。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Value {
+    bits: i64,
+    is_unsigned: bool,
+}
+
+impl Value {
+    const fn signed(bits: i64) -> Self {
+        Self {
+            bits,
+            is_unsigned: false,
+        }
+    }
+
+    const fn truth(condition: bool) -> Self {
+        Self::signed(condition as i64)
+    }
+
+    const fn is_true(self) -> bool {
+        self.bits != 0
+    }
+
+    #[allow(clippy::cast_sign_loss)]
+    const fn unsigned_bits(self) -> u64 {
+        self.bits as u64
+    }
+}
+
+/// `#if` の定数式を評価する。`live` が偽の被演算子は評価されないので、0 除算を報告しない。
+struct Evaluator<'a> {
+    tokens: &'a [PpToken],
+    next: usize,
+    directive: &'a PpToken,
+}
+
+impl Evaluator<'_> {
+    fn conditional(&mut self, live: bool) -> CompileResult<Value> {
+        let condition = self.binary(0, live)?;
+        if !self.consume("?") {
+            return Ok(condition);
+        }
+        let then = self.conditional(live && condition.is_true())?;
+        if !self.consume(":") {
+            return Err(self.unexpected("expected ':' in #if"));
+        }
+        let els = self.conditional(live && !condition.is_true())?;
+        let value = if condition.is_true() { then } else { els };
+        Ok(Value {
+            bits: value.bits,
+            is_unsigned: then.is_unsigned || els.is_unsigned,
+        })
+    }
+
+    fn binary(&mut self, min_precedence: u8, live: bool) -> CompileResult<Value> {
+        let mut lhs = self.unary(live)?;
+        while let Some(operator) = self.tokens.get(self.next) {
+            let Some(precedence) = precedence(operator) else {
+                break;
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            self.next += 1;
+            let rhs_live = match operator.text.as_str() {
+                "&&" => live && lhs.is_true(),
+                "||" => live && !lhs.is_true(),
+                _ => live,
+            };
+            let rhs = self.binary(precedence + 1, rhs_live)?;
+            lhs = apply_binary(operator, lhs, rhs, rhs_live)?;
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self, live: bool) -> CompileResult<Value> {
+        let Some(token) = self.tokens.get(self.next) else {
+            return Err(self.unexpected("expected value in #if"));
+        };
+        self.next += 1;
+        match (token.kind, token.text.as_str()) {
+            (PpTokenKind::Punctuator, "+") => self.unary(live),
+            (PpTokenKind::Punctuator, "-") => {
+                let value = self.unary(live)?;
+                Ok(Value {
+                    bits: value.bits.wrapping_neg(),
+                    ..value
+                })
+            }
+            (PpTokenKind::Punctuator, "!") => Ok(Value::truth(!self.unary(live)?.is_true())),
+            (PpTokenKind::Punctuator, "~") => {
+                let value = self.unary(live)?;
+                Ok(Value {
+                    bits: !value.bits,
+                    ..value
+                })
+            }
+            (PpTokenKind::Punctuator, "(") => {
+                let value = self.conditional(live)?;
+                if self.consume(")") {
+                    Ok(value)
+                } else {
+                    Err(self.unexpected("expected ')' in #if"))
+                }
+            }
+            // 数と文字定数は字句解析と同じ規則で読む。
+            (PpTokenKind::Number | PpTokenKind::Literal, text) if !text.starts_with('"') => {
+                match lex::tokenize(&text.chars().collect::<Vec<_>>()) {
+                    #[allow(clippy::cast_possible_wrap)]
+                    Ok(tokens) => match tokens.as_slice() {
+                        [(Token::Num(literal), _)] => Ok(Value {
+                            bits: literal.value as i64,
+                            is_unsigned: literal.is_unsigned,
+                        }),
+                        _ => Err(error(
+                            format!("{text} is not an integer constant in #if"),
+                            token.position,
+                            text.len(),
+                        )),
+                    },
+                    Err(errors) => {
+                        Err(error(errors[0].message.clone(), token.position, text.len()))
+                    }
+                }
+            }
+            _ => Err(error(
+                format!("unexpected token {} in #if", token.text),
+                token.position,
+                token.text.len(),
+            )),
+        }
+    }
+
+    fn consume(&mut self, punctuator: &str) -> bool {
+        let matched = self
+            .tokens
+            .get(self.next)
+            .is_some_and(|token| token.is_punctuator(punctuator));
+        if matched {
+            self.next += 1;
+        }
+        matched
+    }
+
+    fn unexpected(&self, message: &str) -> CompileError {
+        let token = self.tokens.get(self.next).unwrap_or(self.directive);
+        error(message, token.position, token.text.len())
+    }
+}
+
+fn precedence(token: &PpToken) -> Option<u8> {
+    if token.kind != PpTokenKind::Punctuator {
+        return None;
+    }
+    match token.text.as_str() {
+        "||" => Some(1),
+        "&&" => Some(2),
+        "|" => Some(3),
+        "^" => Some(4),
+        "&" => Some(5),
+        "==" | "!=" => Some(6),
+        "<" | ">" | "<=" | ">=" => Some(7),
+        "<<" | ">>" => Some(8),
+        "+" | "-" => Some(9),
+        "*" | "/" | "%" => Some(10),
+        _ => None,
+    }
+}
+
+/// 通常の算術変換に従い、どちらかが符号なしなら `uintmax_t` で計算する。
+/// シフトの結果は左辺の型になる。
+#[allow(clippy::cast_possible_wrap)]
+fn apply_binary(operator: &PpToken, lhs: Value, rhs: Value, live: bool) -> CompileResult<Value> {
+    let is_unsigned = lhs.is_unsigned || rhs.is_unsigned;
+    let (l, r) = (lhs.unsigned_bits(), rhs.unsigned_bits());
+    let compare =
+        |signed: bool, unsigned: bool| Value::truth(if is_unsigned { unsigned } else { signed });
+    let shift = |rhs: Value| u32::try_from(rhs.bits).unwrap_or(u32::MAX);
+    let bits = match operator.text.as_str() {
+        "||" => return Ok(Value::truth(lhs.is_true() || rhs.is_true())),
+        "&&" => return Ok(Value::truth(lhs.is_true() && rhs.is_true())),
+        "==" => return Ok(Value::truth(lhs.bits == rhs.bits)),
+        "!=" => return Ok(Value::truth(lhs.bits != rhs.bits)),
+        "<" => return Ok(compare(lhs.bits < rhs.bits, l < r)),
+        ">" => return Ok(compare(lhs.bits > rhs.bits, l > r)),
+        "<=" => return Ok(compare(lhs.bits <= rhs.bits, l <= r)),
+        ">=" => return Ok(compare(lhs.bits >= rhs.bits, l >= r)),
+        "<<" => {
+            return Ok(Value {
+                bits: lhs.bits.checked_shl(shift(rhs)).unwrap_or(0),
+                ..lhs
+            })
+        }
+        ">>" => {
+            let bits = if lhs.is_unsigned {
+                l.checked_shr(shift(rhs)).unwrap_or(0) as i64
+            } else {
+                lhs.bits
+                    .checked_shr(shift(rhs))
+                    .unwrap_or(if lhs.bits < 0 { -1 } else { 0 })
+            };
+            return Ok(Value { bits, ..lhs });
+        }
+        "|" => lhs.bits | rhs.bits,
+        "^" => lhs.bits ^ rhs.bits,
+        "&" => lhs.bits & rhs.bits,
+        "+" => lhs.bits.wrapping_add(rhs.bits),
+        "-" => lhs.bits.wrapping_sub(rhs.bits),
+        "*" => lhs.bits.wrapping_mul(rhs.bits),
+        "/" | "%" if rhs.bits == 0 => {
+            if live {
+                return Err(directive_error("division by zero in #if", operator));
+            }
+            0
+        }
+        "/" if is_unsigned => (l / r) as i64,
+        "%" if is_unsigned => (l % r) as i64,
+        "/" => lhs.bits.wrapping_div(rhs.bits),
+        _ => lhs.bits.wrapping_rem(rhs.bits),
+    };
+    Ok(Value { bits, is_unsigned })
+}
+
+fn directive_error(message: impl Into<String>, token: &PpToken) -> CompileError {
+    error(message, token.position, token.text.len())
+}
+
+fn error(message: impl Into<String>, position: usize, length: usize) -> CompileError {
+    CompileError::new(
+        CompileErrorKind::Preprocess,
+        message,
+        Some(Span::new(
+            SourcePosition(position),
+            SourcePosition(position + length),
+        )),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocess_str(source: &str) -> (Preprocessed, Vec<CompileError>) {
+        preprocess(source, "a.c", &PreprocessOptions::default())
+    }
+
+    #[test]
+    fn test_preprocess_keeps_lines() {
+        let (preprocessed, errors) = preprocess_str(
+            "#define N 1\nint a = N; /* x\ny */ int b;\n#if 0\nint c;\n#endif\nint d; // z",
+        );
+        assert_eq!(errors, vec![]);
+        assert_eq!(
+            preprocessed.text,
+            "#define N 1\nint a = 1 ;\n     int b;\n#if 0\n\n#endif\nint d;     "
+        );
+        assert_eq!(preprocessed.lines.len(), 7);
+        assert_eq!(
+            preprocessed.lines[6],
+            LineOrigin {
+                file: "a.c".to_string(),
+                line: 7,
+                expansion: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_preprocess_options() {
+        let options = PreprocessOptions {
+            include_paths: vec![],
+            macro_options: vec![
+                MacroOption::Define("A".to_string(), "1".to_string()),
+                MacroOption::Define("F(x)".to_string(), "x + A".to_string()),
+                MacroOption::Define("B".to_string(), "2".to_string()),
+                MacroOption::Undefine("B".to_string()),
+            ],
+        };
+        let (preprocessed, errors) = preprocess("F(3) B", "a.c", &options);
+        assert_eq!(errors, vec![]);
+        assert_eq!(preprocessed.text, "3 + 1 B");
+    }
+
+    #[test]
+    fn test_preprocess_builtin_stdarg() {
+        let (preprocessed, errors) =
+            preprocess_str("#include <stdarg.h>\nva_arg(ap, int); va_end(ap);\n");
+        assert_eq!(errors, vec![]);
+        assert!(preprocessed
+            .text
+            .ends_with("__builtin_va_arg(ap, int) ; __builtin_va_end(ap) ;\n"));
+        assert!(preprocessed
+            .lines
+            .iter()
+            .any(|origin| origin.file == "<stdarg.h>"));
+    }
+
+    #[test]
+    fn test_preprocess_builtin_stddef() {
+        let (preprocessed, errors) = preprocess_str(
+            "#define __need_size_t\n#include <stddef.h>\n#ifdef NULL\nearly\n#endif\n#include <stddef.h>\n#include <stdbool.h>\nNULL true\n",
+        );
+        assert_eq!(errors, vec![]);
+        assert!(!preprocessed.text.contains("early"));
+        assert_eq!(
+            preprocessed
+                .text
+                .matches("typedef unsigned long size_t")
+                .count(),
+            1
+        );
+        assert!(preprocessed.text.ends_with("((void *)0) 1\n"));
+    }
+
+    #[test]
+    fn test_preprocess_errors_point_at_directives() {
+        let (_, errors) = preprocess_str("int a;\n#if 1 +\n#endif\n#else\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| (error.message.as_str(), error.span.unwrap().start.0))
+                .collect::<Vec<_>>(),
+            vec![("expected value in #if", 8), ("#else without #if", 23),]
+        );
+    }
+
+    #[test]
+    fn test_evaluate_if() {
+        let (preprocessed, errors) = preprocess_str(
+            "#if 0x10 + 010 + 0b1 + 'a' == 122 && -1 < 0 && (1 || 1 / 0) && !UNDEFINED\nyes\n#endif",
+        );
+        assert_eq!(errors, vec![]);
+        assert!(preprocessed.text.contains("yes"));
+
+        let (preprocessed, errors) = preprocess_str(
+            "#if -1 > 0u && -1 / 2u > 0 && (0 ? 1u : -1) > 0 && L'\\0' - 1 < 0 && U'\\0' - 1 > 0\nyes\n#endif",
+        );
+        assert_eq!(errors, vec![]);
+        assert!(preprocessed.text.contains("yes"));
+
+        let (_, errors) = preprocess_str("#if 09\n#endif\n#if 1.5\n#endif\n#if 1 ? 2\n#endif\n");
+        assert_eq!(
+            errors
+                .iter()
+                .map(|error| error.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "invalid digit '9' in octal constant",
+                "1.5 is not an integer constant in #if",
+                "expected ':' in #if",
+            ]
+        );
+    }
+}
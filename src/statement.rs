@@ -1,32 +1,118 @@
 use crate::{
     expr::{Expr, TypedExpr},
+    lex::Span,
+    top_level::Initializer,
     types::Type,
 };
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub const fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum Statement {
+pub enum StatementKind {
     Expr(Expr),
-    Return(Expr),
+    /// `return;` は式を持たない。
+    Return(Option<Expr>),
     If(Box<Expr>, Box<Statement>),
     IfElse(Box<Expr>, Box<Statement>, Box<Statement>),
     While(Box<Expr>, Box<Statement>),
-    For(Box<Expr>, Box<Expr>, Box<Expr>, Box<Statement>),
+    /// 初期化節は式文か変数宣言か空文。宣言した変数のスコープは `for` 文の中に限られる。
+    /// 条件式と更新式は省略できる。条件式を省略すると常に真になる。
+    For(
+        Box<Statement>,
+        Option<Box<Expr>>,
+        Option<Box<Expr>>,
+        Box<Statement>,
+    ),
+    DoWhile(Box<Statement>, Box<Expr>),
+    Break,
+    Continue,
+    Switch(Box<Expr>, Box<Statement>),
+    /// `case` の値は構文解析の時点で評価しておく。条件式の型への変換は型検査で行う。
+    Case(i64, Box<Statement>),
+    Default(Box<Statement>),
+    Goto(String),
+    Labeled(String, Box<Statement>),
     Block(Vec<Statement>),
-    VariableDeclaration(String, Type),
+    /// `int a = 1, *b;` のように 1 つの文でまとめて宣言した変数。
+    VariableDeclaration(Vec<Declaration>),
+    /// `;` だけの文や、`struct s { ... };` のように変数を宣言しない宣言。
+    Empty,
+}
+
+/// 宣言される変数 1 つ分。
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Declaration {
+    pub name: String,
+    pub ty: Type,
+    pub initializer: Option<Initializer>,
+    pub storage: Storage,
+}
+
+/// ブロック内で宣言された変数の記憶域。
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Storage {
+    /// スタックに置く通常の変数。
+    Automatic,
+    /// `static` を付けたもの。関数ごとの静的領域に置く。
+    Static,
+    /// `extern` を付けたもの。同名のグローバル変数を指す。
+    Extern,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum TypedStatement {
+pub struct TypedStatement {
+    pub kind: TypedStatementKind,
+    pub span: Span,
+}
+
+impl TypedStatement {
+    pub const fn new(kind: TypedStatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TypedStatementKind {
     Expr(TypedExpr),
-    Return(TypedExpr),
+    Return(Option<TypedExpr>),
     If(Box<TypedExpr>, Box<TypedStatement>),
     IfElse(Box<TypedExpr>, Box<TypedStatement>, Box<TypedStatement>),
     While(Box<TypedExpr>, Box<TypedStatement>),
     For(
-        Box<TypedExpr>,
-        Box<TypedExpr>,
-        Box<TypedExpr>,
+        Box<TypedStatement>,
+        Option<Box<TypedExpr>>,
+        Option<Box<TypedExpr>>,
         Box<TypedStatement>,
     ),
+    DoWhile(Box<TypedStatement>, Box<TypedExpr>),
+    Break,
+    Continue,
+    Switch(Box<TypedExpr>, Box<TypedStatement>),
+    /// `case` の値は条件式を整数拡張した型に変換してある。
+    Case(i64, Box<TypedStatement>),
+    Default(Box<TypedStatement>),
+    Goto(String),
+    Labeled(String, Box<TypedStatement>),
     Block(Vec<TypedStatement>),
-    VariableDeclaration(String, Type),
+    VariableDeclaration(Vec<TypedDeclaration>),
+    Empty,
+}
+
+/// スタックに置く変数だけを表す。名前は `LocalScopes` が付けた関数内で一意なもの。
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TypedDeclaration {
+    pub name: String,
+    pub ty: Type,
+    /// 初期化子があれば、変数を 0 で埋めたあとに評価する代入式の列。
+    pub initializer: Option<Vec<TypedExpr>>,
 }
@@ -1,11 +1,18 @@
+use std::fmt;
+
+use crate::types::Type;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Token {
-    Num(i32),
+    Num(IntegerLiteral),
+    FloatNum(FloatLiteral),
+    StringLiteral(StringLiteral),
     Identifier(String),
     Plus,
     Minus,
     Asterisk,
     Slash,
+    Percent,
     LParen,
     RParen,
     Equality,
@@ -15,19 +22,387 @@ pub enum Token {
     GreaterThan,
     GreaterThanOrEqual,
     Assign,
+    PlusAssign,
+    MinusAssign,
+    AsteriskAssign,
+    SlashAssign,
+    PercentAssign,
+    LeftShiftAssign,
+    RightShiftAssign,
+    AmpersandAssign,
+    PipeAssign,
+    CaretAssign,
+    Increment,
+    Decrement,
+    Question,
+    Colon,
     Semicolon,
     Return,
     If,
     Else,
     While,
     For,
+    Do,
+    Break,
+    Continue,
+    Switch,
+    Case,
+    Default,
+    Goto,
     LBracket,
     RBracket,
     LBrace,
     RBrace,
     Comma,
     Ampersand,
+    LogicalAnd,
+    Pipe,
+    LogicalOr,
+    Caret,
+    Exclamation,
+    Tilde,
+    LeftShift,
+    RightShift,
+    Dot,
+    Arrow,
+    Ellipsis,
     Int,
+    Char,
+    Short,
+    Long,
+    Signed,
+    Unsigned,
+    Bool,
+    Float,
+    Double,
+    Void,
+    Struct,
+    Union,
+    Enum,
     Extern,
+    Static,
+    Typedef,
+    Const,
+    Volatile,
     Sizeof,
+    Alignof,
+    Auto,
+    Register,
+    Inline,
+    Restrict,
+    Noreturn,
+    Alignas,
+    Atomic,
+    Generic,
+    StaticAssert,
+    ThreadLocal,
+    Complex,
+    Imaginary,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Self::Num(literal) => return write!(f, "{}", literal.value),
+            Self::FloatNum(literal) => return write!(f, "{}", literal.value()),
+            Self::StringLiteral(literal) => {
+                return write!(f, "\"{}\"", literal.bytes.escape_ascii());
+            }
+            Self::Identifier(name) => return write!(f, "{name}"),
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Asterisk => "*",
+            Self::Slash => "/",
+            Self::Percent => "%",
+            Self::LParen => "(",
+            Self::RParen => ")",
+            Self::Equality => "==",
+            Self::Inequality => "!=",
+            Self::LessThan => "<",
+            Self::LessThanOrEqual => "<=",
+            Self::GreaterThan => ">",
+            Self::GreaterThanOrEqual => ">=",
+            Self::Assign => "=",
+            Self::PlusAssign => "+=",
+            Self::MinusAssign => "-=",
+            Self::AsteriskAssign => "*=",
+            Self::SlashAssign => "/=",
+            Self::PercentAssign => "%=",
+            Self::LeftShiftAssign => "<<=",
+            Self::RightShiftAssign => ">>=",
+            Self::AmpersandAssign => "&=",
+            Self::PipeAssign => "|=",
+            Self::CaretAssign => "^=",
+            Self::Increment => "++",
+            Self::Decrement => "--",
+            Self::Question => "?",
+            Self::Colon => ":",
+            Self::Semicolon => ";",
+            Self::Return => "return",
+            Self::If => "if",
+            Self::Else => "else",
+            Self::While => "while",
+            Self::For => "for",
+            Self::Do => "do",
+            Self::Break => "break",
+            Self::Continue => "continue",
+            Self::Switch => "switch",
+            Self::Case => "case",
+            Self::Default => "default",
+            Self::Goto => "goto",
+            Self::LBracket => "[",
+            Self::RBracket => "]",
+            Self::LBrace => "{",
+            Self::RBrace => "}",
+            Self::Comma => ",",
+            Self::Ampersand => "&",
+            Self::LogicalAnd => "&&",
+            Self::Pipe => "|",
+            Self::LogicalOr => "||",
+            Self::Caret => "^",
+            Self::Exclamation => "!",
+            Self::Tilde => "~",
+            Self::LeftShift => "<<",
+            Self::RightShift => ">>",
+            Self::Dot => ".",
+            Self::Arrow => "->",
+            Self::Ellipsis => "...",
+            Self::Int => "int",
+            Self::Char => "char",
+            Self::Short => "short",
+            Self::Long => "long",
+            Self::Signed => "signed",
+            Self::Unsigned => "unsigned",
+            Self::Bool => "_Bool",
+            Self::Float => "float",
+            Self::Double => "double",
+            Self::Void => "void",
+            Self::Struct => "struct",
+            Self::Union => "union",
+            Self::Enum => "enum",
+            Self::Extern => "extern",
+            Self::Static => "static",
+            Self::Typedef => "typedef",
+            Self::Const => "const",
+            Self::Volatile => "volatile",
+            Self::Sizeof => "sizeof",
+            Self::Alignof => "_Alignof",
+            Self::Auto => "auto",
+            Self::Register => "register",
+            Self::Inline => "inline",
+            Self::Restrict => "restrict",
+            Self::Noreturn => "_Noreturn",
+            Self::Alignas => "_Alignas",
+            Self::Atomic => "_Atomic",
+            Self::Generic => "_Generic",
+            Self::StaticAssert => "_Static_assert",
+            Self::ThreadLocal => "_Thread_local",
+            Self::Complex => "_Complex",
+            Self::Imaginary => "_Imaginary",
+        };
+        write!(f, "{text}")
+    }
+}
+
+/// 整数定数。型は接尾辞と値の大きさから字句解析で決める。
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct IntegerLiteral {
+    /// 値のビット列。`unsigned long` の定数は `i64` に収まらないことがある。
+    pub value: u64,
+    pub is_unsigned: bool,
+    /// `long` 型。`long long` も同じ大きさなので `long` として扱う。
+    pub is_long: bool,
+}
+
+impl IntegerLiteral {
+    /// 文字定数や `int` に収まる接尾辞なしの定数。
+    #[must_use]
+    #[allow(clippy::cast_sign_loss)]
+    pub const fn int(value: i32) -> Self {
+        Self {
+            value: value as i64 as u64,
+            is_unsigned: false,
+            is_long: false,
+        }
+    }
+
+    #[must_use]
+    pub const fn ty(self) -> Type {
+        match (self.is_unsigned, self.is_long) {
+            (false, false) => Type::IntTyp,
+            (true, false) => Type::UIntTyp,
+            (false, true) => Type::LongTyp,
+            (true, true) => Type::ULongTyp,
+        }
+    }
+}
+
+/// 浮動小数点数リテラル。`Eq` を保てるよう値はビット列で持つ。
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FloatLiteral {
+    bits: u64,
+    /// `f` 接尾辞の付いた `float` 型のリテラル。
+    pub is_float: bool,
+}
+
+impl FloatLiteral {
+    #[must_use]
+    pub const fn new(value: f64, is_float: bool) -> Self {
+        Self {
+            bits: value.to_bits(),
+            is_float,
+        }
+    }
+
+    #[must_use]
+    pub const fn value(self) -> f64 {
+        f64::from_bits(self.bits)
+    }
+}
+
+/// 文字列リテラルの要素の種類。接頭辞で決まる。
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Encoding {
+    /// 接頭辞なしと `u8`。
+    Char,
+    /// `L`。`wchar_t` は `int`。
+    Wide,
+    /// `u`。`char16_t` は `unsigned short`。
+    Char16,
+    /// `U`。`char32_t` は `unsigned int`。
+    Char32,
+}
+
+impl Encoding {
+    #[must_use]
+    pub const fn element_type(self) -> Type {
+        match self {
+            Self::Char => Type::CharTyp,
+            Self::Wide => Type::IntTyp,
+            Self::Char16 => Type::UShortTyp,
+            Self::Char32 => Type::UIntTyp,
+        }
+    }
+
+    const fn size(self) -> usize {
+        match self {
+            Self::Char => 1,
+            Self::Char16 => 2,
+            Self::Wide | Self::Char32 => 4,
+        }
+    }
+}
+
+/// 文字列リテラル。接頭辞のあるものは、要素の値をリトルエンディアンで並べる。
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StringLiteral {
+    /// エスケープを解釈した後の配列の中身。終端の NUL は含まない。
+    pub bytes: Vec<u8>,
+    pub encoding: Encoding,
+}
+
+impl StringLiteral {
+    /// 接頭辞のない文字列リテラルと `u8"..."`。
+    #[must_use]
+    pub const fn narrow(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            encoding: Encoding::Char,
+        }
+    }
+
+    /// `L`、`u`、`U` の接頭辞の文字列リテラル。`bytes` は UTF-8 として読み、
+    /// UTF-8 にならないバイト (`\xff` など) はそのままの値の要素にする。
+    #[must_use]
+    pub fn wide(prefix: char, bytes: &[u8]) -> Self {
+        let encoding = match prefix {
+            'L' => Encoding::Wide,
+            'u' => Encoding::Char16,
+            _ => Encoding::Char32,
+        };
+        let mut literal = Self {
+            bytes: vec![],
+            encoding,
+        };
+        literal.push_code_points(bytes);
+        literal
+    }
+
+    /// 隣り合う文字列リテラルをつなげる。接頭辞のないものは他方の接頭辞に合わせる。
+    /// 異なる接頭辞どうしはつなげられないので `None` を返す。
+    #[must_use]
+    pub fn concat(literals: &[Self]) -> Option<Self> {
+        let encoding = literals
+            .iter()
+            .map(|literal| literal.encoding)
+            .find(|encoding| *encoding != Encoding::Char)
+            .unwrap_or(Encoding::Char);
+        let mut result = Self {
+            bytes: vec![],
+            encoding,
+        };
+        for literal in literals {
+            if literal.encoding == encoding {
+                result.bytes.extend_from_slice(&literal.bytes);
+            } else if literal.encoding == Encoding::Char {
+                result.push_code_points(&literal.bytes);
+            } else {
+                return None;
+            }
+        }
+        Some(result)
+    }
+
+    /// 終端の NUL を含めた配列の型。
+    #[must_use]
+    pub fn ty(&self) -> Type {
+        Type::Array(
+            Box::new(self.encoding.element_type()),
+            self.element_count() + 1,
+        )
+    }
+
+    /// 終端の NUL を除いた要素の数。
+    #[must_use]
+    pub const fn element_count(&self) -> usize {
+        self.bytes.len() / self.encoding.size()
+    }
+
+    /// 終端の NUL の大きさ。
+    #[must_use]
+    pub const fn terminator_size(&self) -> usize {
+        self.encoding.size()
+    }
+
+    /// 終端の NUL を除いた各要素の値。`char` の要素は符号なしの値にする。
+    #[must_use]
+    pub fn values(&self) -> Vec<u32> {
+        self.bytes
+            .chunks(self.encoding.size())
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .rev()
+                    .fold(0, |value, byte| value << 8 | u32::from(*byte))
+            })
+            .collect()
+    }
+
+    fn push_code_points(&mut self, bytes: &[u8]) {
+        for chunk in bytes.utf8_chunks() {
+            let chars = chunk
+                .valid()
+                .chars()
+                .chain(chunk.invalid().iter().map(|byte| char::from(*byte)));
+            for c in chars {
+                if self.encoding == Encoding::Char16 {
+                    for unit in c.encode_utf16(&mut [0; 2]) {
+                        self.bytes.extend_from_slice(&unit.to_le_bytes());
+                    }
+                } else {
+                    self.bytes.extend_from_slice(&u32::from(c).to_le_bytes());
+                }
+            }
+        }
+    }
 }
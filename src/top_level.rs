@@ -1,23 +1,109 @@
-use std::collections::HashMap;
-
 use crate::{
+    expr::Expr,
+    lex::Span,
     statement::{Statement, TypedStatement},
+    token::StringLiteral,
     types::Type,
 };
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum TopLevel {
-    FunctionDefinition(String, Vec<(String, Type)>, Type, Vec<Statement>),
-    ExternalFunctionDeclaration(String, Vec<(String, Type)>, Type),
+pub struct TopLevel {
+    pub kind: TopLevelKind,
+    pub span: Span,
+}
+
+impl TopLevel {
+    pub const fn new(kind: TopLevelKind, span: Span) -> Self {
+        Self { kind, span }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub enum TypedTopLevel {
+pub enum TopLevelKind {
+    /// 名前、仮引数、`...` を持つか、戻り値の型、本体、リンケージ。
+    FunctionDefinition(
+        String,
+        Vec<(String, Type)>,
+        bool,
+        Type,
+        Vec<Statement>,
+        Linkage,
+    ),
+    ExternalFunctionDeclaration(String, Vec<(String, Type)>, bool, Type),
+    GlobalVariable(String, Type, Option<Initializer>, Linkage),
+    /// 初期化子のない `extern` 宣言。定義はほかの翻訳単位か、同じファイルの後の方にある。
+    ExternalVariableDeclaration(String, Type),
+}
+
+/// 関数とグローバル変数のリンケージ。
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Linkage {
+    External,
+    /// `static` を付けたもの。ほかの翻訳単位からは見えない。
+    Internal,
+}
+
+/// 変数の初期化子。`= 3` なら `Expr`、`= {1, 2, 3}` なら `List`。
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Initializer {
+    Expr(Expr),
+    List(Vec<Self>, Span),
+    /// `{[2] = 1, .x = 3}` の `[2] = 1` のような指示付きの要素。`List` の中にだけ現れる。
+    Designated(Vec<Designator>, Box<Self>, Span),
+}
+
+impl Initializer {
+    pub const fn span(&self) -> Span {
+        match self {
+            Self::Expr(expr) => expr.span,
+            Self::List(_, span) | Self::Designated(_, _, span) => *span,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Designator {
+    /// `[2]`。添字は構文解析の時点で評価しておく。
+    Index(usize, Span),
+    /// `.x`
+    Member(String, Span),
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TypedTopLevel {
+    pub kind: TypedTopLevelKind,
+    pub span: Span,
+}
+
+impl TypedTopLevel {
+    pub const fn new(kind: TypedTopLevelKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TypedTopLevelKind {
+    /// 局所変数の名前は `LocalScopes` が付けた関数内で一意なもの。
     FunctionDefinition(
         String,
         Vec<(String, Type)>,
         Type,
         Vec<TypedStatement>,
-        HashMap<String, Type>,
+        Linkage,
     ),
+    /// 初期値が `None` なら .bss に置く。
+    GlobalVariable(String, Type, Option<Vec<StaticValue>>, Linkage),
+}
+
+/// 静的領域に書き出す値。
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StaticValue {
+    /// 値とバイト数。
+    Integer(i64, usize),
+    /// ラベルのアドレスにバイト数を足したもの (8 バイト)。
+    Address(String, i64),
+    /// .rodata に置いた文字列リテラルのアドレスにバイト数を足したもの (8 バイト)。
+    StringLiteral(StringLiteral, i64),
+    /// 指定バイト数の 0。
+    Zero(usize),
 }
@@ -1,19 +1,660 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Type {
+    BoolTyp,
+    /// `signed char` も `char` として扱う (x86-64 では `char` は符号付き)。
+    CharTyp,
+    UCharTyp,
+    ShortTyp,
+    UShortTyp,
     IntTyp,
-    Pointer(Box<Type>),
-    Array(Box<Type>, usize),
+    UIntTyp,
+    LongTyp,
+    ULongTyp,
+    LongLongTyp,
+    ULongLongTyp,
+    FloatTyp,
+    DoubleTyp,
+    /// x87 の拡張精度 (16 バイト)。システムのヘッダの宣言を読むための型で、値としては使えない。
+    LongDoubleTyp,
+    /// 値を持たない不完全型。関数の戻り値かポインタの指す先として使う。
+    Void,
+    Pointer(Box<Self>),
+    Array(Box<Self>, usize),
+    /// 要素数を省略した配列。初期化子から要素数を決めて `Array` にする。
+    IncompleteArray(Box<Self>),
+    Struct(Rc<StructType>),
+    /// 仮引数の型と戻り値の型、`...` で可変長引数を取るか。値としては関数へのポインタに変換される。
+    Function(Vec<Self>, Box<Self>, bool),
+    /// `const` や `volatile` の付いた型。`qualified` で作り、入れ子にも配列にもしない。
+    Qualified(Box<Self>, Qualifiers),
+}
+
+/// 型修飾子。`volatile` は記録するだけで、コード生成には影響しない。
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Qualifiers {
+    pub is_const: bool,
+    pub is_volatile: bool,
+}
+
+impl Qualifiers {
+    pub const fn is_empty(self) -> bool {
+        !self.is_const && !self.is_volatile
+    }
+
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self {
+            is_const: self.is_const || other.is_const,
+            is_volatile: self.is_volatile || other.is_volatile,
+        }
+    }
+
+    /// `other` の修飾子をすべて持つなら真。
+    pub const fn contains(self, other: Self) -> bool {
+        (self.is_const || !other.is_const) && (self.is_volatile || !other.is_volatile)
+    }
 }
 
 impl Type {
     #[allow(dead_code)]
     pub fn get_size(&self) -> usize {
         match self {
-            Self::Pointer(_) => 8,
-            Self::IntTyp => 4,
+            // GCC と同じく関数と void の大きさは 1 とする。
+            Self::BoolTyp
+            | Self::CharTyp
+            | Self::UCharTyp
+            | Self::Void
+            | Self::Function(_, _, _) => 1,
+            Self::ShortTyp | Self::UShortTyp => 2,
+            Self::IntTyp | Self::UIntTyp | Self::FloatTyp => 4,
+            Self::LongTyp
+            | Self::ULongTyp
+            | Self::LongLongTyp
+            | Self::ULongLongTyp
+            | Self::DoubleTyp
+            | Self::Pointer(_) => 8,
+            Self::LongDoubleTyp => 16,
             Self::Array(t, n) => t.get_size() * n,
+            Self::IncompleteArray(_) => 0,
+            Self::Struct(struct_type) => struct_type.layout().map_or(0, |layout| layout.size),
+            Self::Qualified(t, _) => t.get_size(),
+        }
+    }
+
+    pub fn get_align(&self) -> usize {
+        match self {
+            Self::Array(t, _) | Self::IncompleteArray(t) | Self::Qualified(t, _) => t.get_align(),
+            Self::Struct(struct_type) => struct_type.layout().map_or(1, |layout| layout.align),
+            _ => self.get_size(),
+        }
+    }
+
+    /// 修飾子を取り除いた型。
+    pub fn unqualified(&self) -> &Self {
+        match self {
+            Self::Qualified(t, _) => t,
+            _ => self,
+        }
+    }
+
+    /// 配列の修飾子は要素の型のもの。
+    pub fn qualifiers(&self) -> Qualifiers {
+        match self {
+            Self::Qualified(_, qualifiers) => *qualifiers,
+            Self::Array(t, _) | Self::IncompleteArray(t) => t.qualifiers(),
+            _ => Qualifiers::default(),
+        }
+    }
+
+    /// 修飾子を加える。配列に付けた修飾子は要素の型に付く。
+    #[must_use]
+    pub fn qualified(self, qualifiers: Qualifiers) -> Self {
+        if qualifiers.is_empty() {
+            return self;
+        }
+        match self {
+            Self::Qualified(t, previous) => Self::Qualified(t, previous.union(qualifiers)),
+            Self::Array(t, n) => Self::Array(Box::new(t.qualified(qualifiers)), n),
+            Self::IncompleteArray(t) => Self::IncompleteArray(Box::new(t.qualified(qualifiers))),
+            t => Self::Qualified(Box::new(t), qualifiers),
+        }
+    }
+
+    /// 一番外側の修飾子を無視すれば同じ型なら真。ポインタの指す先の修飾子は無視しない。
+    pub fn eq_ignoring_qualifiers(&self, other: &Self) -> bool {
+        self.unqualified() == other.unqualified()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.integer_rank().is_some()
+    }
+
+    pub fn is_floating(&self) -> bool {
+        matches!(self.unqualified(), Self::FloatTyp | Self::DoubleTyp)
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || self.is_floating()
+    }
+
+    pub fn is_unsigned(&self) -> bool {
+        matches!(
+            self.unqualified(),
+            Self::BoolTyp
+                | Self::UCharTyp
+                | Self::UShortTyp
+                | Self::UIntTyp
+                | Self::ULongTyp
+                | Self::ULongLongTyp
+        )
+    }
+
+    /// 整数変換の順位。整数型でなければ `None`。
+    fn integer_rank(&self) -> Option<u8> {
+        match self.unqualified() {
+            Self::BoolTyp => Some(0),
+            Self::CharTyp | Self::UCharTyp => Some(1),
+            Self::ShortTyp | Self::UShortTyp => Some(2),
+            Self::IntTyp | Self::UIntTyp => Some(3),
+            Self::LongTyp | Self::ULongTyp => Some(4),
+            Self::LongLongTyp | Self::ULongLongTyp => Some(5),
+            _ => None,
+        }
+    }
+
+    /// 整数拡張。int より順位の低い整数型は int になる。
+    #[must_use]
+    pub fn promote(&self) -> Self {
+        match self.integer_rank() {
+            Some(rank) if rank < 3 => Self::IntTyp,
+            _ => self.clone(),
+        }
+    }
+
+    /// 既定の実引数拡張。可変長引数に渡す `float` は `double` にし、整数は整数拡張する。
+    #[must_use]
+    pub fn promote_argument(&self) -> Self {
+        if *self == Self::FloatTyp {
+            Self::DoubleTyp
+        } else {
+            self.promote()
+        }
+    }
+
+    /// 同じ順位の符号なし整数型。
+    #[must_use]
+    pub fn to_unsigned(&self) -> Self {
+        match self {
+            Self::CharTyp => Self::UCharTyp,
+            Self::ShortTyp => Self::UShortTyp,
+            Self::IntTyp => Self::UIntTyp,
+            Self::LongTyp => Self::ULongTyp,
+            Self::LongLongTyp => Self::ULongLongTyp,
+            _ => self.clone(),
+        }
+    }
+
+    /// 通常の算術型変換で 2 つの算術型から決まる共通の型。
+    /// 浮動小数点型があればそちらに、なければ整数型同士で揃える。
+    #[must_use]
+    pub fn common_arithmetic_type(lhs: &Self, rhs: &Self) -> Self {
+        if *lhs == Self::DoubleTyp || *rhs == Self::DoubleTyp {
+            Self::DoubleTyp
+        } else if *lhs == Self::FloatTyp || *rhs == Self::FloatTyp {
+            Self::FloatTyp
+        } else {
+            Self::common_integer_type(lhs, rhs)
+        }
+    }
+
+    /// 通常の算術型変換で 2 つの整数型から決まる共通の型。
+    #[must_use]
+    pub fn common_integer_type(lhs: &Self, rhs: &Self) -> Self {
+        let (lhs, rhs) = (lhs.promote(), rhs.promote());
+        if lhs == rhs {
+            return lhs;
+        }
+        let (lhs_rank, rhs_rank) = (lhs.integer_rank(), rhs.integer_rank());
+        if lhs.is_unsigned() == rhs.is_unsigned() {
+            return if lhs_rank >= rhs_rank { lhs } else { rhs };
+        }
+        let (unsigned, signed) = if lhs.is_unsigned() {
+            (lhs, rhs)
+        } else {
+            (rhs, lhs)
+        };
+        if unsigned.integer_rank() >= signed.integer_rank() {
+            unsigned
+        } else if signed.get_size() > unsigned.get_size() {
+            signed
+        } else {
+            signed.to_unsigned()
+        }
+    }
+
+    /// System V ABI の `va_list`。`__va_list_tag` 1 つの配列で、仮引数や値としてはポインタになる。
+    pub fn va_list() -> Self {
+        let tag = StructType::new(StructKind::Struct, Some(VA_LIST_TAG.to_string()));
+        tag.define(vec![
+            ("gp_offset".to_string(), Self::UIntTyp),
+            ("fp_offset".to_string(), Self::UIntTyp),
+            (
+                "overflow_arg_area".to_string(),
+                Self::Pointer(Box::new(Self::Void)),
+            ),
+            (
+                "reg_save_area".to_string(),
+                Self::Pointer(Box::new(Self::Void)),
+            ),
+        ]);
+        Self::Array(Box::new(Self::Struct(Rc::new(tag))), 1)
+    }
+
+    /// `va_list` が変換されたポインタなら真。
+    pub fn is_va_list_pointer(&self) -> bool {
+        matches!(
+            self.unqualified(),
+            Self::Pointer(pointee) if matches!(
+                pointee.unqualified(),
+                Self::Struct(struct_type) if struct_type.tag.as_deref() == Some(VA_LIST_TAG)
+            )
+        )
+    }
+
+    /// `long double` の値を (配列の要素や構造体のメンバとして) 含むなら真。ポインタの先は見ない。
+    pub fn contains_long_double(&self) -> bool {
+        match self {
+            Self::LongDoubleTyp => true,
+            Self::Array(t, _) | Self::IncompleteArray(t) | Self::Qualified(t, _) => {
+                t.contains_long_double()
+            }
+            Self::Struct(struct_type) => struct_type
+                .members()
+                .iter()
+                .any(|member| member.ty.contains_long_double()),
+            _ => false,
+        }
+    }
+
+    /// 前方宣言だけされた構造体 (とその配列) と要素数を省略した配列は大きさが分からない。
+    pub fn is_complete(&self) -> bool {
+        match self {
+            Self::Array(t, _) | Self::Qualified(t, _) => t.is_complete(),
+            Self::Struct(struct_type) => struct_type.layout().is_some(),
+            Self::Void | Self::IncompleteArray(_) => false,
+            _ => true,
         }
     }
 }
 
-pub type FunctionType = (Vec<Type>, Box<Type>);
+const VA_LIST_TAG: &str = "__va_list_tag";
+
+pub type FunctionType = (Vec<Type>, Box<Type>, bool);
+
+/// 構造体型と共用体型。自己参照できるよう、メンバは宣言の後から `define` で与える。
+/// 同じ宣言から作られたものだけが等しい。
+pub struct StructType {
+    pub kind: StructKind,
+    pub tag: Option<String>,
+    layout: RefCell<Option<StructLayout>>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StructKind {
+    Struct,
+    /// すべてのメンバがオフセット 0 に重なる。
+    Union,
+}
+
+#[derive(Debug, Clone)]
+struct StructLayout {
+    members: Vec<Member>,
+    size: usize,
+    align: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Member {
+    pub name: String,
+    pub ty: Type,
+    pub offset: usize,
+}
+
+impl StructType {
+    pub const fn new(kind: StructKind, tag: Option<String>) -> Self {
+        Self {
+            kind,
+            tag,
+            layout: RefCell::new(None),
+        }
+    }
+
+    /// System V ABI に従い、各メンバをその型のアラインメントに揃えて順に並べる。
+    /// 共用体ではすべてのメンバをオフセット 0 に置く。
+    /// 全体の大きさは最大のアラインメントの倍数に切り上げる。
+    pub fn define(&self, members: Vec<(String, Type)>) {
+        let mut offset = 0;
+        let mut size = 0;
+        let mut align = 1;
+        let members = members
+            .into_iter()
+            .map(|(name, ty)| {
+                if self.kind == StructKind::Struct {
+                    offset = align_to(offset, ty.get_align());
+                }
+                align = align.max(ty.get_align());
+                let member = Member { name, ty, offset };
+                size = size.max(offset + member.ty.get_size());
+                if self.kind == StructKind::Struct {
+                    offset += member.ty.get_size();
+                }
+                member
+            })
+            .collect();
+        *self.layout.borrow_mut() = Some(StructLayout {
+            members,
+            size: align_to(size, align),
+            align,
+        });
+    }
+
+    pub fn members(&self) -> Vec<Member> {
+        self.layout().map_or_else(Vec::new, |layout| layout.members)
+    }
+
+    pub fn member(&self, name: &str) -> Option<Member> {
+        self.members()
+            .into_iter()
+            .find(|member| member.name == name)
+    }
+
+    fn layout(&self) -> Option<StructLayout> {
+        self.layout.borrow().clone()
+    }
+}
+
+impl PartialEq for StructType {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Eq for StructType {}
+
+// 自己参照する構造体で無限に再帰しないよう、メンバは表示しない。
+impl fmt::Debug for StructType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+impl fmt::Display for StructType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let keyword = match self.kind {
+            StructKind::Struct => "struct",
+            StructKind::Union => "union",
+        };
+        match &self.tag {
+            Some(tag) => write!(f, "{keyword} {tag}"),
+            None => write!(f, "{keyword} <anonymous>"),
+        }
+    }
+}
+
+/// C の型名として表示する。`int (*)[3]` のように宣言子の形で書く。
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.declarator(""))
+    }
+}
+
+impl Type {
+    /// `inner` を宣言子として、この型の宣言を組み立てる。
+    fn declarator(&self, inner: &str) -> String {
+        let name = match self {
+            Self::BoolTyp => "_Bool",
+            Self::CharTyp => "char",
+            Self::UCharTyp => "unsigned char",
+            Self::ShortTyp => "short",
+            Self::UShortTyp => "unsigned short",
+            Self::IntTyp => "int",
+            Self::UIntTyp => "unsigned int",
+            Self::LongTyp => "long",
+            Self::ULongTyp => "unsigned long",
+            Self::LongLongTyp => "long long",
+            Self::ULongLongTyp => "unsigned long long",
+            Self::FloatTyp => "float",
+            Self::DoubleTyp => "double",
+            Self::LongDoubleTyp => "long double",
+            Self::Void => "void",
+            Self::Struct(struct_type) => return join_declarator(&struct_type.to_string(), inner),
+            Self::Pointer(pointee) => {
+                return pointee.pointer_declarator(Qualifiers::default(), inner)
+            }
+            Self::Qualified(ty, qualifiers) => {
+                if let Self::Pointer(pointee) = ty.as_ref() {
+                    return pointee.pointer_declarator(*qualifiers, inner);
+                }
+                return format!("{qualifiers}{}", ty.declarator(inner));
+            }
+            Self::Array(element, length) => {
+                return element.declarator(&format!("{inner}[{length}]"))
+            }
+            Self::IncompleteArray(element) => return element.declarator(&format!("{inner}[]")),
+            Self::Function(params, return_type, is_variadic) => {
+                let mut params = params.iter().map(ToString::to_string).collect::<Vec<_>>();
+                if *is_variadic {
+                    params.push("...".to_string());
+                } else if params.is_empty() {
+                    params.push("void".to_string());
+                }
+                return return_type.declarator(&format!("{inner}({})", params.join(", ")));
+            }
+        };
+        join_declarator(name, inner)
+    }
+
+    /// この型を指すポインタの宣言子。配列と関数を指すときは括弧で囲む。
+    fn pointer_declarator(&self, qualifiers: Qualifiers, inner: &str) -> String {
+        let qualifiers = qualifiers.to_string();
+        let pointer = join_declarator(&format!("*{}", qualifiers.trim_end()), inner);
+        match self {
+            Self::Array(_, _) | Self::IncompleteArray(_) | Self::Function(_, _, _) => {
+                self.declarator(&format!("({pointer})"))
+            }
+            _ => self.declarator(&pointer),
+        }
+    }
+}
+
+fn join_declarator(specifier: &str, inner: &str) -> String {
+    if inner.is_empty() {
+        specifier.to_string()
+    } else if inner.starts_with('[') || specifier.ends_with('*') {
+        format!("{specifier}{inner}")
+    } else {
+        format!("{specifier} {inner}")
+    }
+}
+
+/// 後ろに空白を付けて `const volatile ` のように表示する。
+impl fmt::Display for Qualifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_const {
+            write!(f, "const ")?;
+        }
+        if self.is_volatile {
+            write!(f, "volatile ")?;
+        }
+        Ok(())
+    }
+}
+
+pub const fn align_to(num: usize, align: usize) -> usize {
+    num.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_struct_layout() {
+        let struct_type = StructType::new(StructKind::Struct, Some("s".to_string()));
+        struct_type.define(vec![
+            ("c".to_string(), Type::CharTyp),
+            ("i".to_string(), Type::IntTyp),
+            ("d".to_string(), Type::CharTyp),
+            ("p".to_string(), Type::Pointer(Box::new(Type::CharTyp))),
+            ("a".to_string(), Type::Array(Box::new(Type::CharTyp), 3)),
+        ]);
+        let ty = Type::Struct(Rc::new(struct_type));
+        let Type::Struct(struct_type) = &ty else {
+            unreachable!()
+        };
+
+        assert_eq!(
+            struct_type
+                .members()
+                .iter()
+                .map(|member| (member.name.as_str(), member.offset))
+                .collect::<Vec<_>>(),
+            vec![("c", 0), ("i", 4), ("d", 8), ("p", 16), ("a", 24)]
+        );
+        assert_eq!(ty.get_size(), 32);
+        assert_eq!(ty.get_align(), 8);
+    }
+
+    #[test]
+    fn test_display_type() {
+        let int = || Box::new(Type::IntTyp);
+        let constant = Qualifiers {
+            is_const: true,
+            is_volatile: false,
+        };
+        let cases = [
+            (Type::Pointer(int()), "int *"),
+            (Type::Array(Box::new(Type::Pointer(int())), 3), "int *[3]"),
+            (Type::Pointer(Box::new(Type::Array(int(), 3))), "int (*)[3]"),
+            (Type::IncompleteArray(int()), "int[]"),
+            (
+                Type::Pointer(Box::new(Type::Function(
+                    vec![Type::IntTyp, Type::Pointer(Box::new(Type::CharTyp))],
+                    int(),
+                    true,
+                ))),
+                "int (*)(int, char *, ...)",
+            ),
+            (
+                Type::Function(vec![], Box::new(Type::Void), false),
+                "void (void)",
+            ),
+            (Type::IntTyp.qualified(constant), "const int"),
+            (
+                Type::Pointer(Box::new(Type::CharTyp.qualified(constant))).qualified(constant),
+                "const char *const",
+            ),
+            (
+                Type::Struct(Rc::new(StructType::new(
+                    StructKind::Union,
+                    Some("u".to_string()),
+                ))),
+                "union u",
+            ),
+        ];
+        for (ty, expected) in cases {
+            assert_eq!(ty.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_common_integer_type() {
+        assert_eq!(
+            Type::common_integer_type(&Type::CharTyp, &Type::ShortTyp),
+            Type::IntTyp
+        );
+        assert_eq!(
+            Type::common_integer_type(&Type::IntTyp, &Type::UIntTyp),
+            Type::UIntTyp
+        );
+        assert_eq!(
+            Type::common_integer_type(&Type::UIntTyp, &Type::LongTyp),
+            Type::LongTyp
+        );
+        assert_eq!(
+            Type::common_integer_type(&Type::ULongTyp, &Type::LongLongTyp),
+            Type::ULongLongTyp
+        );
+        assert_eq!(
+            Type::common_integer_type(&Type::BoolTyp, &Type::UShortTyp),
+            Type::IntTyp
+        );
+    }
+
+    #[test]
+    fn test_common_arithmetic_type() {
+        assert_eq!(
+            Type::common_arithmetic_type(&Type::ULongTyp, &Type::FloatTyp),
+            Type::FloatTyp
+        );
+        assert_eq!(
+            Type::common_arithmetic_type(&Type::FloatTyp, &Type::DoubleTyp),
+            Type::DoubleTyp
+        );
+        assert_eq!(
+            Type::common_arithmetic_type(&Type::CharTyp, &Type::CharTyp),
+            Type::IntTyp
+        );
+    }
+
+    #[test]
+    fn test_union_layout() {
+        let union_type = StructType::new(StructKind::Union, Some("u".to_string()));
+        union_type.define(vec![
+            ("c".to_string(), Type::CharTyp),
+            ("a".to_string(), Type::Array(Box::new(Type::CharTyp), 5)),
+            ("i".to_string(), Type::IntTyp),
+        ]);
+        let ty = Type::Struct(Rc::new(union_type));
+        let Type::Struct(union_type) = &ty else {
+            unreachable!()
+        };
+
+        assert!(union_type.members().iter().all(|member| member.offset == 0));
+        assert_eq!(ty.get_size(), 8);
+        assert_eq!(ty.get_align(), 4);
+    }
+
+    #[test]
+    fn test_self_referential_struct() {
+        let node = Rc::new(StructType::new(
+            StructKind::Struct,
+            Some("node".to_string()),
+        ));
+        assert!(!Type::Struct(node.clone()).is_complete());
+
+        node.define(vec![
+            ("value".to_string(), Type::CharTyp),
+            (
+                "next".to_string(),
+                Type::Pointer(Box::new(Type::Struct(node.clone()))),
+            ),
+        ]);
+        let ty = Type::Struct(node.clone());
+        assert!(ty.is_complete());
+        assert_eq!(ty.get_size(), 16);
+        assert_eq!(
+            node.member("next").unwrap().ty,
+            Type::Pointer(Box::new(ty.clone()))
+        );
+        assert_ne!(
+            ty,
+            Type::Struct(Rc::new(StructType::new(
+                StructKind::Struct,
+                Some("node".to_string())
+            )))
+        );
+    }
+}
@@ -1,312 +1,1566 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
-    expr::Expr,
-    expr::TypedExpr,
-    statement::{Statement, TypedStatement},
-    top_level::{TopLevel, TypedTopLevel},
-    types::{FunctionType, Type},
-    variable_collector::collect_variables,
+    constant::{convert, convert_integer, Constant, ConstantEvaluator, StaticSymbols},
+    error::{CompileError, CompileErrorKind, CompileResult},
+    expr::{Expr, ExprKind, TypedExpr, TypedExprKind},
+    fall_through::can_fall_through,
+    initializer::{complete_array_type, flatten_initializer},
+    jump_checker::check_jumps,
+    lex::{SourcePosition, Span},
+    local_scope::LocalScopes,
+    statement::{
+        Declaration, Statement, StatementKind, Storage, TypedDeclaration, TypedStatement,
+        TypedStatementKind,
+    },
+    token::StringLiteral,
+    top_level::{
+        Initializer, Linkage, StaticValue, TopLevel, TopLevelKind, TypedTopLevel, TypedTopLevelKind,
+    },
+    types::{FunctionType, Qualifiers, Type},
 };
 
 pub struct Typist {
     function_type_environment: HashMap<String, FunctionType>,
+    global_type_environment: HashMap<String, Type>,
+    /// 型検査中に見つかった警告。エラーがなくても報告する。
+    pub warnings: Vec<CompileError>,
 }
 
 impl Typist {
-    pub const fn new(function_type_environment: HashMap<String, FunctionType>) -> Self {
+    pub const fn new(
+        function_type_environment: HashMap<String, FunctionType>,
+        global_type_environment: HashMap<String, Type>,
+    ) -> Self {
         Self {
             function_type_environment,
+            global_type_environment,
+            warnings: vec![],
         }
     }
 
-    pub fn type_program(&self, program: &Vec<TopLevel>) -> Vec<TypedTopLevel> {
+    /// 同じ変数の仮定義は 1 つだけを残す。初期化子のある定義があれば仮定義は残さない。
+    pub fn type_program(
+        &mut self,
+        program: &Vec<TopLevel>,
+    ) -> Result<Vec<TypedTopLevel>, Vec<CompileError>> {
+        let initialized = program
+            .iter()
+            .filter_map(|top_level| match &top_level.kind {
+                TopLevelKind::GlobalVariable(name, _, Some(_), _) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let mut tentative = HashSet::new();
         let mut typed_program = Vec::new();
+        let mut errors = Vec::new();
         for top_level in program {
-            if let Some(top_level) = self.type_top_level(top_level) {
-                typed_program.push(top_level);
+            if let TopLevelKind::GlobalVariable(name, _, None, _) = &top_level.kind {
+                if initialized.contains(name.as_str()) || !tentative.insert(name.as_str()) {
+                    continue;
+                }
             }
+            match self.type_top_level(top_level) {
+                Ok(mut top_levels) => typed_program.append(&mut top_levels),
+                Err(mut top_level_errors) => errors.append(&mut top_level_errors),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(typed_program)
+        } else {
+            Err(errors)
         }
-        typed_program
     }
 
-    pub fn type_top_level(&self, top_level: &TopLevel) -> Option<TypedTopLevel> {
-        match top_level {
-            TopLevel::FunctionDefinition(name, args, return_type, statements) => {
-                let function_typist = FunctionTypist::new(
+    /// 関数の定義は、その中の static 変数もグローバル変数として並べる。
+    pub fn type_top_level(
+        &mut self,
+        top_level: &TopLevel,
+    ) -> Result<Vec<TypedTopLevel>, Vec<CompileError>> {
+        match &top_level.kind {
+            TopLevelKind::FunctionDefinition(name, args, _, return_type, statements, linkage) => {
+                let mut function_typist = FunctionTypist::new(
                     self.function_type_environment.clone(),
+                    self.global_type_environment.clone(),
                     name.clone(),
                     args.clone(),
                     return_type.clone(),
                     statements.clone(),
-                );
+                    top_level.span,
+                )?;
 
-                Some(function_typist.type_function())
+                let mut typed_top_levels = vec![function_typist.type_function(*linkage)?];
+                self.warnings.append(&mut function_typist.warnings);
+                for variable in function_typist.static_variables {
+                    typed_top_levels.push(TypedTopLevel::new(
+                        TypedTopLevelKind::GlobalVariable(
+                            variable.label,
+                            variable.ty.unqualified().clone(),
+                            variable.values,
+                            Linkage::Internal,
+                        ),
+                        variable.span,
+                    ));
+                }
+                Ok(typed_top_levels)
             }
-            TopLevel::ExternalFunctionDeclaration(_, _, _) => None,
+            TopLevelKind::ExternalFunctionDeclaration(_, _, _, _)
+            | TopLevelKind::ExternalVariableDeclaration(_, _) => Ok(vec![]),
+            TopLevelKind::GlobalVariable(name, ty, initializer, linkage) => Ok(vec![self
+                .type_global_variable(
+                    name.clone(),
+                    ty,
+                    initializer.as_ref(),
+                    *linkage,
+                    top_level.span,
+                )?]),
+        }
+    }
+
+    fn type_global_variable(
+        &self,
+        name: String,
+        ty: &Type,
+        initializer: Option<&Initializer>,
+        linkage: Linkage,
+        span: Span,
+    ) -> CompileResult<TypedTopLevel> {
+        check_long_double(ty, span)?;
+        let ty = &match initializer {
+            Some(initializer) => complete_array_type(ty, initializer, &|_| false)?,
+            None => ty.clone(),
+        };
+        let globals = |name: &str| {
+            global_symbol(
+                &self.global_type_environment,
+                &self.function_type_environment,
+                name,
+            )
+        };
+        let values = initializer
+            .map(|initializer| evaluate_static_initializer(ty, initializer, &globals))
+            .transpose()?;
+        Ok(TypedTopLevel::new(
+            TypedTopLevelKind::GlobalVariable(name, ty.unqualified().clone(), values, linkage),
+            span,
+        ))
+    }
+}
+
+/// グローバル変数と関数は名前がそのままラベルになる。
+fn global_symbol(
+    globals: &HashMap<String, Type>,
+    functions: &HashMap<String, FunctionType>,
+    name: &str,
+) -> Option<(String, Type)> {
+    let ty = globals.get(name).cloned().or_else(|| {
+        functions
+            .get(name)
+            .map(|(arg_types, return_type, is_variadic)| {
+                Type::Function(arg_types.clone(), return_type.clone(), *is_variadic)
+            })
+    })?;
+    Some((name.to_string(), ty))
+}
+
+/// 初期化子を静的領域に置く値の列にする。値を与えられない部分は 0 で埋める。
+fn evaluate_static_initializer(
+    ty: &Type,
+    initializer: &Initializer,
+    symbols: StaticSymbols,
+) -> CompileResult<Vec<StaticValue>> {
+    // 構造体の値は定数にならないので、構造体の要素を初期化する式は波括弧の省略とみなす。
+    let mut values = flatten_initializer(ty, initializer, &|_| false)?;
+    // 同じ位置に複数の値があれば後のものを使う。
+    values.reverse();
+    values.sort_by_key(|value| value.offset);
+    values.dedup_by_key(|value| value.offset);
+
+    // メンバ間と末尾のパディングも 0 で埋める。
+    let mut static_values = vec![];
+    let mut offset = 0;
+    for value in values {
+        if let Type::Struct(_) = value.ty.unqualified() {
+            return Err(FunctionTypist::error(
+                format!(
+                    "{} must be initialized with a brace-enclosed list",
+                    value.ty
+                ),
+                value.expr.span,
+            ));
+        }
+        if value.offset > offset {
+            static_values.push(StaticValue::Zero(value.offset - offset));
+        }
+        static_values.push(evaluate_static_scalar(&value.ty, &value.expr, symbols)?);
+        offset = value.offset + value.ty.get_size();
+    }
+    if ty.get_size() > offset {
+        static_values.push(StaticValue::Zero(ty.get_size() - offset));
+    }
+    Ok(static_values)
+}
+
+fn evaluate_static_scalar(
+    ty: &Type,
+    expr: &Expr,
+    symbols: StaticSymbols,
+) -> CompileResult<StaticValue> {
+    let constant = ConstantEvaluator::initializer(symbols).evaluate(expr)?;
+    match (ty.unqualified(), constant) {
+        (_, constant @ (Constant::Integer(_, _) | Constant::Float(_))) if ty.is_arithmetic() => {
+            Ok(match convert(constant, ty.unqualified()) {
+                Some(Constant::Float(value)) => float_static_value(value, ty),
+                Some(Constant::Integer(value, _)) => StaticValue::Integer(value, ty.get_size()),
+                _ => unreachable!(),
+            })
+        }
+        (Type::Pointer(_), Constant::Integer(0, _)) => Ok(StaticValue::Integer(0, 8)),
+        (Type::Pointer(_), Constant::Address(label, offset, pointer_type))
+            if is_convertible(&pointer_type, ty) =>
+        {
+            Ok(StaticValue::Address(label, offset))
+        }
+        (Type::Pointer(pointee), Constant::StringLiteral(literal, offset))
+            if *pointee.unqualified() == literal.encoding.element_type() =>
+        {
+            Ok(StaticValue::StringLiteral(literal, offset))
         }
+        (_, Constant::StringLiteral(_, _)) => Err(FunctionTypist::error(
+            format!("cannot initialize {ty} with a string literal"),
+            expr.span,
+        )),
+        (_, Constant::Integer(_, constant_type)) => Err(FunctionTypist::error(
+            format!("cannot initialize {ty} with {constant_type}"),
+            expr.span,
+        )),
+        (_, Constant::Float(_)) => Err(FunctionTypist::error(
+            format!("cannot initialize {ty} with a floating constant"),
+            expr.span,
+        )),
+        (_, Constant::Address(_, _, pointer_type)) => Err(FunctionTypist::error(
+            format!("cannot initialize {ty} with {pointer_type}"),
+            expr.span,
+        )),
     }
 }
 
 pub struct FunctionTypist {
     function_type_environment: HashMap<String, FunctionType>,
-    variable_type_environment: HashMap<String, Type>,
+    global_type_environment: HashMap<String, Type>,
+    local_scopes: LocalScopes,
     function_name: String,
     function_args: Vec<(String, Type)>,
     function_return_type: Type,
     function_body: Vec<Statement>,
+    function_span: Span,
+    /// 関数内で宣言された static 変数。関数の後にグローバル変数として置く。
+    static_variables: Vec<StaticVariable>,
+    /// 囲んでいる switch の条件式を整数拡張した型。`case` の値をこの型に変換する。
+    switch_types: Vec<Type>,
+    warnings: Vec<CompileError>,
+}
+
+/// ラベルは `関数名.一意な変数名`。`.` は識別子に使えないので、ほかの名前とは衝突しない。
+/// 初期化子はその宣言の位置で見える名前で評価しておく。
+struct StaticVariable {
+    label: String,
+    ty: Type,
+    values: Option<Vec<StaticValue>>,
+    span: Span,
 }
 
 impl FunctionTypist {
     pub fn new(
         function_type_environment: HashMap<String, FunctionType>,
+        global_type_environment: HashMap<String, Type>,
         function_name: String,
         function_args: Vec<(String, Type)>,
         function_return_type: Type,
         function_body: Vec<Statement>,
-    ) -> Self {
-        let local_variable_types = collect_variables(&function_args, &function_body);
+        function_span: Span,
+    ) -> CompileResult<Self> {
+        // 仮引数は個別の位置を持たないので、重複の報告には関数全体の位置を使う。
+        check_long_double(&function_return_type, function_span)?;
+        for (_, ty) in &function_args {
+            check_long_double(ty, function_span)?;
+        }
+        let mut local_scopes = LocalScopes::new();
+        let function_args = function_args
+            .into_iter()
+            .map(|(name, ty)| {
+                let unique_name =
+                    local_scopes.declare(&name, &ty, Storage::Automatic, function_span)?;
+                Ok((unique_name, ty))
+            })
+            .collect::<CompileResult<Vec<_>>>()?;
 
-        Self {
+        Ok(Self {
             function_type_environment,
-            variable_type_environment: local_variable_types,
+            global_type_environment,
+            local_scopes,
             function_name,
             function_args,
             function_return_type,
             function_body,
+            function_span,
+            static_variables: vec![],
+            switch_types: vec![],
+            warnings: vec![],
+        })
+    }
+
+    /// 仮引数の修飾子は関数の中の型検査にだけ使う。
+    /// `main` は末尾に達すると 0 を返すので、末尾に達しうることを警告しない。
+    pub fn type_function(&mut self, linkage: Linkage) -> Result<TypedTopLevel, Vec<CompileError>> {
+        let function_body = std::mem::take(&mut self.function_body);
+        let typed_statements = self.type_statements(&function_body)?;
+        check_jumps(&typed_statements)?;
+        if *self.function_return_type.unqualified() != Type::Void
+            && self.function_name != "main"
+            && can_fall_through(&function_body)
+        {
+            // 関数の閉じ括弧を指す。
+            let end = self.function_span.end;
+            self.warnings.push(CompileError::warning(
+                CompileErrorKind::Type,
+                format!(
+                    "control reaches end of non-void function {}",
+                    self.function_name
+                ),
+                Some(Span::new(SourcePosition(end.0 - 1), end)),
+            ));
         }
+        let args = self
+            .function_args
+            .iter()
+            .map(|(name, ty)| (name.clone(), ty.unqualified().clone()))
+            .collect();
+        Ok(TypedTopLevel::new(
+            TypedTopLevelKind::FunctionDefinition(
+                self.function_name.clone(),
+                args,
+                self.function_return_type.unqualified().clone(),
+                typed_statements,
+                linkage,
+            ),
+            self.function_span,
+        ))
     }
 
-    pub fn type_function(&self) -> TypedTopLevel {
+    /// 文ごとにエラーを集めるので、1 つの文が型エラーでも後続の文の型検査は続ける。
+    fn type_statements(
+        &mut self,
+        statements: &[Statement],
+    ) -> Result<Vec<TypedStatement>, Vec<CompileError>> {
         let mut typed_statements = Vec::new();
-        for statement in &self.function_body {
-            typed_statements.push(self.type_statement(statement));
+        let mut errors = Vec::new();
+        for statement in statements {
+            match self.type_statement(statement) {
+                Ok(typed_statement) => typed_statements.push(typed_statement),
+                Err(mut statement_errors) => errors.append(&mut statement_errors),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(typed_statements)
+        } else {
+            Err(errors)
         }
-        TypedTopLevel::FunctionDefinition(
-            self.function_name.clone(),
-            self.function_args.clone(),
-            self.function_return_type.clone(),
-            typed_statements,
-            self.variable_type_environment.clone(),
-        )
     }
 
-    fn type_statement(&self, statement: &Statement) -> TypedStatement {
-        match statement {
-            Statement::Return(expr) => self.type_return_statement(expr),
-            Statement::If(expr, statement) => self.type_if_statement(expr, statement),
-            Statement::IfElse(expr, then_statement, else_statement) => {
-                self.type_if_else_statement(expr, then_statement, else_statement)
+    fn type_statement(
+        &mut self,
+        statement: &Statement,
+    ) -> Result<TypedStatement, Vec<CompileError>> {
+        let kind = match &statement.kind {
+            StatementKind::Return(expr) => {
+                self.type_return_statement(expr.as_ref(), statement.span)?
             }
-            Statement::For(init, update, cond, body) => {
-                self.type_for_statement(init, update, cond, body)
+            StatementKind::If(expr, statement) => self.type_if_statement(expr, statement)?,
+            StatementKind::IfElse(expr, then_statement, else_statement) => {
+                self.type_if_else_statement(expr, then_statement, else_statement)?
             }
-            Statement::While(expr, statements) => self.type_while_statement(expr, statements),
-            Statement::Expr(expr) => self.type_expr_statement(expr),
-            Statement::VariableDeclaration(name, ty) => {
-                Self::type_variable_declaration_statement(name, ty)
+            StatementKind::For(init, cond, update, body) => {
+                self.type_for_statement(init, cond.as_deref(), update.as_deref(), body)?
             }
-            Statement::Block(statements) => self.type_block_statement(statements),
-        }
+            StatementKind::DoWhile(body, expr) => TypedStatementKind::DoWhile(
+                Box::new(self.type_statement(body)?),
+                Box::new(self.type_condition(expr)?),
+            ),
+            StatementKind::Switch(expr, body) => self.type_switch_statement(expr, body)?,
+            StatementKind::Case(value, body) => {
+                // switch の外の `case` は jump_checker が報告する。
+                let value = self
+                    .switch_types
+                    .last()
+                    .map_or(*value, |ty| convert_integer(*value, ty));
+                TypedStatementKind::Case(value, Box::new(self.type_statement(body)?))
+            }
+            StatementKind::Default(body) => {
+                TypedStatementKind::Default(Box::new(self.type_statement(body)?))
+            }
+            StatementKind::Labeled(label, body) => {
+                TypedStatementKind::Labeled(label.clone(), Box::new(self.type_statement(body)?))
+            }
+            StatementKind::Goto(label) => TypedStatementKind::Goto(label.clone()),
+            StatementKind::Break => TypedStatementKind::Break,
+            StatementKind::Continue => TypedStatementKind::Continue,
+            StatementKind::While(expr, statements) => {
+                self.type_while_statement(expr, statements)?
+            }
+            StatementKind::Expr(expr) => self.type_expr_statement(expr)?,
+            StatementKind::VariableDeclaration(declarations) => {
+                self.type_variable_declaration_statement(declarations, statement.span)?
+            }
+            StatementKind::Block(statements) => self.type_block_statement(statements)?,
+            StatementKind::Empty => TypedStatementKind::Empty,
+        };
+        Ok(TypedStatement::new(kind, statement.span))
     }
 
-    fn type_return_statement(&self, expr: &Expr) -> TypedStatement {
-        let typed_expr = self.type_expr(expr);
-        assert_eq!(self.function_return_type, typed_expr.get_type());
-        TypedStatement::Return(typed_expr)
+    fn type_return_statement(
+        &self,
+        expr: Option<&Expr>,
+        span: Span,
+    ) -> CompileResult<TypedStatementKind> {
+        let is_void = *self.function_return_type.unqualified() == Type::Void;
+        let expr = match expr {
+            None if is_void => return Ok(TypedStatementKind::Return(None)),
+            None => {
+                return Err(Self::error(
+                    format!(
+                        "non-void function {} should return a value",
+                        self.function_name
+                    ),
+                    span,
+                ));
+            }
+            Some(expr) if is_void => {
+                return Err(Self::error(
+                    format!(
+                        "void function {} should not return a value",
+                        self.function_name
+                    ),
+                    expr.span,
+                ));
+            }
+            Some(expr) => expr,
+        };
+        let typed_expr = self.type_expr(expr)?.decay_if_array();
+        if !is_assignable(&typed_expr, &self.function_return_type) {
+            return Err(Self::error(
+                format!(
+                    "function {} returns {}, but the returned value has type {}",
+                    self.function_name,
+                    self.function_return_type,
+                    typed_expr.get_type()
+                ),
+                expr.span,
+            ));
+        }
+        Ok(TypedStatementKind::Return(Some(
+            typed_expr.convert_to(&self.function_return_type),
+        )))
     }
 
-    fn type_if_statement(&self, expr: &Expr, statement: &Statement) -> TypedStatement {
-        let typed_expr = self.type_expr(expr);
-        TypedStatement::If(
+    fn type_if_statement(
+        &mut self,
+        expr: &Expr,
+        statement: &Statement,
+    ) -> Result<TypedStatementKind, Vec<CompileError>> {
+        let typed_expr = self.type_condition(expr)?;
+        Ok(TypedStatementKind::If(
             Box::new(typed_expr),
-            Box::new(self.type_statement(statement)),
-        )
+            Box::new(self.type_statement(statement)?),
+        ))
     }
 
     fn type_if_else_statement(
-        &self,
+        &mut self,
         expr: &Expr,
         then_statement: &Statement,
         else_statement: &Statement,
-    ) -> TypedStatement {
-        let typed_expr = self.type_expr(expr);
-        TypedStatement::IfElse(
+    ) -> Result<TypedStatementKind, Vec<CompileError>> {
+        let typed_expr = self.type_condition(expr)?;
+        Ok(TypedStatementKind::IfElse(
             Box::new(typed_expr),
-            Box::new(self.type_statement(then_statement)),
-            Box::new(self.type_statement(else_statement)),
-        )
+            Box::new(self.type_statement(then_statement)?),
+            Box::new(self.type_statement(else_statement)?),
+        ))
     }
 
-    fn type_while_statement(&self, expr: &Expr, statement: &Statement) -> TypedStatement {
-        let typed_expr = self.type_expr(expr);
-        let typed_statement = self.type_statement(statement);
-        TypedStatement::While(Box::new(typed_expr), Box::new(typed_statement))
+    fn type_while_statement(
+        &mut self,
+        expr: &Expr,
+        statement: &Statement,
+    ) -> Result<TypedStatementKind, Vec<CompileError>> {
+        let typed_expr = self.type_condition(expr)?;
+        let typed_statement = self.type_statement(statement)?;
+        Ok(TypedStatementKind::While(
+            Box::new(typed_expr),
+            Box::new(typed_statement),
+        ))
     }
 
+    /// 初期化節で宣言した変数のために、`for` 文全体を 1 つのスコープにする。
     fn type_for_statement(
-        &self,
-        init: &Expr,
-        update: &Expr,
-        cond: &Expr,
+        &mut self,
+        init: &Statement,
+        cond: Option<&Expr>,
+        update: Option<&Expr>,
         body: &Statement,
-    ) -> TypedStatement {
-        let typed_init = self.type_expr(init);
-        let typed_update = self.type_expr(update);
-        let typed_cond = self.type_expr(cond);
-        let typed_body = self.type_statement(body);
-        TypedStatement::For(
+    ) -> Result<TypedStatementKind, Vec<CompileError>> {
+        self.local_scopes.push_scope();
+        let result = self.type_for_clauses(init, cond, update, body);
+        self.local_scopes.pop_scope();
+        result
+    }
+
+    fn type_for_clauses(
+        &mut self,
+        init: &Statement,
+        cond: Option<&Expr>,
+        update: Option<&Expr>,
+        body: &Statement,
+    ) -> Result<TypedStatementKind, Vec<CompileError>> {
+        let typed_init = self.type_statement(init)?;
+        let typed_cond = cond.map(|cond| self.type_condition(cond)).transpose()?;
+        let typed_update = update.map(|update| self.type_expr(update)).transpose()?;
+        let typed_body = self.type_statement(body)?;
+        Ok(TypedStatementKind::For(
             Box::new(typed_init),
-            Box::new(typed_update),
-            Box::new(typed_cond),
+            typed_cond.map(Box::new),
+            typed_update.map(Box::new),
             Box::new(typed_body),
-        )
+        ))
     }
 
-    fn type_expr_statement(&self, expr: &Expr) -> TypedStatement {
-        let typed_expr = self.type_expr(expr);
-        TypedStatement::Expr(typed_expr)
+    /// 条件式は整数拡張した型で `case` の値と比べる。
+    fn type_switch_statement(
+        &mut self,
+        expr: &Expr,
+        body: &Statement,
+    ) -> Result<TypedStatementKind, Vec<CompileError>> {
+        let typed_expr = self.type_expr(expr)?;
+        let ty = typed_expr.get_type();
+        if !ty.is_integer() {
+            return Err(vec![Self::error(
+                format!("switch quantity is not an integer: {ty}"),
+                expr.span,
+            )]);
+        }
+        self.switch_types.push(ty.promote());
+        let typed_body = self.type_statement(body);
+        self.switch_types.pop();
+        Ok(TypedStatementKind::Switch(
+            Box::new(typed_expr.convert_to(&ty.promote())),
+            Box::new(typed_body?),
+        ))
     }
 
-    fn type_variable_declaration_statement(name: &str, ty: &Type) -> TypedStatement {
-        TypedStatement::VariableDeclaration(name.to_string(), ty.clone())
+    fn type_expr_statement(&self, expr: &Expr) -> CompileResult<TypedStatementKind> {
+        let typed_expr = self.type_expr(expr)?;
+        Ok(TypedStatementKind::Expr(typed_expr))
     }
 
-    fn type_block_statement(&self, statements: &Vec<Statement>) -> TypedStatement {
-        let mut typed_statements = Vec::new();
-        for statement in statements {
-            typed_statements.push(self.type_statement(statement));
-        }
-        TypedStatement::Block(typed_statements)
-    }
-
-    pub fn type_expr(&self, expr: &Expr) -> TypedExpr {
-        match expr {
-            Expr::Add(lhs, rhs)
-            | Expr::Sub(lhs, rhs)
-            | Expr::Mul(lhs, rhs)
-            | Expr::Div(lhs, rhs) => self.type_arithmetic_operator(lhs, rhs, expr),
-            Expr::Num(n) => TypedExpr::IntNum(*n),
-            Expr::LessThan(lhs, rhs)
-            | Expr::LessEqual(lhs, rhs)
-            | Expr::Equal(lhs, rhs)
-            | Expr::NotEqual(lhs, rhs)
-            | Expr::GreaterThan(lhs, rhs)
-            | Expr::GreaterEqual(lhs, rhs) => self.type_comparator(lhs, rhs, expr),
-            Expr::Assign(lhs, rhs) => self.type_assign(lhs, rhs),
-            Expr::Variable(name) => self.type_variable(name),
-            Expr::FunctionCall(name, args) => self.type_function_call(name, args),
-            Expr::Address(expr) => self.type_address(expr),
-            Expr::Dereference(expr) => self.type_dereference(expr),
-            Expr::Sizeof(expr) => TypedExpr::Sizeof(Box::new(self.type_expr(expr))),
-        }
-    }
-
-    fn type_dereference(&self, expr: &Expr) -> TypedExpr {
-        let typed_expr = self.type_expr(expr).decay_if_array();
+    /// 変数は初期化子より先に宣言するので、初期化子の中から自身を参照できる。
+    /// static 変数と extern 宣言はスタックに置かないので、型付きの宣言には残さない。
+    fn type_variable_declaration_statement(
+        &mut self,
+        declarations: &[Declaration],
+        span: Span,
+    ) -> CompileResult<TypedStatementKind> {
+        let mut typed_declarations = vec![];
+        for declaration in declarations {
+            if let Type::Function(_, _, _) = declaration.ty {
+                return Err(Self::error(
+                    format!(
+                        "function {} cannot be declared inside a function",
+                        declaration.name
+                    ),
+                    span,
+                ));
+            }
+            let ty = match &declaration.initializer {
+                Some(initializer) => complete_array_type(&declaration.ty, initializer, &|expr| {
+                    self.is_struct_value(expr)
+                })?,
+                None => declaration.ty.clone(),
+            };
+            if declaration.storage != Storage::Extern {
+                check_long_double(&ty, span)?;
+            }
+            let name =
+                self.local_scopes
+                    .declare(&declaration.name, &ty, declaration.storage, span)?;
+            match declaration.storage {
+                Storage::Automatic => {}
+                Storage::Static => {
+                    let symbols = |name: &str| self.static_symbol(name);
+                    let values = declaration
+                        .initializer
+                        .as_ref()
+                        .map(|initializer| evaluate_static_initializer(&ty, initializer, &symbols))
+                        .transpose()?;
+                    self.static_variables.push(StaticVariable {
+                        label: self.static_label(&name),
+                        ty,
+                        values,
+                        span,
+                    });
+                    continue;
+                }
+                Storage::Extern => continue,
+            }
+            let initializer = declaration
+                .initializer
+                .as_ref()
+                .map(|initializer| self.type_local_initializer(&name, &ty, initializer, span))
+                .transpose()?;
+            typed_declarations.push(TypedDeclaration {
+                name,
+                ty: ty.unqualified().clone(),
+                initializer,
+            });
+        }
+        Ok(TypedStatementKind::VariableDeclaration(typed_declarations))
+    }
+
+    /// 初期化子の値ごとに、変数の部分オブジェクトへの代入式を作る。
+    fn type_local_initializer(
+        &self,
+        name: &str,
+        ty: &Type,
+        initializer: &Initializer,
+        span: Span,
+    ) -> CompileResult<Vec<TypedExpr>> {
+        let variable = TypedExpr::new(TypedExprKind::Variable(name.to_string()), ty.clone(), span);
+        flatten_initializer(ty, initializer, &|expr| self.is_struct_value(expr))?
+            .into_iter()
+            .map(|value| {
+                let rhs = self.type_expr(&value.expr)?.decay_if_array();
+                if !is_assignable(&rhs, &value.ty) {
+                    return Err(Self::error(
+                        format!("cannot initialize {} with {}", value.ty, rhs.get_type()),
+                        value.expr.span,
+                    ));
+                }
+                let lhs = if value.offset == 0 && value.ty == *ty.unqualified() {
+                    variable.clone()
+                } else {
+                    TypedExpr::new(
+                        TypedExprKind::Member(Box::new(variable.clone()), value.offset),
+                        value.ty,
+                        span,
+                    )
+                };
+                Self::type_typed_assign(lhs, &rhs, value.expr.span)
+            })
+            .collect()
+    }
+
+    /// 構造体の要素の初期化子が、波括弧を省いた値ではなく構造体の値なら真。
+    fn is_struct_value(&self, expr: &Expr) -> bool {
+        self.type_expr(expr)
+            .is_ok_and(|expr| matches!(expr.get_type(), Type::Struct(_)))
+    }
+
+    fn type_block_statement(
+        &mut self,
+        statements: &[Statement],
+    ) -> Result<TypedStatementKind, Vec<CompileError>> {
+        self.local_scopes.push_scope();
+        let typed_statements = self.type_statements(statements);
+        self.local_scopes.pop_scope();
+        Ok(TypedStatementKind::Block(typed_statements?))
+    }
+
+    pub fn type_expr(&self, expr: &Expr) -> CompileResult<TypedExpr> {
+        let typed_expr = self.type_expr_kind(expr)?;
+        check_long_double(&typed_expr.get_type(), expr.span)?;
+        Ok(typed_expr)
+    }
+
+    fn type_expr_kind(&self, expr: &Expr) -> CompileResult<TypedExpr> {
+        match &expr.kind {
+            ExprKind::Add(lhs, rhs)
+            | ExprKind::Sub(lhs, rhs)
+            | ExprKind::Mul(lhs, rhs)
+            | ExprKind::Div(lhs, rhs)
+            | ExprKind::Mod(lhs, rhs)
+            | ExprKind::BitAnd(lhs, rhs)
+            | ExprKind::BitOr(lhs, rhs)
+            | ExprKind::BitXor(lhs, rhs)
+            | ExprKind::LeftShift(lhs, rhs)
+            | ExprKind::RightShift(lhs, rhs) => self.type_binary_operator(lhs, rhs, expr),
+            ExprKind::LogicalAnd(_, _) | ExprKind::LogicalOr(_, _) | ExprKind::LogicalNot(_) => {
+                self.type_logical_operator(expr)
+            }
+            ExprKind::BitNot(operand) => self.type_bit_not(operand, expr.span),
+            ExprKind::Num(n) => Ok(TypedExpr::new(
+                TypedExprKind::IntNum(i64::from(*n)),
+                Type::IntTyp,
+                expr.span,
+            )),
+            #[allow(clippy::cast_possible_wrap)]
+            ExprKind::IntNum(literal) => Ok(TypedExpr::new(
+                TypedExprKind::IntNum(literal.value as i64),
+                literal.ty(),
+                expr.span,
+            )),
+            ExprKind::FloatNum(literal) => Ok(TypedExpr::new(
+                TypedExprKind::FloatNum(*literal),
+                if literal.is_float {
+                    Type::FloatTyp
+                } else {
+                    Type::DoubleTyp
+                },
+                expr.span,
+            )),
+            ExprKind::StringLiteral(literal) => Ok(TypedExpr::new(
+                TypedExprKind::StringLiteral(literal.clone()),
+                literal.ty(),
+                expr.span,
+            )),
+            ExprKind::LessThan(lhs, rhs)
+            | ExprKind::LessEqual(lhs, rhs)
+            | ExprKind::Equal(lhs, rhs)
+            | ExprKind::NotEqual(lhs, rhs)
+            | ExprKind::GreaterThan(lhs, rhs)
+            | ExprKind::GreaterEqual(lhs, rhs) => self.type_comparator(lhs, rhs, expr),
+            ExprKind::Assign(lhs, rhs) => self.type_assign(lhs, rhs, expr.span),
+            ExprKind::CompoundAssign(_) | ExprKind::PostfixAssign(_) => {
+                self.type_compound_assign(expr)
+            }
+            ExprKind::Conditional(cond, then, els) => {
+                self.type_conditional(cond, then, els, expr.span)
+            }
+            ExprKind::Comma(lhs, rhs) => {
+                let typed_lhs = self.type_expr(lhs)?;
+                let typed_rhs = self.type_expr(rhs)?.decay_if_array();
+                let ty = typed_rhs.get_type();
+                Ok(TypedExpr::new(
+                    TypedExprKind::Comma(Box::new(typed_lhs), Box::new(typed_rhs)),
+                    ty,
+                    expr.span,
+                ))
+            }
+            ExprKind::Variable(name) => self.type_variable(name, expr.span),
+            ExprKind::FunctionCall(callee, args) => {
+                self.type_function_call(callee, args, expr.span)
+            }
+            ExprKind::Address(operand) => self.type_address(operand, expr.span),
+            ExprKind::Dereference(operand) => self.type_dereference(operand, expr.span),
+            // オペランドは型を調べるだけで、実行時には評価しない。
+            ExprKind::Sizeof(operand) => {
+                let ty = self.type_expr(operand)?.get_type();
+                Self::type_size_constant(ty.get_size(), &ty, operand.span, expr.span)
+            }
+            ExprKind::SizeofType(ty) => {
+                Self::type_size_constant(ty.get_size(), ty, expr.span, expr.span)
+            }
+            ExprKind::Alignof(ty) => {
+                Self::type_size_constant(ty.get_align(), ty, expr.span, expr.span)
+            }
+            ExprKind::Cast(ty, operand) => self.type_cast(ty, operand, expr.span),
+            ExprKind::Member(operand, name) => self.type_member(operand, name, expr.span),
+            ExprKind::VaStart(ap) => self.type_va_start(ap, expr.span),
+            ExprKind::VaArg(ap, ty) => self.type_va_arg(ap, ty, expr.span),
+        }
+    }
+
+    /// `va_start` は `...` を持つ関数の中でだけ使える。
+    fn type_va_start(&self, ap: &Expr, span: Span) -> CompileResult<TypedExpr> {
+        let is_variadic = self
+            .function_type_environment
+            .get(&self.function_name)
+            .is_some_and(|(_, _, is_variadic)| *is_variadic);
+        if !is_variadic {
+            return Err(Self::error(
+                format!(
+                    "va_start used in function {} with fixed arguments",
+                    self.function_name
+                ),
+                span,
+            ));
+        }
+        Ok(TypedExpr::new(
+            TypedExprKind::VaStart(Box::new(self.type_va_list(ap)?)),
+            Type::Void,
+            span,
+        ))
+    }
+
+    /// 可変長引数は既定の実引数拡張を受けているので、`float` は取り出せない。
+    fn type_va_arg(&self, ap: &Expr, ty: &Type, span: Span) -> CompileResult<TypedExpr> {
+        let typed_ap = self.type_va_list(ap)?;
+        Self::check_complete(ty, span)?;
+        match ty.unqualified() {
+            Type::Array(_, _) | Type::Function(_, _, _) => {
+                return Err(Self::error(format!("va_arg cannot take {ty}"), span));
+            }
+            Type::FloatTyp => {
+                return Err(Self::error(
+                    "float is promoted to double when passed through '...'",
+                    span,
+                ));
+            }
+            _ => {}
+        }
+        Ok(TypedExpr::new(
+            TypedExprKind::VaArg(Box::new(typed_ap)),
+            ty.unqualified().clone(),
+            span,
+        ))
+    }
+
+    fn type_va_list(&self, ap: &Expr) -> CompileResult<TypedExpr> {
+        let typed_ap = self.type_expr(ap)?.decay_if_array();
+        if typed_ap.get_type().is_va_list_pointer() {
+            Ok(typed_ap)
+        } else {
+            Err(Self::error(
+                format!("expected va_list, but got {}", typed_ap.get_type()),
+                ap.span,
+            ))
+        }
+    }
+
+    fn type_member(&self, operand: &Expr, name: &str, span: Span) -> CompileResult<TypedExpr> {
+        let typed_operand = self.type_expr(operand)?;
+        let Type::Struct(struct_type) = typed_operand.get_type() else {
+            return Err(Self::error(
+                format!(
+                    "request for member {name} in non-struct type {}",
+                    typed_operand.get_type()
+                ),
+                operand.span,
+            ));
+        };
+        Self::check_complete(&typed_operand.get_type(), operand.span)?;
+        let member = struct_type.member(name).ok_or_else(|| {
+            Self::error(format!("{struct_type} has no member named {name}"), span)
+        })?;
+        // const な構造体のメンバは const になる。
+        let ty = member.ty.qualified(typed_operand.ty.qualifiers());
+        Ok(TypedExpr::new(
+            TypedExprKind::Member(Box::new(typed_operand), member.offset),
+            ty,
+            span,
+        ))
+    }
+
+    /// `sizeof` と `_Alignof` の結果は `size_t` (`unsigned long`) の定数になる。
+    fn type_size_constant(
+        value: usize,
+        ty: &Type,
+        type_span: Span,
+        span: Span,
+    ) -> CompileResult<TypedExpr> {
+        Self::check_complete(ty, type_span)?;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+        Ok(TypedExpr::new(
+            TypedExprKind::IntNum(value as i64),
+            Type::ULongTyp,
+            span,
+        ))
+    }
+
+    /// スカラー型同士のキャストだけを許す。浮動小数点数とポインタの間は変換できない。
+    fn type_cast(&self, ty: &Type, operand: &Expr, span: Span) -> CompileResult<TypedExpr> {
+        let typed_operand = self.type_expr(operand)?.decay_if_array();
+        let from = typed_operand.get_type();
+        let is_pointer = |ty: &Type| matches!(ty, Type::Pointer(_));
+        // void へのキャストは値を捨てる。
+        let castable = match (from.is_arithmetic(), ty.is_arithmetic()) {
+            _ if *ty.unqualified() == Type::Void => true,
+            (true, true) => true,
+            (true, false) => is_pointer(ty) && from.is_integer(),
+            (false, true) => is_pointer(&from) && ty.is_integer(),
+            (false, false) => is_pointer(&from) && is_pointer(ty),
+        };
+        if !castable {
+            return Err(Self::error(format!("cannot cast {from} to {ty}"), span));
+        }
+        // 同じ型へのキャストでも結果は左辺値にならないよう、必ず変換を挟む。
+        Ok(TypedExpr::new(
+            TypedExprKind::Cast(Box::new(typed_operand)),
+            ty.clone(),
+            span,
+        ))
+    }
+
+    fn check_complete(ty: &Type, span: Span) -> CompileResult<()> {
+        if ty.is_complete() {
+            Ok(())
+        } else {
+            Err(Self::error(format!("incomplete type {ty}"), span))
+        }
+    }
+
+    fn type_dereference(&self, expr: &Expr, span: Span) -> CompileResult<TypedExpr> {
+        let typed_expr = self.type_expr(expr)?.decay_if_array();
         if let Type::Pointer(ty) = typed_expr.get_type() {
-            TypedExpr::Dereference(*ty, Box::new(typed_expr))
+            if *ty.unqualified() == Type::Void {
+                return Err(Self::error("cannot dereference a void pointer", expr.span));
+            }
+            Ok(TypedExpr::new(
+                TypedExprKind::Dereference(Box::new(typed_expr)),
+                *ty,
+                span,
+            ))
         } else {
-            panic!("cannot dereference non-pointer type: {typed_expr:?}")
+            Err(Self::error(
+                format!(
+                    "cannot dereference non-pointer type: {}",
+                    typed_expr.get_type()
+                ),
+                expr.span,
+            ))
         }
     }
 
-    fn type_address(&self, expr: &Expr) -> TypedExpr {
-        let typed_expr = self.type_expr(expr);
-        TypedExpr::Address(
-            Type::Pointer(Box::new(typed_expr.get_type())),
-            Box::new(typed_expr),
-        )
+    fn type_address(&self, expr: &Expr, span: Span) -> CompileResult<TypedExpr> {
+        let typed_expr = self.type_lvalue(expr)?;
+        // 修飾子は指す先の型に残す。
+        let ty = Type::Pointer(Box::new(typed_expr.ty.clone()));
+        Ok(TypedExpr::new(
+            TypedExprKind::Address(Box::new(typed_expr)),
+            ty,
+            span,
+        ))
     }
 
-    fn type_variable(&self, name: &String) -> TypedExpr {
-        let ty = self
-            .variable_type_environment
+    /// ローカル変数がグローバル変数より優先される。ローカル変数は宣言より後でしか参照できない。
+    fn type_variable(&self, name: &String, span: Span) -> CompileResult<TypedExpr> {
+        if let Some((unique_name, ty, storage)) = self.local_scopes.lookup(name) {
+            let kind = match storage {
+                Storage::Automatic => TypedExprKind::Variable(unique_name.to_string()),
+                Storage::Static => TypedExprKind::GlobalVariable(self.static_label(unique_name)),
+                Storage::Extern => TypedExprKind::GlobalVariable(name.clone()),
+            };
+            return Ok(TypedExpr::new(kind, ty.clone(), span));
+        }
+        // `__func__` は関数名の `const char` の配列。左辺値にはならないが、文字列リテラルとして扱う。
+        if name == "__func__" {
+            let literal = StringLiteral::narrow(self.function_name.as_bytes().to_vec());
+            let const_char = Type::CharTyp.qualified(Qualifiers {
+                is_const: true,
+                is_volatile: false,
+            });
+            let ty = Type::Array(Box::new(const_char), literal.element_count() + 1);
+            return Ok(TypedExpr::new(
+                TypedExprKind::StringLiteral(literal),
+                ty,
+                span,
+            ));
+        }
+        if let Some(ty) = self.global_type_environment.get(name) {
+            return Ok(TypedExpr::new(
+                TypedExprKind::GlobalVariable(name.clone()),
+                ty.clone(),
+                span,
+            ));
+        }
+        let (arg_types, return_type, is_variadic) = self
+            .function_type_environment
             .get(name)
-            .unwrap_or_else(|| panic!("undefined variable: {name}"));
-        TypedExpr::Variable(ty.clone(), name.clone())
+            .ok_or_else(|| Self::error(format!("undefined variable: {name}"), span))?;
+        Ok(TypedExpr::new(
+            TypedExprKind::Function(name.clone()),
+            Type::Function(arg_types.clone(), return_type.clone(), *is_variadic),
+            span,
+        ))
+    }
+
+    fn static_label(&self, unique_name: &str) -> String {
+        format!("{}.{unique_name}", self.function_name)
+    }
+
+    /// 静的な初期化子の中の名前を、この位置で見える静的な変数のラベルと型にする。
+    fn static_symbol(&self, name: &str) -> Option<(String, Type)> {
+        match self.local_scopes.lookup(name) {
+            Some((unique_name, ty, Storage::Static)) => {
+                Some((self.static_label(unique_name), ty.clone()))
+            }
+            Some((_, ty, Storage::Extern)) => Some((name.to_string(), ty.clone())),
+            Some((_, _, Storage::Automatic)) => None,
+            None => global_symbol(
+                &self.global_type_environment,
+                &self.function_type_environment,
+                name,
+            ),
+        }
+    }
+
+    fn type_lvalue(&self, expr: &Expr) -> CompileResult<TypedExpr> {
+        let typed_expr = self.type_expr(expr)?;
+        match typed_expr.kind {
+            TypedExprKind::Variable(_)
+            | TypedExprKind::GlobalVariable(_)
+            | TypedExprKind::Function(_)
+            | TypedExprKind::Dereference(_)
+            | TypedExprKind::Member(_, _) => Ok(typed_expr),
+            _ => Err(Self::error("expression is not an lvalue", expr.span)),
+        }
+    }
+
+    /// 代入の左辺。初期化子は `type_typed_assign` を直接使うので、const 変数も初期化できる。
+    fn type_modifiable_lvalue(&self, expr: &Expr) -> CompileResult<TypedExpr> {
+        let typed_expr = self.type_lvalue(expr)?;
+        if typed_expr.ty.qualifiers().is_const {
+            return Err(Self::error(
+                "cannot assign to a const-qualified lvalue",
+                expr.span,
+            ));
+        }
+        Ok(typed_expr)
     }
 
-    fn type_assign(&self, lhs: &Expr, rhs: &Expr) -> TypedExpr {
-        let lhs = self.type_expr(lhs);
-        let rhs = self.type_expr(rhs);
+    fn type_assign(&self, lhs: &Expr, rhs: &Expr, span: Span) -> CompileResult<TypedExpr> {
+        let typed_lhs = self.type_modifiable_lvalue(lhs)?;
+        let typed_rhs = self.type_expr(rhs)?;
+        Self::type_typed_assign(typed_lhs, &typed_rhs, span)
+    }
+
+    fn type_typed_assign(
+        typed_lhs: TypedExpr,
+        typed_rhs: &TypedExpr,
+        span: Span,
+    ) -> CompileResult<TypedExpr> {
         //        assert_eq!(lhs.get_type(), rhs.get_type(), "lhs: {lhs:?}, rhs: {rhs:?}",); // 左にポインタ、右に配列の時困るのでコメントアウト
-        assert!(!matches!(lhs.get_type(), Type::Array(_, _)));
-        TypedExpr::Assign(
-            lhs.get_type(),
-            Box::new(lhs),
-            Box::new(rhs.decay_if_array()),
-        )
+        if typed_rhs.get_type() == Type::Void {
+            return Err(Self::error(
+                "void value not ignored as it ought to be",
+                typed_rhs.span,
+            ));
+        }
+        match typed_lhs.get_type() {
+            Type::Array(_, _) => {
+                return Err(Self::error("array is not assignable", typed_lhs.span));
+            }
+            Type::Function(_, _, _) => {
+                return Err(Self::error("function is not assignable", typed_lhs.span));
+            }
+            _ => {}
+        }
+        // 構造体は同じ型同士でだけ代入できる。
+        if matches!(typed_lhs.get_type(), Type::Struct(_))
+            || matches!(typed_rhs.get_type(), Type::Struct(_))
+        {
+            if typed_lhs.get_type() != typed_rhs.get_type() {
+                return Err(Self::error(
+                    format!(
+                        "cannot assign {} to {}",
+                        typed_rhs.get_type(),
+                        typed_lhs.get_type()
+                    ),
+                    span,
+                ));
+            }
+            Self::check_complete(&typed_lhs.get_type(), typed_lhs.span)?;
+        }
+        let ty = typed_lhs.get_type();
+        let typed_rhs = typed_rhs.decay_if_array();
+        if !is_assignable(&typed_rhs, &ty) {
+            return Err(Self::error(
+                format!("cannot assign {} to {ty}", typed_rhs.get_type()),
+                span,
+            ));
+        }
+        let typed_rhs = typed_rhs.convert_to(&ty);
+        Ok(TypedExpr::new(
+            TypedExprKind::Assign(Box::new(typed_lhs), Box::new(typed_rhs)),
+            ty,
+            span,
+        ))
     }
 
-    fn type_function_call(&self, name: &String, args: &Vec<Expr>) -> TypedExpr {
-        let (arg_types, return_type) = self
-            .function_type_environment
-            .get(name)
-            .unwrap_or_else(|| panic!("undefined function: {name}"));
-        assert_eq!(
-            arg_types.len(),
-            args.len(),
-            "arg_types: {arg_types:?}, args: {args:?}",
+    fn type_logical_operator(&self, expr: &Expr) -> CompileResult<TypedExpr> {
+        let kind = match &expr.kind {
+            ExprKind::LogicalAnd(lhs, rhs) => TypedExprKind::LogicalAnd(
+                Box::new(self.type_condition(lhs)?),
+                Box::new(self.type_condition(rhs)?),
+            ),
+            ExprKind::LogicalOr(lhs, rhs) => TypedExprKind::LogicalOr(
+                Box::new(self.type_condition(lhs)?),
+                Box::new(self.type_condition(rhs)?),
+            ),
+            ExprKind::LogicalNot(operand) => {
+                TypedExprKind::LogicalNot(Box::new(self.type_condition(operand)?))
+            }
+            _ => unreachable!(),
+        };
+        Ok(TypedExpr::new(kind, Type::IntTyp, expr.span))
+    }
+
+    fn type_bit_not(&self, operand: &Expr, span: Span) -> CompileResult<TypedExpr> {
+        let typed_operand = self.type_expr(operand)?;
+        let ty = typed_operand.get_type();
+        if !ty.is_integer() {
+            return Err(Self::error(
+                format!("invalid operand to unary ~: {ty}"),
+                operand.span,
+            ));
+        }
+        Ok(TypedExpr::new(
+            TypedExprKind::BitNot(Box::new(typed_operand.convert_to(&ty.promote()))),
+            ty.promote(),
+            span,
+        ))
+    }
+
+    /// 複合代入の演算を、左辺の値を `CompoundAssignLhs` として型付けし、左辺の型に変換して代入する。
+    fn type_compound_assign(&self, expr: &Expr) -> CompileResult<TypedExpr> {
+        let (ExprKind::CompoundAssign(operation) | ExprKind::PostfixAssign(operation)) = &expr.kind
+        else {
+            unreachable!();
+        };
+        let (ExprKind::Add(lhs, rhs)
+        | ExprKind::Sub(lhs, rhs)
+        | ExprKind::Mul(lhs, rhs)
+        | ExprKind::Div(lhs, rhs)
+        | ExprKind::Mod(lhs, rhs)
+        | ExprKind::BitAnd(lhs, rhs)
+        | ExprKind::BitOr(lhs, rhs)
+        | ExprKind::BitXor(lhs, rhs)
+        | ExprKind::LeftShift(lhs, rhs)
+        | ExprKind::RightShift(lhs, rhs)) = &operation.kind
+        else {
+            unreachable!("compound assignment without a binary operation: {operation:?}");
+        };
+        let typed_lhs = self.type_modifiable_lvalue(lhs)?;
+        let current_value = TypedExpr::new(
+            TypedExprKind::CompoundAssignLhs,
+            typed_lhs.get_type(),
+            lhs.span,
         );
-        let typed_args: Vec<TypedExpr> = args
+        let typed_rhs = self.type_expr(rhs)?.decay_if_array();
+        let value = Self::type_binary_operation(current_value, typed_rhs, operation)?;
+        if !is_convertible(&value.get_type(), &typed_lhs.get_type()) {
+            return Err(Self::error(
+                format!(
+                    "cannot assign {} to {}",
+                    value.get_type(),
+                    typed_lhs.get_type()
+                ),
+                operation.span,
+            ));
+        }
+        let value = Box::new(value.convert_to(&typed_lhs.get_type()));
+        let ty = typed_lhs.get_type();
+        let kind = if let ExprKind::PostfixAssign(_) = expr.kind {
+            TypedExprKind::PostfixAssign(Box::new(typed_lhs), value)
+        } else {
+            TypedExprKind::CompoundAssign(Box::new(typed_lhs), value)
+        };
+        Ok(TypedExpr::new(kind, ty, expr.span))
+    }
+
+    /// 算術型同士なら共通の型に、それ以外は同じ型か、ポインタと null ポインタ定数でなければならない。
+    fn type_conditional(
+        &self,
+        cond: &Expr,
+        then: &Expr,
+        els: &Expr,
+        span: Span,
+    ) -> CompileResult<TypedExpr> {
+        let typed_cond = self.type_condition(cond)?;
+        let typed_then = self.type_expr(then)?.decay_if_array();
+        let typed_else = self.type_expr(els)?.decay_if_array();
+        let ty = match (typed_then.get_type(), typed_else.get_type()) {
+            (then_type, else_type) if then_type.is_arithmetic() && else_type.is_arithmetic() => {
+                Type::common_arithmetic_type(&then_type, &else_type)
+            }
+            (then_type, else_type) if then_type.eq_ignoring_qualifiers(&else_type) => then_type,
+            // 指す先の修飾子が異なるなら、両方の修飾子を持つ型へのポインタになる。
+            (Type::Pointer(then_pointee), Type::Pointer(else_pointee))
+                if then_pointee.eq_ignoring_qualifiers(&else_pointee) =>
+            {
+                let qualifiers = then_pointee.qualifiers().union(else_pointee.qualifiers());
+                Type::Pointer(Box::new(
+                    then_pointee.unqualified().clone().qualified(qualifiers),
+                ))
+            }
+            (pointer @ Type::Pointer(_), _) if is_null_pointer_constant(&typed_else) => pointer,
+            (_, pointer @ Type::Pointer(_)) if is_null_pointer_constant(&typed_then) => pointer,
+            (then_type, else_type) => {
+                return Err(Self::error(
+                    format!("type mismatch in conditional expression: {then_type} and {else_type}"),
+                    span,
+                ));
+            }
+        };
+        Ok(TypedExpr::new(
+            TypedExprKind::Conditional(
+                Box::new(typed_cond),
+                Box::new(typed_then.convert_to(&ty)),
+                Box::new(typed_else.convert_to(&ty)),
+            ),
+            ty,
+            span,
+        ))
+    }
+
+    /// 関数の名前でも、関数へのポインタでも呼び出せる。
+    fn type_function_call(
+        &self,
+        callee: &Expr,
+        args: &[Expr],
+        span: Span,
+    ) -> CompileResult<TypedExpr> {
+        if let ExprKind::Variable(name) = &callee.kind {
+            if self.local_scopes.lookup(name).is_none()
+                && !self.global_type_environment.contains_key(name)
+                && !self.function_type_environment.contains_key(name)
+            {
+                return Err(Self::error(format!("undefined function: {name}"), span));
+            }
+        }
+        let typed_callee = self.type_expr(callee)?;
+        let function_type = match typed_callee.get_type() {
+            Type::Pointer(pointee) => *pointee,
+            ty => ty,
+        };
+        let Type::Function(arg_types, return_type, is_variadic) = function_type else {
+            return Err(Self::error(
+                format!(
+                    "called object is not a function: {}",
+                    typed_callee.get_type()
+                ),
+                callee.span,
+            ));
+        };
+        let function = match &typed_callee.kind {
+            TypedExprKind::Function(name) => format!("function {name}"),
+            _ => "function pointer".to_string(),
+        };
+        if args.len() < arg_types.len() || (!is_variadic && args.len() > arg_types.len()) {
+            let expected = if is_variadic {
+                format!("at least {}", arg_types.len())
+            } else {
+                arg_types.len().to_string()
+            };
+            return Err(Self::error(
+                format!(
+                    "{function} takes {expected} arguments, but {} were given",
+                    args.len()
+                ),
+                span,
+            ));
+        }
+        for ty in &arg_types {
+            check_long_double(ty, span)?;
+        }
+        let typed_args = args
             .iter()
-            .zip(arg_types.iter())
-            .map(|(arg, ty)| {
-                let typed_arg = self.type_expr(arg);
-                assert_eq!(
-                    typed_arg.get_type(),
-                    *ty,
-                    "typed_arg: {typed_arg:?}, t: {ty:?}",
-                );
-                typed_arg.decay_if_array()
+            .enumerate()
+            .map(|(i, arg)| {
+                let typed_arg = self.type_expr(arg)?.decay_if_array();
+                // `...` に渡す引数は既定の実引数拡張をする。
+                let Some(ty) = arg_types.get(i) else {
+                    if typed_arg.get_type() == Type::Void {
+                        return Err(Self::error(
+                            format!("argument of {function} must not be void"),
+                            arg.span,
+                        ));
+                    }
+                    let ty = typed_arg.get_type().promote_argument();
+                    return Ok(typed_arg.convert_to(&ty));
+                };
+                if is_assignable(&typed_arg, ty) {
+                    Ok(typed_arg.convert_to(ty))
+                } else {
+                    Err(Self::error(
+                        format!(
+                            "argument of {function} is expected to be {ty}, but got {}",
+                            typed_arg.get_type()
+                        ),
+                        arg.span,
+                    ))
+                }
             })
-            .collect();
-        for i in 0..arg_types.len() {
-            let typed_arg = &typed_args[i];
-            let arg_type = &arg_types[i];
-            assert_eq!(
-                typed_arg.get_type(),
-                *arg_type,
-                "typed_arg: {typed_arg:?}, arg_type: {arg_type:?}",
-            );
-        }
-        TypedExpr::FunctionCall(*return_type.clone(), name.clone(), typed_args)
-    }
-
-    fn type_comparator(&self, lhs: &Expr, rhs: &Expr, expr: &Expr) -> TypedExpr {
-        let lhs = self.type_expr(lhs);
-        let rhs = self.type_expr(rhs);
-        assert_eq!(lhs.get_type(), rhs.get_type(), "lhs: {lhs:?}, rhs: {rhs:?}",);
-        let constructor = match expr {
-            Expr::LessThan(_, _) => TypedExpr::LessThan,
-            Expr::LessEqual(_, _) => TypedExpr::LessEqual,
-            Expr::Equal(_, _) => TypedExpr::Equal,
-            Expr::NotEqual(_, _) => TypedExpr::NotEqual,
-            Expr::GreaterThan(_, _) => TypedExpr::GreaterThan,
-            Expr::GreaterEqual(_, _) => TypedExpr::GreaterEqual,
+            .collect::<CompileResult<Vec<TypedExpr>>>()?;
+        Ok(TypedExpr::new(
+            TypedExprKind::FunctionCall(Box::new(typed_callee), typed_args),
+            *return_type,
+            span,
+        ))
+    }
+
+    fn type_comparator(&self, lhs: &Expr, rhs: &Expr, expr: &Expr) -> CompileResult<TypedExpr> {
+        let typed_lhs = self.type_expr(lhs)?.decay_if_array();
+        let typed_rhs = self.type_expr(rhs)?.decay_if_array();
+        // ポインタは null ポインタ定数とも比べられる。
+        let is_null_comparison = |pointer: &TypedExpr, null: &TypedExpr| {
+            matches!(pointer.get_type(), Type::Pointer(_)) && is_null_pointer_constant(null)
+        };
+        let is_comparable = match (typed_lhs.get_type(), typed_rhs.get_type()) {
+            (Type::Pointer(lhs_pointee), Type::Pointer(rhs_pointee)) => {
+                are_compatible_pointees(&lhs_pointee, &rhs_pointee)
+            }
+            (lhs_type, rhs_type) => is_convertible(&lhs_type, &rhs_type),
+        } || is_null_comparison(&typed_lhs, &typed_rhs)
+            || is_null_comparison(&typed_rhs, &typed_lhs);
+        if !is_comparable || matches!(typed_lhs.get_type(), Type::Struct(_)) {
+            return Err(Self::error(
+                format!(
+                    "cannot compare {} with {}",
+                    typed_lhs.get_type(),
+                    typed_rhs.get_type()
+                ),
+                expr.span,
+            ));
+        }
+        let (typed_lhs, typed_rhs) = convert_to_common_type(typed_lhs, typed_rhs);
+        let constructor = match expr.kind {
+            ExprKind::LessThan(_, _) => TypedExprKind::LessThan,
+            ExprKind::LessEqual(_, _) => TypedExprKind::LessEqual,
+            ExprKind::Equal(_, _) => TypedExprKind::Equal,
+            ExprKind::NotEqual(_, _) => TypedExprKind::NotEqual,
+            ExprKind::GreaterThan(_, _) => TypedExprKind::GreaterThan,
+            ExprKind::GreaterEqual(_, _) => TypedExprKind::GreaterEqual,
+            _ => unreachable!(),
+        };
+        Ok(TypedExpr::new(
+            constructor(Box::new(typed_lhs), Box::new(typed_rhs)),
+            Type::IntTyp,
+            expr.span,
+        ))
+    }
+
+    fn type_binary_operator(
+        &self,
+        lhs: &Expr,
+        rhs: &Expr,
+        expr: &Expr,
+    ) -> CompileResult<TypedExpr> {
+        let typed_lhs = self.type_expr(lhs)?.decay_if_array();
+        let typed_rhs = self.type_expr(rhs)?.decay_if_array();
+        Self::type_binary_operation(typed_lhs, typed_rhs, expr)
+    }
+
+    /// 型付けしたオペランドに `expr` の二項演算を適用する。
+    fn type_binary_operation(
+        typed_lhs: TypedExpr,
+        typed_rhs: TypedExpr,
+        expr: &Expr,
+    ) -> CompileResult<TypedExpr> {
+        match expr.kind {
+            ExprKind::Mod(_, _)
+            | ExprKind::BitAnd(_, _)
+            | ExprKind::BitOr(_, _)
+            | ExprKind::BitXor(_, _) => Self::type_integer_operation(typed_lhs, typed_rhs, expr),
+            ExprKind::LeftShift(_, _) | ExprKind::RightShift(_, _) => {
+                Self::type_shift(typed_lhs, typed_rhs, expr)
+            }
+            _ => Self::type_arithmetic_operation(typed_lhs, typed_rhs, expr),
+        }
+    }
+
+    fn type_arithmetic_operation(
+        typed_lhs: TypedExpr,
+        typed_rhs: TypedExpr,
+        expr: &Expr,
+    ) -> CompileResult<TypedExpr> {
+        let (constructor, operator): (fn(_, _) -> _, _) = match expr.kind {
+            ExprKind::Add(_, _) => (TypedExprKind::Add, "+"),
+            ExprKind::Sub(_, _) => (TypedExprKind::Sub, "-"),
+            ExprKind::Mul(_, _) => (TypedExprKind::Mul, "*"),
+            ExprKind::Div(_, _) => (TypedExprKind::Div, "/"),
             _ => unreachable!(),
         };
-        constructor(
-            Box::new(lhs.decay_if_array()),
-            Box::new(rhs.decay_if_array()),
-        )
-    }
-
-    fn type_arithmetic_operator(&self, lhs: &Expr, rhs: &Expr, expr: &Expr) -> TypedExpr {
-        let lhs = self.type_expr(lhs);
-        let rhs = self.type_expr(rhs);
-        let constructor = match expr {
-            Expr::Add(_, _) => TypedExpr::Add,
-            Expr::Sub(_, _) => TypedExpr::Sub,
-            Expr::Mul(_, _) => TypedExpr::Mul,
-            Expr::Div(_, _) => TypedExpr::Div,
+        let (typed_lhs, typed_rhs) = convert_to_common_type(typed_lhs, typed_rhs);
+        let ty = match (operator, typed_lhs.get_type(), typed_rhs.get_type()) {
+            // 算術型同士なら通常の算術型変換で揃えた型になる。
+            (_, lhs_type, rhs_type) if lhs_type.is_arithmetic() && rhs_type.is_arithmetic() => {
+                lhs_type
+            }
+            // 要素の大きさが分からない `void *` などは足し引きできない。
+            ("+" | "-", pointer, integer)
+                if has_complete_pointee(&pointer) && integer.is_integer() =>
+            {
+                pointer
+            }
+            ("+", integer, pointer) if has_complete_pointee(&pointer) && integer.is_integer() => {
+                pointer
+            }
+            // ポインタ同士の差は要素数 (ptrdiff_t) になる。
+            ("-", Type::Pointer(lhs_pointee), Type::Pointer(rhs_pointee))
+                if lhs_pointee.is_complete()
+                    && lhs_pointee.eq_ignoring_qualifiers(&rhs_pointee) =>
+            {
+                Type::LongTyp
+            }
+            (_, lhs_type, rhs_type) => {
+                return Err(Self::error(
+                    format!("invalid operands to binary {operator}: {lhs_type} and {rhs_type}"),
+                    expr.span,
+                ));
+            }
+        };
+        Ok(TypedExpr::new(
+            constructor(Box::new(typed_lhs), Box::new(typed_rhs)),
+            ty,
+            expr.span,
+        ))
+    }
+
+    /// `%` とビット演算は整数型同士にだけ適用でき、通常の算術型変換で揃えた型になる。
+    fn type_integer_operation(
+        typed_lhs: TypedExpr,
+        typed_rhs: TypedExpr,
+        expr: &Expr,
+    ) -> CompileResult<TypedExpr> {
+        let (constructor, operator): (fn(_, _) -> _, _) = match expr.kind {
+            ExprKind::Mod(_, _) => (TypedExprKind::Mod, "%"),
+            ExprKind::BitAnd(_, _) => (TypedExprKind::BitAnd, "&"),
+            ExprKind::BitOr(_, _) => (TypedExprKind::BitOr, "|"),
+            ExprKind::BitXor(_, _) => (TypedExprKind::BitXor, "^"),
             _ => unreachable!(),
         };
-        constructor(
-            lhs.get_type(),
-            Box::new(lhs.decay_if_array()),
-            Box::new(rhs.decay_if_array()),
-        )
+        Self::check_integer_operands(&typed_lhs, &typed_rhs, operator, expr.span)?;
+        let (typed_lhs, typed_rhs) = convert_to_common_type(typed_lhs, typed_rhs);
+        let ty = typed_lhs.get_type();
+        Ok(TypedExpr::new(
+            constructor(Box::new(typed_lhs), Box::new(typed_rhs)),
+            ty,
+            expr.span,
+        ))
+    }
+
+    /// シフトは左右のオペランドを別々に整数拡張し、左辺の型になる。
+    fn type_shift(
+        typed_lhs: TypedExpr,
+        typed_rhs: TypedExpr,
+        expr: &Expr,
+    ) -> CompileResult<TypedExpr> {
+        let (constructor, operator): (fn(_, _) -> _, _) = match expr.kind {
+            ExprKind::LeftShift(_, _) => (TypedExprKind::LeftShift, "<<"),
+            _ => (TypedExprKind::RightShift, ">>"),
+        };
+        Self::check_integer_operands(&typed_lhs, &typed_rhs, operator, expr.span)?;
+        let ty = typed_lhs.get_type().promote();
+        let rhs_type = typed_rhs.get_type().promote();
+        Ok(TypedExpr::new(
+            constructor(
+                Box::new(typed_lhs.convert_to(&ty)),
+                Box::new(typed_rhs.convert_to(&rhs_type)),
+            ),
+            ty,
+            expr.span,
+        ))
+    }
+
+    fn check_integer_operands(
+        lhs: &TypedExpr,
+        rhs: &TypedExpr,
+        operator: &str,
+        span: Span,
+    ) -> CompileResult<()> {
+        if lhs.get_type().is_integer() && rhs.get_type().is_integer() {
+            Ok(())
+        } else {
+            Err(Self::error(
+                format!(
+                    "invalid operands to binary {operator}: {} and {}",
+                    lhs.get_type(),
+                    rhs.get_type()
+                ),
+                span,
+            ))
+        }
+    }
+
+    /// 条件式はスカラー型でなければならない。算術型は `_Bool` に変換して `-0.0` なども偽にする。
+    fn type_condition(&self, expr: &Expr) -> CompileResult<TypedExpr> {
+        let typed_expr = self.type_expr(expr)?.decay_if_array();
+        match typed_expr.get_type() {
+            Type::Pointer(_) => Ok(typed_expr),
+            ty if ty.is_arithmetic() => Ok(typed_expr.convert_to(&Type::BoolTyp)),
+            ty => Err(Self::error(
+                format!("used {ty} where a scalar is required"),
+                expr.span,
+            )),
+        }
+    }
+
+    fn error(message: impl Into<String>, span: Span) -> CompileError {
+        CompileError::new(CompileErrorKind::Type, message, Some(span))
+    }
+}
+
+/// `long double` は宣言を読めるだけで、値を持つ変数や式には使えない。
+fn check_long_double(ty: &Type, span: Span) -> CompileResult<()> {
+    if ty.contains_long_double() {
+        Err(CompileError::new(
+            CompileErrorKind::Type,
+            "long double is not supported",
+            Some(span),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// 修飾子を除いて同じ型か、どちらも算術型なら `from` の値を `to` として扱える。
+/// ポインタは指す先の修飾子を減らせない。`int *` は `const int *` にできるが、逆はできない。
+fn is_convertible(from: &Type, to: &Type) -> bool {
+    match (from.unqualified(), to.unqualified()) {
+        (Type::Pointer(from_pointee), Type::Pointer(to_pointee)) => {
+            to_pointee.qualifiers().contains(from_pointee.qualifiers())
+                && are_compatible_pointees(from_pointee, to_pointee)
+        }
+        _ => from.eq_ignoring_qualifiers(to) || (from.is_arithmetic() && to.is_arithmetic()),
+    }
+}
+
+/// 指す先が修飾子を除いて同じ型か、一方が `void` なら、ポインタ同士を変換したり比べたりできる。
+fn are_compatible_pointees(lhs: &Type, rhs: &Type) -> bool {
+    *lhs.unqualified() == Type::Void
+        || *rhs.unqualified() == Type::Void
+        || lhs.eq_ignoring_qualifiers(rhs)
+}
+
+/// 初期化や代入で `value` を `to` の値にできるなら真。
+/// null ポインタ定数は任意のポインタに、ポインタは `_Bool` に変換できる。
+fn is_assignable(value: &TypedExpr, to: &Type) -> bool {
+    let from = value.get_type();
+    match (&from, to.unqualified()) {
+        (_, Type::Pointer(_)) if is_null_pointer_constant(value) => true,
+        (Type::Pointer(_), Type::BoolTyp) => true,
+        _ => is_convertible(&from, to),
+    }
+}
+
+/// 値が 0 の整数定数式。
+const fn is_null_pointer_constant(expr: &TypedExpr) -> bool {
+    matches!(expr.kind, TypedExprKind::IntNum(0))
+}
+
+fn has_complete_pointee(ty: &Type) -> bool {
+    matches!(ty, Type::Pointer(pointee) if pointee.is_complete())
+}
+
+/// 算術型同士なら通常の算術型変換で共通の型に揃える。
+fn convert_to_common_type(lhs: TypedExpr, rhs: TypedExpr) -> (TypedExpr, TypedExpr) {
+    if lhs.get_type().is_arithmetic() && rhs.get_type().is_arithmetic() {
+        let ty = Type::common_arithmetic_type(&lhs.get_type(), &rhs.get_type());
+        (lhs.convert_to(&ty), rhs.convert_to(&ty))
+    } else {
+        (lhs, rhs)
+    }
+}
+
+/// 浮動小数点数はビット列を整数として静的領域に置く。
+fn float_static_value(value: f64, ty: &Type) -> StaticValue {
+    if *ty == Type::FloatTyp {
+        #[allow(clippy::cast_possible_truncation)]
+        StaticValue::Integer(i64::from((value as f32).to_bits()), 4)
+    } else {
+        #[allow(clippy::cast_possible_wrap)]
+        StaticValue::Integer(value.to_bits() as i64, 8)
     }
 }
@@ -0,0 +1,414 @@
+extern crate rand;
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+use rand::Rng;
+
+const COMPILER: &str = env!("CARGO_BIN_EXE_yuchiki-c-compiler");
+
+fn temporary_path(extension: &str) -> PathBuf {
+    let suffix = rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(30)
+        .map(char::from)
+        .collect::<String>();
+    PathBuf::from(format!("tmpdir/cli-{suffix}.{extension}"))
+}
+
+fn write_source(source: &str) -> PathBuf {
+    let path = temporary_path("c");
+    std::fs::write(&path, source).unwrap();
+    path
+}
+
+fn run_compiler(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(COMPILER)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn version_and_help() {
+    let version = run_compiler(&["--version"], "");
+    assert!(version.status.success());
+    assert!(String::from_utf8_lossy(&version.stdout).starts_with("yuchiki-c-compiler "));
+
+    let help = run_compiler(&["--help"], "");
+    assert!(help.status.success());
+    assert!(String::from_utf8_lossy(&help.stdout).contains("Usage:"));
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    assert_eq!(run_compiler(&[], "").status.code(), Some(2));
+    assert_eq!(
+        run_compiler(&["--unknown", "a.c"], "").status.code(),
+        Some(2)
+    );
+    assert_eq!(run_compiler(&["a.c", "-o"], "").status.code(), Some(2));
+    assert_eq!(run_compiler(&["a.c", "-I"], "").status.code(), Some(2));
+}
+
+#[test]
+fn assembly_from_stdin_to_stdout() {
+    let output = run_compiler(&["-S", "-"], "int main() { return 0; }");
+    assert!(output.status.success());
+    let assembly = String::from_utf8_lossy(&output.stdout);
+    assert!(assembly.contains("main:"));
+    // 関数のラベルより前に命令を置かない。
+    let main_label = assembly.lines().position(|line| line == "main:").unwrap();
+    assert!(assembly
+        .lines()
+        .take(main_label)
+        .all(|line| !line.starts_with("  ")));
+}
+
+#[test]
+fn assembly_to_file() {
+    let source = write_source("int main() { return 0; }");
+    let assembly = temporary_path("s");
+    let output = run_compiler(
+        &[
+            "-S",
+            source.to_str().unwrap(),
+            "-o",
+            assembly.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert!(output.status.success());
+    assert!(std::fs::read_to_string(&assembly)
+        .unwrap()
+        .contains("main:"));
+    std::fs::remove_file(source).unwrap();
+    std::fs::remove_file(assembly).unwrap();
+}
+
+#[test]
+fn object_file() {
+    let source = write_source("int f() { return 0; }");
+    let object = temporary_path("o");
+    let output = run_compiler(
+        &[
+            "-c",
+            source.to_str().unwrap(),
+            "-o",
+            object.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert!(output.status.success());
+    assert!(std::fs::read(&object).unwrap().starts_with(b"\x7fELF"));
+    std::fs::remove_file(source).unwrap();
+    std::fs::remove_file(object).unwrap();
+}
+
+#[test]
+fn executable_linked_with_other_inputs() {
+    let main = write_source(
+        "extern int external_func(int a, int b, int c, int d, int e, int f); extern int f(); int main() { return external_func(1, 1, 1, 1, 1, 1) + f(); }",
+    );
+    let f = write_source("int f() { return 2; }");
+    let assembly = temporary_path("s");
+    let gcc_status = Command::new("gcc")
+        .args([
+            "-S",
+            "-o",
+            assembly.to_str().unwrap(),
+            "tmpdir/external_func.c",
+        ])
+        .status()
+        .unwrap();
+    assert!(gcc_status.success());
+    let executable = temporary_path("out");
+    let output = run_compiler(
+        &[
+            main.to_str().unwrap(),
+            f.to_str().unwrap(),
+            assembly.to_str().unwrap(),
+            "-o",
+            executable.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let status = Command::new(&executable).status().unwrap();
+    assert_eq!(status.code(), Some(23));
+    std::fs::remove_file(main).unwrap();
+    std::fs::remove_file(f).unwrap();
+    std::fs::remove_file(assembly).unwrap();
+    std::fs::remove_file(executable).unwrap();
+}
+
+#[test]
+fn printf_with_double_argument() {
+    let source = write_source(
+        r#"extern int printf(char *format, double value); int main() { printf("%.2f\n", 1.5); return 0; }"#,
+    );
+    let executable = temporary_path("out");
+    let output = run_compiler(
+        &[source.to_str().unwrap(), "-o", executable.to_str().unwrap()],
+        "",
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let run = Command::new(&executable).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "1.50\n");
+    std::fs::remove_file(source).unwrap();
+    std::fs::remove_file(executable).unwrap();
+}
+
+#[test]
+fn libraries_are_passed_to_the_linker() {
+    let source = write_source("double sqrt(double x); int main() { return sqrt(49.0); }");
+    let executable = temporary_path("out");
+    let output = run_compiler(
+        &[
+            source.to_str().unwrap(),
+            "-lm",
+            "-L",
+            "tmpdir",
+            "-Wl,--as-needed",
+            "-o",
+            executable.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let status = Command::new(&executable).status().unwrap();
+    assert_eq!(status.code(), Some(7));
+    std::fs::remove_file(source).unwrap();
+    std::fs::remove_file(executable).unwrap();
+}
+
+#[test]
+fn compile_errors_are_reported_with_file_location() {
+    let source = write_source("int main() {\n  return x;\n}\n");
+    let output = run_compiler(&["-S", source.to_str().unwrap(), "-o", "-"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "{}:2:10: error: undefined variable: x",
+            source.display()
+        )),
+        "{stderr}"
+    );
+    std::fs::remove_file(source).unwrap();
+}
+
+#[test]
+fn types_are_reported_with_c_names() {
+    let output = run_compiler(
+        &["-S", "-"],
+        "int main() { int (*p)[3]; return p < 1.5; }\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("cannot compare int (*)[3] with double"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("IntTyp"), "{stderr}");
+}
+
+#[test]
+fn sibling_blocks_reuse_stack_slots() {
+    let output = run_compiler(
+        &["-S", "-"],
+        "int main() { { long a; long b; } { long c; long d; } return 0; }",
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("  sub rsp, 16\n"));
+}
+
+#[test]
+fn function_pointers_are_called_indirectly() {
+    let output = run_compiler(
+        &["-S", "-"],
+        "int f() { return 1; } int main() { int (*p)() = f; return p() + f(); }",
+    );
+    assert!(output.status.success());
+    let assembly = String::from_utf8_lossy(&output.stdout);
+    assert!(assembly.contains("  call r10\n"), "{assembly}");
+    assert!(assembly.contains("  call f\n"), "{assembly}");
+}
+
+#[test]
+fn static_symbols_are_not_global() {
+    let output = run_compiler(
+        &["-S", "-"],
+        "static int g; static int f() { static int n; return g + n; } int main() { return f(); }",
+    );
+    assert!(output.status.success());
+    let assembly = String::from_utf8_lossy(&output.stdout);
+    assert!(assembly.contains("f.n:\n"), "{assembly}");
+    assert!(assembly.contains(".globl main\n"), "{assembly}");
+    assert!(!assembly.contains(".globl f"), "{assembly}");
+    assert!(!assembly.contains(".globl g"), "{assembly}");
+}
+
+#[test]
+fn falling_off_non_void_function_is_warned() {
+    let output = run_compiler(
+        &["-S", "-"],
+        "int f(int x) {\n  if (x) return 1;\n}\nvoid g() {}\nint main() {}\n",
+    );
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "<stdin>:3:1: warning: control reaches end of non-void function f\n 3 | }\n   | ^\n1 warning generated.\n"
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("main:\n"));
+}
+
+#[test]
+fn warnings_are_reported_with_errors() {
+    let output = run_compiler(
+        &["-S", "-"],
+        "int f(int x) {\n  if (x) return 1;\n}\nint main() { return y; }\n",
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("<stdin>:3:1: warning: control reaches end of non-void function f\n"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("<stdin>:4:21: error:"), "{stderr}");
+    assert!(
+        stderr.ends_with("1 warning and 1 error generated.\n"),
+        "{stderr}"
+    );
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn headers_are_searched_in_include_paths() {
+    let header = temporary_path("h");
+    std::fs::write(&header, "int twice(int x) { return x * 2; }\n").unwrap();
+    let source = write_source(&format!(
+        "#include <{}>\nint main() {{ return twice(VALUE); }}\n",
+        header.file_name().unwrap().to_str().unwrap()
+    ));
+    let executable = temporary_path("out");
+
+    let output = run_compiler(
+        &[
+            "-I",
+            "tmpdir",
+            "-DVALUE=21",
+            source.to_str().unwrap(),
+            "-o",
+            executable.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert!(output.status.success(), "{output:?}");
+    let status = Command::new(&executable).status().unwrap();
+    assert_eq!(status.code(), Some(42));
+
+    let output = run_compiler(&["-S", source.to_str().unwrap(), "-o", "-"], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("file not found"));
+
+    for path in [header, source, executable] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn quoted_includes_are_relative_to_the_including_file() {
+    let header = temporary_path("h");
+    std::fs::write(&header, "#pragma once\nint g = N;\n").unwrap();
+    let header_name = header.file_name().unwrap().to_str().unwrap();
+    let source = write_source(&format!(
+        "#include \"{header_name}\"\n#include \"{header_name}\"\n#ifdef M\n#error M is defined\n#endif\nint main() {{ return g; }}\n"
+    ));
+
+    let output = run_compiler(
+        &[
+            "-S",
+            "-DM",
+            "-D",
+            "N=3",
+            "-UM",
+            source.to_str().unwrap(),
+            "-o",
+            "-",
+        ],
+        "",
+    );
+    assert!(output.status.success(), "{output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("g:\n  .long 3\n"));
+
+    for path in [header, source] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn errors_in_headers_are_reported_with_header_location() {
+    let header = temporary_path("h");
+    std::fs::write(&header, "int f() {\n  return missing;\n}\n").unwrap();
+    let source = write_source(&format!(
+        "#include \"{}\"\nint main() {{ return f(); }}\n",
+        header.file_name().unwrap().to_str().unwrap()
+    ));
+
+    let output = run_compiler(&["-S", source.to_str().unwrap(), "-o", "-"], "");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "{}:2:10: error: undefined variable: missing\n 2 |   return missing;\n",
+            header.display()
+        )),
+        "{stderr}"
+    );
+
+    for path in [header, source] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn malformed_tokens_are_reported_with_line_and_column() {
+    let output = run_compiler(
+        &["-S", "-"],
+        "int main() {\n  // 0x\n  return 0x + /* 1e */ 1e;\n}\n",
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr,
+        "<stdin>:3:10: error: invalid suffix 'x' on integer constant\n 3 |   return 0x +          1e;\n   |          ^~\n<stdin>:3:24: error: invalid floating constant 1e\n 3 |   return 0x +          1e;\n   |                        ^~\n2 errors generated.\n"
+    );
+}
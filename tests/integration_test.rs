@@ -6,7 +6,7 @@ use std::process::Command;
 use rand::Rng;
 
 use rstest::rstest;
-use yuchiki_c_compiler::process;
+use yuchiki_c_compiler::{process, CompileErrorKind, SourcePosition, Span};
 
 const OUT_FILE_BASE_NAME: &str = "tmpdir/tmp";
 const EXTERNAL_FUNC_FILE_BASE_NAME: &str = "tmpdir/external_func";
@@ -37,9 +37,9 @@ const EXTERNAL_FUNC_FILE_BASE_NAME: &str = "tmpdir/external_func";
 #[case::greaterthanorequal_greater("int main () { return 20 >= 10; }", 1)]
 #[case::greaterthanorequal_equal("int main () { return 10 >= 10; }", 1)]
 #[case::greaterthanorequal_less("int main () { return 10 >= 20; }", 0)]
-#[case::multiple_statement("int main () { 1; 2; 3; }", 3)]
+#[case::multiple_statement("int main () { 1; 2; 3; }", 0)]
 #[case::return_statement("int main () { 1; return 2; 3; }", 2)]
-#[case::assign("int main () {int num; num = 3; }", 3)]
+#[case::assign("int main () {int num; num = 3; return num; }", 3)]
 #[case::variable("int main () {int num; num = 3; return num + 2;}", 5)]
 #[case::chained_assign(
     "int main () {int num1; int num2;  num1 = num2 = 3; return num1 + num2; }",
@@ -62,17 +62,20 @@ const EXTERNAL_FUNC_FILE_BASE_NAME: &str = "tmpdir/external_func";
     "int main () {int i; int sum;  i = sum = 0; while(i <= 10) { sum = sum + i; i = i + 1; } return sum; }",
     55
 )]
-#[case::external_function_call("extern int external_func(int a, int b, int c, int d, int e, int f); int main () { external_func(1,2,3,4,5,6); }", 91)]
-#[case::function_call_without_args("int my_func() { return 3; } int main () { my_func(); }", 3)]
+#[case::external_function_call("extern int external_func(int a, int b, int c, int d, int e, int f); int main () { return external_func(1,2,3,4,5,6); }", 91)]
+#[case::function_call_without_args(
+    "int my_func() { return 3; } int main () { return my_func(); }",
+    3
+)]
 #[case::function_call_with_an_arg(
-    "int my_func(int a) { int b; b = 3; return a + b; } int main () { my_func(3); }",
+    "int my_func(int a) { int b; b = 3; return a + b; } int main () { return my_func(3); }",
     6
 )]
 #[case::function_call_with_two_args(
-    "int my_func(int a, int b) { int c; c = 10; return a; } int main () { my_func(3, 5); }",
+    "int my_func(int a, int b) { int c; c = 10; return a; } int main () { return my_func(3, 5); }",
     3
 )]
-#[case::function_call( "int my_func(int a, int b, int c, int d, int e, int f){int g; int h; g = 7; h = a + b * 2 + c * 3 + d * 4 + e * 5 + f * 6 + g; return h / 2;} int main(){my_func(1,2,3,4,5,6);}", 49)]
+#[case::function_call( "int my_func(int a, int b, int c, int d, int e, int f){int g; int h; g = 7; h = a + b * 2 + c * 3 + d * 4 + e * 5 + f * 6 + g; return h / 2;} int main(){return my_func(1,2,3,4,5,6);}", 49)]
 #[case::pointer_dereference(
     "int main () {int a; a = 5; return f(&a); return a; } int f (int *pointer) { *pointer = *pointer + 5 ; } ",
     10
@@ -104,6 +107,942 @@ const EXTERNAL_FUNC_FILE_BASE_NAME: &str = "tmpdir/external_func";
     5
 )]
 #[case::array_access_sugar("int main() { int a[2]; a[0] = 1; a[1] = 2; return a[0] + a[1]; }", 3)]
+#[case::global_variable(
+    "int g; int set() { g = 3; return 0; } int main() { set(); return g; }",
+    3
+)]
+#[case::global_variable_is_zero_initialized("int g; int *p; int main() { return g; }", 0)]
+#[case::global_variable_with_initializer("int g = 2 * 3 - 1; int main() { return g; }", 5)]
+#[case::global_pointer_with_initializer("int g = 7; int *p = &g; int main() { return *p; }", 7)]
+#[case::global_array_with_initializer("int a[3] = {5, 2, 3}; int main() { return a[0]; }", 5)]
+#[case::global_pointer_array_with_initializer(
+    "int x = 3; int y = 4; int *ps[3] = {&x, &y}; int main() { return *ps[0] + *ps[1]; }",
+    7
+)]
+#[case::global_pointers_with_offsets(
+    "struct P { int x; int y; int z[3]; } g = {1, 2, {3, 4, 5}}; int a[4] = {6, 7, 8, 9}; int *p = &a[1]; int *q = a + 2; int *r = &g.y; int *s = g.z + 1; int *t = &(&g)->z[2] - 1; char *c = \"abc\" + 1; int main() { return (*p == 7) + (*q == 8) * 2 + (*r == 2) * 4 + (*s == 4) * 8 + (*t == 4) * 16 + (*c == 'b') * 32 + (a + 3 - 1 == q) * 64; }",
+    127
+)]
+#[case::tentative_definitions(
+    "int g; int g = 3; int g; int h; int h; int main() { h = 4; return g + h; }",
+    7
+)]
+#[case::local_variable_shadows_global_variable(
+    "int g = 1; int main() { int g; g = 2; return g; }",
+    2
+)]
+#[case::char_variable("int main() { char c; c = 300; return c; }", 44)]
+#[case::char_is_sign_extended("int main() { char c; c = 255; return c + 2; }", 1)]
+#[case::char_comparison("int main() { char c; c = 'a'; return c == 97; }", 1)]
+#[case::char_literal("int main() { return 'a' + '\\n' - '\\x0a'; }", 97)]
+#[case::sizeof_char("int main() { char c; return sizeof(c); }", 1)]
+#[case::string_literal(r#"int main() { return *"abc"; }"#, 97)]
+#[case::sizeof_string_literal(r#"int main() { return sizeof("ab" "c\n"); }"#, 5)]
+#[case::string_literal_escape(r#"int main() { return *"\101"; }"#, 65)]
+#[case::char_pointer_parameter(
+    r#"int first(char *s) { return *s; } int main() { return first("xyz"); }"#,
+    120
+)]
+#[case::global_char_array(r#"char s[6] = "hello"; int main() { return *s; }"#, 104)]
+#[case::global_char_pointer(r#"char *s = "hi"; int main() { return *s; }"#, 104)]
+#[case::printf(
+    r#"extern int printf(char *format, int value); int main() { printf("%d\n", 42); return 0; }"#,
+    0
+)]
+#[case::int_pointer_arithmetic_scales_by_element_size(
+    "int main() { int a[3]; a[0] = 1; a[1] = 2; a[2] = 3; return *(a + 2) * 10 + a[1]; }",
+    32
+)]
+#[case::store_through_int_pointer_writes_4_bytes(
+    "int main() { int a[2]; int *p; p = a; *(p + 1) = 5; *p = 3; return a[1] * 10 + a[0]; }",
+    53
+)]
+#[case::char_pointer_arithmetic(r#"int main() { char *s; s = "hello"; return *(s + 1); }"#, 101)]
+#[case::string_literal_index(r#"int main() { return "abc"[2]; }"#, 99)]
+#[case::integer_plus_pointer("int main() { int a[2]; a[1] = 7; return *(1 + a); }", 7)]
+#[case::pointer_difference("int main() { int a[4]; return &a[3] - a; }", 3)]
+#[case::char_pointer_difference(r#"int main() { char *s; s = "hello"; return (s + 4) - s; }"#, 4)]
+#[case::pointer_to_pointer_arithmetic(
+    "int main() { int x; int y; int *ps[2]; ps[0] = &x; ps[1] = &y; y = 9; return **(ps + 1); }",
+    9
+)]
+#[case::negative_int_is_sign_extended("int main() { int a; a = -1; return a < 0; }", 1)]
+#[case::negative_return_value_is_sign_extended(
+    "extern int external_func(int a, int b, int c, int d, int e, int f); int main() { return external_func(-1, 0, 0, 0, 0, 0) < 0; }",
+    1
+)]
+#[case::char_parameter("int f(char c) { return c; } int main() { return f(65); }", 65)]
+#[case::struct_member(
+    "int main() { struct { int a; char b; int c; } s; s.a = 3; s.b = 4; s.c = 5; return s.a * s.b + s.c; }",
+    17
+)]
+#[case::struct_arrow(
+    "struct point { int x; int y; }; int main() { struct point p; struct point *q; q = &p; q->y = 7; return p.y; }",
+    7
+)]
+#[case::nested_struct(
+    "struct in { char c; int i; }; struct out { char a; struct in in; }; int main() { struct out o; o.in.i = 9; return o.in.i + sizeof(o); }",
+    21
+)]
+#[case::self_referential_struct(
+    "struct node { int value; struct node *next; }; int main() { struct node a; struct node b; a.value = 1; a.next = &b; b.value = 2; return a.value + a.next->value; }",
+    3
+)]
+#[case::sizeof_struct(
+    "struct s { char c; int *p; char d; }; int main() { return sizeof(struct s); }",
+    24
+)]
+#[case::sizeof_struct_pointer("int main() { return sizeof(struct s *); }", 8)]
+#[case::struct_assignment(
+    "struct s { int a; char b[5]; }; int main() { struct s x; struct s y; x.a = 3; x.b[4] = 4; y = x; x.a = 0; return y.a + y.b[4]; }",
+    7
+)]
+#[case::array_of_structs(
+    "struct s { char c; int i; }; int main() { struct s a[3]; a[2].i = 5; a[1].c = 2; return a[2].i - a[1].c + (&a[2] - &a[0]); }",
+    5
+)]
+#[case::global_struct_initializer(
+    "struct s { char c; int i; char *p; }; struct s g = {1, 2, \"x\"}; int main() { return g.c + g.i + g.p[0]; }",
+    123
+)]
+#[case::struct_tag_in_block_scope(
+    "struct s { int a; }; int main() { { struct s { char c[20]; } x; x.c[19] = 1; } struct s y; return sizeof(y); }",
+    4
+)]
+#[case::union_members_overlap(
+    "int main() { union { int i; char c; } u; u.i = 258; return u.c; }",
+    2
+)]
+#[case::sizeof_union(
+    "union u { char c[5]; int i; }; int main() { return sizeof(union u); }",
+    8
+)]
+#[case::global_union_initializer(
+    "union u { char c; int i; }; union u g = {3}; int main() { return g.i; }",
+    3
+)]
+#[case::enum_constants(
+    "enum color { red, green = 5, blue }; int main() { return red + green * blue; }",
+    30
+)]
+#[case::enum_variable(
+    "enum e { three = 3 }; int main() { enum e x; x = three; return x + sizeof(x); }",
+    7
+)]
+#[case::negative_enum_constant("enum { a = -2, b, }; int main() { return b + 10; }", 9)]
+#[case::enum_constant_shadowed_by_variable(
+    "enum { a = 1 }; int main() { int a; a = 5; return a; }",
+    5
+)]
+#[case::enum_constant_in_expression(
+    "enum { n = 2 * 3 }; int main() { int a[n]; a[n - 1] = n; return a[5] + sizeof(a); }",
+    30
+)]
+#[case::large_local_does_not_clobber_frame(
+    "int f() { int a[20]; a[19] = 1; return a[19]; } int main() { return f() + 1; }",
+    2
+)]
+#[case::short_conversion_on_assignment(
+    "int main() { short s; s = 32767; s = s + 1; return s < 0; }",
+    1
+)]
+#[case::unsigned_char_wraps("int main() { unsigned char c; c = 255; c = c + 1; return c; }", 0)]
+#[case::unsigned_char_is_zero_extended(
+    "int main() { unsigned char c; c = 200; return c > 100; }",
+    1
+)]
+#[case::unsigned_comparison("int main() { unsigned int a; a = 0; return a - 1 > 0; }", 1)]
+#[case::signed_converted_to_unsigned(
+    "int main() { int a; unsigned b; a = -1; b = 0; return a < b; }",
+    0
+)]
+#[case::unsigned_int_converted_to_long(
+    "int main() { long a; unsigned b; a = -1; b = 1; return a < b; }",
+    1
+)]
+#[case::unsigned_division("int main() { unsigned a; a = -2; return a / 2 > 1000000000; }", 1)]
+#[case::long_arithmetic(
+    "int main() { long a; a = 65536; a = a * a; return a / 65536 / 65536; }",
+    1
+)]
+#[case::int_overflow_wraps("int main() { int a; a = 1073741824; return a + a < 0; }", 1)]
+#[case::bool_conversion("int main() { _Bool b; _Bool c; b = 5; c = 256; return b + c; }", 2)]
+#[case::sizeof_integer_types(
+    "int main() { return sizeof(unsigned short) + sizeof(long int) * 4 + sizeof(long long) * 8 + sizeof(_Bool) * 100; }",
+    198
+)]
+#[case::struct_with_short_and_long(
+    "struct s { char c; short h; long l; }; int main() { return sizeof(struct s); }",
+    16
+)]
+#[case::unsigned_short_parameter(
+    "int f(unsigned short x) { return x == 65535; } int main() { return f(-1); }",
+    1
+)]
+#[case::global_short_and_unsigned_char(
+    "short g = -3; unsigned char h = 255; int main() { return g + h; }",
+    252
+)]
+#[case::long_return_value(
+    "long f() { long a; a = 1; return a * 4096 * 4096 * 4096; } int main() { return f() / 4096 / 4096 / 4096; }",
+    1
+)]
+#[case::specifier_order(
+    "int main() { unsigned long long x; long unsigned int y; signed s; x = 1; y = 2; s = 3; return x + y + s; }",
+    6
+)]
+#[case::double_arithmetic(
+    "int main() { double a; double b; a = 1.5; b = .25; return (a + b) * 4 - 1 / b; }",
+    3
+)]
+#[case::float_arithmetic("int main() { float f; f = 2.5f; return f * f * 4; }", 25)]
+#[case::long_double_declarations(
+    "long double ld(long double); typedef long double LD; int main() { LD *p = 0; return sizeof(long double) + sizeof(LD) + (p == 0); }",
+    33
+)]
+#[case::float_literal_exponent("int main() { double d; d = 1e-3; return d * 5e3; }", 5)]
+#[case::hexadecimal_float_literals(
+    "int main() { double d = 0x1.8p3; float f = 0x1p-2f; return d + f * 4; }",
+    13
+)]
+#[case::digraphs(
+    "%:define N 2\nint main() <% int a<:N:> = <%1, 2%>; return a<:0:> + a<:1:>; %>",
+    3
+)]
+#[case::prefixed_string_literals(
+    "int main() { int s[] = L\"ab\"; int *p = L\"xyz\"; unsigned short t[] = u\"あ\"; unsigned int u[] = U\"c\" \"d\"; return sizeof(s) + (p[2] - 'x') + (t[0] == 0x3042) * 10 + (u[1] == 'd') * 20 + sizeof(u) * 4 + sizeof(u8\"ab\"); }",
+    95
+)]
+#[case::global_wide_string_literals(
+    "int *g = L\"hi\"; int w[3] = L\"q\"; int main() { return (g[1] == 'i') + (w[0] == 'q') * 2 + (w[2] == 0) * 4; }",
+    7
+)]
+#[case::double_to_int_truncates("int main() { int a; a = -7.9; return a + 10; }", 3)]
+#[case::int_divided_as_double("int main() { int a; a = 7; return a / 2.0 * 2; }", 7)]
+#[case::float_promoted_to_double(
+    "int main() { float f; double d; f = 0.1f; d = f; return (d != 0.1) + (d == f) * 2; }",
+    3
+)]
+#[case::double_comparisons(
+    "int main() { double a; double b; a = 1.5; b = 2.5; return (a < b) + (a <= b) * 2 + (a > b) * 4 + (b >= a) * 8 + (a == 1.5) * 16 + (a != b) * 32; }",
+    59
+)]
+#[case::mixed_parameters(
+    "double f(double a, int n, float b) { return a * n + b; } int main() { return f(1.5, 4, 0.5f); }",
+    6
+)]
+#[case::eight_double_arguments(
+    "double f(double a, double b, double c, double d, double e, double g, double h, double i) { return a + b * 2 + c * 3 + d * 4 + e * 5 + g * 6 + h * 7 + i * 8; } int main() { return f(1, 1, 1, 1, 1, 1, 1, 1.5); }",
+    40
+)]
+#[case::eight_int_arguments(
+    "int f(int a, int b, int c, int d, int e, int g, int h, int i) { return a + b * 2 + c * 3 + d * 4 + e * 5 + g * 6 + h * 7 + i * 8; } int main() { return f(1, 1, 1, 1, 1, 1, 1, 2); }",
+    44
+)]
+#[case::small_integers_on_stack(
+    "long f(int a, int b, int c, int d, int e, int g, char h, short i, long j) { return h + i + j - a; } int main() { return f(1, 0, 0, 0, 0, 0, -3, -4, 10); }",
+    2
+)]
+#[case::ten_double_arguments(
+    "double f(double a, double b, double c, double d, double e, double g, double h, double i, double j, double k) { return a + j * 2 + k * 3; } int main() { return f(1, 0, 0, 0, 0, 0, 0, 0, 1.5, 2.5); }",
+    11
+)]
+#[case::mixed_arguments_on_stack(
+    "double f(int a, int b, int c, int d, int e, int g, int h, double x, float y, int i) { return h * 100 + x * 10 + y + i; } int main() { return f(0, 0, 0, 0, 0, 0, 1, 2.5, 0.5f, 3); }",
+    128
+)]
+#[case::nested_calls_with_stack_arguments(
+    "int f(int a, int b, int c, int d, int e, int g, int h) { return a - h; } int main() { return 1 + f(10, 0, 0, 0, 0, 0, f(9, 0, 0, 0, 0, 0, 4)); }",
+    6
+)]
+#[case::function_pointer_with_stack_arguments(
+    "int f(int a, int b, int c, int d, int e, int g, int h, int i) { return a * h + i; } int main() { int (*p)(int, int, int, int, int, int, int, int) = f; return p(3, 0, 0, 0, 0, 0, 4, 5); }",
+    17
+)]
+#[case::external_many_mixed_arguments(
+    "extern long many_mixed_args(int a, double b, long c, float d, int e, int f, int g, int h, double i, char j, double k, double l, double m, double n, double o, double p, short q); int main() { return many_mixed_args(1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1); }",
+    119
+)]
+#[case::aligned_library_call_with_stack_arguments(
+    r#"extern int snprintf(char *buf, long n, char *format, int a, int b, int c, int d, int e, double f); int main() { char buf[32]; return (1 + snprintf(buf, 32, "%d%d%d%d%d%.1f", 1, 2, 3, 4, 5, 6.5)) * 10 + buf[5] - '0'; }"#,
+    96
+)]
+#[case::external_caller_with_stack_arguments(
+    "extern int apply_eight_ints(int (*f)(int, int, int, int, int, int, int, int)); int f(int a, int b, int c, int d, int e, int g, int h, int i) { return a + b * 2 + c * 3 + d * 4 + e * 5 + g * 6 + h * 7 + i * 8; } int main() { return apply_eight_ints(f); }",
+    204
+)]
+#[case::pass_and_return_small_struct(
+    "struct p { int x; int y; }; struct p make(int x, int y) { struct p r; r.x = x; r.y = y; return r; } int sum(struct p a) { return a.x * 10 + a.y; } int main() { return sum(make(3, 4)); }",
+    34
+)]
+#[case::struct_with_double_and_int(
+    "struct m { double d; int i; }; struct m twice(struct m a) { a.d = a.d * 2; a.i = a.i * 2; return a; } int main() { struct m x; x.d = 1.5; x.i = 4; struct m y = twice(x); return y.d * 10 + y.i; }",
+    38
+)]
+#[case::struct_of_floating_members(
+    "struct f { float x; float y; double z; }; struct f make(double z) { struct f r; r.x = 0.5f; r.y = 1.5f; r.z = z; return r; } double sum(struct f a) { return a.x + a.y * 2 + a.z * 3; } int main() { return sum(make(2.5)) * 2; }",
+    22
+)]
+#[case::large_struct_returned_through_memory(
+    "struct big { long a; long b; long c; }; struct big make(long n) { struct big r; r.a = n; r.b = n * 2; r.c = n * 3; return r; } long sum(struct big b) { return b.a + b.b * 2 + b.c * 3; } int main() { return sum(make(3)); }",
+    42
+)]
+#[case::struct_with_odd_size(
+    "struct o { char c[3]; }; struct o make(char x) { struct o r; r.c[0] = x; r.c[1] = x + 1; r.c[2] = x + 2; return r; } int sum(struct o v) { return v.c[0] + v.c[1] * 2 + v.c[2] * 3; } int main() { return sum(make(1)); }",
+    14
+)]
+#[case::member_of_returned_struct(
+    "struct p { int x; int y; }; struct p make(int x, int y) { struct p r; r.x = x; r.y = y; return r; } int main() { return make(3, 4).y + make(5, 6).x; }",
+    9
+)]
+#[case::assign_returned_struct(
+    "struct big { long a; long b; long c; }; struct big make(long n) { struct big r; r.a = n; r.b = n; r.c = n; return r; } int main() { struct big b; b = make(2); b.a = 1; return b.a + b.b * 10 + sizeof(b = make(7)); }",
+    45
+)]
+#[case::struct_arguments_spill_to_stack(
+    "struct q { long x; long y; }; long f(int a, int b, int c, int d, int e, struct q s, int g) { return a + s.x * 100 + s.y * 10 + g; } int main() { struct q s; s.x = 1; s.y = 2; return f(3, 0, 0, 0, 0, s, 7); }",
+    130
+)]
+#[case::multiple_struct_temporaries(
+    "struct big { long a; long b; long c; }; struct big make(long n) { struct big r; r.a = n; r.b = n; r.c = n; return r; } long diff(struct big l, struct big r) { return l.a * 10 - r.c; } int main() { return diff(make(5), make(8)); }",
+    42
+)]
+#[case::struct_through_function_pointer(
+    "struct big { long a; long b; long c; }; struct big make(long n) { struct big r; r.a = n; r.b = n + 1; r.c = n + 2; return r; } int main() { struct big (*p)(long) = make; return p(4).c; }",
+    6
+)]
+#[case::struct_returned_from_conditional_call(
+    "struct p { int x; int y; }; struct p make(int x) { struct p r; r.x = x; r.y = x * 2; return r; } int main() { int c = 1; struct p a = c ? make(3) : make(4); return a.y; }",
+    6
+)]
+#[case::external_small_struct_return(
+    "struct ext_pair { int a; int b; }; extern struct ext_pair ext_make_pair(int a, int b); int main() { struct ext_pair p = ext_make_pair(3, 4); return p.a * 10 + p.b; }",
+    34
+)]
+#[case::external_mixed_struct(
+    "struct ext_mixed { double d; long l; }; extern struct ext_mixed ext_scale_mixed(struct ext_mixed m, int k); int main() { struct ext_mixed m; m.d = 1.5; m.l = 2; struct ext_mixed r = ext_scale_mixed(m, 4); return r.d * 10 + r.l; }",
+    68
+)]
+#[case::external_floating_struct_argument(
+    "struct ext_floats { float x; float y; double z; }; extern double ext_floats_sum(struct ext_floats f); int main() { struct ext_floats f; f.x = 0.5f; f.y = 1.5f; f.z = 2.5; return ext_floats_sum(f) * 2; }",
+    22
+)]
+#[case::external_odd_sized_struct(
+    "struct ext_odd { char c[3]; }; extern struct ext_odd ext_make_odd(char x); extern int ext_odd_sum(struct ext_odd o); int main() { return ext_odd_sum(ext_make_odd(1)) + ext_make_odd(5).c[2]; }",
+    21
+)]
+#[case::external_large_struct(
+    "struct ext_big { long a; long b; long c; }; extern struct ext_big ext_make_big(long n); extern long ext_big_sum(int scale, struct ext_big b, int offset); int main() { return ext_big_sum(2, ext_make_big(1), 5) + ext_make_big(4).c; }",
+    45
+)]
+#[case::external_caller_with_small_struct(
+    "struct ext_pair { int a; int b; }; extern int ext_apply_pair(struct ext_pair (*f)(struct ext_pair)); struct ext_pair swap(struct ext_pair p) { int t = p.a; p.a = p.b; p.b = t; return p; } int main() { return ext_apply_pair(swap); }",
+    43
+)]
+#[case::external_caller_with_large_struct(
+    "struct ext_big { long a; long b; long c; }; extern long ext_apply_big(struct ext_big (*f)(struct ext_big, long)); struct ext_big scale(struct ext_big b, long k) { b.a = b.a * k; b.b = b.b * k; b.c = b.c * k; return b; } int main() { return ext_apply_big(scale); }",
+    60
+)]
+#[case::external_double_function(
+    "extern double weighted_double_sum(double a, int n, float b); int main() { return weighted_double_sum(2.5, 4, 0.5f); }",
+    10
+)]
+#[case::global_floating_initializers(
+    "double g = 1.5; float h = 2.5f; double i = 3; int j = 11.9; int main() { return g * 2 + h * 2 + i + j; }",
+    22
+)]
+#[case::unsigned_long_to_double(
+    "int main() { unsigned long u; double d; u = 0; u = u - 1; d = u; return d > 1e19; }",
+    1
+)]
+#[case::large_double_to_unsigned_long(
+    "int main() { double d = 1e19; float f = 1e19f; unsigned long u = d; unsigned long v = f; return (u == 10000000000000000000ul) + (v / 1000000000000 == 9999999) * 2 + ((unsigned long)3.5 == 3) * 4; }",
+    7
+)]
+#[case::global_unsigned_long_and_double(
+    "double d = 18446744073709551615ul; unsigned long ul = 1e19; float f = 4294967295u; int main() { return (d > 1.8e19) + (ul == 10000000000000000000ul) * 2 + (f > 4e9) * 4; }",
+    7
+)]
+#[case::double_to_bool("int main() { _Bool b; b = 0.5; return b; }", 1)]
+#[case::struct_with_double(
+    "struct s { char c; double d; }; int main() { struct s x; x.d = 4.5; return sizeof(struct s) + x.d * 2; }",
+    25
+)]
+#[case::sizeof_floating_types("int main() { return sizeof(float) * 10 + sizeof(double); }", 48)]
+#[case::modulo("int main() { return 17 % 5 + (-7 % 3 + 5) * 10; }", 42)]
+#[case::unsigned_modulo("int main() { unsigned a; a = -1; return a % 10; }", 5)]
+#[case::logical_and_or(
+    "int main() { return (1 && 2) + (0 && 1) * 2 + (0 || 3) * 4 + (0 || 0) * 8; }",
+    5
+)]
+#[case::logical_and_short_circuit(
+    "int main() { int a; a = 1; 0 && (a = 2); 1 || (a = 3); return a; }",
+    1
+)]
+#[case::logical_and_evaluates_rhs(
+    "int main() { int a; a = 1; 1 && (a = 2); 0 || (a = a + 3); return a; }",
+    5
+)]
+#[case::logical_not(
+    "int main() { int *p; p = 0; return !0 + !5 * 2 + !p * 4 + !0.0 * 8; }",
+    13
+)]
+#[case::double_condition("int main() { double d; d = -0.0; if (d) return 1; return 2; }", 2)]
+#[case::bitwise_operators("int main() { return (12 & 10) + (12 | 3) * 2 + (6 ^ 3) * 40; }", 238)]
+#[case::bitwise_not("int main() { unsigned char c; c = 5; return ~c + ~-8 * 10; }", 64)]
+#[case::left_shift("int main() { return (1 << 4) + (3 << 1); }", 22)]
+#[case::arithmetic_right_shift("int main() { int a; a = -16; return (a >> 2) + 10; }", 6)]
+#[case::logical_right_shift(
+    "int main() { unsigned a; a = -16; return (a >> 28) + (a >> 31) * 100; }",
+    115
+)]
+#[case::shift_type_is_left_operand(
+    "int main() { char c; long l; c = 1; l = 2; return sizeof(c << l) + sizeof(l << c); }",
+    12
+)]
+#[case::operator_precedence("int main() { return 1 | 2 ^ 3 & 4 == 4 << 0; }", 3)]
+#[case::constant_bitwise_expressions(
+    "enum { A = 1 << 3, B = A | 1, C = ~B & 255 % 128 }; int g = (1 << 5) ^ 3; int main() { return A + B + C + g; }",
+    170
+)]
+#[case::compound_assignment(
+    "int main() { int a; a = 5; a += 3; a -= 1; a *= 6; a /= 4; a %= 7; return a; }",
+    3
+)]
+#[case::compound_bitwise_assignment(
+    "int main() { int a; a = 1; a <<= 5; a |= 3; a &= 49; a ^= 2; a >>= 1; return a; }",
+    17
+)]
+#[case::compound_assignment_value(
+    "int main() { int a; int b; a = 1; b = (a += 2) * 10; return a + b; }",
+    33
+)]
+#[case::compound_assignment_converts(
+    "int main() { char c; c = 100; c += 200; int i; i = 7; i *= 1.5; return c + i; }",
+    54
+)]
+#[case::compound_assignment_evaluates_lvalue_once(
+    "int main() { int a[3]; int i; a[0] = 1; a[1] = 2; a[2] = 3; i = 0; a[i = i + 1] += 10; return a[1] + i * 100; }",
+    112
+)]
+#[case::increment_decrement(
+    "int main() { int a; int b; int c; a = 5; b = a++; c = ++a; --a; a--; return a * 100 + b * 10 + c - 500; }",
+    57
+)]
+#[case::increment_pointer(
+    "int main() { int a[3]; int *p; a[0] = 1; a[1] = 2; a[2] = 3; p = a; p++; ++p; return *p + *--p * 10; }",
+    23
+)]
+#[case::pointer_compound_assignment(
+    "int main() { long a[4]; long *p; a[3] = 9; p = a; p += 3; return *p; }",
+    9
+)]
+#[case::post_increment_of_double(
+    "int main() { double d; double e; d = 0.25; e = d++; return (e == 0.25) + (d == 1.25) * 2; }",
+    3
+)]
+#[case::post_increment_of_char(
+    "int main() { char c; int old; c = 127; old = c++; return (old == 127) + (c == -128) * 2; }",
+    3
+)]
+#[case::increment_in_for_loop(
+    "int main() { int sum; int i; sum = 0; for (i = 0; i < 10; i++) sum += i; return sum; }",
+    45
+)]
+#[case::increment_member(
+    "struct s { int a; int b; }; int main() { struct s x; struct s *p; p = &x; x.b = 4; p->b++; ++x.b; return x.b; }",
+    6
+)]
+#[case::conditional("int main() { int a; a = 3; return a > 2 ? a * 10 : 5; }", 30)]
+#[case::conditional_only_evaluates_one_branch(
+    "int main() { int a; int b; a = 0; b = 0; 1 ? (a = 1) : (b = 1); 0 ? (a = 2) : (b = 2); return a * 10 + b; }",
+    12
+)]
+#[case::nested_conditional("int f(int x) { return x < 0 ? 1 : x == 0 ? 2 : 3; } int main() { return f(-5) * 100 + f(0) * 10 + f(5); }", 123)]
+#[case::conditional_common_type(
+    "int main() { int a; a = 1; return sizeof(a ? 1 : 2.0) + (a ? 1 : 0.5) * 2; }",
+    10
+)]
+#[case::conditional_with_null_pointer(
+    "int main() { int a; int *p; a = 4; p = 0 ? 0 : &a; return *p; }",
+    4
+)]
+#[case::null_pointer_constant_conversions(
+    "int *f() { return 0; } int g(int *p) { return p == 0; } int main() { int a; int *p = f(); return g(0) + (0 != p) * 2 + (p == 0) * 4 + g(&a) * 8; }",
+    5
+)]
+#[case::conditional_struct(
+    "struct s { int a; }; int main() { struct s x; struct s y; struct s z; x.a = 1; y.a = 2; z = 0 ? x : y; return z.a; }",
+    2
+)]
+#[case::comma_operator(
+    "int main() { int a; int b; a = (b = 3, b + 4); return a * 10 + b; }",
+    73
+)]
+#[case::for_without_clauses(
+    "int main() { int i = 0; int n = 0; for (;;) { if (++i > 4) break; n += i; } for (; i < 7;) i++; for (i = 0; i < 3;) i++; for (; ; i++) if (i == 5) break; return n * 10 + i; }",
+    105
+)]
+#[case::comma_in_for(
+    "int main() { int i; int j; int n; n = 0; for (i = 0, j = 10; i < j; i++, j--) n++; return n; }",
+    5
+)]
+#[case::conditional_in_enum(
+    "enum { A = 1 ? 4 : 5, B = A > 3 ? 10 : 20 }; int main() { return A + B; }",
+    14
+)]
+#[case::unsigned_constant_expressions(
+    "unsigned g = -1 / 2u; enum { E = -1 / 2u > 0 }; int a[(unsigned)-1 / 1000000000]; int main() { return (g == 2147483647) + E * 10 + sizeof(a) / sizeof(int) * 100 - 400; }",
+    11
+)]
+#[case::do_while(
+    "int main() { int i; i = 0; do i += 3; while (i < 10); return i; }",
+    12
+)]
+#[case::do_while_runs_once("int main() { int i; i = 0; do { i++; } while (0); return i; }", 1)]
+#[case::break_in_while(
+    "int main() { int i; i = 0; while (1) { if (i == 7) break; i++; } return i; }",
+    7
+)]
+#[case::continue_in_for(
+    "int main() { int i; int sum; sum = 0; for (i = 0; i < 10; i++) { if (i % 2) continue; sum += i; } return sum; }",
+    20
+)]
+#[case::continue_in_do_while(
+    "int main() { int i; int n; i = 0; n = 0; do { i++; if (i < 5) continue; n++; } while (i < 8); return n; }",
+    4
+)]
+#[case::break_from_inner_loop(
+    "int main() { int i; int j; int n; n = 0; for (i = 0; i < 3; i++) for (j = 0; j < 10; j++) { if (j == 2) break; n++; } return n; }",
+    6
+)]
+#[case::switch_sparse(
+    "int f(int x) { switch (x) { case 1: return 10; case 100: return 20; case -5: return 30; default: return 40; } return 0; } int main() { return f(1) + f(100) + f(-5) + f(7); }",
+    100
+)]
+#[case::switch_jump_table(
+    "int f(int x) { int r; r = 0; switch (x) { case 0: r = 1; break; case 1: r = 2; break; case 2: r = 3; break; case 4: r = 5; break; default: r = 9; } return r; } int main() { return f(0) + f(1) * 10 + f(2) * 100 + f(3) + f(4) + f(-1) + f(100); }",
+    97
+)]
+#[case::switch_fallthrough(
+    "int main() { int n; n = 0; switch (2) { case 1: n += 1; case 2: n += 2; case 3: n += 4; break; case 4: n += 8; } return n; }",
+    6
+)]
+#[case::switch_without_default(
+    "int main() { int n; n = 3; switch (n) { case 1: n = 5; } return n; }",
+    3
+)]
+#[case::switch_default_in_middle(
+    "int f(int x) { switch (x) { case 1: return 1; default: return 2; case 3: return 3; } } int main() { return f(1) + f(3) * 10 + f(9) * 100; }",
+    231
+)]
+#[case::switch_unsigned_jump_table(
+    "int f(unsigned x) { switch (x) { case -1: return 1; case 0: return 2; case 1: return 3; case 2: return 4; } return 5; } int main() { return f(-1) + f(0) * 10 + f(2) * 100 + f(7); }",
+    170
+)]
+#[case::switch_large_case_values(
+    "int f(unsigned x) { switch (x) { case 0xFFFFFFFF: return 1; case 1: return 2; } return 3; } int g(long x) { switch (x) { case 4294967296: return 4; case 1: return 5; } return 6; } int main() { return f(-1) + f(1) * 2 + g(1l << 32) * 10 + g(1); }",
+    50
+)]
+#[case::switch_char_and_enum(
+    "enum color { RED, GREEN, BLUE }; int main() { char c; enum color e; c = 'b'; e = BLUE; switch (c) { case 'a': return 1; case 'b': switch (e) { case RED: return 2; case BLUE: return 3; } } return 4; }",
+    3
+)]
+#[case::continue_inside_switch(
+    "int main() { int i; int n; n = 0; for (i = 0; i < 5; i++) { switch (i) { case 2: continue; default: break; } n++; } return n; }",
+    4
+)]
+#[case::duffs_device(
+    "int main() { int n; int count; count = 0; n = 5; switch (n % 4) { case 0: do { count++; case 3: count++; case 2: count++; case 1: count++; } while ((n -= 4) > 0); } return count; }",
+    5
+)]
+#[case::goto_forward_and_backward(
+    "int main() { int i; i = 0; loop: i++; if (i < 5) goto loop; goto end; i = 100; end: return i; }",
+    5
+)]
+#[case::same_label_in_different_functions(
+    "int f() { goto out; return 1; out: return 2; } int main() { goto out; return 3; out: return f() * 10; }",
+    20
+)]
+#[case::same_name_in_sibling_loops(
+    "int main() { int n; int i; n = 0; for (i = 0; i < 3; i++) { int j; j = i; n += j; } for (i = 0; i < 4; i++) { int j; j = 10; n += j; } return n; }",
+    43
+)]
+#[case::nested_shadowing(
+    "int main() { int x; int y; x = 1; { int x; x = 2; { char x; x = 3; y = x; } y = y * 10 + x; } return y + x * 100; }",
+    132
+)]
+#[case::local_shadows_global("int x; int main() { x = 5; { int x; x = 7; } return x; }", 5)]
+#[case::block_shadows_parameter(
+    "int f(int a) { { int a; a = 3; } return a; } int main() { return f(9); }",
+    9
+)]
+#[case::sibling_blocks_share_stack_slots(
+    "int main() { int r; { int a; a = 4; r = a; } { int b; int c[2]; b = 6; c[1] = 2; r = r * 100 + b * 10 + c[1]; } return r % 256; }",
+    462 % 256
+)]
+#[case::multiple_declarators_with_initializers(
+    "int main() { int a = 1, *b = &a, c[3] = {1, 2, 3}; *b = 5; return a + c[0] + c[2]; }",
+    9
+)]
+#[case::omitted_elements_are_zero_filled(
+    "int main() { { int d[4]; d[0] = 9; d[1] = 9; d[2] = 9; d[3] = 9; } { int a[4] = {1}; return a[0] + a[1] + a[2] + a[3]; } }",
+    1
+)]
+#[case::char_array_initialized_with_string(
+    "int main() { char s[8] = \"abc\"; char t[3] = \"xyz\"; return s[0] - 'a' + s[2] - 'c' + s[3] + s[7] + t[2] - 'x'; }",
+    2
+)]
+#[case::array_length_from_initializer(
+    "char g[] = \"abc\"; int h[] = {1, [4] = 2}; int main() { int a[] = {1, 2, 3}; char s[] = \"hello\"; int m[][2] = {1, 2, 3}; return sizeof(a) + sizeof(s) * 2 + sizeof(g) * 3 + sizeof(h) + sizeof(m); }",
+    72
+)]
+#[case::braced_string_initializer(
+    "char g[4] = {\"xy\"}; int main() { char s[4] = {\"ab\"}; return s[1] - 'b' + s[2] + s[3] + g[1] - 'y' + sizeof(s); }",
+    4
+)]
+#[case::brace_elision(
+    "struct p { int x; int y; }; struct p g[2] = {1, 2, 3}; int main() { int a[2][2] = {1, 2, 3, 4}; struct p q = {5, 6}; struct p ps[2] = {q, 7, 8}; return a[0][1] * 10 + a[1][1] + g[1].x * 20 + g[1].y + ps[0].y * 2 + ps[1].y; }",
+    104
+)]
+#[case::designated_array_initializer(
+    "int main() { int a[5] = {[3] = 4, 5, [1] = 2}; return a[1] * 100 + a[3] * 10 + a[4] + a[0] + a[2]; }",
+    245
+)]
+#[case::designated_struct_initializer(
+    "struct p { int x; int y; int z; }; int main() { struct p v = {.z = 3, .x = 1}; return v.x * 100 + v.y * 10 + v.z; }",
+    103
+)]
+#[case::nested_designators(
+    "struct p { int x; int a[3]; }; int main() { struct p v = {.a[1] = 7, .x = 2}; return v.a[1] * 10 + v.a[2] + v.x; }",
+    72
+)]
+#[case::array_of_structs_initializer(
+    "struct p { int x; int y; }; int main() { struct p ps[2] = {{1, 2}, [1].y = 5}; return ps[0].x + ps[0].y * 10 + ps[1].x + ps[1].y * 10; }",
+    71
+)]
+#[case::struct_initialized_by_copy(
+    "struct p { int x; int y; }; int main() { struct p a = {3, 4}; struct p b = a; return b.x * 10 + b.y; }",
+    34
+)]
+#[case::initializer_refers_to_previous_declarator(
+    "int main() { double d = 2.5, e = d * 2; int n = e; return n; }",
+    5
+)]
+#[case::char_pointer_initialized_with_string("int main() { char *s = \"hi\"; return s[1]; }", 105)]
+#[case::initializer_runs_on_each_iteration(
+    "int main() { int n = 0; int k; for (k = 0; k < 3; k++) { int a[2] = {k}; n += a[0] + a[1]; a[1] = 50; } return n; }",
+    3
+)]
+#[case::for_init_declarations(
+    "int main() { int n = 0; for (int i = 0; i < 5; i++) n += i; for (int i = 10; i < 12; i++) n += i; return n; }",
+    31
+)]
+#[case::for_init_declaration_shadows_outer_variable(
+    "int main() { int i = 100; int n = 0; for (int i = 0; i < 3; i++) { int i = 7; n += i; } return n + i; }",
+    121
+)]
+#[case::global_multiple_declarators(
+    "int a = 1, b[3] = {[2] = 5}, *p = &a; int main() { return a + b[2] + *p + b[0]; }",
+    7
+)]
+#[case::global_designated_struct_initializer(
+    "struct p { char c; int x; }; struct p g = {.x = 7}; int main() { return g.c + g.x; }",
+    7
+)]
+#[case::pointer_to_array(
+    "int main() { int a[2][3]; int (*p)[3]; a[1][2] = 9; p = a; return p[1][2] + sizeof(*p); }",
+    21
+)]
+#[case::array_of_pointers(
+    "int main() { int x = 3; int y = 4; int *a[2]; a[0] = &x; a[1] = &y; return *a[0] * *a[1]; }",
+    12
+)]
+#[case::multi_dimensional_array(
+    "int main() { int a[2][3]; int i; int j; for (i = 0; i < 2; i++) for (j = 0; j < 3; j++) a[i][j] = i * 3 + j; return a[1][1] * 10 + sizeof(a) / sizeof(a[0]); }",
+    42
+)]
+#[case::function_pointer_call(
+    "int add(int a, int b) { return a + b; } int main() { int (*fp)(int, int); fp = add; return fp(1, 2) + (*fp)(3, 4) + (&add)(5, 6); }",
+    21
+)]
+#[case::array_of_function_pointers(
+    "int add(int a, int b) { return a + b; } int sub(int a, int b) { return a - b; } int main() { int (*ops[2])(int, int) = {add, sub}; return ops[0](5, 3) * ops[1](5, 3); }",
+    16
+)]
+#[case::function_pointer_parameter(
+    "int twice(int x) { return x * 2; } int apply(int (*f)(int), int x) { return f(x); } int main() { return apply(twice, 21); }",
+    42
+)]
+#[case::function_pointer_member(
+    "struct s { int (*f)(int); int x; }; int inc(int x) { return x + 1; } int main() { struct s v; v.f = inc; v.x = 6; return v.f(v.x); }",
+    7
+)]
+#[case::function_pointer_returning_function(
+    "int three() { return 3; } int (*pick())() { return three; } int main() { return pick()(); }",
+    3
+)]
+#[case::function_prototype(
+    "int f(int); int main() { return f(4); } int f(int x) { return x * x; }",
+    16
+)]
+#[case::sizeof_abstract_declarators(
+    "int main() { return sizeof(int (*)[3]) + sizeof(int[2][3]) + sizeof(int *[2]); }",
+    48
+)]
+#[case::cast_truncates_to_char("int main() { return (char)300; }", 44)]
+#[case::cast_sign_extends_char("int main() { return (int)(char)255 + 2; }", 1)]
+#[case::cast_to_unsigned_char("int main() { return (unsigned char)-1; }", 255)]
+#[case::cast_double_to_int("int main() { return (int)3.9 + (int)-2.5; }", 1)]
+#[case::cast_to_bool("int main() { return (_Bool)0.5 + (_Bool)256; }", 2)]
+#[case::cast_between_pointers(
+    "int main() { int a[2]; a[1] = 7; char *p = (char *)a; return *(int *)(p + 4); }",
+    7
+)]
+#[case::cast_pointer_to_integer("int main() { int a[2]; return (long)&a[1] - (long)&a[0]; }", 4)]
+#[case::sizeof_does_not_evaluate_operand(
+    "int main() { int x = 1; sizeof(x = 5); sizeof x++; return x; }",
+    1
+)]
+#[case::sizeof_does_not_call_function(
+    "int n; int f() { n = 5; return 1; } int main() { return sizeof(f()) + n; }",
+    4
+)]
+#[case::sizeof_without_parentheses("int main() { int a[10]; return sizeof a + sizeof *a; }", 44)]
+#[case::alignof(
+    "struct s { char c; double d; }; int main() { return _Alignof(int) + _Alignof(struct s) + _Alignof(char[3]); }",
+    13
+)]
+#[case::cast_in_global_initializer(
+    "char g = (char)257; long h = (long)(int)2.5 + sizeof(int); int main() { return g + h; }",
+    7
+)]
+#[case::cast_in_array_length("int main() { int a[(char)260]; return sizeof(a); }", 16)]
+#[case::typedef_of_int(
+    "typedef int myint; int main() { myint a = 3; return a + sizeof(myint); }",
+    7
+)]
+#[case::typedef_of_struct_and_pointer(
+    "typedef struct { int x; int y; } point, *point_ptr; int main() { point p; point_ptr q = &p; q->y = 5; return p.y; }",
+    5
+)]
+#[case::typedef_in_block("int main() { typedef char byte; byte b[3]; return sizeof(b); }", 3)]
+#[case::typedef_name_shadowed_by_variable("typedef int t; int main() { int t = 4; return t; }", 4)]
+#[case::global_function_pointers(
+    "int abs(int); int twice(int x) { return x * 2; } int (*fp)(int) = twice; int (*table[3])(int) = {twice, &twice, abs}; int main() { static int (*local)(int) = twice; return fp(1) + table[0](2) + table[1](3) + table[2](-5) + local(4); }",
+    25
+)]
+#[case::typedef_of_function_type(
+    "typedef int F(int); F sq, twice; int main() { return sq(3) + twice(4); } int sq(int x) { return x * x; } int twice(int x) { return x * 2; }",
+    17
+)]
+#[case::typedef_of_function_pointer(
+    "typedef int (*fn)(int); int twice(int x) { return x * 2; } int main() { fn f = twice; return f(21); }",
+    42
+)]
+#[case::const_local("int main() { const int a = 3; int const b = 4; return a + b; }", 7)]
+#[case::const_global(
+    "const int g = 8; const char *s = \"abc\"; int main() { return g + s[1]; }",
+    106
+)]
+#[case::const_pointer_to_mutable(
+    "int main() { int a = 1; int *const p = &a; *p = 9; return a; }",
+    9
+)]
+#[case::pointer_to_const_can_be_reassigned(
+    "int main() { int a = 1; int b = 2; const int *p = &a; p = &b; return *p; }",
+    2
+)]
+#[case::const_char_argument(
+    "int len(const char *s) { int n = 0; while (s[n]) n++; return n; } int main() { return len(\"hello\"); }",
+    5
+)]
+#[case::pointer_qualifier_conversions(
+    "int first(int *const *p) { return **p; } int main() { int a = 3; int *p = &a; const int *q = p; void *v = p; const void *w = q; return first(&p) + (p == q) + (q == w) + *(1 ? p : q); }",
+    8
+)]
+#[case::volatile_local("int main() { volatile int a = 1; a += 2; return a; }", 3)]
+#[case::static_local_persists(
+    "int count() { static int n; n++; return n; } int main() { count(); count(); return count(); }",
+    3
+)]
+#[case::static_local_with_initializer(
+    "int next() { static int n = 10; return n++; } int main() { next(); return next(); }",
+    11
+)]
+#[case::static_locals_in_different_functions(
+    "int f() { static int n = 1; return n; } int g() { static int n = 2; return n; } int main() { return f() * 10 + g(); }",
+    12
+)]
+#[case::static_local_addresses(
+    "int x = 5; int main() { static int x = 1; static int *p = &x; static int a[3] = {1, 2, 3}; static int *q = a; { static int x = 7; static int *r = &x; return *p + q[2] * 10 + *r * 30; } }",
+    241
+)]
+#[case::static_function_and_global(
+    "static int g = 5; static int f() { return g + 1; } int main() { return f(); }",
+    6
+)]
+#[case::block_scope_extern("int g = 7; int main() { int g = 1; { extern int g; return g; } }", 7)]
+#[case::extern_defined_later("extern int g; int main() { return g; } int g = 12;", 12)]
+#[case::extern_variable_from_other_file(
+    "extern int external_counter; int main() { external_counter++; return external_counter; }",
+    6
+)]
+#[case::void_function(
+    "int g; void set(int x) { if (x < 0) return; g = x; } int main() { set(-1); set(4); return g; }",
+    4
+)]
+#[case::void_parameter_list("int f(void) { return 6; } int main(void) { return f(); }", 6)]
+#[case::void_pointer_conversions(
+    "int main() { int a = 9; void *p = &a; int *q = p; return *q; }",
+    9
+)]
+#[case::void_pointer_from_malloc(
+    "void *malloc(long size); int main() { int *p = malloc(2 * sizeof(int)); p[1] = 4; return p[1]; }",
+    4
+)]
+#[case::void_pointer_argument(
+    "int first(void *p) { return *(char *)p; } int main() { char s[2] = {7, 0}; return first(s); }",
+    7
+)]
+#[case::cast_to_void("int main() { int a = 3; (void)a; (void)(a = 5); return a; }", 5)]
+#[case::conditional_of_void_calls(
+    "int g; void a() { g = 1; } void b() { g = 2; } int main() { 0 ? a() : b(); return g; }",
+    2
+)]
+#[case::main_returns_zero_implicitly("int main() { int a = 5; a++; }", 0)]
+#[case::return_value_is_converted(
+    "int f() { return 2.7; } char g() { return 300; } int main() { return f() + g(); }",
+    46
+)]
+#[case::object_like_macro("#define N 3\n#define M N + 1\nint main() { return M * 2; }", 5)]
+#[case::function_like_macro(
+    "#define SQ(x) ((x) * (x))\n#define MAX(a, b) ((a) > (b) ? (a) : (b))\nint main() { return MAX(SQ(1 + 2), 4); }",
+    9
+)]
+#[case::function_like_macro_name_without_arguments(
+    "#define f(x) (x + 1)\nint g(int f) { return f; }\nint main() { return g(f(2)); }",
+    3
+)]
+#[case::stringify_macro_argument(
+    "#define STR(x) #x\nint main() { return sizeof(STR(a  +  \"b\")); }",
+    8
+)]
+#[case::paste_macro_arguments(
+    "#define CAT(a, b) a ## b\nint main() { int xy = 4; return CAT(x, y) + CAT(1, 0); }",
+    14
+)]
+#[case::variadic_macro(
+    "#define SUM(f, ...) f(0, __VA_ARGS__)\nint add(int a, int b, int c) { return a + b + c; }\nint main() { return SUM(add, 2, 3); }",
+    5
+)]
+#[case::self_referential_macro_is_not_expanded_again(
+    "int main() {\n  int v = 2;\n#define v (v * 3)\n  return v;\n}",
+    6
+)]
+#[case::macro_invocation_across_lines(
+    "#define ADD(a, b) (a + b)\nint main() {\n  return ADD(1,\n    2);\n}",
+    3
+)]
+#[case::undef_macro("#define N 3\n#undef N\nint main() { int N = 5; return N; }", 5)]
+#[case::conditional_compilation(
+    "#define A 2\n#if A == 1\nint v = 1;\n#elif defined(A) && A * 2 == 4\nint v = 2;\n#else\nint v = 3;\n#endif\nint main() { return v; }",
+    2
+)]
+#[case::nested_conditionals(
+    "#ifdef NOPE\n#if 1/0\n#error unreachable\n#endif\nint v = 1;\n#else\n#ifndef NOPE\nint v = 4;\n#endif\n#endif\nint main() { return v; }",
+    4
+)]
+#[case::if_expression_operators(
+    "#if (0x10 | 010) == 24 && -1 < 0 && 'a' == 97 && (1 ? 2 : 1 / 0) == 2 && UNDEFINED == 0\nint main() { return 1; }\n#endif",
+    1
+)]
+#[case::line_macro("int main() {\n\n  return __LINE__;\n}", 3)]
+#[case::predefined_macros("int main() { return __STDC__ + __x86_64__; }", 2)]
+#[case::comments_are_removed(
+    "int main() { // comment\n  /* block\n  comment */ return 1 /* 2 */ + 3;\n}",
+    4
+)]
+#[case::line_splice_in_directive("#define N \\\n  7\nint main() { return N; }", 7)]
+#[case::line_comment_continued_by_splice(
+    "int main() { int x = 5; // hi \\\n x = 7;\n return x; }",
+    5
+)]
+#[case::line_comment_continued_by_splice_in_directive(
+    "#define X 1 // c \\\n + 5\nint main() { return X; }",
+    1
+)]
+#[case::hex_octal_and_binary_literals("int main() { return 0x1F + 017 + 0b101 + 0XaB - 0; }", 222)]
+#[case::unsigned_literal_comparison("int main() { return -1 < 0u; }", 0)]
+#[case::long_literal_does_not_wrap(
+    "int main() { long a = 4294967296; return a / 1073741824 + sizeof(2147483648) + sizeof(1L); }",
+    20
+)]
+#[case::hex_literal_is_unsigned_int(
+    "int main() { return 0xffffffff > 0 && sizeof(0xffffffff) == 4; }",
+    1
+)]
+#[case::unsigned_long_literal(
+    "int main() { unsigned long a = 0xffffffffffffffffUL; return (a >> 60) + sizeof(1ul); }",
+    23
+)]
+#[case::line_continuation_in_code("int main() { int ab = 3; return a\\\nb; }", 3)]
+#[case::ignored_specifiers(
+    "static inline int f(int *restrict p) { return *p; } int main() { register int a = 4; auto int b = f(&a); return b; }",
+    4
+)]
+#[case::variadic_function_with_stack_arguments(
+    "#include <stdarg.h>\nint sum(int n, ...) { va_list ap; va_start(ap, n); int s = 0; for (int i = 0; i < n; i++) s += va_arg(ap, int); va_end(ap); return s; }\nint main() { return sum(3, 1, 2, 3) + sum(8, 1, 2, 3, 4, 5, 6, 7, 8); }",
+    42
+)]
+#[case::variadic_doubles_with_float_promotion(
+    "#include <stdarg.h>\ndouble average(int n, ...) { va_list ap; va_start(ap, n); double s = 0; for (int i = 0; i < n; i++) s += va_arg(ap, double); va_end(ap); return s / n; }\nint main() { float f = 2.5f; return average(10, 1.0, 2.0, f, 3.5, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0) * 4; }",
+    19
+)]
+#[case::external_caller_of_variadic_function(
+    "#include <stdarg.h>\nstruct ext_pair { int a; int b; };\nextern long ext_call_format_sum(long (*f)(const char *, ...));\nlong format_sum(const char *format, ...) { va_list ap; va_start(ap, format); long sum = 0; while (*format) { if (*format == 'i') { sum = sum * 3 + va_arg(ap, int); } else if (*format == 'd') { sum = sum * 3 + (long)(va_arg(ap, double) * 2); } else if (*format == 'p') { struct ext_pair p = va_arg(ap, struct ext_pair); sum = sum * 3 + p.a * 10 + p.b; } format++; } va_end(ap); return sum; }\nint main() { return ext_call_format_sum(format_sum) % 256; }",
+    11
+)]
+#[case::external_variadic_function_with_promotions(
+    r#"struct ext_pair { int a; int b; }; extern long ext_format_sum(const char *format, ...); int main() { struct ext_pair p = {2, 3}; char c = 4; float f = 1.5f; return ext_format_sum("ipdid", 1, p, f, c, 2.0) % 256; }"#,
+    233
+)]
+#[case::library_variadic_call(
+    r#"int snprintf(char *buf, long n, const char *format, ...); int strcmp(const char *a, const char *b); int main() { char buf[64]; int n = snprintf(buf, 64, "%d %.2f %.1f %c %s %ld", -5, 2.25, 1.5f, 'x', "ok", 1234567890123); return n * 2 + (strcmp(buf, "-5 2.25 1.5 x ok 1234567890123") == 0); }"#,
+    61
+)]
+#[case::va_copy(
+    "#include <stdarg.h>\nint twice(int n, ...) { va_list ap; va_list copy; va_start(ap, n); va_copy(copy, ap); int s = 0; for (int i = 0; i < n; i++) s += va_arg(ap, int); for (int i = 0; i < n; i++) s = s * 2 + va_arg(copy, int); va_end(copy); va_end(ap); return s; }\nint main() { return twice(3, 1, 2, 3); }",
+    59
+)]
+#[case::pointer_to_variadic_function(
+    "#include <stdarg.h>\nint sum(int n, ...) { va_list ap; va_start(ap, n); int s = 0; for (int i = 0; i < n; i++) s += va_arg(ap, int); va_end(ap); return s; }\nint main() { int (*p)(int, ...) = sum; return p(2, 20, 22); }",
+    42
+)]
+#[case::va_start_after_return_buffer_and_float_parameters(
+    "#include <stdarg.h>\nstruct big { long a; long b; long c; };\nstruct big collect(double scale, int n, ...) { va_list ap; va_start(ap, n); struct big r = {0, 0, 0}; for (int i = 0; i < n; i++) { r.a += va_arg(ap, long); r.b += va_arg(ap, double) * scale; } r.c = va_arg(ap, int); va_end(ap); return r; }\nint main() { struct big r = collect(2.0, 3, 1L, 1.5, 2L, 2.5, 3L, 3.5, 7); return r.a * 10 + r.b + r.c; }",
+    82
+)]
+#[case::va_arg_of_structs_and_pointers(
+    "#include <stdarg.h>\nstruct pair { int a; double b; };\nstruct big { long a; long b; long c; };\nlong take(int n, ...) { va_list ap; va_start(ap, n); long s = 0; for (int i = 0; i < n; i++) { struct pair p = va_arg(ap, struct pair); struct big b = va_arg(ap, struct big); char *q = va_arg(ap, char *); s = s * 7 + p.a + p.b * 2 + b.a + b.b * 2 + b.c * 3 + *q; } va_end(ap); return s; }\nint main() { struct pair p = {1, 1.5}; struct big b = {1, 2, 3}; char c = 5; return take(4, p, b, &c, p, b, &c, p, b, &c, p, b, &c) % 256; }",
+    240
+)]
+#[case::va_list_passed_to_functions(
+    "#include <stdarg.h>\nint vsnprintf(char *buf, long n, const char *format, va_list ap);\nint sum_list(int n, va_list ap) { int s = 0; for (int i = 0; i < n; i++) s += va_arg(ap, int); return s; }\nint format(char *buf, const char *format, ...) { va_list ap; va_start(ap, format); int n = vsnprintf(buf, 32, format, ap); va_end(ap); return n; }\nint sum(int n, ...) { va_list ap; va_start(ap, n); int s = sum_list(n, ap); va_end(ap); return s; }\nint main() { char buf[32]; int n = format(buf, \"%d-%.1f-%s\", 12, 0.5, \"ab\"); return n * 10 + sum(3, 1, 2, 3) + (buf[3] == '0'); }",
+    97
+)]
+#[case::stdio_printf(
+    "#include <stdio.h>\nint main() { return printf(\"%s %d\\n\", \"hi\", 42); }",
+    6
+)]
+#[case::standard_headers(
+    "#include <stdlib.h>\n#include <string.h>\n#include <stdint.h>\n#include <stdbool.h>\n#include <stddef.h>\n#include <limits.h>\n#include <assert.h>\nstruct s { char c; int i; };\nint main() { bool ok = true; char *p = malloc(8); assert(p != NULL); strcpy(p, \"abc\"); int n = strlen(p); free(p); return ok + n * 2 + offsetof(struct s, i) * 10 + (INT64_MAX > 0) * 50 + (UINT32_MAX == 4294967295u) * 100; }",
+    197
+)]
+#[case::func_name(
+    "int function() { return sizeof(__func__) * 10 + (__func__[0] == 'f'); } int main() { return function(); }",
+    91
+)]
 fn integration_test(#[case] input: &str, #[case] expected: i32) {
     let mut failure_count = 0;
     let status = loop {
@@ -121,6 +1060,451 @@ fn integration_test(#[case] input: &str, #[case] expected: i32) {
     assert_eq!(status, expected);
 }
 
+#[rstest]
+#[case::invalid_character("int main () { return 1 @ 2; }", vec![CompileErrorKind::Lex])]
+#[case::missing_semicolon("int main () { return 1 }", vec![CompileErrorKind::Parse])]
+#[case::parse_error_in_each_function(
+    "int f () { return 1 } int main () { return 2 * ; }",
+    vec![CompileErrorKind::Parse, CompileErrorKind::Parse]
+)]
+#[case::undefined_variable("int main () { return x; }", vec![CompileErrorKind::Type])]
+#[case::type_error_in_each_statement(
+    "int main () { x = 1; return y; }",
+    vec![CompileErrorKind::Type, CompileErrorKind::Type]
+)]
+#[case::assign_to_rvalue("int main () { 1 = 2; }", vec![CompileErrorKind::Type])]
+#[case::redefined_variable("int main () { int a; int a; }", vec![CompileErrorKind::Type])]
+#[case::use_before_declaration(
+    "int main () { x = 1; int x; return x; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::variable_out_of_scope("int main () { { int a; } return a; }", vec![CompileErrorKind::Type])]
+#[case::for_variable_out_of_scope(
+    "int main () { for (int i = 0; i < 3; i++) ; return i; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::designator_out_of_range(
+    "int main () { int a[2] = {[2] = 1}; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::unknown_member_designator(
+    "struct p { int x; }; int main () { struct p v = {.y = 1}; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::too_many_local_initializers(
+    "int main () { int a[2] = {1, 2, 3}; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::char_initialized_with_string(
+    "int main () { char c = \"ab\"; return c; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::pointer_initialized_with_integer(
+    "int main () { int *q = 5; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::array_size_missing("int main () { int a[]; return 0; }", vec![CompileErrorKind::Type])]
+#[case::struct_initialized_with_integer(
+    "struct p { int x; }; int main () { struct p v = 1; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::redefined_parameter(
+    "int f(int a) { int a; return a; } int main () { return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::wrong_number_of_arguments(
+    "int f(int a) { return a; } int main () { return f(1, 2); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::redefined_global_variable(
+    "int g = 1; int g; int g = 2; int main() { return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::address_of_incomplete_element_plus_offset(
+    "struct S; extern struct S s; struct S *p = &s + 1;",
+    vec![CompileErrorKind::Type]
+)]
+#[case::tentative_definition_with_conflicting_type(
+    "int g; long g; int main() { return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::redefined_function(
+    "int f() { return 1; } int f() { return 2; } int main() { return f(); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::conflicting_function_types(
+    "int f(int); char f(int x) { return x; } int main() { return f(1); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::global_variable_and_function_with_same_name(
+    "int f; int f() { return 0; } int main() { return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::non_constant_global_initializer(
+    "int f() { return 1; } int g = f(); int h = 1; int i = h; int main() { return 0; }",
+    vec![CompileErrorKind::Type, CompileErrorKind::Type]
+)]
+#[case::too_many_global_array_initializers(
+    "int a[2] = {1, 2, 3}; int main() { return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::unterminated_string_literal(
+    "int main() { return *\"abc; }",
+    vec![CompileErrorKind::Lex]
+)]
+#[case::string_literal_too_long_for_array(
+    "char s[2] = \"abc\"; int main() { return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::pointer_plus_pointer(
+    "int main() { int *p; int *q; return *(p + q); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::pointer_multiplication(
+    "int main() { int *p; p * 2; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::difference_of_different_pointer_types(
+    "int main() { int *p; char *q; return p - q; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::unknown_member(
+    "struct s { int a; }; int main() { struct s x; return x.b; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::member_of_non_struct(
+    "int main() { int a; return a.b; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::incomplete_struct_variable(
+    "struct s; int main() { struct s x; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::redefined_struct(
+    "struct s { int a; }; struct s { int b; }; int main() { return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::duplicate_member(
+    "struct s { int a; char a; }; int main() { return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::assign_different_structs(
+    "struct s { int a; }; struct t { int a; }; int main() { struct s x; struct t y; x = y; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::compare_structs(
+    "struct s { int a; }; int main() { struct s x; struct s y; return x == y; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::return_nonzero_integer_as_pointer(
+    "int *f() { return 1; } int main() { int *p; return p == 1; }",
+    vec![CompileErrorKind::Type, CompileErrorKind::Type]
+)]
+#[case::tag_kind_mismatch(
+    "struct s { int a; }; int main() { union s x; return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::undefined_enum(
+    "int main() { enum e x; return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::non_constant_enumerator(
+    "int g; enum { a = g }; int main() { return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::enum_constant_out_of_scope(
+    "int main() { { enum { a = 7 }; } return a; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::invalid_type_specifiers(
+    "int main() { short long x; signed unsigned y; return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::pointer_plus_double(
+    "int main() { int a; int *p; p = &a; p = p + 1.5; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::long_double_variable(
+    "int main() { long double a = 1.25; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::long_double_cast(
+    "int main() { return (long double)1 > 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::long_double_call(
+    "long double f(long double);\nint main() { f(1.0); return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::long_double_definition(
+    "double f(long double x) { return 0; }\nint main() { return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::long_double_member(
+    "struct S { int i; long double d; } s;\nint main() { return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::long_long_double(
+    "int main() { long long double d; return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::modulo_of_double(
+    "int main() { double d; d = 1.5; return d % 2; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::shift_of_pointer(
+    "int main() { int *p; p = 0; return p << 1; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::bitwise_not_of_double("int main() { return ~1.5; }", vec![CompileErrorKind::Type])]
+#[case::struct_as_condition(
+    "struct s { int a; }; int main() { struct s x; if (x) return 1; return !x; }",
+    vec![CompileErrorKind::Type, CompileErrorKind::Type]
+)]
+#[case::compound_assign_to_rvalue("int main() { 1 += 2; return 0; }", vec![CompileErrorKind::Type])]
+#[case::increment_array(
+    "int main() { int a[2]; a++; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::pointer_minus_assign_pointer(
+    "int main() { int a; int *p; int *q; p = &a; q = &a; p -= q; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::conditional_type_mismatch(
+    "int main() { int a; int *p; p = &a; return *(1 ? p : 1.5); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::missing_colon("int main() { return 1 ? 2; }", vec![CompileErrorKind::Parse])]
+#[case::break_outside_loop("int main() { break; return 0; }", vec![CompileErrorKind::Type])]
+#[case::continue_in_switch(
+    "int main() { switch (1) { case 1: continue; } return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::case_outside_switch("int main() { case 1: return 0; }", vec![CompileErrorKind::Type])]
+#[case::duplicate_case(
+    "int main() { switch (1) { case 1: case 2 - 1: break; } return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::duplicate_case_after_conversion(
+    "int main() { int x = 1; switch (x) { case 1: case 4294967297: break; } return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::multiple_defaults(
+    "int main() { switch (1) { default: default: break; } return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::non_constant_case(
+    "int main() { int a; switch (1) { case a: break; } return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::switch_on_pointer(
+    "int main() { int *p; p = 0; switch (p) { case 0: break; } return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::undefined_label("int main() { goto nowhere; return 0; }", vec![CompileErrorKind::Type])]
+#[case::duplicate_label("int main() { a: a: return 0; }", vec![CompileErrorKind::Type])]
+#[case::call_non_function("int main() { int a; return a(1); }", vec![CompileErrorKind::Type])]
+#[case::cast_is_not_lvalue("int main() { int a; (int)a = 1; return a; }", vec![CompileErrorKind::Type])]
+#[case::cast_struct(
+    "struct s { int x; }; int main() { struct s v; return (int)v; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::cast_double_to_pointer("int main() { int *p = (int *)1.5; return 0; }", vec![CompileErrorKind::Type])]
+#[case::sizeof_incomplete_type("struct s; int main() { return sizeof(struct s); }", vec![CompileErrorKind::Type])]
+#[case::function_pointer_argument_count(
+    "int f(int x) { return x; } int main() { int (*p)(int); p = f; return p(1, 2); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::unnamed_parameter_in_definition(
+    "int f(int) { return 0; } int main() { return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::assign_to_const_local("int main() { const int a = 1; a = 2; return a; }", vec![CompileErrorKind::Type])]
+#[case::assign_to_const_global("const int g = 1; int main() { g = 2; return g; }", vec![CompileErrorKind::Type])]
+#[case::assign_through_pointer_to_const(
+    "int main() { int a; const int *p = &a; *p = 1; return a; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::assign_to_const_pointer(
+    "int main() { int a; int *const p = &a; p = 0; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::assign_to_member_of_const_struct(
+    "struct s { int x; }; int main() { const struct s v = {1}; v.x = 2; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::increment_const("int main() { const int a = 1; a++; return a; }", vec![CompileErrorKind::Type])]
+#[case::compound_assign_to_const_element(
+    "int main() { const int a[2] = {1, 2}; a[0] += 1; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::discard_const_in_argument(
+    "const int g = 1; int f(int *p) { *p = 5; return 0; } int main() { return f(&g); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::discard_const_in_assignment(
+    "int main() { const int a = 1; const int *p = &a; int *q; q = p; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::discard_const_through_void_pointer(
+    "int main() { const int a = 1; const void *p = &a; int *q = p; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::add_const_below_top_level(
+    "int main() { int a; int *p = &a; int **pp = &p; const int **q = pp; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::discard_const_in_global_initializer(
+    "const int g = 1; int *p = &g;",
+    vec![CompileErrorKind::Type]
+)]
+#[case::conflicting_typedef("typedef int t; typedef char t;", vec![CompileErrorKind::Parse])]
+#[case::address_of_automatic_in_static_initializer(
+    "int x; int main() { int x; static int *p = &x; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::initialize_typedef_function("typedef int F(int); F f = 0;", vec![CompileErrorKind::Parse])]
+#[case::typedef_redeclares_variable("int t; typedef int t;", vec![CompileErrorKind::Parse])]
+#[case::multiple_storage_classes("static extern int g;", vec![CompileErrorKind::Parse])]
+#[case::block_extern_with_initializer(
+    "int main() { extern int g = 1; return g; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::conflicting_extern_declaration(
+    "extern int g; char g; int main() { return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::storage_class_in_parameter("int f(static int a) { return a; }", vec![CompileErrorKind::Parse])]
+#[case::qualifier_without_type("int main() { const a = 1; return a; }", vec![CompileErrorKind::Parse])]
+#[case::void_function_returns_value("void f() { return 1; }", vec![CompileErrorKind::Type])]
+#[case::non_void_function_returns_nothing("int f() { return; }", vec![CompileErrorKind::Type])]
+#[case::use_void_value(
+    "void f() {} int main() { int a = f(); return a; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::void_value_in_arithmetic("void f() {} int main() { return f() + 1; }", vec![CompileErrorKind::Type])]
+#[case::void_variable("int main() { void a; return 0; }", vec![CompileErrorKind::Type])]
+#[case::dereference_void_pointer(
+    "int main() { int a; void *p = &a; return *p; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::void_pointer_arithmetic(
+    "int main() { int a; void *p = &a; p = p + 1; return 0; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::argument_to_void_parameter_list(
+    "int f(void) { return 0; } int main() { return f(1); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::sizeof_void("int main() { return sizeof(void); }", vec![CompileErrorKind::Type])]
+#[case::void_combined_with_int("void int f() {}", vec![CompileErrorKind::Parse])]
+#[case::error_directive(
+    "#error stop\nint main() { return 0; }",
+    vec![CompileErrorKind::Preprocess]
+)]
+#[case::unterminated_conditional("#if 1\nint main() { return 0; }", vec![CompileErrorKind::Preprocess])]
+#[case::endif_without_if("#endif\nint main() { return 0; }", vec![CompileErrorKind::Preprocess])]
+#[case::else_after_else(
+    "#if 0\n#else\n#else\n#endif\nint main() { return 0; }",
+    vec![CompileErrorKind::Preprocess]
+)]
+#[case::invalid_directive("#frobnicate\nint main() { return 0; }", vec![CompileErrorKind::Preprocess])]
+#[case::division_by_zero_in_if(
+    "#if 1 / 0\n#endif\nint main() { return 0; }",
+    vec![CompileErrorKind::Preprocess]
+)]
+#[case::wrong_number_of_macro_arguments(
+    "#define F(a, b) a\nint main() { return F(1); }",
+    vec![CompileErrorKind::Preprocess]
+)]
+#[case::unterminated_macro_arguments(
+    "#define F(a) a\nint main() { return F(1; }",
+    vec![CompileErrorKind::Preprocess]
+)]
+#[case::include_not_found(
+    "#include \"no_such_header.h\"\nint main() { return 0; }",
+    vec![CompileErrorKind::Preprocess]
+)]
+#[case::unterminated_comment("int main() { /* return 0; }", vec![CompileErrorKind::Preprocess])]
+#[case::concatenate_different_prefixes(
+    "int main() { L\"a\" u\"b\"; return 0; }",
+    vec![CompileErrorKind::Parse]
+)]
+#[case::invalid_suffix_on_integer("int main() { return 12abc; }", vec![CompileErrorKind::Lex])]
+#[case::invalid_octal_digit("int main() { return 09; }", vec![CompileErrorKind::Lex])]
+#[case::integer_literal_too_large(
+    "int main() { return 99999999999999999999; }",
+    vec![CompileErrorKind::Lex]
+)]
+#[case::malformed_exponent("int main() { return 1e; }", vec![CompileErrorKind::Lex])]
+#[case::variadic_without_named_parameter("int f(...);", vec![CompileErrorKind::Parse])]
+#[case::too_few_arguments_to_variadic_function(
+    "int f(int n, ...); int main() { return f(); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::void_variadic_argument(
+    "int f(int n, ...); void g(); int main() { return f(1, g()); }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::va_start_in_function_with_fixed_arguments(
+    "#include <stdarg.h>\nint f(int n) { va_list ap; va_start(ap, n); va_end(ap); return n; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::va_arg_of_float(
+    "#include <stdarg.h>\nint f(int n, ...) { va_list ap; va_start(ap, n); float x = va_arg(ap, float); va_end(ap); return x; }",
+    vec![CompileErrorKind::Type]
+)]
+#[case::va_arg_of_non_va_list(
+    "#include <stdarg.h>\nint f(int n, ...) { int ap; return va_arg(ap, int); }",
+    vec![CompileErrorKind::Type]
+)]
+fn compile_error_test(#[case] input: &str, #[case] expected: Vec<CompileErrorKind>) {
+    let errors = process(input, std::io::sink()).expect_err("compilation should fail");
+    assert_eq!(
+        errors.iter().map(|error| error.kind).collect::<Vec<_>>(),
+        expected
+    );
+}
+
+#[rstest]
+#[case::undefined_variable("int main () { return x; }", (21, 22))]
+#[case::undefined_function("int main () { return f(1) + 2; }", (21, 25))]
+#[case::call_non_function("int main () { int a; return a(1); }", (28, 29))]
+#[case::invalid_cast("int main () { double d; return *(int *)d; }", (32, 40))]
+#[case::argument_type_mismatch(
+    "int f(int *p) { return 0; } int main () { int a; return f(a); }",
+    (58, 59)
+)]
+#[case::dereference_non_pointer("int main () { int a; return *a + 1; }", (29, 30))]
+#[case::redefined_variable("int main () { int a; int a; }", (21, 27))]
+#[case::use_before_declaration("int main () { x = 1; int x; return x; }", (14, 15))]
+#[case::designator_out_of_range("int main () { int a[2] = {[2] = 1}; return 0; }", (26, 29))]
+#[case::redefined_in_inner_block("int main () { int a; { int b; int b; } }", (30, 36))]
+#[case::too_many_global_array_initializers("int a[2] = {1, 2, 3};", (18, 19))]
+#[case::non_constant_global_initializer("int g = 1; int h = g + 1;", (19, 20))]
+#[case::assign_to_const("int main () { const int a = 1; a = 2; return a; }", (31, 32))]
+#[case::return_without_value("int f () { if (1) return; return 1; }", (18, 25))]
+#[case::undefined_variable_after_directive(
+    "#define N 1\nint main () { return x; }",
+    (33, 34)
+)]
+#[case::invalid_directive("int a;\n#foo\n", (8, 11))]
+#[case::invalid_suffix_on_integer("int main() { return 0x1g; }", (20, 24))]
+#[case::too_few_arguments_to_variadic_function(
+    "int f(int n, ...); int main() { return f(); }",
+    (39, 42)
+)]
+fn compile_error_span_test(#[case] input: &str, #[case] expected: (usize, usize)) {
+    let errors = process(input, std::io::sink()).expect_err("compilation should fail");
+    assert_eq!(
+        errors[0].span,
+        Some(Span::new(
+            SourcePosition(expected.0),
+            SourcePosition(expected.1)
+        ))
+    );
+}
+
 fn execute_test_case(input: &str) -> Result<i32, Box<dyn std::error::Error>> {
     let suffix = rand::thread_rng()
         .sample_iter(&rand::distributions::Alphanumeric)
@@ -131,26 +1515,22 @@ fn execute_test_case(input: &str) -> Result<i32, Box<dyn std::error::Error>> {
     {
         let write = std::fs::File::create(format!("{}-{}.s", OUT_FILE_BASE_NAME, suffix))
             .expect("cannot open the assembly file as create mode");
-        process(input, write);
+        process(input, write).map_err(|errors| format!("compile errors: {errors:?}"))?;
     }
 
-    Command::new("gcc")
-        .arg("-S")
-        .arg("-o")
-        .arg(format!("{}.s", EXTERNAL_FUNC_FILE_BASE_NAME))
-        .arg(format!("{}.c", EXTERNAL_FUNC_FILE_BASE_NAME))
-        .output()
-        .or(Err("error on gcc-ing the external file"))?;
-
+    // 外部関数は共有のファイルを書き換えないよう、ソースのまま一緒にリンクする。
     let gcc_output = Command::new("gcc")
         .arg("--static")
         .arg("-o")
         .arg(format!("{}-{}", OUT_FILE_BASE_NAME, suffix))
         .arg(format!("{}-{}.s", OUT_FILE_BASE_NAME, suffix))
-        .arg(format!("{}.s", EXTERNAL_FUNC_FILE_BASE_NAME))
+        .arg(format!("{}.c", EXTERNAL_FUNC_FILE_BASE_NAME))
         .output()
         .or(Err("error on gcc-ing the generated assembly"))?;
-    let status = Command::new(format!("./{}-{}", OUT_FILE_BASE_NAME, suffix)).status();
+    // 標準出力はテストの出力に混ざらないよう捨てる。
+    let status = Command::new(format!("./{}-{}", OUT_FILE_BASE_NAME, suffix))
+        .output()
+        .map(|output| output.status);
 
     Command::new("rm")
         .arg(format!("{}-{}.s", OUT_FILE_BASE_NAME, suffix))
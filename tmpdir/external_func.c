@@ -1,12 +1,123 @@
+#include <stdarg.h>
+#include <stdlib.h>
+
 int external_func(int a, int b, int c, int d, int e, int f) {
   return a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f;
 }
 
 int *test_malloc_4() {
-  long *a = (int *)malloc(4 * sizeof(int));
+  int *a = malloc(4 * sizeof(int));
   a[0] = 1;
   a[1] = 2;
   a[2] = 3;
   a[3] = 4;
   return a;
 }
+
+double weighted_double_sum(double a, int n, float b) { return a * n + b; }
+
+long many_mixed_args(int a, double b, long c, float d, int e, int f, int g, int h,
+                     double i, char j, double k, double l, double m, double n,
+                     double o, double p, short q) {
+  return a + b * 2 + c * 3 + d * 4 + e * 5 + f * 6 + g * 7 + h * 8 + i * 9 +
+         j * 10 + k * 11 + l * 12 + m * 13 + n * 14 + o * 15 + p * 16 + q * 17;
+}
+
+int apply_eight_ints(int (*f)(int, int, int, int, int, int, int, int)) {
+  return f(1, 2, 3, 4, 5, 6, 7, 8);
+}
+
+struct ext_pair {
+  int a;
+  int b;
+};
+
+struct ext_mixed {
+  double d;
+  long l;
+};
+
+struct ext_floats {
+  float x;
+  float y;
+  double z;
+};
+
+struct ext_odd {
+  char c[3];
+};
+
+struct ext_big {
+  long a;
+  long b;
+  long c;
+};
+
+struct ext_pair ext_make_pair(int a, int b) {
+  struct ext_pair p = {a, b};
+  return p;
+}
+
+struct ext_mixed ext_scale_mixed(struct ext_mixed m, int k) {
+  m.d *= k;
+  m.l *= k;
+  return m;
+}
+
+double ext_floats_sum(struct ext_floats f) { return f.x + f.y * 2 + f.z * 3; }
+
+struct ext_odd ext_make_odd(char x) {
+  struct ext_odd o = {{x, x + 1, x + 2}};
+  return o;
+}
+
+int ext_odd_sum(struct ext_odd o) { return o.c[0] + o.c[1] * 2 + o.c[2] * 3; }
+
+struct ext_big ext_make_big(long n) {
+  struct ext_big b = {n, n * 2, n * 3};
+  return b;
+}
+
+long ext_big_sum(int scale, struct ext_big b, int offset) {
+  return (b.a + b.b * 2 + b.c * 3) * scale + offset;
+}
+
+int ext_apply_pair(struct ext_pair (*f)(struct ext_pair)) {
+  struct ext_pair p = {3, 4};
+  struct ext_pair r = f(p);
+  return r.a * 10 + r.b;
+}
+
+long ext_apply_big(struct ext_big (*f)(struct ext_big, long)) {
+  struct ext_big b = {1, 2, 3};
+  struct ext_big r = f(b, 10);
+  return r.a + r.b + r.c;
+}
+
+/* 書式の文字ごとに int, double, struct ext_pair を取り出して混ぜ合わせる。 */
+long ext_format_sum(const char *format, ...) {
+  va_list ap;
+  va_start(ap, format);
+  long sum = 0;
+  for (; *format; format++) {
+    if (*format == 'i') {
+      sum = sum * 3 + va_arg(ap, int);
+    } else if (*format == 'd') {
+      sum = sum * 3 + (long)(va_arg(ap, double) * 2);
+    } else if (*format == 'p') {
+      struct ext_pair p = va_arg(ap, struct ext_pair);
+      sum = sum * 3 + p.a * 10 + p.b;
+    }
+  }
+  va_end(ap);
+  return sum;
+}
+
+long ext_call_format_sum(long (*f)(const char *, ...)) {
+  struct ext_pair p = {1, 2};
+  return f("idpiddddddddip", 3, 1.5, p, 4, 0.5, 1.0, 1.5, 2.0, 2.5, 3.0, 3.5,
+           4.0, 5, p) %
+         1000;
+}
+
+int external_counter = 5;